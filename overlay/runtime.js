@@ -0,0 +1,515 @@
+// Overlay runtime for `/alert/:id`, embedded in and served by the server, see `src/overlay.rs`.
+//
+// Started by the page with `StreamAlerts.start({ alertId, version, preload, preview })`, or for
+// a scene with `StreamAlerts.startScene({ sceneId, version, preload, preview })`, which drives
+// every element with a `data-alert-id` over one websocket.
+(function () {
+  "use strict";
+
+  // websocket protocol versions this runtime understands
+  var PROTOCOLS = [1];
+  // must match `CLOSE_UNSUPPORTED_PROTOCOL`
+  var CLOSE_UNSUPPORTED_PROTOCOL = 4001;
+  var BACKOFF_BASE_MS = 500;
+  var BACKOFF_MAX_MS = 30000;
+  // media waiting to play on the media part, the oldest is dropped when full
+  var MEDIA_QUEUE_MAX = 20;
+
+  var config;
+  var masterVolume = 1;
+
+  // media elements by url, volume is scaled by the `?volume=` query param
+  var mediaCache = {};
+  function loadMedia(media) {
+    if (mediaCache[media.url]) {
+      return mediaCache[media.url];
+    }
+    var el;
+    if (media.kind === "audio") {
+      el = new Audio();
+      el.preload = "auto";
+    } else if (media.kind === "video") {
+      el = document.createElement("video");
+      el.preload = "auto";
+      el.playsInline = true;
+    } else {
+      el = new Image();
+    }
+    el.src = media.url;
+    mediaCache[media.url] = el;
+    return el;
+  }
+  // play `list` in `container`, resolves when all audio and video has ended
+  function playMedia(list, container) {
+    container.innerHTML = "";
+    return Promise.all(
+      (list || []).map(function (media) {
+        var el = loadMedia(media);
+        if (media.kind === "image") {
+          container.appendChild(el.cloneNode());
+          return Promise.resolve();
+        }
+        el.volume = Math.min(1, Math.max(0, media.volume * masterVolume));
+        el.currentTime = 0;
+        if (media.kind === "video") {
+          container.appendChild(el);
+        }
+        return new Promise(function (resolve) {
+          el.onended = el.onerror = function () {
+            if (media.kind === "video") {
+              el.remove();
+            }
+            resolve();
+          };
+          el.play().catch(function (error) {
+            console.warn("could not play media", media.url, error);
+            resolve();
+          });
+        });
+      })
+    );
+  }
+  // transitions, `direction: "reverse"` is used for exits
+  var transitionKeyframes = {
+    fade: [{ opacity: 0 }, { opacity: 1 }],
+    slide: [
+      { opacity: 0, transform: "translateY(1em)" },
+      { opacity: 1, transform: "none" },
+    ],
+    scale_pop: [
+      { opacity: 0, transform: "scale(0.5)" },
+      { opacity: 1, transform: "scale(1.1)", offset: 0.7 },
+      { opacity: 1, transform: "scale(1)" },
+    ],
+  };
+  var noTransition = { kind: "none", duration_ms: 0, easing: "linear" };
+  function transitionDuration(transition) {
+    return transition && transition.kind !== "none" ? transition.duration_ms : 0;
+  }
+  function animateTransition(el, transition, exit) {
+    var frames = transitionKeyframes[transition.kind];
+    if (!frames || !el.animate) {
+      return Promise.resolve();
+    }
+    var options = {
+      duration: transition.duration_ms,
+      easing: transition.easing,
+      direction: exit ? "reverse" : "normal",
+      fill: exit ? "forwards" : "none",
+    };
+    var animation;
+    try {
+      animation = el.animate(frames, options);
+    } catch (e) {
+      // an easing the browser doesn't understand throws
+      options.easing = "linear";
+      animation = el.animate(frames, options);
+    }
+    return animation.finished.catch(function () {});
+  }
+  function cancelAnimations(el) {
+    if (el.getAnimations) {
+      el.getAnimations().forEach(function (a) {
+        a.cancel();
+      });
+    }
+  }
+  // reveal the text nodes of `el` one character at a time
+  function typewrite(el, duration, token) {
+    var walker = document.createTreeWalker(el, NodeFilter.SHOW_TEXT);
+    var nodes = [];
+    var total = 0;
+    while (walker.nextNode()) {
+      nodes.push([walker.currentNode, walker.currentNode.data]);
+      total += walker.currentNode.data.length;
+      walker.currentNode.data = "";
+    }
+    var start = performance.now();
+    function step(now) {
+      if (el._swapToken !== token) {
+        return;
+      }
+      var progress = duration > 0 ? Math.min(1, (now - start) / duration) : 1;
+      var shown = Math.floor(total * progress);
+      nodes.forEach(function (node) {
+        node[0].data = node[1].slice(0, Math.max(0, shown));
+        shown -= node[1].length;
+      });
+      if (progress < 1) {
+        requestAnimationFrame(step);
+      }
+    }
+    requestAnimationFrame(step);
+  }
+  // replace the content of `el` with `set`, animating the exit of the old content
+  // and the enter of the new one. A newer swap cancels an older one.
+  function swap(el, set, transition) {
+    transition = transition || noTransition;
+    var token = (el._swapToken = (el._swapToken || 0) + 1);
+    if (transition.kind === "typewriter") {
+      cancelAnimations(el);
+      set();
+      typewrite(el, transition.duration_ms, token);
+      return;
+    }
+    var exit =
+      el.hidden || !el.textContent.trim()
+        ? Promise.resolve()
+        : animateTransition(el, transition, true);
+    exit.then(function () {
+      if (el._swapToken !== token) {
+        return;
+      }
+      cancelAnimations(el);
+      set();
+      animateTransition(el, transition, false);
+    });
+  }
+  function swapHtml(el, html, transition) {
+    swap(
+      el,
+      function () {
+        el.innerHTML = html;
+      },
+      transition
+    );
+  }
+
+  // timer layers count down to `data-ends-at`
+  function tickTimers() {
+    document.querySelectorAll(".alert-timer").forEach(function (el) {
+      var left = Math.max(0, Math.ceil((parseInt(el.dataset.endsAt) - Date.now()) / 1000));
+      var text = Math.floor(left / 60) + ":" + String(left % 60).padStart(2, "0");
+      if (el.textContent !== text) {
+        el.textContent = text;
+      }
+    });
+  }
+
+  // the handlers for messages of one alert, shown in `root`: the document for `/alert/:id`,
+  // or the element of the alert in a scene.
+  function createOverlay(root) {
+    // the parts of the overlay are marked with `data-part`, a scene can show an alert more than once
+    function part(name) {
+      return root.querySelector('[data-part="' + name + '"]');
+    }
+
+    function fieldElements(name) {
+      return part("text").querySelectorAll('[data-alert-field="' + CSS.escape(name) + '"]');
+    }
+    function tweenCounter(el, from, to, duration, token) {
+      var start = performance.now();
+      function step(now) {
+        if (el._swapToken !== token) {
+          return;
+        }
+        var progress = duration > 0 ? Math.min(1, (now - start) / duration) : 1;
+        var eased = 1 - Math.pow(1 - progress, 3);
+        el.textContent =
+          progress < 1 ? String(Math.round(from + (to - from) * eased)) : String(to);
+        if (progress < 1) {
+          requestAnimationFrame(step);
+        }
+      }
+      requestAnimationFrame(step);
+    }
+    // update fields in place in elements marked with `data-alert-field="name"`,
+    // only done if every changed field has such an element and none was removed.
+    function updateFieldsInPlace(fields, transition) {
+      transition = transition || noTransition;
+      if (
+        !fields.length ||
+        !fields.every(function (field) {
+          return field.value !== null && fieldElements(field.name).length > 0;
+        })
+      ) {
+        return false;
+      }
+      var duration = transitionDuration(transition);
+      fields.forEach(function (field) {
+        fieldElements(field.name).forEach(function (el) {
+          var from = parseFloat(el.textContent);
+          if (typeof field.value === "number" && !isNaN(from)) {
+            var token = (el._swapToken = (el._swapToken || 0) + 1);
+            tweenCounter(el, from, field.value, duration, token);
+          } else {
+            swap(
+              el,
+              function () {
+                el.textContent = String(field.value);
+              },
+              transition
+            );
+          }
+        });
+      });
+      return true;
+    }
+
+    function updateLayers(data) {
+      var canvas = part("layers");
+      canvas.style.width = data.canvas.width + "px";
+      canvas.style.height = data.canvas.height + "px";
+      var keep = {};
+      data.layers.forEach(function (layer) {
+        keep[layer.layer_id] = true;
+        var el = canvas.querySelector('[data-layer-id="' + CSS.escape(layer.layer_id) + '"]');
+        if (!el) {
+          el = document.createElement("div");
+          el.className = "alert-layer";
+          el.dataset.layerId = layer.layer_id;
+          canvas.appendChild(el);
+        }
+        // compare the html as the browser serializes it
+        var template = document.createElement("template");
+        template.innerHTML = layer.html;
+        var html = template.innerHTML;
+        if ((el._html !== undefined ? el._html : el.innerHTML) === html) {
+          return;
+        }
+        el._html = html;
+        var bar = el.querySelector(".alert-progress-bar");
+        var newBar = template.content.querySelector(".alert-progress-bar");
+        if (bar && newBar) {
+          // let the css transition on the width animate progress
+          bar.style.width = newBar.style.width;
+        } else {
+          swapHtml(el, html, data.transition);
+        }
+      });
+      Array.prototype.slice.call(canvas.children).forEach(function (el) {
+        if (!keep[el.dataset.layerId]) {
+          el.remove();
+        }
+      });
+    }
+
+    var mediaQueue = [];
+    var mediaPlaying = false;
+    function queueMedia(list) {
+      if (mediaQueue.length >= MEDIA_QUEUE_MAX) {
+        mediaQueue.shift();
+      }
+      mediaQueue.push(list);
+      if (!mediaPlaying) {
+        nextMedia();
+      }
+    }
+    function nextMedia() {
+      var list = mediaQueue.shift();
+      mediaPlaying = !!list;
+      if (list) {
+        playMedia(list, part("media")).then(nextMedia);
+      }
+    }
+
+    // set when a listener for "alert-fields" calls preventDefault(),
+    // the following full render is then left to the listener.
+    var skipMarkdown = false;
+    // the render following fields updated in place is applied without a transition,
+    // once the in place animations are done.
+    var quietMarkdown = false;
+    return {
+      // the full state, sent after connecting and when messages were missed
+      sync: function (data) {
+        skipMarkdown = false;
+        quietMarkdown = false;
+        var textEl = part("text");
+        if (textEl.innerHTML !== data.text) {
+          textEl._swapToken = (textEl._swapToken || 0) + 1;
+          cancelAnimations(textEl);
+          textEl.innerHTML = data.text;
+        }
+        part("dynamic-style").textContent = data.style;
+        updateLayers({ canvas: data.canvas, layers: data.layers, transition: noTransition });
+      },
+      update: function () {
+        window.location.reload();
+      },
+      fields: function (data) {
+        // detail is a list of { id, name, value, previous }, value is null for removed fields,
+        // the target is the alert element in a scene
+        var fieldsEvent = new CustomEvent("alert-fields", {
+          detail: data.fields,
+          cancelable: true,
+          bubbles: true,
+        });
+        var handled = !root.dispatchEvent(fieldsEvent);
+        skipMarkdown = handled && data.rerender;
+        quietMarkdown =
+          !handled && data.rerender && updateFieldsInPlace(data.fields, data.transition);
+      },
+      message_markdown: function (data) {
+        var textEl = part("text");
+        if (skipMarkdown) {
+          skipMarkdown = false;
+        } else if (quietMarkdown) {
+          quietMarkdown = false;
+          var token = textEl._swapToken;
+          setTimeout(function () {
+            if (textEl._swapToken === token && textEl.innerHTML !== data.text) {
+              textEl.innerHTML = data.text;
+            }
+          }, transitionDuration(data.transition) + 50);
+        } else {
+          swapHtml(textEl, data.text, data.transition);
+        }
+      },
+      event_show: function (data) {
+        var eventEl = part("event");
+        eventEl.dataset.eventId = data.event_id;
+        eventEl._transition = data.transition;
+        part("event-style").textContent = data.style;
+        playMedia(data.media, part("event-media"));
+        swap(
+          eventEl,
+          function () {
+            eventEl.innerHTML = data.text;
+            eventEl.hidden = false;
+          },
+          data.transition
+        );
+      },
+      event_hide: function (data) {
+        var eventEl = part("event");
+        if (eventEl.dataset.eventId !== data.event_id) {
+          return;
+        }
+        var token = (eventEl._swapToken = (eventEl._swapToken || 0) + 1);
+        animateTransition(eventEl, eventEl._transition || noTransition, true).then(
+          function () {
+            if (eventEl._swapToken !== token) {
+              return;
+            }
+            cancelAnimations(eventEl);
+            eventEl.hidden = true;
+            eventEl.innerHTML = "";
+            part("event-media").innerHTML = "";
+          }
+        );
+      },
+      layers: updateLayers,
+      media: function (data) {
+        queueMedia(data.media);
+      },
+      style: function (data) {
+        part("dynamic-style").textContent = data.style;
+      },
+    };
+  }
+
+  // connection status, only shown in preview mode
+  var statusEl;
+  function setStatus(status) {
+    if (!config.preview) {
+      return;
+    }
+    if (!statusEl) {
+      statusEl = document.createElement("div");
+      document.body.appendChild(statusEl);
+    }
+    statusEl.className = "alert-status alert-status-" + status;
+    statusEl.textContent = status;
+  }
+
+  // overlays by alert id
+  var overlays = {};
+  // messages not for a specific alert
+  var connectionHandlers = {
+    hello: function (data) {
+      attempts = 0;
+      setStatus("connected");
+      if (data.runtime !== config.version) {
+        console.info("runtime changed, reloading", data.runtime);
+        window.location.reload();
+      }
+    },
+    reload: function () {
+      window.location.reload();
+    },
+  };
+  function handle(data) {
+    var overlay = overlays[data.alert_id];
+    var handler = connectionHandlers[data.type] || (overlay && overlay[data.type]);
+    if (handler) {
+      handler(data);
+    } else {
+      console.warn("unhandled message", data);
+    }
+  }
+
+  // "equal jitter", half of the delay is fixed and half random
+  function backoff(attempt) {
+    var max = Math.min(BACKOFF_MAX_MS, BACKOFF_BASE_MS * Math.pow(2, attempt));
+    return max / 2 + (Math.random() * max) / 2;
+  }
+  var attempts = 0;
+  // connect to the websocket at `path`, sending `init` on open
+  function connect(path, init) {
+    setStatus("connecting");
+    var protocol = window.location.protocol === "http:" ? "ws://" : "wss://";
+    var ws = new WebSocket(protocol + window.location.host + path);
+    ws.onopen = function () {
+      ws.send(
+        JSON.stringify(
+          Object.assign({ type: "init", protocols: PROTOCOLS, runtime: config.version }, init)
+        )
+      );
+    };
+    ws.onmessage = function (event) {
+      handle(JSON.parse(event.data));
+    };
+    ws.onerror = function (event) {
+      console.error("error", event);
+    };
+    ws.onclose = function (event) {
+      var delay = backoff(attempts++);
+      if (event.code === CLOSE_UNSUPPORTED_PROTOCOL) {
+        // the server is newer, the page refers to its runtime
+        setStatus("outdated");
+        setTimeout(function () {
+          window.location.reload();
+        }, delay);
+        return;
+      }
+      setStatus("disconnected");
+      setTimeout(function () {
+        connect(path, init);
+      }, delay);
+    };
+  }
+
+  function run(options, setup) {
+    config = options;
+    var volume = parseFloat(new URLSearchParams(window.location.search).get("volume"));
+    // setting a volume outside 0..1 throws, e.g. with `?volume=abc`
+    masterVolume = isFinite(volume) ? Math.min(1, Math.max(0, volume)) : 1;
+    (config.preload || []).forEach(loadMedia);
+    function go() {
+      setup();
+      setInterval(tickTimers, 250);
+    }
+    if (document.readyState === "loading") {
+      document.addEventListener("DOMContentLoaded", go);
+    } else {
+      go();
+    }
+  }
+
+  window.StreamAlerts = {
+    start: function (options) {
+      run(options, function () {
+        overlays[options.alertId] = createOverlay(document);
+        connect("/alert/ws/" + options.alertId, { alert_id: options.alertId });
+      });
+    },
+    startScene: function (options) {
+      run(options, function () {
+        document.querySelectorAll("[data-alert-id]").forEach(function (el) {
+          overlays[el.dataset.alertId] = createOverlay(el);
+        });
+        connect("/scene/ws/" + options.sceneId, { scene_id: options.sceneId });
+      });
+    },
+  };
+})();
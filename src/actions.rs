@@ -0,0 +1,521 @@
+//! Actions on alerts triggered by integrations, e.g. a Twitch follow incrementing a counter.
+//!
+//! Values in actions may reference variables of the triggering event with `$name`.
+
+use crate::alerts::*;
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum AlertAction {
+    /// Increment a counter field, `amount` may be negative.
+    IncrementField {
+        alert_id: AlertId,
+        field: AlertFieldName,
+        amount: String,
+    },
+    SetField {
+        alert_id: AlertId,
+        field: AlertFieldName,
+        value: String,
+    },
+    /// Append a line to a text field, keeping the last [`PUSH_LIMIT`] lines.
+    PushField {
+        alert_id: AlertId,
+        field: AlertFieldName,
+        value: String,
+    },
+    SetText {
+        alert_id: AlertId,
+        text: String,
+    },
+    /// Enqueue an event with the variables as payload.
+    EnqueueEvent {
+        alert_id: AlertId,
+        event_type: String,
+    },
+    /// Start the timer layers of an alert, counting down `seconds`.
+    StartTimers {
+        alert_id: AlertId,
+        seconds: String,
+    },
+    /// Switch the program scene in OBS.
+    ObsSetScene {
+        scene: String,
+    },
+    /// Show, hide or toggle a source, in the current scene if `scene` is empty.
+    ObsSetSourceVisible {
+        scene: String,
+        source: String,
+        /// `show`, `hide` or `toggle`
+        visible: String,
+    },
+    /// Reload a browser source in OBS, bypassing the cache.
+    ObsRefreshBrowserSource {
+        source: String,
+    },
+}
+
+/// Lines kept in a field by [`AlertAction::PushField`]
+pub const PUSH_LIMIT: usize = 10;
+
+/// Result of running an action.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct ActionOutcome {
+    pub action: String,
+    pub error: Option<String>,
+}
+
+impl AlertAction {
+    /// Build an action from a form, `value` is the amount, value, text or event type depending on
+    /// `kind`.
+    ///
+    /// OBS actions ignore the alert, `value` is the scene or source. To show or hide a source
+    /// `field` is the source, or `scene/source`, and `value` is `show`, `hide` or `toggle`.
+    pub fn from_form(
+        kind: &str,
+        alert_id: AlertId,
+        field: &str,
+        value: String,
+    ) -> Result<Self, eyre::Report> {
+        let field = AlertFieldName::from(field.trim());
+        Ok(match kind {
+            "increment_field" => AlertAction::IncrementField {
+                alert_id,
+                field,
+                amount: value,
+            },
+            "set_field" => AlertAction::SetField {
+                alert_id,
+                field,
+                value,
+            },
+            "push_field" => AlertAction::PushField {
+                alert_id,
+                field,
+                value,
+            },
+            "set_text" => AlertAction::SetText {
+                alert_id,
+                text: value,
+            },
+            "enqueue_event" => AlertAction::EnqueueEvent {
+                alert_id,
+                event_type: value,
+            },
+            "start_timers" => AlertAction::StartTimers {
+                alert_id,
+                seconds: value,
+            },
+            "obs_set_scene" => AlertAction::ObsSetScene { scene: value },
+            "obs_set_source_visible" => {
+                let (scene, source) = match field.as_str().split_once('/') {
+                    Some((scene, source)) => (scene.trim(), source.trim()),
+                    None => ("", field.as_str()),
+                };
+                if source.is_empty() {
+                    eyre::bail!("missing source");
+                }
+                if !matches!(value.trim(), "show" | "hide" | "toggle") && !value.contains('$') {
+                    eyre::bail!("visibility has to be show, hide or toggle");
+                }
+                AlertAction::ObsSetSourceVisible {
+                    scene: scene.to_owned(),
+                    source: source.to_owned(),
+                    visible: value.trim().to_owned(),
+                }
+            }
+            "obs_refresh_browser_source" => AlertAction::ObsRefreshBrowserSource { source: value },
+            _ => eyre::bail!("invalid action `{kind}`"),
+        })
+    }
+
+    /// The alert the action changes, `None` for OBS actions.
+    pub fn alert_id(&self) -> Option<&AlertId> {
+        match self {
+            AlertAction::IncrementField { alert_id, .. }
+            | AlertAction::SetField { alert_id, .. }
+            | AlertAction::PushField { alert_id, .. }
+            | AlertAction::SetText { alert_id, .. }
+            | AlertAction::EnqueueEvent { alert_id, .. }
+            | AlertAction::StartTimers { alert_id, .. } => Some(alert_id),
+            AlertAction::ObsSetScene { .. }
+            | AlertAction::ObsSetSourceVisible { .. }
+            | AlertAction::ObsRefreshBrowserSource { .. } => None,
+        }
+    }
+}
+
+impl std::fmt::Display for AlertAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AlertAction::IncrementField { field, amount, .. } => {
+                write!(f, "increment {field} by {amount}")
+            }
+            AlertAction::SetField { field, value, .. } => write!(f, "set {field} to {value}"),
+            AlertAction::PushField { field, value, .. } => write!(f, "push {value} to {field}"),
+            AlertAction::SetText { text, .. } => write!(f, "set text to {text}"),
+            AlertAction::EnqueueEvent { event_type, .. } => write!(f, "enqueue {event_type} event"),
+            AlertAction::StartTimers { seconds, .. } => write!(f, "start timers at {seconds}s"),
+            AlertAction::ObsSetScene { scene } => write!(f, "switch OBS to scene {scene}"),
+            AlertAction::ObsSetSourceVisible {
+                scene,
+                source,
+                visible,
+            } if scene.is_empty() => write!(f, "{visible} OBS source {source}"),
+            AlertAction::ObsSetSourceVisible {
+                scene,
+                source,
+                visible,
+            } => write!(f, "{visible} OBS source {source} in {scene}"),
+            AlertAction::ObsRefreshBrowserSource { source } => {
+                write!(f, "refresh OBS browser source {source}")
+            }
+        }
+    }
+}
+
+/// Replace `$name` with the variable `name`, unknown variables are left as is.
+pub fn substitute_vars(text: &str, vars: &[(String, String)]) -> String {
+    substitute_with(text, vars, str::to_owned, false)
+}
+
+/// Like [`substitute_vars`] for markdown, values are html escaped except when marked raw with
+/// `$!name`, the same as fields in alert text.
+///
+/// The result is alert text, so `$` in values is doubled to show up as is when rendered.
+pub fn substitute_vars_html(text: &str, vars: &[(String, String)]) -> String {
+    let vars: Vec<_> = vars
+        .iter()
+        .map(|(name, value)| (name.clone(), value.replace('$', "$$")))
+        .collect();
+    substitute_with(text, &vars, escape_html, false)
+}
+
+/// Replace `$name` with `escape(value)` and `$!name` with the value as is, in a single pass.
+///
+/// The longest name matching at a `$` wins, and of variables with the same name the first.
+/// Inserted values aren't scanned again, so they can't refer to other variables. With `dollars`
+/// `$$` is replaced with `$`.
+pub(crate) fn substitute_with(
+    text: &str,
+    vars: &[(String, String)],
+    escape: impl Fn(&str) -> String,
+    dollars: bool,
+) -> String {
+    let vars: Vec<_> = by_longest_name(vars)
+        .filter(|(name, _)| !name.is_empty())
+        .collect();
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(pos) = rest.find('$') {
+        out.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+        if let Some(after) = after.strip_prefix('$').filter(|_| dollars) {
+            out.push('$');
+            rest = after;
+            continue;
+        }
+        let (raw, name_start) = match after.strip_prefix('!') {
+            Some(name_start) => (true, name_start),
+            None => (false, after),
+        };
+        match vars
+            .iter()
+            .find(|(name, _)| name_start.starts_with(name.as_str()))
+        {
+            Some((name, value)) if raw => {
+                out.push_str(value);
+                rest = &name_start[name.len()..];
+            }
+            Some((name, value)) => {
+                out.push_str(&escape(value));
+                rest = &name_start[name.len()..];
+            }
+            None => {
+                out.push('$');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn by_longest_name(vars: &[(String, String)]) -> impl Iterator<Item = &(String, String)> {
+    let mut vars: Vec<_> = vars.iter().collect();
+    // longer names first, so `$user` doesn't match the start of `$user_name`
+    vars.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+    vars.into_iter()
+}
+
+#[cfg(feature = "ssr")]
+impl AlertManager {
+    #[tracing::instrument(skip(self, vars), err)]
+    pub async fn run_action(
+        &self,
+        action: &AlertAction,
+        vars: &[(String, String)],
+    ) -> Result<(), leptos::server_fn::ServerFnError> {
+        use leptos::server_fn::ServerFnError;
+
+        let err = |e: eyre::Report| {
+            ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+        };
+        match action {
+            AlertAction::IncrementField {
+                alert_id,
+                field,
+                amount,
+            } => {
+                let amount: i32 = substitute_vars(amount, vars).trim().parse().map_err(|e| {
+                    ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(format!(
+                        "invalid amount: {e}"
+                    ))
+                })?;
+                let field = field.clone();
+                self.try_edit_alert(alert_id, move |alert| match alert.entry_field_name(field) {
+                    Some((_, (_, field))) if field.can_incr() => {
+                        field.incr(amount);
+                        Ok(())
+                    }
+                    Some(_) => Err(eyre::eyre!("field is not a counter")),
+                    None => Err(eyre::eyre!("no such field")),
+                })
+                .await
+                .map_err(err)?
+            }
+            AlertAction::SetField {
+                alert_id,
+                field,
+                value,
+            } => {
+                let value = substitute_vars(value, vars);
+                let field = field.clone();
+                self.try_edit_alert(alert_id, move |alert| match alert.entry_field_name(field) {
+                    Some((_, (_, field))) => field.set(value),
+                    None => Err(eyre::eyre!("no such field")),
+                })
+                .await
+                .map_err(err)?
+            }
+            AlertAction::PushField {
+                alert_id,
+                field,
+                value,
+            } => {
+                let value = substitute_vars(value, vars);
+                let field = field.clone();
+                self.try_edit_alert(alert_id, move |alert| match alert.entry_field_name(field) {
+                    Some((_, (_, AlertField::Text(text)))) => {
+                        let mut lines: Vec<_> = text.lines().map(str::to_owned).collect();
+                        lines.push(value);
+                        let skip = lines.len().saturating_sub(PUSH_LIMIT);
+                        *text = lines[skip..].join("\n");
+                        Ok(())
+                    }
+                    Some(_) => Err(eyre::eyre!("field is not text")),
+                    None => Err(eyre::eyre!("no such field")),
+                })
+                .await
+                .map_err(err)?
+            }
+            AlertAction::SetText { alert_id, text } => {
+                // values come from events, webhooks and chat, they can't add html
+                let text = substitute_vars_html(text, vars);
+                self.edit_alert_html(alert_id, move |alert| {
+                    alert.last_text = text.into();
+                    Ok(())
+                })
+                .await
+            }
+            AlertAction::EnqueueEvent {
+                alert_id,
+                event_type,
+            } => {
+                let payload: std::collections::BTreeMap<_, _> = vars
+                    .iter()
+                    .map(|(k, v)| (k.clone(), serde_json::Value::String(v.clone())))
+                    .collect();
+                self.enqueue_new_event(
+                    alert_id,
+                    crate::events::NewAlertEvent {
+                        event_type: Some(event_type.clone()),
+                        template: None,
+                        payload,
+                        duration_ms: None,
+                    },
+                )
+                .await
+                .map(|_| ())
+            }
+            AlertAction::StartTimers { alert_id, seconds } => {
+                let seconds: u64 = substitute_vars(seconds, vars).trim().parse().map_err(|e| {
+                    ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(format!(
+                        "invalid seconds: {e}"
+                    ))
+                })?;
+                let ends_at = crate::util::now_ms().saturating_add(seconds.saturating_mul(1000));
+                self.edit_alert(alert_id, move |alert| {
+                    for layer in &mut alert.layers {
+                        if let crate::layers::LayerContent::Timer { ends_at_ms } =
+                            &mut layer.content
+                        {
+                            *ends_at_ms = ends_at;
+                        }
+                    }
+                })
+                .await
+            }
+            AlertAction::ObsSetScene { scene } => self
+                .obs
+                .set_scene(&substitute_vars(scene, vars))
+                .await
+                .map_err(err),
+            AlertAction::ObsSetSourceVisible {
+                scene,
+                source,
+                visible,
+            } => {
+                let visible = match substitute_vars(visible, vars).trim() {
+                    "show" => Some(true),
+                    "hide" => Some(false),
+                    "toggle" => None,
+                    visible => {
+                        return Err(ServerFnError::ServerError(format!(
+                            "invalid visibility `{visible}`"
+                        )))
+                    }
+                };
+                self.obs
+                    .set_source_visible(
+                        Some(substitute_vars(scene, vars)).filter(|s| !s.is_empty()),
+                        &substitute_vars(source, vars),
+                        visible,
+                    )
+                    .await
+                    .map_err(err)
+            }
+            AlertAction::ObsRefreshBrowserSource { source } => self
+                .obs
+                .refresh_browser_source(&substitute_vars(source, vars))
+                .await
+                .map_err(err),
+        }
+    }
+
+    /// Run actions in order, an action failing doesn't stop the rest.
+    pub async fn run_actions<'a>(
+        &self,
+        actions: impl IntoIterator<Item = &'a AlertAction>,
+        vars: &[(String, String)],
+    ) -> Vec<ActionOutcome> {
+        let mut outcomes = vec![];
+        for action in actions {
+            let result = self.run_action(action, vars).await;
+            outcomes.push(ActionOutcome {
+                action: action.to_string(),
+                error: result.err().map(|e| e.to_string()),
+            });
+        }
+        outcomes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> Vec<(String, String)> {
+        vec![
+            ("user".to_owned(), "<b>x</b>".to_owned()),
+            ("user_name".to_owned(), "y".to_owned()),
+        ]
+    }
+
+    #[test]
+    fn substitute_longest_name_first() {
+        assert_eq!(
+            substitute_vars("$user_name $user $other", &vars()),
+            "y <b>x</b> $other"
+        );
+    }
+
+    #[test]
+    fn substitute_html_escapes() {
+        assert_eq!(
+            substitute_vars_html("$user_name $user $!user", &vars()),
+            "y &lt;b&gt;x&lt;/b&gt; <b>x</b>"
+        );
+    }
+
+    #[test]
+    fn values_are_not_substituted() {
+        let vars = vec![
+            ("user".to_owned(), "$!html $$ $user".to_owned()),
+            ("html".to_owned(), "<b>x</b>".to_owned()),
+        ];
+        assert_eq!(
+            substitute_vars("$user $!user", &vars),
+            "$!html $$ $user $!html $$ $user"
+        );
+        assert_eq!(
+            substitute_vars_html("$user $!user $!html", &vars),
+            "$$!html $$$$ $$user $$!html $$$$ $$user <b>x</b>"
+        );
+    }
+
+    #[cfg(feature = "ssr")]
+    #[tokio::test]
+    async fn set_text_escapes_values() {
+        let (_dir, manager) = crate::alerts::test_manager(&[]).await;
+        let alert_id = crate::alerts::test_alert(&manager, "count").await;
+        let vars = vec![("user".to_owned(), "<script>x</script>".to_owned())];
+        let action = AlertAction::SetText {
+            alert_id: alert_id.clone(),
+            text: "$user $!user".to_owned(),
+        };
+        manager.run_action(&action, &vars).await.unwrap();
+
+        let alert = manager.get_alert(&alert_id).await.unwrap();
+        assert_eq!(
+            alert.last_text.as_str(),
+            "&lt;script&gt;x&lt;/script&gt; <script>x</script>"
+        );
+        // the raw value is sanitized, the alert isn't trusted
+        assert!(!alert.to_html(&alert.render()).contains("<script>"));
+
+        // values can't refer to fields once they're part of the alert text
+        let vars = vec![("user".to_owned(), "$!count $$".to_owned())];
+        manager.run_action(&action, &vars).await.unwrap();
+        let alert = manager.get_alert(&alert_id).await.unwrap();
+        assert_eq!(alert.render().as_str(), "$!count $$ $!count $$");
+    }
+
+    #[cfg(feature = "ssr")]
+    #[tokio::test]
+    async fn set_text_keeps_trusted_alerts() {
+        let (_dir, manager) = crate::alerts::test_manager(&[]).await;
+        let alert_id = crate::alerts::test_alert(&manager, "count").await;
+        manager
+            .edit_alert(&alert_id, |alert| alert.trusted_html = true)
+            .await
+            .unwrap();
+        let vars = vec![("user".to_owned(), "<script>x</script>".to_owned())];
+        let action = AlertAction::SetText {
+            alert_id: alert_id.clone(),
+            text: "$!user".to_owned(),
+        };
+        // actions aren't run by the admin
+        assert!(manager.run_action(&action, &vars).await.is_err());
+
+        let alert = manager.get_alert(&alert_id).await.unwrap();
+        assert_eq!(alert.last_text.as_str(), "$count");
+        let text = AlertText::from("$user $!user");
+        let html = alert.to_html(&alert.render_template(&text, &vars));
+        assert!(html.contains("&lt;script&gt;x&lt;/script&gt; <script>x</script>"));
+        let mut untrusted = alert.clone();
+        untrusted.trusted_html = false;
+        let html = untrusted.to_html(&untrusted.render_template(&text, &vars));
+        assert!(!html.contains("<script>"));
+    }
+}
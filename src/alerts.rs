@@ -34,9 +34,34 @@ use tokio::{
 #[derive(Clone)]
 #[cfg(feature = "ssr")]
 pub struct AlertManager {
-    alerts: Arc<RwLock<HashMap<AlertId, Alert>>>,
+    pub(crate) alerts: Arc<RwLock<HashMap<AlertId, Alert>>>,
     pub sender: broadcast::Sender<AlertMessage>,
     pub db_path: std::path::PathBuf,
+    pub(crate) events: crate::events::EventQueues,
+    pub media: crate::media::MediaLibrary,
+    pub tts: Option<crate::tts::Tts>,
+    pub themes: crate::themes::Themes,
+    pub templates: crate::templates::Templates,
+    pub scenes: crate::scenes::Scenes,
+    pub twitch: crate::twitch::EventSub,
+    pub webhooks: crate::webhooks::Webhooks,
+    pub outbound: crate::outbound::OutboundWebhooks,
+    pub automation: crate::automation::Automation,
+    pub schedules: crate::schedules::Schedules,
+    pub obs: crate::obs::Obs,
+    pub chat: crate::chat::Chat,
+    pub api_tokens: crate::api::ApiTokens,
+    /// Field changes made with [`AlertManager::try_edit_alert`]
+    pub changes: broadcast::Sender<AlertChange>,
+}
+
+/// Fields of an alert that changed in one edit.
+#[derive(Clone, Debug)]
+#[cfg(feature = "ssr")]
+pub struct AlertChange {
+    pub alert_id: AlertId,
+    pub alert_name: AlertName,
+    pub fields: Vec<FieldDelta>,
 }
 
 #[cfg(feature = "ssr")]
@@ -59,6 +84,26 @@ impl AlertManager {
         Ok(())
     }
 
+    /// Edit text, style or markdown of an alert from a server function.
+    ///
+    /// Alerts with [`Alert::trusted_html`] render as is, so only the admin can edit those.
+    pub async fn edit_alert_html(
+        &self,
+        alert_id: &AlertId,
+        f: impl (FnOnce(&mut Alert) -> Result<(), ServerFnError>) + 'static,
+    ) -> Result<(), ServerFnError> {
+        let admin = crate::auth::is_admin().await;
+        self.try_edit_alert(alert_id, move |alert| {
+            if alert.trusted_html && !admin {
+                return Err(ServerFnError::ServerError(
+                    "Only the admin can edit alerts with trusted html".to_owned(),
+                ));
+            }
+            f(alert)
+        })
+        .await?
+    }
+
     pub async fn try_edit_alert<E>(
         &self,
         alert_id: &AlertId,
@@ -70,10 +115,45 @@ impl AlertManager {
         };
         let old = alert.clone();
         f(alert)?;
-        if old.render() != alert.render() {
-            let _ = self
-                .sender
-                .send(AlertMessage::new_message(alert_id.clone(), alert.render()));
+        let html = alert.to_html(&alert.render());
+        let rerender = old.to_html(&old.render()) != html;
+        let deltas = alert.field_deltas(&old);
+        let fields_changed = !deltas.is_empty();
+        if fields_changed {
+            let _ = self.changes.send(AlertChange {
+                alert_id: alert_id.clone(),
+                alert_name: alert.name.clone(),
+                fields: deltas.clone(),
+            });
+            let _ = self.sender.send(AlertMessage::new_fields(
+                alert_id.clone(),
+                deltas,
+                rerender,
+                alert.transition.clone(),
+            ));
+        }
+        if rerender {
+            let _ = self.sender.send(AlertMessage::new_message(
+                alert_id.clone(),
+                html,
+                alert.transition.clone(),
+            ));
+            if !alert.change_media().is_empty() {
+                let media = self.media.resolve(alert.change_media()).await;
+                let _ = self.sender.send(AlertMessage::Media {
+                    alert_id: alert_id.clone(),
+                    media,
+                });
+            }
+        }
+        let layers = alert.render_layers();
+        if old.render_layers() != layers || old.canvas != alert.canvas {
+            let _ = self.sender.send(AlertMessage::Layers {
+                alert_id: alert_id.clone(),
+                canvas: alert.canvas,
+                layers,
+                transition: alert.transition.clone(),
+            });
         }
         if old.render_style() != alert.render_style() {
             let _ = self.sender.send(AlertMessage::new_style(
@@ -84,6 +164,14 @@ impl AlertManager {
         tracing::info!(count = self.sender.receiver_count(), "updated alert.");
 
         alert.save_alert(&self.db_path).await.expect("oops");
+        if fields_changed {
+            let (before, after) = (
+                crate::automation::rule_vars(&old),
+                crate::automation::rule_vars(alert),
+            );
+            drop(map_w);
+            self.run_automation(alert_id, &before, &after).await;
+        }
         Ok(Ok(()))
     }
 
@@ -98,6 +186,40 @@ impl AlertManager {
         Ok(alert.clone())
     }
 
+    /// Reload the overlays of an alert, and its OBS browser source if it has one.
+    pub async fn refresh_alert(
+        &self,
+        alert_id: &AlertId,
+    ) -> Result<(), leptos::server_fn::ServerFnError> {
+        let obs_source = self.get_alert(alert_id).await?.obs_source;
+        let sent = self.sender.send(AlertMessage::Update {
+            alert_id: alert_id.clone(),
+        });
+        // the browser source reloads even if its overlay lost the connection
+        if let Some(source) = obs_source {
+            return self
+                .obs
+                .refresh_browser_source(&source)
+                .await
+                .map_err(|e| ServerFnError::ServerError(format!("Couldn't refresh OBS: {e}")));
+        }
+        sent?;
+        Ok(())
+    }
+
+    /// The full state of an alert, for overlays that (re)connect.
+    pub(crate) async fn sync_message(&self, alert_id: &AlertId) -> Option<AlertMessage> {
+        let alerts = self.read_alerts().await;
+        let alert = alerts.get(alert_id)?;
+        Some(AlertMessage::Sync {
+            alert_id: alert_id.clone(),
+            text: alert.to_html(&alert.render()),
+            style: alert.render_style(),
+            canvas: alert.canvas,
+            layers: alert.render_layers(),
+        })
+    }
+
     pub async fn new_alert(&self, alert: Alert) -> Result<(), leptos::server_fn::ServerFnError> {
         {
             let mut map_w = self.alerts.write().await;
@@ -107,7 +229,8 @@ impl AlertManager {
 
         let _ = self.sender.send(AlertMessage::new_message(
             alert.alert_id.clone(),
-            alert.render(),
+            alert.to_html(&alert.render()),
+            alert.transition.clone(),
         ));
         tracing::info!(count = self.sender.receiver_count(), "updated alert.");
 
@@ -164,7 +287,10 @@ pub async fn read_all_alerts() -> Result<Vec<(AlertId, Alert)>, ServerFnError> {
 
 #[cfg(feature = "ssr")]
 pub async fn setup<S>(opts: &Opts) -> Result<(axum::Router<S>, AlertManager), eyre::Report> {
-    use axum::{routing::get, Router};
+    use axum::{
+        routing::{get, post},
+        Router,
+    };
 
     let (sender, _) = broadcast::channel(16);
     let map = Arc::new(RwLock::new(HashMap::<AlertId, Alert>::new()));
@@ -174,13 +300,50 @@ pub async fn setup<S>(opts: &Opts) -> Result<(axum::Router<S>, AlertManager), ey
         alerts: map.clone(),
         sender: sender.clone(),
         db_path: opts.db_path.clone(),
+        events: Default::default(),
+        media: crate::media::MediaLibrary::load(&opts.db_path).await?,
+        tts: crate::tts::Tts::from_opts(opts)?,
+        themes: crate::themes::Themes::load(&opts.db_path).await?,
+        templates: crate::templates::Templates::load(&opts.db_path).await?,
+        scenes: crate::scenes::Scenes::load(&opts.db_path).await?,
+        twitch: crate::twitch::EventSub::load(opts).await?,
+        webhooks: crate::webhooks::Webhooks::load(&opts.db_path).await?,
+        outbound: crate::outbound::OutboundWebhooks::load(&opts.db_path).await?,
+        automation: crate::automation::Automation::load(&opts.db_path).await?,
+        schedules: crate::schedules::Schedules::load(&opts.db_path).await?,
+        obs: crate::obs::Obs::load(opts).await?,
+        chat: crate::chat::Chat::load(opts).await?,
+        api_tokens: crate::api::ApiTokens::load(&opts.db_path).await?,
+        changes: broadcast::channel(256).0,
     };
+    manager.load_event_queues().await?;
+    tokio::spawn(manager.clone().deliver_outbound_webhooks());
+    tokio::spawn(manager.clone().run_schedules());
+    tokio::spawn(manager.clone().run_playlists());
+    if let Some(config) = crate::twitch::websocket::Config::from_opts(opts) {
+        tokio::spawn(crate::twitch::websocket::run(manager.clone(), config));
+    }
+    if let Some(config) = crate::obs::client::Config::from_opts(opts) {
+        tokio::spawn(crate::obs::client::run(manager.clone(), config));
+    }
+    if let Some(config) = crate::chat::irc::Config::from_opts(opts) {
+        tokio::spawn(crate::chat::irc::run(manager.clone(), config));
+    }
 
     let app = Router::new()
         .route("/ws/:id", get(handler))
+        .route("/runtime/:file", get(crate::overlay::serve_runtime))
         .route("/:id", get(serve_alert))
         .route("/:id/update/:field", get(update_alert_field))
         .route("/:id/get/:field", get(get_alert_field))
+        .route(
+            "/:id/events",
+            get(crate::events::read_event_queue_handler).post(crate::events::enqueue_event_handler),
+        )
+        .route(
+            "/:id/events/:control",
+            post(crate::events::control_event_queue_handler),
+        )
         .with_state(sender.clone())
         .with_state(map.clone());
 
@@ -193,9 +356,16 @@ pub async fn setup<S>(opts: &Opts) -> Result<(axum::Router<S>, AlertManager), ey
 struct AlertSite {
     alert_id: AlertId,
     alert_name: AlertName,
-    last_text: AlertMarkdown,
+    /// Rendered html
+    last_text: String,
     cache_bust: String,
     style: String,
+    /// json list of [`crate::media::MediaPlay`] for the overlay to preload
+    preload: String,
+    canvas: crate::layers::Canvas,
+    layers: String,
+    runtime_url: String,
+    runtime_version: String,
 }
 
 #[derive(Template)]
@@ -217,8 +387,11 @@ impl AlertSite {
     pub fn new(
         alert_id: AlertId,
         alert_name: AlertName,
-        last_text: AlertMarkdown,
+        last_text: String,
         style: String,
+        preload: Vec<crate::media::MediaPlay>,
+        canvas: crate::layers::Canvas,
+        layers: String,
     ) -> Self {
         Self {
             alert_id,
@@ -230,6 +403,11 @@ impl AlertSite {
                 .map(char::from)
                 .collect(),
             style,
+            preload: serde_json::to_string(&preload).unwrap_or_else(|_| "[]".to_owned()),
+            canvas,
+            layers,
+            runtime_url: crate::overlay::runtime_url(),
+            runtime_version: crate::overlay::RUNTIME_VERSION.clone(),
         }
     }
 }
@@ -251,12 +429,22 @@ async fn serve_alert(
             );
         }
     };
+    let attachments: Vec<_> = alert
+        .change_media()
+        .iter()
+        .chain(alert.variants.iter().flat_map(|v| v.media.iter()))
+        .cloned()
+        .collect();
+    let preload = manager.media.resolve(&attachments).await;
     axum::response::Html(
         AlertSite::new(
             alert_id,
             alert.name.clone(),
-            alert.render(),
+            alert.to_html(&alert.render()),
             alert.render_style(),
+            preload,
+            alert.canvas,
+            alert.render_layers_html(),
         )
         .render()
         .unwrap_or_default(),
@@ -353,7 +541,11 @@ pub(crate) async fn handler(
     tracing::debug!("handling ws connection");
     ws.on_upgrade(|f| async {
         let alert_id = alert_id;
-        if let Some(err) = handle_socket(f, broadcast, alert_id.clone(), manager)
+        let subscription = Subscription {
+            alert_ids: vec![alert_id.clone()],
+            scene: None,
+        };
+        if let Some(err) = handle_socket(f, broadcast, subscription, manager)
             .await
             .err()
         {
@@ -362,51 +554,115 @@ pub(crate) async fn handler(
     })
 }
 
+/// The alerts shown by an overlay connection.
 #[cfg(feature = "ssr")]
-async fn handle_socket(
+#[derive(Debug, Clone)]
+pub(crate) struct Subscription {
+    pub(crate) alert_ids: Vec<AlertId>,
+    /// Set for scenes, styles are scoped to the element of each alert.
+    pub(crate) scene: Option<crate::scenes::SceneId>,
+}
+
+#[cfg(feature = "ssr")]
+impl Subscription {
+    fn prepare(&self, mut msg: AlertMessage) -> AlertMessage {
+        if self.scene.is_some() {
+            if let AlertMessage::Style { alert_id, style }
+            | AlertMessage::Sync {
+                alert_id, style, ..
+            }
+            | AlertMessage::EventShow {
+                alert_id, style, ..
+            } = &mut msg
+            {
+                *style = crate::css::scope_parts(style, &crate::scenes::scope_selector(alert_id));
+            }
+        }
+        msg
+    }
+
+    async fn sync_messages(&self, manager: &AlertManager) -> Vec<ws::Message> {
+        let mut messages = vec![];
+        for alert_id in &self.alert_ids {
+            if let Some(sync) = manager.sync_message(alert_id).await {
+                messages.extend(self.prepare(sync).to_message());
+            }
+        }
+        messages
+    }
+}
+
+#[cfg(feature = "ssr")]
+pub(crate) async fn handle_socket(
     socket: WebSocket,
     broadcast: broadcast::Sender<AlertMessage>,
-    alert_id: AlertId,
+    subscription: Subscription,
     manager: AlertManager,
 ) -> Result<(), eyre::Report> {
     let (sender, receiver) = socket.split();
+    // replies to this client only
+    let (direct_tx, direct_rx) = tokio::sync::mpsc::channel(8);
 
     tokio::select!(
         r = tokio::spawn(write(
             sender,
             broadcast.subscribe(),
-            alert_id.clone()
+            direct_rx,
+            manager.clone(),
+            subscription.clone()
         )) => {
             r
         }
-        r = tokio::spawn(read(receiver, broadcast, manager, alert_id)) => {
+        r = tokio::spawn(read(receiver, direct_tx, manager, subscription)) => {
             r
         }
     )
     .wrap_err_with(|| "in stream join")
     .map(|_| ())
 }
-// Reads, basically only responds to pongs. Should not be a need for refreshes, but maybe.
+
+/// Reads messages from the overlay, answering `init` with the negotiated protocol and the state.
 #[cfg(feature = "ssr")]
 async fn read(
     mut receiver: SplitStream<WebSocket>,
-    _broadcast: broadcast::Sender<AlertMessage>,
+    direct: tokio::sync::mpsc::Sender<ws::Message>,
     manager: AlertManager,
-    alert_id: AlertId,
+    subscription: Subscription,
 ) -> Result<(), eyre::Report> {
+    use crate::overlay::{
+        OverlayMessage, CLOSE_UNSUPPORTED_PROTOCOL, PROTOCOL_VERSION, RUNTIME_VERSION,
+    };
+
     while let Some(msg) = receiver.next().await {
         let msg = msg?;
-        if matches!(msg, ws::Message::Text(..)) {
-            let map = manager.read_alerts().await;
-            if let Some(_alert) = map.get(&alert_id) {
-                // TODO: This blasts out to all clients, maybe should nerf it.
-                // broadcast
-                //     .send(AlertMessage::new_message(
-                //         alert_id.clone(),
-                //         alert.last_text.clone(),
-                //     ))
-                //     .wrap_err("could not send message")?;
+        if !matches!(msg, ws::Message::Text(..)) {
+            continue;
+        }
+        match AlertMessageRecv::from_ws_message(&msg) {
+            Ok(AlertMessageRecv::Init {
+                protocols, runtime, ..
+            }) => {
+                // the inline script before the runtime didn't send any
+                if !protocols.is_empty() && !protocols.contains(&PROTOCOL_VERSION) {
+                    tracing::info!(?protocols, ?runtime, "unsupported overlay protocol");
+                    direct
+                        .send(ws::Message::Close(Some(ws::CloseFrame {
+                            code: CLOSE_UNSUPPORTED_PROTOCOL,
+                            reason: "unsupported protocol".into(),
+                        })))
+                        .await?;
+                    return Ok(());
+                }
+                let hello = OverlayMessage::Hello {
+                    protocol: PROTOCOL_VERSION,
+                    runtime: RUNTIME_VERSION.clone(),
+                };
+                direct.send(hello.to_message()?).await?;
+                for sync in subscription.sync_messages(&manager).await {
+                    direct.send(sync).await?;
+                }
             }
+            Err(error) => tracing::debug!(%error, "ignoring overlay message"),
         }
     }
     Ok(())
@@ -417,19 +673,48 @@ async fn read(
 async fn write(
     mut sender: SplitSink<WebSocket, ws::Message>,
     mut broadcast: broadcast::Receiver<AlertMessage>,
-    alert_id: AlertId,
+    mut direct: tokio::sync::mpsc::Receiver<ws::Message>,
+    manager: AlertManager,
+    subscription: Subscription,
 ) -> Result<(), eyre::Report> {
     use std::error::Error as _;
 
     use futures::SinkExt as _;
 
+    let mut scene_changes = manager.scenes.subscribe();
     loop {
-        let msg = broadcast.recv().await?;
-        // Check if alert id matches
-        if msg.alert_id() != alert_id {
-            continue;
-        }
-        if let Ok(msg) = msg.to_message() {
+        let messages = tokio::select! {
+            msg = direct.recv() => match msg {
+                Some(msg) => vec![msg],
+                None => return Ok(()),
+            },
+            msg = broadcast.recv() => match msg {
+                Ok(msg) => {
+                    // Check if alert id matches
+                    if !subscription.alert_ids.iter().any(|id| msg.alert_id() == *id) {
+                        continue;
+                    }
+                    let Ok(msg) = subscription.prepare(msg).to_message() else {
+                        continue;
+                    };
+                    vec![msg]
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    // the overlay missed messages, send it the whole state instead
+                    tracing::debug!(skipped, "client lagged, resyncing");
+                    subscription.sync_messages(&manager).await
+                }
+                Err(error) => return Err(error.into()),
+            },
+            changed = scene_changes.recv() => match changed {
+                Ok(scene_id) if subscription.scene.as_ref() == Some(&scene_id) => {
+                    vec![crate::overlay::OverlayMessage::Reload.to_message()?]
+                }
+                _ => continue,
+            },
+        };
+        for msg in messages {
+            let close = matches!(msg, ws::Message::Close(..));
             tracing::debug!("sending message to client");
             if let Err(error) = sender.send(msg).await {
                 if let Some(e) = error.source() {
@@ -442,6 +727,9 @@ async fn write(
                     }
                 }
             };
+            if close {
+                return Ok(());
+            }
         }
     }
 }
@@ -449,10 +737,20 @@ async fn write(
 #[derive(Clone, serde::Serialize, Debug)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum AlertMessage {
+    /// Everything shown by the overlay, sent after connecting or when messages were missed.
+    Sync {
+        alert_id: AlertId,
+        /// Rendered html
+        text: String,
+        style: String,
+        canvas: crate::layers::Canvas,
+        layers: Vec<crate::layers::RenderedLayer>,
+    },
     MessageMarkdown {
         alert_id: AlertId,
-        #[serde(serialize_with = "alert_ser")]
-        text: AlertMarkdown,
+        /// Rendered html
+        text: String,
+        transition: Transition,
     },
     Update {
         alert_id: AlertId,
@@ -461,17 +759,84 @@ pub enum AlertMessage {
         alert_id: AlertId,
         style: String,
     },
+    /// Fields that changed in an edit, sent before the full render.
+    Fields {
+        alert_id: AlertId,
+        fields: Vec<FieldDelta>,
+        /// A [`AlertMessage::MessageMarkdown`] follows this message.
+        rerender: bool,
+        transition: Transition,
+    },
+    EventShow {
+        alert_id: AlertId,
+        event_id: crate::events::AlertEventId,
+        /// Rendered html
+        text: String,
+        style: String,
+        media: Vec<crate::media::MediaPlay>,
+        duration_ms: u64,
+        transition: Transition,
+    },
+    EventHide {
+        alert_id: AlertId,
+        event_id: crate::events::AlertEventId,
+    },
+    /// Media to play now, sent when the rendered text changes.
+    Media {
+        alert_id: AlertId,
+        media: Vec<crate::media::MediaPlay>,
+    },
+    Layers {
+        alert_id: AlertId,
+        canvas: crate::layers::Canvas,
+        layers: Vec<crate::layers::RenderedLayer>,
+        transition: Transition,
+    },
 }
 
-fn alert_ser<S: serde::Serializer>(alert: &AlertMarkdown, ser: S) -> Result<S::Ok, S::Error> {
-    use serde::Serialize;
-    alert.to_markdown().serialize(ser)
+#[derive(Clone, serde::Serialize, Debug, PartialEq)]
+pub struct FieldDelta {
+    pub id: AlertFieldId,
+    pub name: AlertFieldName,
+    /// `None` if the field was removed.
+    #[serde(serialize_with = "opt_field_value_ser")]
+    pub value: Option<AlertField>,
+    /// `None` if the field was just added.
+    #[serde(serialize_with = "opt_field_value_ser")]
+    pub previous: Option<AlertField>,
+}
+
+/// Serializes a field as a plain json value, so overlays don't need to know about the enum.
+fn field_value_ser<S: serde::Serializer>(field: &AlertField, ser: S) -> Result<S::Ok, S::Error> {
+    match field {
+        AlertField::Text(text) => ser.serialize_str(text),
+        AlertField::Counter(counter) => ser.serialize_i32(*counter),
+    }
+}
+
+fn opt_field_value_ser<S: serde::Serializer>(
+    field: &Option<AlertField>,
+    ser: S,
+) -> Result<S::Ok, S::Error> {
+    match field {
+        Some(field) => field_value_ser(field, ser),
+        None => ser.serialize_none(),
+    }
 }
 
 #[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
-#[serde(tag = "type")]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum AlertMessageRecv {
-    Init { alert_id: AlertId },
+    Init {
+        /// Not sent for scenes.
+        #[serde(default)]
+        alert_id: Option<AlertId>,
+        /// Protocol versions the overlay supports.
+        #[serde(default)]
+        protocols: Vec<u32>,
+        #[serde(default)]
+        runtime: Option<String>,
+    },
 }
 
 impl AlertMessageRecv {
@@ -498,6 +863,34 @@ pub struct Alert {
     #[serde(default, deserialize_with = "deserialize_fields")]
     #[store(key: AlertFieldId = |(id, _)| id.clone())]
     pub fields: Vec<(AlertFieldId, (AlertFieldName, AlertField))>,
+    /// Named event templates, see [`Alert::select_variant`].
+    #[serde(default)]
+    pub variants: Vec<crate::events::AlertVariant>,
+    /// Played when the rendered text changes.
+    #[serde(default)]
+    pub media: Vec<crate::media::MediaAttachment>,
+    #[serde(default)]
+    pub tts: crate::tts::TtsSettings,
+    /// How the overlay shows changes to the text.
+    #[serde(default)]
+    pub transition: Transition,
+    #[serde(default)]
+    pub theme: Option<crate::themes::Theme>,
+    /// Scope the style to the overlay, see [`crate::css::scope`]
+    #[serde(default)]
+    pub scope_style: bool,
+    /// Skip sanitizing the rendered html, only admins may set this.
+    #[serde(default)]
+    pub trusted_html: bool,
+    #[serde(default)]
+    pub canvas: crate::layers::Canvas,
+    #[serde(default)]
+    pub layers: Vec<crate::layers::Layer>,
+    #[serde(default)]
+    pub playlist: crate::playlists::Playlist,
+    /// Name of the OBS browser source showing the alert.
+    #[serde(default)]
+    pub obs_source: Option<String>,
 }
 
 #[allow(clippy::type_complexity)]
@@ -533,6 +926,104 @@ pub enum AlertField {
     Counter(i32),
 }
 
+#[derive(Clone, serde::Deserialize, serde::Serialize, Debug, PartialEq)]
+#[serde(default)]
+pub struct Transition {
+    pub kind: TransitionKind,
+    pub duration_ms: u32,
+    /// A css easing function, e.g. `ease-out`
+    pub easing: String,
+}
+
+impl Default for Transition {
+    fn default() -> Self {
+        Self {
+            kind: TransitionKind::None,
+            duration_ms: 300,
+            easing: "ease-out".to_owned(),
+        }
+    }
+}
+
+impl Transition {
+    /// Check `easing` against the css easing grammar, a keyword, `cubic-bezier(x1, y1, x2, y2)` or
+    /// `steps(n[, position])`.
+    pub fn is_valid_easing(easing: &str) -> bool {
+        const KEYWORDS: [&str; 7] = [
+            "linear",
+            "ease",
+            "ease-in",
+            "ease-out",
+            "ease-in-out",
+            "step-start",
+            "step-end",
+        ];
+        let easing = easing.trim();
+        if KEYWORDS.contains(&easing) {
+            return true;
+        }
+        let Some((function, args)) = easing
+            .strip_suffix(')')
+            .and_then(|easing| easing.split_once('('))
+        else {
+            return false;
+        };
+        let args: Vec<&str> = args.split(',').map(str::trim).collect();
+        match (function.trim(), args.as_slice()) {
+            ("cubic-bezier", [x1, y1, x2, y2]) => {
+                let number = |arg: &str| arg.parse::<f64>().ok().filter(|n| n.is_finite());
+                let in_range = |arg: &str| number(arg).is_some_and(|x| (0.0..=1.0).contains(&x));
+                in_range(x1) && number(y1).is_some() && in_range(x2) && number(y2).is_some()
+            }
+            ("steps", [steps, rest @ ..]) if rest.len() <= 1 => {
+                let Ok(steps) = steps.parse::<u32>() else {
+                    return false;
+                };
+                match rest.first() {
+                    None => steps >= 1,
+                    Some(&"jump-none") => steps >= 2,
+                    Some(position) => {
+                        steps >= 1
+                            && ["jump-start", "jump-end", "jump-both", "start", "end"]
+                                .contains(position)
+                    }
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, serde::Deserialize, serde::Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TransitionKind {
+    None,
+    Fade,
+    Slide,
+    Typewriter,
+    ScalePop,
+}
+
+impl TransitionKind {
+    pub const ALL: [TransitionKind; 5] = [
+        TransitionKind::None,
+        TransitionKind::Fade,
+        TransitionKind::Slide,
+        TransitionKind::Typewriter,
+        TransitionKind::ScalePop,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransitionKind::None => "none",
+            TransitionKind::Fade => "fade",
+            TransitionKind::Slide => "slide",
+            TransitionKind::Typewriter => "typewriter",
+            TransitionKind::ScalePop => "scale_pop",
+        }
+    }
+}
+
 impl Default for AlertField {
     fn default() -> Self {
         Self::Text(String::new())
@@ -559,12 +1050,12 @@ impl AlertField {
         }
     }
 
-    /// increment value, noop if not supported
+    /// increment value, saturating at the bounds of a counter, noop if not supported
     pub fn incr(&mut self, incr: i32) {
         match self {
             AlertField::Text(_) => {}
             AlertField::Counter(counter) => {
-                *counter += incr;
+                *counter = counter.saturating_add(incr);
             }
         }
     }
@@ -595,6 +1086,37 @@ impl Alert {
             .find(|(_, (name, _))| name == field_name)
             .map(|(_, (_, field))| field)
     }
+
+    /// Fields that were added, renamed or changed value compared to `old`.
+    pub fn field_deltas(&self, old: &Alert) -> Vec<FieldDelta> {
+        let changed = self.fields.iter().filter_map(|(id, (name, value))| {
+            let previous = old
+                .fields
+                .iter()
+                .find(|(old_id, _)| old_id == id)
+                .map(|(_, old)| old);
+            match previous {
+                Some((old_name, old_value)) if old_name == name && old_value == value => None,
+                _ => Some(FieldDelta {
+                    id: id.clone(),
+                    name: name.clone(),
+                    value: Some(value.clone()),
+                    previous: previous.map(|(_, old_value)| old_value.clone()),
+                }),
+            }
+        });
+        let removed = old
+            .fields
+            .iter()
+            .filter(|(old_id, _)| !self.fields.iter().any(|(id, _)| id == old_id))
+            .map(|(id, (name, value))| FieldDelta {
+                id: id.clone(),
+                name: name.clone(),
+                value: None,
+                previous: Some(value.clone()),
+            });
+        changed.chain(removed).collect()
+    }
 }
 
 impl Alert {
@@ -628,28 +1150,159 @@ impl Alert {
             last_style: String::new(),
             name,
             fields: Vec::new(),
+            variants: Vec::new(),
+            media: Vec::new(),
+            tts: Default::default(),
+            transition: Default::default(),
+            theme: None,
+            scope_style: false,
+            trusted_html: false,
+            canvas: Default::default(),
+            layers: Vec::new(),
+            playlist: Default::default(),
+            obs_source: None,
         }
     }
 
+    /// Render the alert text, or the current item of a running playlist.
     pub fn render(&self) -> AlertMarkdown {
         tracing::info!("and i op");
-        let mut text = self.last_text.to_string();
-        for (name, value) in self.fields.iter().map(|(_, (n, v))| (n, v)) {
-            text = text.replace(&format!("${name}"), &value.to_string());
-        }
-        text = text.replace("$$", "$");
+        let text = match self.playlist.current() {
+            Some(text) => AlertTextRef::from_str(text),
+            None => &self.last_text,
+        };
+        self.render_template(text, &[])
+    }
 
-        AlertMarkdown::from(text)
+    /// Render a template with this alerts fields, `vars` take precedence over fields.
+    pub fn render_template(
+        &self,
+        template: &AlertTextRef,
+        vars: &[(String, String)],
+    ) -> AlertMarkdown {
+        AlertMarkdown::from(self.substitute(template.as_str(), vars, true))
     }
+
     pub fn render_style(&self) -> String {
         tracing::info!("and i op style");
-        let mut text = self.last_style.to_string();
-        for (name, value) in self.fields.iter().map(|(_, (n, v))| (n, v)) {
-            text = text.replace(&format!("${name}"), &value.to_string());
+        let style = match &self.theme {
+            // the alert's own style comes last to override the theme
+            Some(theme) => format!("{}\n{}", theme.style, self.last_style),
+            None => self.last_style.clone(),
+        };
+        let style = self.render_style_template(&style, &[]);
+        if self.layers.is_empty() {
+            style
+        } else {
+            format!("{style}\n{}", self.layers_style())
         }
-        text = text.replace("$$", "$");
+    }
 
-        text
+    pub fn render_style_template(&self, style: &str, vars: &[(String, String)]) -> String {
+        let style = crate::css::expand_assets(&self.substitute(style, vars, false));
+        let style = if self.scope_style {
+            crate::css::scope(&style, crate::css::ALERT_ROOT)
+        } else {
+            style
+        };
+        // field values aren't validated, make sure they can't end the style element
+        crate::css::neutralize(&style)
+    }
+
+    /// Replace `$name` with the value of `vars` and fields, and `$$` with `$`
+    ///
+    /// With `escape` values are html escaped, except when marked raw with `$!name`.
+    pub(crate) fn substitute(&self, text: &str, vars: &[(String, String)], escape: bool) -> String {
+        // variables come first, so they win over fields with the same name
+        let vars: Vec<_> = vars
+            .iter()
+            .cloned()
+            .chain(
+                self.all_fields()
+                    .map(|(name, value)| (name.to_string(), value.to_string())),
+            )
+            .collect();
+        if escape {
+            crate::actions::substitute_with(text, &vars, escape_html, true)
+        } else {
+            crate::actions::substitute_with(text, &vars, str::to_owned, true)
+        }
+    }
+
+    /// Fields of the alert, and defaults from the theme for fields the alert doesn't have.
+    pub fn all_fields(&self) -> impl Iterator<Item = (&AlertFieldName, &AlertField)> {
+        let own = self.fields.iter().map(|(_, (n, v))| (n, v));
+        let defaults = self
+            .theme
+            .iter()
+            .flat_map(|t| t.fields.iter())
+            .filter(|(name, _)| !self.fields.iter().any(|(_, (n, _))| n == name))
+            .map(|(n, v)| (n, v));
+        own.chain(defaults)
+    }
+
+    /// Media played on change, from the theme if the alert has none.
+    pub fn change_media(&self) -> &[crate::media::MediaAttachment] {
+        match &self.theme {
+            Some(theme) if self.media.is_empty() => &theme.media,
+            _ => &self.media,
+        }
+    }
+
+    /// Render markdown to html, sanitized unless the alert has [`Alert::trusted_html`].
+    pub fn to_html(&self, markdown: &AlertMarkdownRef) -> String {
+        if self.trusted_html {
+            markdown.to_markdown_unsanitized()
+        } else {
+            markdown.to_markdown()
+        }
+    }
+
+    /// Problems in a template, e.g. a `$name` that doesn't match any field.
+    pub fn template_errors(&self, template: &str) -> Vec<String> {
+        let mut errors = vec![];
+        let mut rest = template;
+        while let Some(pos) = rest.find('$') {
+            rest = &rest[pos + 1..];
+            if let Some(after) = rest.strip_prefix('$') {
+                rest = after;
+                continue;
+            }
+            rest = rest.strip_prefix('!').unwrap_or(rest);
+            if self
+                .all_fields()
+                .any(|(name, _)| rest.starts_with(name.as_str()))
+            {
+                continue;
+            }
+            let name: String = rest
+                .chars()
+                .take_while(|c| c.is_alphanumeric() || *c == '_')
+                .collect();
+            if !name.is_empty() {
+                errors.push(format!("unknown field `${name}`"));
+            }
+        }
+        errors.dedup();
+        errors
+    }
+
+    /// The most specific variant for `event_type` whose conditions all hold.
+    pub fn select_variant(
+        &self,
+        event_type: &str,
+        vars: &[(String, String)],
+    ) -> Option<&crate::events::AlertVariant> {
+        self.variants
+            .iter()
+            .filter(|v| v.event_type == event_type && v.matches(vars))
+            .fold(
+                None,
+                |best: Option<&crate::events::AlertVariant>, v| match best {
+                    Some(best) if best.conditions.len() >= v.conditions.len() => Some(best),
+                    _ => Some(v),
+                },
+            )
     }
 
     #[cfg(feature = "ssr")]
@@ -856,8 +1509,21 @@ impl AlertFieldId {
     }
 }
 
+static SANITIZER: once_cell::sync::Lazy<ammonia::Builder<'static>> =
+    once_cell::sync::Lazy::new(|| {
+        let mut builder = ammonia::Builder::default();
+        builder.add_generic_attributes(["class", "data-alert-field"]);
+        builder
+    });
+
 impl AlertMarkdownRef {
+    /// Render to html, with tags and attributes not on the allowlist removed.
     pub fn to_markdown(&self) -> String {
+        SANITIZER.clean(&self.to_markdown_unsanitized()).to_string()
+    }
+
+    /// Render to html as is, only for alerts with [`Alert::trusted_html`].
+    pub fn to_markdown_unsanitized(&self) -> String {
         let mut options = comrak::ComrakOptions::default();
         options.extension.table = true;
         options.render.unsafe_ = true;
@@ -865,24 +1531,299 @@ impl AlertMarkdownRef {
     }
 }
 
+/// Escape text for use in html.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
 impl AlertMessage {
     pub fn alert_id(&self) -> &AlertIdRef {
         match self {
+            AlertMessage::Sync { alert_id, .. } => alert_id,
             AlertMessage::Update { alert_id } => alert_id,
             AlertMessage::MessageMarkdown { alert_id, .. } => alert_id,
             AlertMessage::Style { alert_id, .. } => alert_id,
+            AlertMessage::Fields { alert_id, .. } => alert_id,
+            AlertMessage::EventShow { alert_id, .. } => alert_id,
+            AlertMessage::EventHide { alert_id, .. } => alert_id,
+            AlertMessage::Media { alert_id, .. } => alert_id,
+            AlertMessage::Layers { alert_id, .. } => alert_id,
         }
     }
 
-    pub fn new_message(alert_id: AlertId, text: AlertMarkdown) -> Self {
-        Self::MessageMarkdown { alert_id, text }
+    pub fn new_message(alert_id: AlertId, text: String, transition: Transition) -> Self {
+        Self::MessageMarkdown {
+            alert_id,
+            text,
+            transition,
+        }
     }
     pub fn new_style(alert_id: AlertId, style: String) -> Self {
         Self::Style { alert_id, style }
     }
+    pub fn new_fields(
+        alert_id: AlertId,
+        fields: Vec<FieldDelta>,
+        rerender: bool,
+        transition: Transition,
+    ) -> Self {
+        Self::Fields {
+            alert_id,
+            fields,
+            rerender,
+            transition,
+        }
+    }
 
     #[cfg(feature = "ssr")]
     pub(crate) fn to_message(&self) -> Result<ws::Message, eyre::Report> {
         Ok(ws::Message::Text(serde_json::to_string(self)?))
     }
 }
+
+/// A manager with its database in a temporary directory, `args` are added to the options.
+#[cfg(all(test, feature = "ssr"))]
+pub(crate) async fn test_manager(args: &[&str]) -> (tempfile::TempDir, AlertManager) {
+    let dir = tempfile::tempdir().unwrap();
+    let (_, manager) = setup::<()>(&test_opts(dir.path(), args)).await.unwrap();
+    (dir, manager)
+}
+
+#[cfg(all(test, feature = "ssr"))]
+pub(crate) fn test_opts(db_path: &Path, args: &[&str]) -> Opts {
+    use clap::Parser;
+
+    let db_path = db_path.to_str().unwrap();
+    Opts::parse_from(
+        [
+            "stream_alerts",
+            "--db-path",
+            db_path,
+            "--admin-password",
+            "test",
+        ]
+        .into_iter()
+        .chain(args.iter().copied()),
+    )
+}
+
+/// Add an alert with a counter field named `field`.
+#[cfg(all(test, feature = "ssr"))]
+pub(crate) async fn test_alert(manager: &AlertManager, field: &str) -> AlertId {
+    let mut alert = Alert::new(
+        AlertId::new_id(),
+        AlertText::from(format!("${field}")),
+        AlertName::from("test"),
+    );
+    alert.fields.push((
+        AlertFieldId::new_id(),
+        (AlertFieldName::from(field), AlertField::Counter(0)),
+    ));
+    manager.new_alert(alert.clone()).await.unwrap();
+    alert.alert_id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_easings() {
+        for easing in [
+            "linear",
+            "ease-in-out",
+            " step-end ",
+            "cubic-bezier(0.1, -0.6, 0.2, 1.5)",
+            "cubic-bezier(0,0,1,1)",
+            "steps(4)",
+            "steps(4, jump-end)",
+            "steps(2, jump-none)",
+            "steps(1, start)",
+        ] {
+            assert!(Transition::is_valid_easing(easing), "{easing}");
+        }
+    }
+
+    #[test]
+    fn invalid_easings() {
+        for easing in [
+            "",
+            "bounce",
+            "ease-out, x",
+            "cubic-bezier(0, 0, 1)",
+            "cubic-bezier(1.5, 0, 1, 1)",
+            "cubic-bezier(0, NaN, 1, 1)",
+            "cubic-bezier(a, b, c, d)",
+            "steps(0)",
+            "steps(-1)",
+            "steps(1, jump-none)",
+            "steps(3, middle)",
+            "steps(3, end, end)",
+            "linear(0, 1)",
+            "url(x)",
+        ] {
+            assert!(!Transition::is_valid_easing(easing), "{easing}");
+        }
+    }
+
+    fn alert() -> Alert {
+        let mut alert = Alert::new(
+            AlertId::from("alert"),
+            AlertText::from(""),
+            AlertName::from("test"),
+        );
+        alert.fields.push((
+            AlertFieldId::from("user"),
+            (
+                AlertFieldName::from("user"),
+                AlertField::Text("field".to_owned()),
+            ),
+        ));
+        alert
+    }
+
+    #[test]
+    fn substitute_longest_name_first() {
+        let alert = alert();
+        let vars = [
+            ("user".to_owned(), "<b>var</b>".to_owned()),
+            ("user_name".to_owned(), "name".to_owned()),
+            ("user.name".to_owned(), "dotted".to_owned()),
+        ];
+        assert_eq!(
+            alert.substitute("$user_name $user.name $user $!user", &vars, true),
+            "name dotted &lt;b&gt;var&lt;/b&gt; <b>var</b>"
+        );
+        // fields are used when there's no variable with the name
+        assert_eq!(
+            alert.substitute("$user_name $user", &vars[1..], false),
+            "name field"
+        );
+        // values aren't substituted again
+        let vars = [("name".to_owned(), "$!user $$ $name".to_owned())];
+        assert_eq!(
+            alert.substitute("$name $$name $!name", &vars, true),
+            "$!user $$ $name $name $!user $$ $name"
+        );
+    }
+
+    fn variant(name: &str, event_type: &str, conditions: &str) -> crate::events::AlertVariant {
+        crate::events::AlertVariant {
+            name: name.to_owned(),
+            event_type: event_type.to_owned(),
+            conditions: crate::events::parse_conditions(conditions).unwrap(),
+            text: AlertText::from(name),
+            style: String::new(),
+            media: vec![],
+            duration_ms: 1000,
+        }
+    }
+
+    #[test]
+    fn select_variant() {
+        let mut alert = alert();
+        alert.variants = vec![
+            variant("follow", "follow", ""),
+            variant("cheer", "cheer", ""),
+            variant("big cheer", "cheer", "amount >= 100"),
+            variant("huge cheer", "cheer", "amount >= 100, amount >= 1000"),
+        ];
+        let select = |event_type, amount: &str| {
+            let vars = [("amount".to_owned(), amount.to_owned())];
+            alert
+                .select_variant(event_type, &vars)
+                .map(|v| v.name.clone())
+        };
+        assert_eq!(select("follow", "").as_deref(), Some("follow"));
+        assert_eq!(select("cheer", "10").as_deref(), Some("cheer"));
+        // the variant with most conditions wins
+        assert_eq!(select("cheer", "100").as_deref(), Some("big cheer"));
+        assert_eq!(select("cheer", "5000").as_deref(), Some("huge cheer"));
+        assert_eq!(select("raid", "5000"), None);
+    }
+
+    fn set(alert: &mut Alert, id: &str, name: &str, value: AlertField) {
+        match alert.fields.iter_mut().find(|(i, _)| i.as_str() == id) {
+            Some((_, field)) => *field = (AlertFieldName::from(name), value),
+            None => alert
+                .fields
+                .push((AlertFieldId::from(id), (AlertFieldName::from(name), value))),
+        }
+    }
+
+    #[test]
+    fn field_deltas() {
+        let mut old = alert();
+        set(&mut old, "subs", "subs", AlertField::Counter(1));
+        set(&mut old, "gone", "gone", AlertField::Counter(2));
+        let mut new = old.clone();
+        assert_eq!(new.field_deltas(&old), []);
+
+        set(&mut new, "subs", "subs", AlertField::Counter(2));
+        new.fields.retain(|(id, _)| id.as_str() != "gone");
+        set(&mut new, "goal", "goal", AlertField::Counter(10));
+        assert_eq!(
+            new.field_deltas(&old),
+            [
+                FieldDelta {
+                    id: AlertFieldId::from("subs"),
+                    name: AlertFieldName::from("subs"),
+                    value: Some(AlertField::Counter(2)),
+                    previous: Some(AlertField::Counter(1)),
+                },
+                FieldDelta {
+                    id: AlertFieldId::from("goal"),
+                    name: AlertFieldName::from("goal"),
+                    value: Some(AlertField::Counter(10)),
+                    previous: None,
+                },
+                FieldDelta {
+                    id: AlertFieldId::from("gone"),
+                    name: AlertFieldName::from("gone"),
+                    value: None,
+                    previous: Some(AlertField::Counter(2)),
+                },
+            ]
+        );
+
+        // a rename is a change, even with the same value
+        let mut renamed = old.clone();
+        set(&mut renamed, "subs", "followers", AlertField::Counter(1));
+        let deltas = renamed.field_deltas(&old);
+        assert_eq!(deltas.len(), 1);
+        assert_eq!(deltas[0].name.as_str(), "followers");
+    }
+
+    #[test]
+    fn field_delta_values_are_escaped() {
+        let old = alert();
+        let mut new = old.clone();
+        let value = "<img src=x onerror=alert(1)> \"quoted\"";
+        set(&mut new, "user", "user", AlertField::Text(value.to_owned()));
+        new.last_text = AlertText::from("<span data-alert-field=\"user\">$user</span>");
+
+        // the overlay sets the value as text, it's sent as is in a json string
+        let deltas = serde_json::to_value(new.field_deltas(&old)).unwrap();
+        assert_eq!(deltas[0]["value"], value);
+        assert_eq!(deltas[0]["previous"], "field");
+        // and escaped in the rendered element it updates
+        let html = new.to_html(&new.render());
+        assert!(html.contains("data-alert-field=\"user\""), "{html}");
+        assert!(
+            html.contains("&lt;img src=x onerror=alert(1)&gt;"),
+            "{html}"
+        );
+        assert!(!html.contains("<img"), "{html}");
+    }
+}
@@ -0,0 +1,594 @@
+//! A JSON API for controllers, e.g. a Stream Deck with the generic HTTP module of Bitfocus
+//! Companion, under `/api/v1`.
+//!
+//! Requests need an API token in the `Authorization: Bearer <token>` header. Tokens are created
+//! in the ui, only a hash of them is stored in `db_path/api/tokens.json`.
+//!
+//! `POST /api/v1/alerts/:id/actions/:action` runs a named action with an optional JSON body and
+//! answers with the new value of the field:
+//!
+//! - `increment` `{"field": "deaths", "amount": -1}`, the amount defaults to 1
+//! - `set` `{"field": "deaths", "value": "0"}`
+//! - `toggle` `{"field": "live"}` switches a counter between 0 and 1, a text field switches
+//!   between two values, e.g. `{"field": "mode", "values": ["day", "night"]}`
+//! - `trigger` enqueues an event like `POST /alert/:id/events`, e.g. `{"type": "follow"}`
+//! - `refresh` reloads the overlays of the alert
+//!
+//! `GET /api/v1/alerts/:id/state` returns the current values of an alert for button feedback,
+//! `GET /api/v1/state` those of every alert.
+
+use leptos::{prelude::*, server};
+
+#[cfg(feature = "ssr")]
+use crate::alerts::*;
+#[cfg(feature = "ssr")]
+use axum::{
+    body::Bytes,
+    extract,
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    Extension, Json,
+};
+#[cfg(feature = "ssr")]
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+#[cfg(feature = "ssr")]
+use tokio::sync::RwLock;
+
+#[aliri_braid::braid(serde)]
+pub struct ApiTokenId;
+
+impl ApiTokenId {
+    pub fn new_id() -> Self {
+        Self(nanoid::nanoid!(8))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct ApiToken {
+    pub token_id: ApiTokenId,
+    pub name: String,
+    /// RFC 3339 timestamp
+    pub created_at: String,
+    /// sha256 of the token, hex encoded
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct ApiTokenOverview {
+    pub token: ApiToken,
+    /// Last request with the token since the server started.
+    pub last_used: Option<String>,
+}
+
+#[cfg(feature = "ssr")]
+fn token_hash(token: &str) -> String {
+    use sha2::Digest;
+
+    hex::encode(sha2::Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Clone)]
+pub struct ApiTokens {
+    path: PathBuf,
+    tokens: Arc<RwLock<Vec<ApiToken>>>,
+    last_used: Arc<RwLock<HashMap<ApiTokenId, String>>>,
+}
+
+#[cfg(feature = "ssr")]
+impl ApiTokens {
+    pub async fn load(db_path: &std::path::Path) -> Result<Self, eyre::Report> {
+        let dir = db_path.join("api");
+        tokio::fs::create_dir_all(&dir).await?;
+        let path = dir.join("tokens.json");
+        let tokens = match tokio::fs::read(&path).await {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path,
+            tokens: Arc::new(RwLock::new(tokens)),
+            last_used: Default::default(),
+        })
+    }
+
+    pub async fn list(&self) -> Vec<ApiTokenOverview> {
+        let last_used = self.last_used.read().await;
+        self.tokens
+            .read()
+            .await
+            .iter()
+            .map(|token| ApiTokenOverview {
+                last_used: last_used.get(&token.token_id).cloned(),
+                token: token.clone(),
+            })
+            .collect()
+    }
+
+    async fn store(&self, tokens: &[ApiToken]) -> Result<(), eyre::Report> {
+        tokio::fs::write(&self.path, serde_json::to_vec(tokens)?).await?;
+        Ok(())
+    }
+
+    /// Create a token, returning it. Only its hash is kept.
+    pub async fn create(&self, name: String) -> Result<String, eyre::Report> {
+        let secret = format!("sa_{}", nanoid::nanoid!(32));
+        let mut tokens = self.tokens.write().await;
+        tokens.push(ApiToken {
+            token_id: ApiTokenId::new_id(),
+            name,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            hash: token_hash(&secret),
+        });
+        self.store(&tokens).await?;
+        Ok(secret)
+    }
+
+    pub async fn remove(&self, token_id: &ApiTokenIdRef) -> Result<(), eyre::Report> {
+        let mut tokens = self.tokens.write().await;
+        tokens.retain(|t| &*t.token_id != token_id);
+        self.last_used.write().await.remove(token_id);
+        self.store(&tokens).await
+    }
+
+    /// Check the token of a request, noting when it was used.
+    async fn verify(&self, secret: &str) -> Option<ApiTokenId> {
+        let hash = token_hash(secret);
+        let token_id = self
+            .tokens
+            .read()
+            .await
+            .iter()
+            .find(|t| t.hash == hash)?
+            .token_id
+            .clone();
+        self.last_used
+            .write()
+            .await
+            .insert(token_id.clone(), chrono::Utc::now().to_rfc3339());
+        Some(token_id)
+    }
+}
+
+#[cfg(feature = "ssr")]
+pub fn router<S: Clone + Send + Sync + 'static>() -> axum::Router<S> {
+    use axum::routing::{get, post};
+
+    axum::Router::new()
+        .route("/state", get(state_handler))
+        .route("/alerts/:id/state", get(alert_state_handler))
+        .route("/alerts/:id/actions/:action", post(action_handler))
+}
+
+#[cfg(feature = "ssr")]
+fn error(status: StatusCode, message: impl std::fmt::Display) -> axum::response::Response {
+    (
+        status,
+        Json(serde_json::json!({ "error": message.to_string() })),
+    )
+        .into_response()
+}
+
+#[cfg(feature = "ssr")]
+async fn authorize(
+    manager: &AlertManager,
+    headers: &HeaderMap,
+) -> Result<ApiTokenId, axum::response::Response> {
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| error(StatusCode::UNAUTHORIZED, "missing bearer token"))?;
+    manager
+        .api_tokens
+        .verify(token)
+        .await
+        .ok_or_else(|| error(StatusCode::UNAUTHORIZED, "invalid token"))
+}
+
+#[cfg(feature = "ssr")]
+fn field_value(field: &AlertField) -> serde_json::Value {
+    match field {
+        AlertField::Text(text) => text.clone().into(),
+        AlertField::Counter(counter) => (*counter).into(),
+    }
+}
+
+/// Feedback state of an alert, its fields by name.
+#[cfg(feature = "ssr")]
+fn alert_state(alert: &Alert) -> serde_json::Value {
+    let fields: serde_json::Map<_, _> = alert
+        .fields
+        .iter()
+        .map(|(_, (name, field))| (name.to_string(), field_value(field)))
+        .collect();
+    serde_json::json!({
+        "alert_id": alert.alert_id,
+        "name": alert.name,
+        "fields": fields,
+    })
+}
+
+#[cfg(feature = "ssr")]
+async fn state_handler(
+    Extension(manager): Extension<AlertManager>,
+    headers: HeaderMap,
+) -> axum::response::Response {
+    if let Err(response) = authorize(&manager, &headers).await {
+        return response;
+    }
+    let alerts: serde_json::Map<_, _> = manager
+        .read_alerts()
+        .await
+        .iter()
+        .map(|(alert_id, alert)| (alert_id.to_string(), alert_state(alert)))
+        .collect();
+    Json(serde_json::json!({ "alerts": alerts })).into_response()
+}
+
+#[cfg(feature = "ssr")]
+async fn alert_state_handler(
+    extract::Path(alert_id): extract::Path<AlertId>,
+    Extension(manager): Extension<AlertManager>,
+    headers: HeaderMap,
+) -> axum::response::Response {
+    if let Err(response) = authorize(&manager, &headers).await {
+        return response;
+    }
+    match manager.get_alert(&alert_id).await {
+        Ok(alert) => Json(alert_state(&alert)).into_response(),
+        Err(e) => error(StatusCode::NOT_FOUND, e),
+    }
+}
+
+/// Body of an action, which parts are used depends on the action.
+#[cfg(feature = "ssr")]
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+struct ActionRequest {
+    field: Option<AlertFieldName>,
+    amount: Option<i32>,
+    value: Option<String>,
+    /// The two values a text field toggles between.
+    values: Option<(String, String)>,
+    #[serde(flatten)]
+    event: crate::events::NewAlertEvent,
+}
+
+#[cfg(feature = "ssr")]
+#[allow(clippy::type_complexity)]
+async fn action_handler(
+    extract::Path((alert_id, action)): extract::Path<(AlertId, String)>,
+    Extension(manager): Extension<AlertManager>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> axum::response::Response {
+    let token_id = match authorize(&manager, &headers).await {
+        Ok(token_id) => token_id,
+        Err(response) => return response,
+    };
+    let request: ActionRequest = if body.iter().all(u8::is_ascii_whitespace) {
+        ActionRequest::default()
+    } else {
+        match serde_json::from_slice(&body) {
+            Ok(request) => request,
+            Err(e) => return error(StatusCode::BAD_REQUEST, format!("invalid body: {e}")),
+        }
+    };
+    tracing::info!(%alert_id, %action, %token_id, "api action");
+
+    let edit: Box<dyn FnOnce(&mut AlertField) -> Result<(), eyre::Report> + Send> =
+        match action.as_str() {
+            "increment" => {
+                let amount = request.amount.unwrap_or(1);
+                Box::new(move |field| {
+                    if !field.can_incr() {
+                        eyre::bail!("field is not a counter");
+                    }
+                    field.incr(amount);
+                    Ok(())
+                })
+            }
+            "set" => {
+                let Some(value) = request.value else {
+                    return error(StatusCode::BAD_REQUEST, "missing value");
+                };
+                Box::new(move |field| field.set(value))
+            }
+            "toggle" => {
+                let values = request.values;
+                Box::new(move |field| {
+                    match (&*field, values) {
+                        (AlertField::Counter(counter), None) => {
+                            *field = AlertField::Counter(if *counter == 0 { 1 } else { 0 })
+                        }
+                        (current, Some((first, second))) => {
+                            let next = if current.to_string() == first {
+                                second
+                            } else {
+                                first
+                            };
+                            field.set(next)?
+                        }
+                        (AlertField::Text(_), None) => {
+                            eyre::bail!("toggling a text field needs two values")
+                        }
+                    }
+                    Ok(())
+                })
+            }
+            "trigger" => {
+                return match manager.enqueue_new_event(&alert_id, request.event).await {
+                    Ok(event_id) => Json(serde_json::json!({
+                        "alert_id": alert_id,
+                        "event_id": event_id,
+                    }))
+                    .into_response(),
+                    Err(e) => error(StatusCode::BAD_REQUEST, e),
+                };
+            }
+            "refresh" => {
+                return match manager.refresh_alert(&alert_id).await {
+                    Ok(()) => Json(serde_json::json!({ "alert_id": alert_id })).into_response(),
+                    Err(e) => error(StatusCode::BAD_REQUEST, e),
+                };
+            }
+            _ => return error(StatusCode::NOT_FOUND, format!("unknown action `{action}`")),
+        };
+
+    let Some(field) = request.field else {
+        return error(StatusCode::BAD_REQUEST, "missing field");
+    };
+    let field_c = field.clone();
+    match manager
+        .try_edit_alert(&alert_id, move |alert| {
+            match alert.entry_field_name(field_c) {
+                Some((_, (_, field))) => edit(field).map_err(|e| (StatusCode::BAD_REQUEST, e)),
+                None => Err((StatusCode::NOT_FOUND, eyre::eyre!("no such field"))),
+            }
+        })
+        .await
+    {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return error(StatusCode::NOT_FOUND, e),
+        Err((status, e)) => return error(status, e),
+    }
+    match manager.get_alert(&alert_id).await {
+        Ok(alert) => Json(serde_json::json!({
+            "alert_id": alert_id,
+            "field": field,
+            "value": alert.get_alert_field(&field).map(field_value),
+        }))
+        .into_response(),
+        Err(e) => error(StatusCode::NOT_FOUND, e),
+    }
+}
+
+#[server(ListApiTokens, "/backend")]
+pub async fn list_api_tokens() -> Result<Vec<ApiTokenOverview>, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<crate::alerts::AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    Ok(manager.api_tokens.list().await)
+}
+
+/// Create a token, it's only shown once.
+#[server(CreateApiToken, "/backend")]
+#[tracing::instrument(err)]
+pub async fn create_api_token(name: String) -> Result<String, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<crate::alerts::AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    let name = name.trim().to_owned();
+    if name.is_empty() {
+        return Err(ServerFnError::ServerError(
+            "a token needs a name".to_owned(),
+        ));
+    }
+    manager
+        .api_tokens
+        .create(name)
+        .await
+        .map_err(|e| ServerFnError::ServerError(e.to_string()))
+}
+
+#[server(RevokeApiToken, "/backend")]
+#[tracing::instrument(err)]
+pub async fn revoke_api_token(token_id: ApiTokenId) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<crate::alerts::AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager
+        .api_tokens
+        .remove(&token_id)
+        .await
+        .map_err(|e| ServerFnError::ServerError(e.to_string()))
+}
+
+#[cfg(all(test, feature = "ssr"))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn tokens_survive_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let opts = crate::alerts::test_opts(dir.path(), &[]);
+        let (_, manager) = setup::<()>(&opts).await.unwrap();
+        crate::alerts::test_alert(&manager, "deaths").await;
+        let secret = manager.api_tokens.create("deck".to_owned()).await.unwrap();
+        drop(manager);
+
+        // the alerts are read from the root of the db path
+        let (_, manager) = setup::<()>(&opts).await.unwrap();
+        assert_eq!(manager.read_alerts().await.len(), 1);
+        let tokens = manager.api_tokens.list().await;
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token.name, "deck");
+        assert_eq!(
+            manager.api_tokens.verify(&secret).await,
+            Some(tokens[0].token.token_id.clone())
+        );
+        assert_eq!(manager.api_tokens.verify("sa_wrong").await, None);
+    }
+
+    async fn body(response: axum::response::Response) -> (StatusCode, serde_json::Value) {
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("Bearer {token}").parse().unwrap(),
+        );
+        headers
+    }
+
+    async fn action(
+        manager: &AlertManager,
+        headers: HeaderMap,
+        alert_id: &AlertId,
+        action: &str,
+        request: serde_json::Value,
+    ) -> (StatusCode, serde_json::Value) {
+        let response = action_handler(
+            extract::Path((alert_id.clone(), action.to_owned())),
+            Extension(manager.clone()),
+            headers,
+            Bytes::from(request.to_string()),
+        )
+        .await;
+        body(response).await
+    }
+
+    #[tokio::test]
+    async fn requests_need_a_valid_token() {
+        let (_dir, manager) = crate::alerts::test_manager(&[]).await;
+        let alert_id = crate::alerts::test_alert(&manager, "deaths").await;
+        manager.api_tokens.create("deck".to_owned()).await.unwrap();
+        let increment = serde_json::json!({ "field": "deaths" });
+
+        let (status, response) = action(
+            &manager,
+            HeaderMap::new(),
+            &alert_id,
+            "increment",
+            increment.clone(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(response["error"], "missing bearer token");
+        let (status, response) = action(
+            &manager,
+            bearer("sa_wrong"),
+            &alert_id,
+            "increment",
+            increment,
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(response["error"], "invalid token");
+
+        let response = state_handler(Extension(manager.clone()), HeaderMap::new()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            manager.get_alert(&alert_id).await.unwrap().fields[0].1 .1,
+            AlertField::Counter(0)
+        );
+    }
+
+    #[tokio::test]
+    async fn actions_answer_with_the_new_value() {
+        let (_dir, manager) = crate::alerts::test_manager(&[]).await;
+        let alert_id = crate::alerts::test_alert(&manager, "deaths").await;
+        let token = manager.api_tokens.create("deck".to_owned()).await.unwrap();
+        let run = |name, request| {
+            let manager = manager.clone();
+            let alert_id = alert_id.clone();
+            let headers = bearer(&token);
+            async move { action(&manager, headers, &alert_id, name, request).await }
+        };
+
+        let (status, response) = run("increment", serde_json::json!({ "field": "deaths" })).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response["field"], "deaths");
+        assert_eq!(response["value"], 1);
+        let (_, response) = run(
+            "increment",
+            serde_json::json!({ "field": "deaths", "amount": -3 }),
+        )
+        .await;
+        assert_eq!(response["value"], -2);
+        let (_, response) = run(
+            "set",
+            serde_json::json!({ "field": "deaths", "value": "5" }),
+        )
+        .await;
+        assert_eq!(response["value"], 5);
+        let (_, response) = run("toggle", serde_json::json!({ "field": "deaths" })).await;
+        assert_eq!(response["value"], 0);
+        let (_, response) = run("toggle", serde_json::json!({ "field": "deaths" })).await;
+        assert_eq!(response["value"], 1);
+
+        // counters saturate instead of overflowing
+        let (status, response) = run(
+            "increment",
+            serde_json::json!({ "field": "deaths", "amount": i32::MAX }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response["value"], i32::MAX);
+
+        let (status, _) = run("explode", serde_json::json!({ "field": "deaths" })).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = run("increment", serde_json::json!({ "field": "lives" })).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = run(
+            "set",
+            serde_json::json!({ "field": "deaths", "value": "many" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn state_feedback() {
+        let (_dir, manager) = crate::alerts::test_manager(&[]).await;
+        let alert_id = crate::alerts::test_alert(&manager, "deaths").await;
+        let token = manager.api_tokens.create("deck".to_owned()).await.unwrap();
+
+        let expected = serde_json::json!({
+            "alert_id": alert_id,
+            "name": "test",
+            "fields": { "deaths": 0 },
+        });
+        let (status, response) = body(
+            alert_state_handler(
+                extract::Path(alert_id.clone()),
+                Extension(manager.clone()),
+                bearer(&token),
+            )
+            .await,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response, expected);
+        let (status, response) =
+            body(state_handler(Extension(manager.clone()), bearer(&token)).await).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            response,
+            serde_json::json!({ "alerts": { alert_id.to_string(): expected } })
+        );
+    }
+}
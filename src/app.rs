@@ -1,11 +1,37 @@
+pub mod actions;
+pub mod api;
+pub mod automation;
+pub mod chat;
+pub mod events;
+pub mod layers;
+pub mod library;
 pub mod list;
 pub mod login;
 pub mod new;
+pub mod obs;
+pub mod outbound;
+pub mod playlists;
+pub mod preview;
+pub mod scenes;
+pub mod schedules;
+pub mod themes;
+pub mod twitch;
 pub mod update;
+pub mod webhooks;
 
+use api::*;
+use automation::*;
+use chat::*;
+use library::*;
 use list::*;
 use new::*;
+use obs::*;
+use scenes::*;
+use schedules::*;
+use themes::*;
+use twitch::*;
 use update::*;
+use webhooks::*;
 
 use leptos::prelude::*;
 use leptos_meta::*;
@@ -47,6 +73,46 @@ pub fn App() -> impl IntoView {
                         path=path!("/alert/new")
                         view=|| view! { <NewAlert/> }
                     />
+                    <Route
+                        path=path!("/library")
+                        view=|| view! { <Library/> }
+                    />
+                    <Route
+                        path=path!("/themes")
+                        view=|| view! { <Themes/> }
+                    />
+                    <Route
+                        path=path!("/scenes")
+                        view=|| view! { <Scenes/> }
+                    />
+                    <Route
+                        path=path!("/twitch")
+                        view=|| view! { <Twitch/> }
+                    />
+                    <Route
+                        path=path!("/webhooks")
+                        view=|| view! { <Webhooks/> }
+                    />
+                    <Route
+                        path=path!("/automation")
+                        view=|| view! { <Automation/> }
+                    />
+                    <Route
+                        path=path!("/schedules")
+                        view=|| view! { <Schedules/> }
+                    />
+                    <Route
+                        path=path!("/obs")
+                        view=|| view! { <Obs/> }
+                    />
+                    <Route
+                        path=path!("/chat")
+                        view=|| view! { <Chat/> }
+                    />
+                    <Route
+                        path=path!("/tokens")
+                        view=|| view! { <Tokens/> }
+                    />
                     <Route ssr=SsrMode::OutOfOrder
                         path=path!("/login")
                         view=move || view! { <Login/> }
@@ -0,0 +1,59 @@
+use leptos::prelude::*;
+
+use crate::actions::AlertAction;
+pub use crate::alerts::*;
+
+/// An action with the alert it changes.
+pub fn action_summary(action: &AlertAction) -> String {
+    match action.alert_id() {
+        Some(alert_id) => format!("{action} on {alert_id}"),
+        None => action.to_string(),
+    }
+}
+
+/// Inputs for a [`crate::actions::AlertAction`], named `kind`, `alert_id`, `field` and `value`.
+#[component]
+#[track_caller]
+pub fn AlertActionInputs() -> impl IntoView {
+    let alerts = Resource::new(|| (), |_| read_all_alerts());
+    view! {
+        <div class="flex gap-2">
+            <select class="border border-gray-300 rounded px-2" name="kind">
+                <option value="increment_field">"Increment field"</option>
+                <option value="set_field">"Set field"</option>
+                <option value="push_field">"Push to field"</option>
+                <option value="set_text">"Set text"</option>
+                <option value="enqueue_event">"Enqueue event"</option>
+                <option value="start_timers">"Start timers"</option>
+                <option value="obs_set_scene">"OBS: switch scene"</option>
+                <option value="obs_set_source_visible">"OBS: show or hide source"</option>
+                <option value="obs_refresh_browser_source">"OBS: refresh browser source"</option>
+            </select>
+            <select class="border border-gray-300 rounded px-2" name="alert_id">
+                {move || {
+                    alerts
+                        .get()
+                        .and_then(Result::ok)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|(id, alert)| {
+                            view! { <option value=id.to_string()>{alert.name.to_string()}</option> }
+                        })
+                        .collect_view()
+                }}
+            </select>
+            <input
+                class="w-32 border border-gray-300 rounded px-2 py-1"
+                type="text"
+                name="field"
+                placeholder="field or OBS source"
+            />
+            <input
+                class="flex-1 border border-gray-300 rounded px-2 py-1"
+                type="text"
+                name="value"
+                placeholder="amount, value, text, event type, seconds or OBS scene"
+            />
+        </div>
+    }
+}
@@ -0,0 +1,130 @@
+use leptos::prelude::*;
+
+use crate::api::*;
+
+#[component]
+#[track_caller]
+pub fn Tokens() -> impl IntoView {
+    let create = ServerAction::<CreateApiToken>::new();
+    let revoke = ServerAction::<RevokeApiToken>::new();
+    let tokens = Resource::new(
+        move || (create.version().get(), revoke.version().get()),
+        |_| list_api_tokens(),
+    );
+
+    let error = move || {
+        [
+            create.value().get().and_then(Result::err),
+            revoke.value().get().and_then(Result::err),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+    };
+    let created = move || {
+        create.value().get().and_then(Result::ok).map(|token| {
+            view! {
+                <p class="text-sm">
+                    "Copy the new token now, it won't be shown again: "
+                    <code class="select-all bg-gray-100 px-1">{token}</code>
+                </p>
+            }
+        })
+    };
+
+    view! {
+        <div class="w-full max-w-4xl bg-white shadow rounded-xl p-8 space-y-6">
+            <h1 class="text-2xl font-semibold">"API tokens"</h1>
+            <p class="text-sm text-gray-600">
+                "Tokens authenticate requests to the action API, e.g. from Bitfocus Companion, with the header "
+                <code>"Authorization: Bearer <token>"</code>
+                ". "
+                <code>"POST /api/v1/alerts/<id>/actions/increment"</code>
+                " with "
+                <code>"{\"field\": \"deaths\"}"</code>
+                " counts up and answers with the new value, the actions are "
+                <code>"increment"</code>
+                ", "
+                <code>"set"</code>
+                ", "
+                <code>"toggle"</code>
+                ", "
+                <code>"trigger"</code>
+                " and "
+                <code>"refresh"</code>
+                ". "
+                <code>"GET /api/v1/alerts/<id>/state"</code>
+                " returns the current values for button feedback."
+            </p>
+            <pre class="text-sm text-red-500">{error}</pre>
+            {created}
+            <Suspense fallback=move || view! { <p>"loading"</p> }>
+                {move || {
+                    tokens
+                        .get()
+                        .map(|tokens| match tokens {
+                            Ok(tokens) => {
+                                view! {
+                                    <ul class="space-y-1">
+                                        {tokens
+                                            .into_iter()
+                                            .map(|overview| {
+                                                view! {
+                                                    <li class="flex items-center gap-2 text-sm">
+                                                        <ActionForm action=revoke>
+                                                            <input
+                                                                type="hidden"
+                                                                name="token_id"
+                                                                value=overview.token.token_id.to_string()
+                                                            />
+                                                            <input
+                                                                class="cursor-pointer rounded border-2 border-red-500 px-1 hover:border-red-900"
+                                                                type="submit"
+                                                                value="𐄂"
+                                                            />
+                                                        </ActionForm>
+                                                        <span class="font-semibold">{overview.token.name}</span>
+                                                        <span class="text-gray-600">
+                                                            {format!("created {}", overview.token.created_at)}
+                                                        </span>
+                                                        <span class="text-gray-600">
+                                                            {overview
+                                                                .last_used
+                                                                .map(|at| format!("last used {at}"))
+                                                                .unwrap_or_else(|| "not used yet".to_owned())}
+                                                        </span>
+                                                    </li>
+                                                }
+                                            })
+                                            .collect_view()}
+                                    </ul>
+                                }
+                                    .into_any()
+                            }
+                            Err(e) => {
+                                view! { <p class="text-red-500">{format!("Error: {e}")}</p> }
+                                    .into_any()
+                            }
+                        })
+                }}
+            </Suspense>
+            <ActionForm action=create>
+                <div class="flex gap-2 text-sm">
+                    <input
+                        class="w-64 border border-gray-300 rounded px-2 py-1"
+                        type="text"
+                        name="name"
+                        placeholder="name, e.g. Stream Deck"
+                    />
+                    <input
+                        class="cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded"
+                        type="submit"
+                        value="Create token"
+                    />
+                </div>
+            </ActionForm>
+        </div>
+    }
+}
@@ -0,0 +1,314 @@
+use leptos::prelude::*;
+
+use super::actions::{action_summary, AlertActionInputs};
+pub use crate::alerts::*;
+use crate::automation::*;
+
+#[component]
+#[track_caller]
+pub fn Automation() -> impl IntoView {
+    let save = ServerAction::<SaveRule>::new();
+    let delete = ServerAction::<DeleteRule>::new();
+    let add_action = ServerAction::<AddRuleAction>::new();
+    let remove_action = ServerAction::<RemoveRuleAction>::new();
+    let dry_run = ServerAction::<DryRunAutomation>::new();
+    let automation = Resource::new(
+        move || {
+            (
+                save.version().get(),
+                delete.version().get(),
+                add_action.version().get(),
+                remove_action.version().get(),
+            )
+        },
+        |_| read_automation(),
+    );
+
+    let error = move || {
+        [
+            save.value().get().and_then(Result::err),
+            delete.value().get().and_then(Result::err),
+            add_action.value().get().and_then(Result::err),
+            remove_action.value().get().and_then(Result::err),
+            dry_run.value().get().and_then(Result::err),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+    };
+    let dry_run_result = move || {
+        dry_run.value().get().and_then(Result::ok).map(|runs| {
+            if runs.is_empty() {
+                return view! { <p class="text-sm text-gray-600">"No rule would fire"</p> }
+                    .into_any();
+            }
+            view! { <RuleRuns runs/> }.into_any()
+        })
+    };
+
+    view! {
+        <div class="w-full max-w-4xl bg-white shadow rounded-xl p-8 space-y-6">
+            <h1 class="text-2xl font-semibold">"Automation"</h1>
+            <p class="text-sm text-gray-600">
+                "Rules run their actions when the fields of an alert start matching the conditions, e.g. "
+                <code>"subs >= $goal"</code>
+                ". Fields are available as "
+                <code>"$name"</code>
+                " in conditions and actions. Chains of more than "
+                {MAX_DEPTH}
+                " rules triggering each other are stopped."
+            </p>
+            <pre class="text-sm text-red-500">{error}</pre>
+            <ActionForm action=dry_run>
+                <div class="flex gap-2 text-sm">
+                    <TriggerAlertSelect name="alert_id" selected=None/>
+                    <input
+                        class="w-32 border border-gray-300 rounded px-2 py-1"
+                        type="text"
+                        name="field"
+                        placeholder="field"
+                    />
+                    <input
+                        class="flex-1 border border-gray-300 rounded px-2 py-1"
+                        type="text"
+                        name="value"
+                        placeholder="new value"
+                    />
+                    <input
+                        class="cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded"
+                        type="submit"
+                        value="Dry run"
+                    />
+                </div>
+            </ActionForm>
+            {dry_run_result}
+            <Suspense fallback=move || view! { <p>"loading"</p> }>
+                {move || {
+                    automation
+                        .get()
+                        .map(|automation| match automation {
+                            Ok(automation) => {
+                                view! {
+                                    {automation
+                                        .rules
+                                        .into_iter()
+                                        .map(|rule| {
+                                            view! {
+                                                <RuleSettings
+                                                    rule
+                                                    save
+                                                    delete
+                                                    add_action
+                                                    remove_action
+                                                />
+                                            }
+                                        })
+                                        .collect_view()}
+                                    <h2 class="text-lg font-medium text-gray-700">"Recent runs"</h2>
+                                    <RuleRuns runs=automation.runs/>
+                                }
+                                    .into_any()
+                            }
+                            Err(e) => {
+                                view! { <p class="text-red-500">{format!("Error: {e}")}</p> }
+                                    .into_any()
+                            }
+                        })
+                }}
+            </Suspense>
+            <h2 class="text-lg font-medium text-gray-700">"New rule"</h2>
+            <RuleForm save rule=None/>
+        </div>
+    }
+}
+
+#[component]
+#[track_caller]
+fn RuleSettings(
+    rule: AutomationRule,
+    save: ServerAction<SaveRule>,
+    delete: ServerAction<DeleteRule>,
+    add_action: ServerAction<AddRuleAction>,
+    remove_action: ServerAction<RemoveRuleAction>,
+) -> impl IntoView {
+    let rule_id = rule.rule_id.to_string();
+    let actions = rule
+        .actions
+        .iter()
+        .enumerate()
+        .map(|(index, action)| {
+            let rule_id = rule_id.clone();
+            view! {
+                <li class="flex items-center gap-2">
+                    <ActionForm action=remove_action>
+                        <input type="hidden" name="rule_id" value=rule_id.clone()/>
+                        <input type="hidden" name="index" value=index/>
+                        <input
+                            class="cursor-pointer rounded border-2 border-red-500 px-1 hover:border-red-900"
+                            type="submit"
+                            value="𐄂"
+                        />
+                    </ActionForm>
+                    <span>{action_summary(action)}</span>
+                </li>
+            }
+        })
+        .collect_view();
+    let delete_id = rule_id.clone();
+
+    view! {
+        <div class="space-y-2 border-t border-gray-200 pt-4 text-sm">
+            <div class="flex items-center gap-2">
+                <ActionForm action=delete>
+                    <input type="hidden" name="rule_id" value=delete_id/>
+                    <input
+                        class="cursor-pointer rounded border-2 border-red-500 px-1 hover:border-red-900"
+                        type="submit"
+                        value="𐄂"
+                    />
+                </ActionForm>
+                <span class="text-lg font-semibold">{rule.name.clone()}</span>
+                <span class="text-gray-600">{rule.mode.as_str()}</span>
+            </div>
+            <RuleForm save rule=Some(rule)/>
+            <ul class="space-y-1">{actions}</ul>
+            <ActionForm action=add_action>
+                <input type="hidden" name="rule_id" value=rule_id/>
+                <div class="flex gap-2">
+                    <div class="flex-1">
+                        <AlertActionInputs/>
+                    </div>
+                    <input
+                        class="cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded"
+                        type="submit"
+                        value="Add action"
+                    />
+                </div>
+            </ActionForm>
+        </div>
+    }
+}
+
+/// Form creating a rule, or editing `rule`.
+#[component]
+#[track_caller]
+fn RuleForm(save: ServerAction<SaveRule>, rule: Option<AutomationRule>) -> impl IntoView {
+    let is_new = rule.is_none();
+    let rule_id = rule
+        .as_ref()
+        .map(|r| r.rule_id.to_string())
+        .unwrap_or_default();
+    let name = rule.as_ref().map(|r| r.name.clone()).unwrap_or_default();
+    let mode = rule.as_ref().map(|r| r.mode).unwrap_or_default();
+    let alert_id = rule.as_ref().map(|r| r.alert_id.clone());
+    let conditions = rule
+        .as_ref()
+        .map(|r| {
+            r.conditions
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .unwrap_or_default();
+
+    view! {
+        <ActionForm action=save>
+            <input type="hidden" name="rule_id" value=rule_id/>
+            <div class="flex gap-2 text-sm">
+                <input
+                    class="w-32 border border-gray-300 rounded px-2 py-1"
+                    type="text"
+                    name="name"
+                    placeholder="name"
+                    value=name
+                />
+                <select class="border border-gray-300 rounded px-2" name="mode">
+                    <option value="enabled" selected={mode == RuleMode::Enabled}>"Enabled"</option>
+                    <option value="dry_run" selected={mode == RuleMode::DryRun}>"Dry run"</option>
+                    <option value="disabled" selected={mode == RuleMode::Disabled}>"Disabled"</option>
+                </select>
+                <TriggerAlertSelect name="trigger_alert_id" selected=alert_id/>
+                <input
+                    class="flex-1 border border-gray-300 rounded px-2 py-1"
+                    type="text"
+                    name="conditions"
+                    placeholder="conditions, e.g. subs >= $goal"
+                    value=conditions
+                />
+                <input
+                    class="cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded"
+                    type="submit"
+                    value={if is_new { "Add rule" } else { "Save" }}
+                />
+            </div>
+        </ActionForm>
+    }
+}
+
+#[component]
+#[track_caller]
+fn TriggerAlertSelect(name: &'static str, selected: Option<AlertId>) -> impl IntoView {
+    let alerts = Resource::new(|| (), |_| read_all_alerts());
+    view! {
+        <select class="border border-gray-300 rounded px-2" name=name>
+            {move || {
+                alerts
+                    .get()
+                    .and_then(Result::ok)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(id, alert)| {
+                        let is_selected = selected.as_ref() == Some(&id);
+                        view! {
+                            <option value=id.to_string() selected=is_selected>
+                                {alert.name.to_string()}
+                            </option>
+                        }
+                    })
+                    .collect_view()
+            }}
+        </select>
+    }
+}
+
+#[component]
+#[track_caller]
+fn RuleRuns(runs: Vec<RuleRun>) -> impl IntoView {
+    let runs = runs
+        .into_iter()
+        .map(|run| {
+            let outcomes = run
+                .outcomes
+                .into_iter()
+                .map(|outcome| match outcome.error {
+                    Some(error) => view! {
+                        <li class="text-red-500">{format!("{}: {error}", outcome.action)}</li>
+                    }
+                    .into_any(),
+                    None => view! { <li>{outcome.action}</li> }.into_any(),
+                })
+                .collect_view();
+            view! {
+                <tr class="align-top">
+                    <td class="pr-2">{run.at}</td>
+                    <td class="pr-2">
+                        {run.rule_name}
+                        {run.dry_run.then_some(" (dry run)")}
+                        {(run.depth > 0).then(|| format!(" (depth {})", run.depth))}
+                    </td>
+                    <td>
+                        <ul>{outcomes}</ul>
+                    </td>
+                </tr>
+            }
+        })
+        .collect_view();
+    view! {
+        <table class="text-xs">
+            <tbody>{runs}</tbody>
+        </table>
+    }
+}
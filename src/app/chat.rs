@@ -0,0 +1,331 @@
+use leptos::prelude::*;
+
+use super::actions::{action_summary, AlertActionInputs};
+pub use crate::alerts::*;
+use crate::chat::*;
+
+#[component]
+#[track_caller]
+pub fn Chat() -> impl IntoView {
+    let save = ServerAction::<SaveChatCommand>::new();
+    let delete = ServerAction::<DeleteChatCommand>::new();
+    let add_action = ServerAction::<AddChatCommandAction>::new();
+    let remove_action = ServerAction::<RemoveChatCommandAction>::new();
+    let simulate = ServerAction::<SimulateChatMessage>::new();
+    let chat = Resource::new(
+        move || {
+            (
+                save.version().get(),
+                delete.version().get(),
+                add_action.version().get(),
+                remove_action.version().get(),
+                simulate.version().get(),
+            )
+        },
+        |_| read_chat(),
+    );
+
+    let error = move || {
+        [
+            save.value().get().and_then(Result::err),
+            delete.value().get().and_then(Result::err),
+            add_action.value().get().and_then(Result::err),
+            remove_action.value().get().and_then(Result::err),
+            simulate.value().get().and_then(Result::err),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+    };
+    let simulated = move || {
+        simulate
+            .value()
+            .get()
+            .and_then(Result::ok)
+            .map(|run| match run {
+                Some(run) => view! { <ChatRuns runs=vec![run]/> }.into_any(),
+                None => view! { <p class="text-sm text-gray-600">"Not a command"</p> }.into_any(),
+            })
+    };
+
+    view! {
+        <div class="w-full max-w-4xl bg-white shadow rounded-xl p-8 space-y-6">
+            <h1 class="text-2xl font-semibold">"Chat commands"</h1>
+            <p class="text-sm text-gray-600">
+                "Commands run their actions when sent in Twitch chat, e.g. "
+                <code>"!death -1"</code>
+                ". The arguments are available as "
+                <code>"$args"</code>
+                " and "
+                <code>"$1"</code>
+                ", "
+                <code>"$2"</code>
+                ", ..., the sender as "
+                <code>"$user"</code>
+                ". Replies can also use the fields of the alerts the actions changed, e.g. "
+                <code>"Deaths: $deaths"</code>
+                "."
+            </p>
+            <pre class="text-sm text-red-500">{error}</pre>
+            <ActionForm action=simulate>
+                <div class="flex gap-2 text-sm">
+                    <input
+                        class="w-32 border border-gray-300 rounded px-2 py-1"
+                        type="text"
+                        name="user"
+                        placeholder="user"
+                    />
+                    <RoleSelect selected=ChatRole::Broadcaster/>
+                    <input
+                        class="flex-1 border border-gray-300 rounded px-2 py-1"
+                        type="text"
+                        name="message"
+                        placeholder="!death -1"
+                    />
+                    <input
+                        class="cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded"
+                        type="submit"
+                        value="Send"
+                    />
+                </div>
+            </ActionForm>
+            {simulated}
+            <Suspense fallback=move || view! { <p>"loading"</p> }>
+                {move || {
+                    chat.get()
+                        .map(|chat| match chat {
+                            Ok(chat) => {
+                                let configured = chat.configured;
+                                view! {
+                                    <Show when=move || !configured>
+                                        <p class="text-sm text-red-500">
+                                            "Set --twitch-chat-channel to read commands from chat."
+                                        </p>
+                                    </Show>
+                                    <p class="text-sm text-gray-600">
+                                        {format!("Status: {}", chat.status)}
+                                    </p>
+                                    {chat
+                                        .commands
+                                        .into_iter()
+                                        .map(|command| {
+                                            view! {
+                                                <CommandSettings
+                                                    command
+                                                    save
+                                                    delete
+                                                    add_action
+                                                    remove_action
+                                                />
+                                            }
+                                        })
+                                        .collect_view()}
+                                    <h2 class="text-lg font-medium text-gray-700">"Recent commands"</h2>
+                                    <ChatRuns runs=chat.runs/>
+                                }
+                                    .into_any()
+                            }
+                            Err(e) => {
+                                view! { <p class="text-red-500">{format!("Error: {e}")}</p> }
+                                    .into_any()
+                            }
+                        })
+                }}
+            </Suspense>
+            <h2 class="text-lg font-medium text-gray-700">"New command"</h2>
+            <CommandForm save command=None/>
+        </div>
+    }
+}
+
+#[component]
+#[track_caller]
+fn CommandSettings(
+    command: ChatCommand,
+    save: ServerAction<SaveChatCommand>,
+    delete: ServerAction<DeleteChatCommand>,
+    add_action: ServerAction<AddChatCommandAction>,
+    remove_action: ServerAction<RemoveChatCommandAction>,
+) -> impl IntoView {
+    let command_id = command.command_id.to_string();
+    let actions = command
+        .actions
+        .iter()
+        .enumerate()
+        .map(|(index, action)| {
+            let command_id = command_id.clone();
+            view! {
+                <li class="flex items-center gap-2">
+                    <ActionForm action=remove_action>
+                        <input type="hidden" name="command_id" value=command_id.clone()/>
+                        <input type="hidden" name="index" value=index/>
+                        <input
+                            class="cursor-pointer rounded border-2 border-red-500 px-1 hover:border-red-900"
+                            type="submit"
+                            value="𐄂"
+                        />
+                    </ActionForm>
+                    <span>{action_summary(action)}</span>
+                </li>
+            }
+        })
+        .collect_view();
+    let delete_id = command_id.clone();
+
+    view! {
+        <div class="space-y-2 border-t border-gray-200 pt-4 text-sm">
+            <div class="flex items-center gap-2">
+                <ActionForm action=delete>
+                    <input type="hidden" name="command_id" value=delete_id/>
+                    <input
+                        class="cursor-pointer rounded border-2 border-red-500 px-1 hover:border-red-900"
+                        type="submit"
+                        value="𐄂"
+                    />
+                </ActionForm>
+                <span class="text-lg font-semibold">{format!("!{}", command.name)}</span>
+                <span class="text-gray-600">{command.role.as_str()}</span>
+            </div>
+            <CommandForm save command=Some(command)/>
+            <ul class="space-y-1">{actions}</ul>
+            <ActionForm action=add_action>
+                <input type="hidden" name="command_id" value=command_id/>
+                <div class="flex gap-2">
+                    <div class="flex-1">
+                        <AlertActionInputs/>
+                    </div>
+                    <input
+                        class="cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded"
+                        type="submit"
+                        value="Add action"
+                    />
+                </div>
+            </ActionForm>
+        </div>
+    }
+}
+
+/// Form creating a command, or editing `command`.
+#[component]
+#[track_caller]
+fn CommandForm(save: ServerAction<SaveChatCommand>, command: Option<ChatCommand>) -> impl IntoView {
+    let is_new = command.is_none();
+    let command_id = command
+        .as_ref()
+        .map(|c| c.command_id.to_string())
+        .unwrap_or_default();
+    let name = command.as_ref().map(|c| c.name.clone()).unwrap_or_default();
+    let role = command
+        .as_ref()
+        .map(|c| c.role)
+        .unwrap_or(ChatRole::Moderator);
+    let cooldown_secs = command.as_ref().map(|c| c.cooldown_secs).unwrap_or(5);
+    let default_args = command
+        .as_ref()
+        .map(|c| c.default_args.clone())
+        .unwrap_or_default();
+    let reply = command.map(|c| c.reply).unwrap_or_default();
+
+    view! {
+        <ActionForm action=save>
+            <input type="hidden" name="command_id" value=command_id/>
+            <div class="flex gap-2 text-sm">
+                <input
+                    class="w-28 border border-gray-300 rounded px-2 py-1"
+                    type="text"
+                    name="name"
+                    placeholder="!death"
+                    value=name
+                />
+                <RoleSelect selected=role/>
+                <input
+                    class="w-20 border border-gray-300 rounded px-2 py-1"
+                    type="number"
+                    name="cooldown_secs"
+                    min="0"
+                    title="cooldown in seconds"
+                    value=cooldown_secs
+                />
+                <input
+                    class="w-24 border border-gray-300 rounded px-2 py-1"
+                    type="text"
+                    name="default_args"
+                    placeholder="default args"
+                    value=default_args
+                />
+                <input
+                    class="flex-1 border border-gray-300 rounded px-2 py-1"
+                    type="text"
+                    name="reply"
+                    placeholder="reply, e.g. Deaths: $deaths"
+                    value=reply
+                />
+                <input
+                    class="cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded"
+                    type="submit"
+                    value={if is_new { "Add command" } else { "Save" }}
+                />
+            </div>
+        </ActionForm>
+    }
+}
+
+#[component]
+#[track_caller]
+fn RoleSelect(selected: ChatRole) -> impl IntoView {
+    view! {
+        <select class="border border-gray-300 rounded px-2" name="role">
+            {ChatRole::ALL
+                .into_iter()
+                .map(|role| {
+                    view! {
+                        <option value=role.as_str() selected={role == selected}>
+                            {role.as_str()}
+                        </option>
+                    }
+                })
+                .collect_view()}
+        </select>
+    }
+}
+
+#[component]
+#[track_caller]
+fn ChatRuns(runs: Vec<ChatRun>) -> impl IntoView {
+    let runs = runs
+        .into_iter()
+        .map(|run| {
+            let outcomes = run
+                .outcomes
+                .into_iter()
+                .map(|outcome| match outcome.error {
+                    Some(error) => view! {
+                        <li class="text-red-500">{format!("{}: {error}", outcome.action)}</li>
+                    }
+                    .into_any(),
+                    None => view! { <li>{outcome.action}</li> }.into_any(),
+                })
+                .collect_view();
+            view! {
+                <tr class="align-top">
+                    <td class="pr-2">{run.at}</td>
+                    <td class="pr-2">{format!("{}: {}", run.user, run.message)}</td>
+                    <td>
+                        <ul>
+                            {outcomes}
+                            {run.skipped.map(|skipped| view! { <li class="text-gray-600">{skipped}</li> })}
+                            {run.reply.map(|reply| view! { <li class="italic">{format!("↪ {reply}")}</li> })}
+                        </ul>
+                    </td>
+                </tr>
+            }
+        })
+        .collect_view();
+    view! {
+        <table class="text-xs">
+            <tbody>{runs}</tbody>
+        </table>
+    }
+}
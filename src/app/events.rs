@@ -0,0 +1,309 @@
+use leptos::prelude::*;
+
+use super::library::{MediaSelect, VolumeInput};
+use super::update::AlertIdInput;
+pub use crate::alerts::*;
+use crate::events::*;
+use crate::tts::*;
+
+#[component]
+#[track_caller]
+pub fn AlertEvents() -> impl IntoView {
+    let alert: RwSignal<Alert> = use_context().unwrap();
+    let enqueue = ServerAction::<EnqueueAlertEvent>::new();
+    let control = ServerAction::<ControlEventQueue>::new();
+
+    let queue = Resource::new(
+        move || {
+            (
+                alert.with(|a| a.alert_id.clone()),
+                enqueue.version().get(),
+                control.version().get(),
+            )
+        },
+        move |(id, ..)| async move { read_event_queue(id).await },
+    );
+
+    view! {
+        <div class="space-y-2">
+            <h2 class="text-lg font-medium text-gray-700">"Events"</h2>
+            <Suspense fallback=|| ()>
+                {move || {
+                    queue
+                        .get()
+                        .map(|queue| match queue {
+                            Ok(queue) => {
+                                view! {
+                                    <p class="text-sm text-gray-600">
+                                        {format!(
+                                            "{} pending{}{}",
+                                            queue.pending.len(),
+                                            if queue.current.is_some() { ", playing" } else { "" },
+                                            if queue.paused { ", paused" } else { "" },
+                                        )}
+                                    </p>
+                                }
+                                    .into_any()
+                            }
+                            Err(e) => {
+                                view! { <p class="text-red-500">{format!("Error: {e}")}</p> }
+                                    .into_any()
+                            }
+                        })
+                }}
+            </Suspense>
+            <div class="flex gap-2">
+                {QueueControl::ALL
+                    .into_iter()
+                    .map(|c| {
+                        view! {
+                            <ActionForm action=control>
+                                <AlertIdInput/>
+                                <input type="hidden" name="control" value=c.as_str()/>
+                                <input
+                                    type="submit"
+                                    value=c.as_str()
+                                    class="text-sm cursor-pointer bg-blue-600 px-3 py-1 rounded text-white hover:bg-blue-700"
+                                />
+                            </ActionForm>
+                        }
+                    })
+                    .collect_view()}
+            </div>
+            <ActionForm action=enqueue>
+                <AlertIdInput/>
+                <div class="flex flex-col gap-2">
+                    <div class="flex gap-2">
+                        <input
+                            class="w-32 border border-gray-300 rounded px-4 py-2"
+                            type="text"
+                            name="event_type"
+                            placeholder="type"
+                        />
+                        <input
+                            class="flex-1 border border-gray-300 rounded px-4 py-2"
+                            type="text"
+                            name="template"
+                            placeholder="text, empty to use a variant"
+                        />
+                        <input
+                            class="w-32 border border-gray-300 rounded px-4 py-2"
+                            type="number"
+                            name="duration_ms"
+                            placeholder="duration ms"
+                        />
+                    </div>
+                    <div class="flex gap-2">
+                        <input
+                            class="flex-1 border border-gray-300 rounded px-4 py-2 font-mono text-sm"
+                            type="text"
+                            name="payload"
+                            placeholder=r#"{"user": "someone", "amount": 100}"#
+                        />
+                        <input
+                            class="cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded text-sm"
+                            type="submit"
+                            value="Test event"
+                        />
+                    </div>
+                </div>
+            </ActionForm>
+            <AlertVariants/>
+            <AlertTts/>
+        </div>
+    }
+}
+
+#[component]
+#[track_caller]
+pub fn AlertVariants() -> impl IntoView {
+    let alert: RwSignal<Alert> = use_context().unwrap();
+    let save_variant = ServerAction::<SaveAlertVariant>::new();
+    let delete_variant = ServerAction::<DeleteAlertVariant>::new();
+
+    for action in [save_variant.value(), delete_variant.value()] {
+        Effect::new(move || {
+            if let Some(Ok(new_alert)) = action.get() {
+                alert.update(|a| a.variants = new_alert.variants);
+            }
+        });
+    }
+
+    view! {
+        <div class="space-y-2">
+            <h3 class="font-medium text-gray-700">"Variants"</h3>
+            <ul class="space-y-1">
+                <For
+                    each=move || alert.with(|a| a.variants.clone())
+                    key=|v| format!("{v:?}")
+                    children=move |variant| {
+                        let conditions = variant
+                            .conditions
+                            .iter()
+                            .map(ToString::to_string)
+                            .collect::<Vec<_>>()
+                            .join(", ");
+                        let name = variant.name.clone();
+                        view! {
+                            <li class="flex items-center gap-2 text-sm">
+                                <ActionForm action=delete_variant>
+                                    <AlertIdInput/>
+                                    <input type="hidden" name="name" value=name/>
+                                    <input
+                                        class="cursor-pointer rounded border-2 border-red-500 px-1 hover:border-red-900"
+                                        type="submit"
+                                        value="𐄂"
+                                    />
+                                </ActionForm>
+                                <span class="font-semibold">{variant.name.clone()}</span>
+                                <span class="text-gray-600">
+                                    {format!("on {} {}", variant.event_type, conditions)}
+                                </span>
+                                <span class="text-gray-600">
+                                    {format!("{}ms", variant.duration_ms)}
+                                </span>
+                            </li>
+                        }
+                    }
+                />
+            </ul>
+            <ActionForm action=save_variant>
+                <AlertIdInput/>
+                <div class="flex flex-col gap-2">
+                    <div class="flex gap-2">
+                        <input
+                            class="w-40 border border-gray-300 rounded px-4 py-2"
+                            type="text"
+                            name="name"
+                            placeholder="name, e.g. sub_tier3"
+                        />
+                        <input
+                            class="w-32 border border-gray-300 rounded px-4 py-2"
+                            type="text"
+                            name="event_type"
+                            placeholder="type, e.g. sub"
+                        />
+                        <input
+                            class="flex-1 border border-gray-300 rounded px-4 py-2"
+                            type="text"
+                            name="conditions"
+                            placeholder="tier == 3, amount >= 100"
+                        />
+                        <input
+                            class="w-32 border border-gray-300 rounded px-4 py-2"
+                            type="number"
+                            name="duration_ms"
+                            value="5000"
+                        />
+                    </div>
+                    <div class="flex gap-2">
+                        <MediaSelect allow_none=true/>
+                        <VolumeInput/>
+                    </div>
+                    <div class="flex gap-2">
+                        <textarea
+                            class="flex-1 rounded-lg border border-gray-300 bg-gray-50 p-3 text-sm"
+                            name="text"
+                            rows="4"
+                            placeholder="$user subscribed!"
+                        ></textarea>
+                        <textarea
+                            class="flex-1 rounded-lg border border-gray-300 bg-gray-50 p-3 text-sm"
+                            name="style"
+                            rows="4"
+                            placeholder="style"
+                        ></textarea>
+                    </div>
+                    <input
+                        class="self-start cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded text-sm"
+                        type="submit"
+                        value="Save variant"
+                    />
+                </div>
+            </ActionForm>
+        </div>
+    }
+}
+
+#[component]
+#[track_caller]
+pub fn AlertTts() -> impl IntoView {
+    let alert: RwSignal<Alert> = use_context().unwrap();
+    let update_tts = ServerAction::<UpdateAlertTts>::new();
+
+    Effect::new(move || {
+        if let Some(Ok(new_alert)) = update_tts.value().get() {
+            alert.update(|a| a.tts = new_alert.tts);
+        }
+    });
+
+    let tts = move || alert.with(|a| a.tts.clone());
+
+    view! {
+        <div class="space-y-2">
+            <h3 class="font-medium text-gray-700">"Text to speech"</h3>
+            <ActionForm action=update_tts>
+                <AlertIdInput/>
+                <div class="flex flex-wrap items-center gap-2 text-sm">
+                    <label class="flex items-center gap-1">
+                        <input type="checkbox" name="enabled" value="on" checked=move || tts().enabled/>
+                        "enabled"
+                    </label>
+                    <input
+                        class="w-32 border border-gray-300 rounded px-4 py-2"
+                        type="text"
+                        name="field"
+                        title="payload field to speak"
+                        value=move || tts().field
+                    />
+                    <input
+                        class="w-40 border border-gray-300 rounded px-4 py-2"
+                        type="text"
+                        name="voice"
+                        placeholder="voice"
+                        value=move || tts().voice
+                    />
+                    <input
+                        class="w-24 border border-gray-300 rounded px-4 py-2"
+                        type="number"
+                        name="rate"
+                        title="rate"
+                        min=*TTS_RATES.start()
+                        max=*TTS_RATES.end()
+                        value=move || tts().rate
+                    />
+                    <input
+                        class="w-24 border border-gray-300 rounded px-4 py-2"
+                        type="number"
+                        name="max_length"
+                        title="max length"
+                        value=move || tts().max_length
+                    />
+                    <input
+                        class="w-24 border border-gray-300 rounded px-4 py-2"
+                        type="number"
+                        name="volume"
+                        min="0"
+                        max="1"
+                        step="0.05"
+                        value=move || tts().volume
+                    />
+                </div>
+                <div class="flex gap-2 mt-2">
+                    <input
+                        class="flex-1 border border-gray-300 rounded px-4 py-2 text-sm"
+                        type="text"
+                        name="blocked_words"
+                        placeholder="blocked words, comma separated"
+                        value=move || tts().blocked_words.join(", ")
+                    />
+                    <input
+                        class="cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded text-sm"
+                        type="submit"
+                        value="Save"
+                    />
+                </div>
+            </ActionForm>
+        </div>
+    }
+}
@@ -0,0 +1,299 @@
+use leptos::prelude::*;
+
+use super::library::MediaSelect;
+use super::update::AlertIdInput;
+pub use crate::alerts::*;
+use crate::layers::*;
+
+/// Width of the canvas preview in the editor, in css pixels.
+const PREVIEW_WIDTH: f64 = 768.0;
+
+#[derive(Clone, Copy, PartialEq)]
+enum DragMode {
+    Move,
+    Resize,
+}
+
+#[derive(Clone)]
+struct Drag {
+    layer_id: LayerId,
+    mode: DragMode,
+    start: (i32, i32),
+    origin: (i32, i32, u32, u32),
+}
+
+#[component]
+#[track_caller]
+pub fn AlertLayers() -> impl IntoView {
+    let alert: RwSignal<Alert> = use_context().unwrap();
+    let update_canvas = ServerAction::<UpdateAlertCanvas>::new();
+    let save_layer = ServerAction::<SaveAlertLayer>::new();
+    let delete_layer = ServerAction::<DeleteAlertLayer>::new();
+    let update_geometry = ServerAction::<UpdateAlertLayerGeometry>::new();
+
+    for action in [
+        update_canvas.value(),
+        save_layer.value(),
+        delete_layer.value(),
+        update_geometry.value(),
+    ] {
+        Effect::new(move || {
+            if let Some(Ok(new_alert)) = action.get() {
+                alert.update(|a| {
+                    a.canvas = new_alert.canvas;
+                    a.layers = new_alert.layers;
+                });
+            }
+        });
+    }
+
+    let drag = RwSignal::new(None::<Drag>);
+    let scale = move || PREVIEW_WIDTH / alert.with(|a| a.canvas.width.max(1) as f64);
+
+    let start_drag = move |ev: leptos::ev::PointerEvent, layer_id: LayerId, mode: DragMode| {
+        ev.prevent_default();
+        ev.stop_propagation();
+        let origin = alert.with_untracked(|a| {
+            a.layers
+                .iter()
+                .find(|l| l.layer_id == layer_id)
+                .map(|l| (l.x, l.y, l.width, l.height))
+        });
+        if let Some(origin) = origin {
+            drag.set(Some(Drag {
+                layer_id,
+                mode,
+                start: (ev.client_x(), ev.client_y()),
+                origin,
+            }));
+        }
+    };
+
+    let move_drag = move |ev: leptos::ev::PointerEvent| {
+        let Some(d) = drag.get_untracked() else {
+            return;
+        };
+        let scale = untrack(scale);
+        let dx = ((ev.client_x() - d.start.0) as f64 / scale).round() as i32;
+        let dy = ((ev.client_y() - d.start.1) as f64 / scale).round() as i32;
+        alert.update(|a| {
+            let Some(layer) = a.layers.iter_mut().find(|l| l.layer_id == d.layer_id) else {
+                return;
+            };
+            match d.mode {
+                DragMode::Move => {
+                    layer.x = d.origin.0 + dx;
+                    layer.y = d.origin.1 + dy;
+                }
+                DragMode::Resize => {
+                    layer.width = (d.origin.2 as i32 + dx).max(10) as u32;
+                    layer.height = (d.origin.3 as i32 + dy).max(10) as u32;
+                }
+            }
+        });
+    };
+
+    let end_drag = move |_: leptos::ev::PointerEvent| {
+        let Some(d) = drag.get_untracked() else {
+            return;
+        };
+        drag.set(None);
+        let Some((alert_id, layer)) = alert.with_untracked(|a| {
+            a.layers
+                .iter()
+                .find(|l| l.layer_id == d.layer_id)
+                .map(|l| (a.alert_id.clone(), l.clone()))
+        }) else {
+            return;
+        };
+        update_geometry.dispatch(UpdateAlertLayerGeometry {
+            alert_id,
+            layer_id: layer.layer_id,
+            x: layer.x,
+            y: layer.y,
+            width: layer.width,
+            height: layer.height,
+            z_index: layer.z_index,
+        });
+    };
+
+    let canvas = move || alert.with(|a| a.canvas);
+
+    view! {
+        <div class="space-y-2">
+            <h2 class="text-lg font-medium text-gray-700">"Layers"</h2>
+            <ActionForm action=update_canvas>
+                <AlertIdInput/>
+                <div class="flex items-center gap-2 text-sm">
+                    "canvas"
+                    <input
+                        class="w-24 border border-gray-300 rounded px-4 py-2"
+                        type="number"
+                        name="width"
+                        min="1"
+                        value=move || canvas().width
+                    />
+                    "x"
+                    <input
+                        class="w-24 border border-gray-300 rounded px-4 py-2"
+                        type="number"
+                        name="height"
+                        min="1"
+                        value=move || canvas().height
+                    />
+                    <input
+                        class="cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded text-sm"
+                        type="submit"
+                        value="Save"
+                    />
+                </div>
+            </ActionForm>
+            <div
+                class="relative overflow-hidden bg-gray-800 select-none touch-none"
+                style=move || {
+                    format!(
+                        "width: {PREVIEW_WIDTH}px; height: {}px",
+                        canvas().height as f64 * scale(),
+                    )
+                }
+                on:pointermove=move_drag
+                on:pointerup=end_drag
+                on:pointerleave=end_drag
+            >
+                <For
+                    each=move || alert.with(|a| a.layers.clone())
+                    key=|l| l.layer_id.clone()
+                    children=move |layer| {
+                        let id = layer.layer_id.clone();
+                        let style = move || {
+                            let scale = scale();
+                            alert
+                                .with(|a| {
+                                    a.layers
+                                        .iter()
+                                        .find(|l| l.layer_id == id)
+                                        .map(|l| {
+                                            format!(
+                                                "left: {}px; top: {}px; width: {}px; height: {}px; z-index: {}",
+                                                l.x as f64 * scale,
+                                                l.y as f64 * scale,
+                                                l.width as f64 * scale,
+                                                l.height as f64 * scale,
+                                                l.z_index,
+                                            )
+                                        })
+                                })
+                                .unwrap_or_default()
+                        };
+                        let move_id = layer.layer_id.clone();
+                        let resize_id = layer.layer_id.clone();
+                        view! {
+                            <div
+                                class="absolute cursor-move overflow-hidden border border-blue-400 bg-blue-500/30 text-xs text-white"
+                                style=style
+                                on:pointerdown=move |ev| start_drag(ev, move_id.clone(), DragMode::Move)
+                            >
+                                <span class="p-1">
+                                    {format!("{} ({})", layer.name, layer.content.kind().as_str())}
+                                </span>
+                                <div
+                                    class="absolute bottom-0 right-0 h-3 w-3 cursor-se-resize bg-blue-400"
+                                    on:pointerdown=move |ev| {
+                                        start_drag(ev, resize_id.clone(), DragMode::Resize)
+                                    }
+                                ></div>
+                            </div>
+                        }
+                    }
+                />
+            </div>
+            <ul class="space-y-1">
+                <For
+                    each=move || alert.with(|a| a.layers.clone())
+                    key=|l| format!("{l:?}")
+                    children=move |layer| {
+                        let delete_id = layer.layer_id.to_string();
+                        let geometry_id = layer.layer_id.to_string();
+                        view! {
+                            <li class="flex items-center gap-2 text-sm">
+                                <ActionForm action=delete_layer>
+                                    <AlertIdInput/>
+                                    <input type="hidden" name="layer_id" value=delete_id/>
+                                    <input
+                                        class="cursor-pointer rounded border-2 border-red-500 px-1 hover:border-red-900"
+                                        type="submit"
+                                        value="𐄂"
+                                    />
+                                </ActionForm>
+                                <span class="w-32 font-semibold">{layer.name.clone()}</span>
+                                <ActionForm action=update_geometry>
+                                    <AlertIdInput/>
+                                    <input type="hidden" name="layer_id" value=geometry_id/>
+                                    <div class="flex gap-1">
+                                        <input class="w-20 border border-gray-300 rounded px-2" type="number" name="x" title="x" value=layer.x/>
+                                        <input class="w-20 border border-gray-300 rounded px-2" type="number" name="y" title="y" value=layer.y/>
+                                        <input class="w-20 border border-gray-300 rounded px-2" type="number" name="width" title="width" min="0" value=layer.width/>
+                                        <input class="w-20 border border-gray-300 rounded px-2" type="number" name="height" title="height" min="0" value=layer.height/>
+                                        <input class="w-16 border border-gray-300 rounded px-2" type="number" name="z_index" title="z-index" value=layer.z_index/>
+                                        <input class="rounded bg-blue-500 hover:bg-blue-700 text-white px-2" type="submit" value="✓"/>
+                                    </div>
+                                </ActionForm>
+                            </li>
+                        }
+                    }
+                />
+            </ul>
+            <ActionForm action=save_layer>
+                <AlertIdInput/>
+                <div class="flex flex-col gap-2">
+                    <div class="flex gap-2">
+                        <input
+                            class="w-40 border border-gray-300 rounded px-4 py-2"
+                            type="text"
+                            name="name"
+                            placeholder="name"
+                        />
+                        <select class="border border-gray-300 rounded px-4 py-2" name="kind">
+                            {LayerKind::ALL
+                                .into_iter()
+                                .map(|kind| view! { <option value=kind.as_str()>{kind.as_str()}</option> })
+                                .collect_view()}
+                        </select>
+                        <MediaSelect allow_none=true/>
+                    </div>
+                    <div class="flex gap-2">
+                        <input
+                            class="flex-1 border border-gray-300 rounded px-4 py-2"
+                            type="text"
+                            name="value"
+                            placeholder="progress value, e.g. $donations, or timer seconds"
+                        />
+                        <input
+                            class="w-32 border border-gray-300 rounded px-4 py-2"
+                            type="text"
+                            name="max"
+                            placeholder="progress max"
+                        />
+                        <input
+                            class="w-32 border border-gray-300 rounded px-4 py-2"
+                            type="text"
+                            name="color"
+                            placeholder="bar color"
+                        />
+                    </div>
+                    <textarea
+                        class="rounded-lg border border-gray-300 bg-gray-50 p-3 text-sm"
+                        name="text"
+                        rows="3"
+                        placeholder="text layer markdown"
+                    ></textarea>
+                    <input
+                        class="self-start cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded text-sm"
+                        type="submit"
+                        value="Save layer"
+                    />
+                </div>
+            </ActionForm>
+        </div>
+    }
+}
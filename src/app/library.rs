@@ -0,0 +1,219 @@
+use leptos::prelude::*;
+
+use super::update::AlertIdInput;
+pub use crate::alerts::*;
+use crate::media::*;
+
+#[component]
+#[track_caller]
+pub fn Library() -> impl IntoView {
+    let delete = ServerAction::<DeleteMedia>::new();
+    let media = Resource::new(move || delete.version().get(), |_| list_media());
+
+    view! {
+        <div class="w-full max-w-4xl bg-white shadow rounded-xl p-8 space-y-6">
+            <h1 class="text-2xl font-semibold">"Media and assets"</h1>
+            <p class="text-sm text-gray-600">
+                "Fonts and images can be used in alert styles with "
+                <code>"asset(\"name\")"</code>
+                ", e.g. "
+                <code>"@font-face { font-family: Brand; src: asset(\"brand.woff2\"); }"</code>
+            </p>
+            <form method="post" action="/media" enctype="multipart/form-data" class="flex gap-2">
+                <input type="hidden" name="redirect" value="/library"/>
+                <input
+                    type="file"
+                    name="file"
+                    accept="audio/*,image/*,video/*,font/*,.woff,.woff2,.ttf,.otf"
+                />
+                <input
+                    type="submit"
+                    value="Upload"
+                    class="text-sm cursor-pointer bg-blue-600 px-3 py-1 rounded text-white hover:bg-blue-700"
+                />
+            </form>
+            <Suspense fallback=move || view! { <p>"loading"</p> }>
+                {move || {
+                    media
+                        .get()
+                        .map(|media| match media {
+                            Ok(media) => {
+                                view! {
+                                    <ul class="space-y-2">
+                                        {media
+                                            .into_iter()
+                                            .map(|m| {
+                                                let url = m.url();
+                                                let media_id = m.media_id.to_string();
+                                                view! {
+                                                    <li class="flex items-center gap-4 border-b border-gray-200 py-2">
+                                                        <ActionForm action=delete>
+                                                            <input type="hidden" name="media_id" value=media_id/>
+                                                            <input
+                                                                class="cursor-pointer rounded border-2 border-red-500 px-1 hover:border-red-900"
+                                                                type="submit"
+                                                                value="𐄂"
+                                                            />
+                                                        </ActionForm>
+                                                        <MediaPreview kind=m.kind() url=url.clone()/>
+                                                        <a class="text-blue-600 hover:underline" href=url>
+                                                            {m.name.clone()}
+                                                        </a>
+                                                        <span class="text-sm text-gray-600">
+                                                            {format!("{}, {} KiB", m.content_type, m.size / 1024)}
+                                                        </span>
+                                                        <code class="text-sm text-gray-600">
+                                                            {format!("asset({:?})", m.name)}
+                                                        </code>
+                                                    </li>
+                                                }
+                                            })
+                                            .collect_view()}
+                                    </ul>
+                                }
+                                    .into_any()
+                            }
+                            Err(e) => {
+                                view! { <p class="text-red-500">{format!("Error: {e}")}</p> }
+                                    .into_any()
+                            }
+                        })
+                }}
+            </Suspense>
+        </div>
+    }
+}
+
+#[component]
+pub fn MediaPreview(kind: MediaKind, url: String) -> impl IntoView {
+    match kind {
+        MediaKind::Audio => view! { <audio controls preload="none" src=url></audio> }.into_any(),
+        MediaKind::Video => {
+            view! { <video class="h-16" controls preload="metadata" src=url></video> }.into_any()
+        }
+        MediaKind::Image => view! { <img class="h-16" src=url/> }.into_any(),
+        MediaKind::Font => {
+            let css = format!("@font-face {{ font-family: {url:?}; src: url({url:?}); }}");
+            view! {
+                <style>{css}</style>
+                <span class="text-2xl" style=format!("font-family: {url:?}")>"Aa"</span>
+            }
+            .into_any()
+        }
+    }
+}
+
+/// A `media_id` select with all media in the library.
+#[component]
+pub fn MediaSelect(#[prop(optional)] allow_none: bool) -> impl IntoView {
+    let media = Resource::new(|| (), |_| list_media());
+
+    view! {
+        <select class="border border-gray-300 rounded px-4 py-2" name="media_id">
+            {allow_none.then(|| view! { <option value="">"no media"</option> })}
+            <Suspense fallback=|| ()>
+                {move || {
+                    media
+                        .get()
+                        .and_then(Result::ok)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|m| view! { <option value=m.media_id.to_string()>{m.name}</option> })
+                        .collect_view()
+                }}
+            </Suspense>
+        </select>
+    }
+}
+
+#[component]
+pub fn VolumeInput() -> impl IntoView {
+    view! {
+        <input
+            class="w-24 border border-gray-300 rounded px-4 py-2"
+            type="number"
+            name="volume"
+            min="0"
+            max="1"
+            step="0.05"
+            value="1"
+        />
+    }
+}
+
+/// Media played when the text of the alert changes.
+#[component]
+#[track_caller]
+pub fn AlertMedia() -> impl IntoView {
+    let alert: RwSignal<Alert> = use_context().unwrap();
+    let add_media = ServerAction::<AddAlertMedia>::new();
+    let remove_media = ServerAction::<RemoveAlertMedia>::new();
+    let library = Resource::new(|| (), |_| list_media());
+
+    for action in [add_media.value(), remove_media.value()] {
+        Effect::new(move || {
+            if let Some(Ok(new_alert)) = action.get() {
+                alert.update(|a| a.media = new_alert.media);
+            }
+        });
+    }
+
+    let media_name = move |media_id: &MediaId| {
+        library
+            .get()
+            .and_then(Result::ok)
+            .and_then(|l| l.into_iter().find(|m| &m.media_id == media_id))
+            .map(|m| m.name)
+            .unwrap_or_else(|| media_id.to_string())
+    };
+
+    view! {
+        <div class="space-y-2">
+            <h2 class="text-lg font-medium text-gray-700">"Media on change"</h2>
+            <ul class="space-y-1">
+                <For
+                    each=move || alert.with(|a| a.media.clone())
+                    key=|m| m.media_id.clone()
+                    children=move |m| {
+                        let media_id = m.media_id.clone();
+                        view! {
+                            <li class="flex items-center gap-2 text-sm">
+                                <ActionForm action=remove_media>
+                                    <AlertIdInput/>
+                                    <input type="hidden" name="media_id" value=m.media_id.to_string()/>
+                                    <input
+                                        class="cursor-pointer rounded border-2 border-red-500 px-1 hover:border-red-900"
+                                        type="submit"
+                                        value="𐄂"
+                                    />
+                                </ActionForm>
+                                <Suspense fallback=|| ()>
+                                    {
+                                        let media_id = media_id.clone();
+                                        move || media_name(&media_id)
+                                    }
+                                </Suspense>
+                                <span class="text-gray-600">{format!("volume {}", m.volume)}</span>
+                            </li>
+                        }
+                    }
+                />
+            </ul>
+            <ActionForm action=add_media>
+                <AlertIdInput/>
+                <div class="flex gap-2">
+                    <MediaSelect/>
+                    <VolumeInput/>
+                    <input
+                        class="cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded text-sm"
+                        type="submit"
+                        value="Add media"
+                    />
+                </div>
+            </ActionForm>
+            <a class="text-sm text-blue-600 hover:underline" href="/library">
+                "Media library"
+            </a>
+        </div>
+    }
+}
@@ -1,7 +1,9 @@
 use leptos::prelude::*;
 use leptos_router::components::A;
 
+use super::update::AlertIdInput;
 pub use crate::alerts::*;
+use crate::templates::*;
 
 #[component()]
 #[track_caller]
@@ -14,6 +16,7 @@ pub fn NewAlert() -> impl IntoView {
         <ActionForm action=new_alert>
             <input type="text" name="name" placeholder="Name"/>
             <input type="submit" value="Submit"/>
+            <TemplateGallery/>
         </ActionForm>
         <Show when=move || {new_alert.value().get().is_some()} fallback=|| view!{_ ""}>
             {move || {
@@ -44,17 +47,138 @@ pub fn NewAlert() -> impl IntoView {
     }
 }
 
+/// Template choices for a new alert, as radio buttons named `template`.
+#[component]
+#[track_caller]
+pub fn TemplateGallery() -> impl IntoView {
+    let delete = ServerAction::<DeleteAlertTemplate>::new();
+    let templates = Resource::new(move || delete.version().get(), |_| list_alert_templates());
+
+    view! {
+        <div class="grid grid-cols-2 gap-2 py-2">
+            <label class="flex gap-2 rounded border border-gray-300 p-2 text-sm">
+                <input type="radio" name="template" value="" checked=true/>
+                <div>
+                    <p class="font-medium">"Empty"</p>
+                    <p class="text-gray-600">"Start from scratch."</p>
+                </div>
+            </label>
+            <Suspense fallback=|| ()>
+                {move || {
+                    templates
+                        .get()
+                        .and_then(Result::ok)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|template| {
+                            let name = template.name.to_string();
+                            let delete_name = template.name.clone();
+                            view! {
+                                <label class="flex gap-2 rounded border border-gray-300 p-2 text-sm">
+                                    <input type="radio" name="template" value=name.clone()/>
+                                    <div class="flex-1">
+                                        <p class="font-medium">{name}</p>
+                                        <p class="text-gray-600">{template.description}</p>
+                                        <pre class="text-xs text-gray-500 whitespace-pre-wrap">
+                                            {template.text.to_string()}
+                                        </pre>
+                                    </div>
+                                    <Show when=move || !template.builtin>
+                                        <button
+                                            type="button"
+                                            class="self-start rounded border-2 border-red-500 px-1 hover:border-red-900"
+                                            on:click={
+                                                let name = delete_name.clone();
+                                                move |_| {
+                                                    delete.dispatch(DeleteAlertTemplate { name: name.clone() });
+                                                }
+                                            }
+                                        >
+                                            "𐄂"
+                                        </button>
+                                    </Show>
+                                </label>
+                            }
+                        })
+                        .collect_view()
+                }}
+            </Suspense>
+        </div>
+    }
+}
+
+/// Save the current alert as a template for new alerts.
+#[component]
+#[track_caller]
+pub fn AlertSaveTemplate() -> impl IntoView {
+    let save = ServerAction::<SaveAlertTemplate>::new();
+
+    view! {
+        <div class="space-y-2">
+            <h2 class="text-lg font-medium text-gray-700">"Save as template"</h2>
+            <ActionForm action=save>
+                <AlertIdInput/>
+                <div class="flex items-center gap-2 text-sm">
+                    <input
+                        class="w-48 border border-gray-300 rounded px-4 py-2"
+                        type="text"
+                        name="name"
+                        placeholder="template name"
+                    />
+                    <input
+                        class="flex-1 border border-gray-300 rounded px-4 py-2"
+                        type="text"
+                        name="description"
+                        placeholder="description"
+                    />
+                    <input
+                        class="cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded text-sm"
+                        type="submit"
+                        value="Save"
+                    />
+                </div>
+                <p class="text-sm">
+                    {move || match save.value().get() {
+                        Some(Ok(())) => view! { <span class="text-green-600">"Saved"</span> }.into_any(),
+                        Some(Err(e)) => view! { <span class="text-red-500">{e.to_string()}</span> }.into_any(),
+                        None => ().into_any(),
+                    }}
+                </p>
+            </ActionForm>
+        </div>
+    }
+}
+
+/// Create an alert, from the template named `template` if it isn't empty.
 #[server(NewAlert, "/backend")]
 #[tracing::instrument(err)]
-pub async fn new_alert(name: String) -> Result<AlertId, ServerFnError> {
+pub async fn new_alert(name: String, template: Option<String>) -> Result<AlertId, ServerFnError> {
+    crate::auth::require_user().await?;
     let Some(manager): Option<AlertManager> = use_context() else {
         return Err(ServerFnError::ServerError("Missing manager".to_owned()));
     };
 
     let id = AlertId::new_id();
-    manager
-        .new_alert(Alert::new(id.clone(), AlertText::from(""), name.into()))
-        .await?;
+    let alert = match template.as_deref().map(str::trim) {
+        None | Some("") => Alert::new(id.clone(), AlertText::from(""), name.into()),
+        Some(template) => {
+            let template = manager
+                .templates
+                .get(TemplateNameRef::from_str(template))
+                .await
+                .ok_or_else(|| {
+                    ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(
+                        "No such template".to_owned(),
+                    )
+                })?;
+            let mut alert = template.to_alert(id.clone(), name.into());
+            if let Some(theme) = &template.theme {
+                alert.theme = manager.themes.get(theme).await;
+            }
+            alert
+        }
+    };
+    manager.new_alert(alert).await?;
 
     Ok(id)
 }
@@ -0,0 +1,175 @@
+use leptos::prelude::*;
+
+use super::actions::{action_summary, AlertActionInputs};
+use super::update::AlertIdInput;
+pub use crate::alerts::*;
+use crate::obs::*;
+
+#[component]
+#[track_caller]
+pub fn Obs() -> impl IntoView {
+    let save = ServerAction::<SaveObsMapping>::new();
+    let delete = ServerAction::<DeleteObsMapping>::new();
+    let obs = Resource::new(
+        move || (save.version().get(), delete.version().get()),
+        |_| read_obs(),
+    );
+
+    let error = move || {
+        [
+            save.value().get().and_then(Result::err),
+            delete.value().get().and_then(Result::err),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+    };
+
+    view! {
+        <div class="w-full max-w-4xl bg-white shadow rounded-xl p-8 space-y-6">
+            <h1 class="text-2xl font-semibold">"OBS"</h1>
+            <p class="text-sm text-gray-600">
+                "Run actions on OBS events, the event data is available as variables, e.g. "
+                <code>"StreamStateChanged"</code>
+                " with "
+                <code>"outputActive == true"</code>
+                " when the stream starts. Alerts can switch scenes, show or hide sources and refresh browser sources with the OBS actions."
+            </p>
+            <pre class="text-sm text-red-500">{error}</pre>
+            <Suspense fallback=move || view! { <p>"loading"</p> }>
+                {move || {
+                    obs.get()
+                        .map(|obs| match obs {
+                            Ok(obs) => {
+                                let configured = obs.configured;
+                                view! {
+                                    <Show when=move || !configured>
+                                        <p class="text-sm text-red-500">
+                                            "Set --obs-websocket-url to connect to OBS."
+                                        </p>
+                                    </Show>
+                                    <p class="text-sm text-gray-600">
+                                        {format!("Status: {}", obs.status)}
+                                    </p>
+                                    <Show when={
+                                        let empty = obs.scenes.is_empty();
+                                        move || !empty
+                                    }>
+                                        <p class="text-sm text-gray-600">
+                                            {format!("Scenes: {}", obs.scenes.join(", "))}
+                                        </p>
+                                    </Show>
+                                    <ul class="space-y-1">
+                                        {obs
+                                            .mappings
+                                            .into_iter()
+                                            .map(|mapping| {
+                                                let conditions = mapping
+                                                    .conditions
+                                                    .iter()
+                                                    .map(ToString::to_string)
+                                                    .collect::<Vec<_>>()
+                                                    .join(", ");
+                                                view! {
+                                                    <li class="flex items-center gap-2 text-sm">
+                                                        <ActionForm action=delete>
+                                                            <input type="hidden" name="mapping_id" value=mapping.mapping_id.to_string()/>
+                                                            <input
+                                                                class="cursor-pointer rounded border-2 border-red-500 px-1 hover:border-red-900"
+                                                                type="submit"
+                                                                value="𐄂"
+                                                            />
+                                                        </ActionForm>
+                                                        <span class="font-semibold">{mapping.event_type.clone()}</span>
+                                                        <span class="text-gray-600">{conditions}</span>
+                                                        <span>{action_summary(&mapping.action)}</span>
+                                                    </li>
+                                                }
+                                            })
+                                            .collect_view()}
+                                    </ul>
+                                }
+                                    .into_any()
+                            }
+                            Err(e) => {
+                                view! { <p class="text-red-500">{format!("Error: {e}")}</p> }
+                                    .into_any()
+                            }
+                        })
+                }}
+            </Suspense>
+            <datalist id="obs-events">
+                {COMMON_EVENTS
+                    .iter()
+                    .map(|event| view! { <option value=*event></option> })
+                    .collect_view()}
+            </datalist>
+            <ActionForm action=save>
+                <div class="flex flex-col gap-2 text-sm">
+                    <div class="flex gap-2">
+                        <input
+                            class="w-64 border border-gray-300 rounded px-2 py-1"
+                            type="text"
+                            name="event_type"
+                            list="obs-events"
+                            placeholder="event, e.g. StreamStateChanged"
+                        />
+                        <input
+                            class="flex-1 border border-gray-300 rounded px-2 py-1"
+                            type="text"
+                            name="conditions"
+                            placeholder="conditions, e.g. outputActive == true"
+                        />
+                    </div>
+                    <AlertActionInputs/>
+                    <input
+                        class="self-start cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded"
+                        type="submit"
+                        value="Add mapping"
+                    />
+                </div>
+            </ActionForm>
+        </div>
+    }
+}
+
+/// The OBS browser source of an alert, refreshed by "Refresh Connected".
+#[component]
+#[track_caller]
+pub fn AlertObsSource() -> impl IntoView {
+    let alert: RwSignal<Alert> = use_context().unwrap();
+    let update = ServerAction::<UpdateAlertObsSource>::new();
+    Effect::new(move || {
+        if let Some(Ok(new_alert)) = update.value().get() {
+            alert.update(|a| a.obs_source = new_alert.obs_source);
+        }
+    });
+
+    view! {
+        <div class="space-y-2">
+            <h2 class="text-lg font-medium text-gray-700">"OBS"</h2>
+            <ActionForm action=update>
+                <AlertIdInput/>
+                <div class="flex gap-2 text-sm">
+                    <input
+                        class="w-64 border border-gray-300 rounded px-2 py-1"
+                        type="text"
+                        name="source"
+                        placeholder="browser source name"
+                        value=move || alert.with(|a| a.obs_source.clone().unwrap_or_default())
+                    />
+                    <input
+                        class="cursor-pointer rounded bg-blue-500 hover:bg-blue-700 text-white px-2"
+                        type="submit"
+                        value="✓"
+                    />
+                </div>
+            </ActionForm>
+            <p class="text-sm text-red-500">
+                {move || update.value().get().and_then(Result::err).map(|e| e.to_string())}
+            </p>
+        </div>
+    }
+}
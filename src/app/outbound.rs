@@ -0,0 +1,260 @@
+use leptos::prelude::*;
+
+use super::update::AlertIdInput;
+pub use crate::alerts::*;
+use crate::outbound::*;
+
+#[component]
+#[track_caller]
+pub fn AlertOutboundWebhooks() -> impl IntoView {
+    let alert: RwSignal<Alert> = use_context().unwrap();
+    let save = ServerAction::<SaveOutboundWebhook>::new();
+    let delete = ServerAction::<DeleteOutboundWebhook>::new();
+    let test = ServerAction::<TestOutboundWebhook>::new();
+    let webhooks = Resource::new(
+        move || {
+            (
+                alert.with(|a| a.alert_id.clone()),
+                save.version().get(),
+                delete.version().get(),
+                test.version().get(),
+            )
+        },
+        move |(id, ..)| async move { list_outbound_webhooks(id).await },
+    );
+    let error = move || {
+        [
+            save.value().get().and_then(Result::err),
+            delete.value().get().and_then(Result::err),
+            test.value().get().and_then(Result::err),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+    };
+
+    view! {
+        <div class="space-y-2">
+            <h2 class="text-lg font-medium text-gray-700">"Outbound webhooks"</h2>
+            <p class="text-sm text-gray-600">
+                "Post to a url when a field changes. The body is a JSON template with "
+                <code>"$alert_id"</code>
+                ", "
+                <code>"$alert_name"</code>
+                ", "
+                <code>"$field"</code>
+                ", "
+                <code>"$value"</code>
+                " and "
+                <code>"$previous"</code>
+                ", with a secret the body is signed in the "
+                <code>{SIGNATURE_HEADER}</code>
+                " header."
+            </p>
+            <pre class="text-sm text-red-500">{error}</pre>
+            <Suspense fallback=|| ()>
+                {move || {
+                    webhooks
+                        .get()
+                        .map(|webhooks| match webhooks {
+                            Ok(webhooks) => {
+                                webhooks
+                                    .into_iter()
+                                    .map(|listed| {
+                                        view! {
+                                            <OutboundWebhookSettings
+                                                listed
+                                                save
+                                                delete
+                                                test
+                                            />
+                                        }
+                                    })
+                                    .collect_view()
+                                    .into_any()
+                            }
+                            Err(e) => {
+                                view! { <p class="text-red-500">{format!("Error: {e}")}</p> }
+                                    .into_any()
+                            }
+                        })
+                }}
+            </Suspense>
+            <OutboundWebhookForm save webhook=None has_secret=false/>
+        </div>
+    }
+}
+
+#[component]
+#[track_caller]
+fn OutboundWebhookSettings(
+    listed: ListedOutboundWebhook,
+    save: ServerAction<SaveOutboundWebhook>,
+    delete: ServerAction<DeleteOutboundWebhook>,
+    test: ServerAction<TestOutboundWebhook>,
+) -> impl IntoView {
+    let ListedOutboundWebhook {
+        webhook,
+        has_secret,
+        deliveries,
+    } = listed;
+    let delete_id = webhook.webhook_id.to_string();
+    let test_id = webhook.webhook_id.to_string();
+    let deliveries = deliveries
+        .into_iter()
+        .map(|delivery| {
+            let status = match (delivery.status, &delivery.error) {
+                (Some(status), None) => format!("{status}"),
+                (Some(status), Some(error)) => format!("{status}: {error}"),
+                (None, Some(error)) => error.clone(),
+                (None, None) => String::new(),
+            };
+            view! {
+                <tr class:text-red-500=delivery.error.is_some()>
+                    <td class="pr-2">{delivery.at}</td>
+                    <td class="pr-2">{delivery.trigger}</td>
+                    <td class="pr-2">{delivery.attempts}</td>
+                    <td>{status}</td>
+                </tr>
+            }
+        })
+        .collect_view();
+
+    view! {
+        <div class="space-y-2 border-t border-gray-200 pt-2 text-sm">
+            <div class="flex items-center gap-2">
+                <ActionForm action=delete>
+                    <input type="hidden" name="webhook_id" value=delete_id/>
+                    <input
+                        class="cursor-pointer rounded border-2 border-red-500 px-1 hover:border-red-900"
+                        type="submit"
+                        value="𐄂"
+                    />
+                </ActionForm>
+                <ActionForm action=test>
+                    <input type="hidden" name="webhook_id" value=test_id/>
+                    <input
+                        class="cursor-pointer rounded bg-blue-500 hover:bg-blue-700 text-white px-2"
+                        type="submit"
+                        value="Test"
+                    />
+                </ActionForm>
+                <span class="font-semibold">{webhook.name.clone()}</span>
+                <code class="text-gray-600">{webhook.url.clone()}</code>
+            </div>
+            <OutboundWebhookForm save webhook=Some(webhook) has_secret/>
+            <table class="text-xs">
+                <tbody>{deliveries}</tbody>
+            </table>
+        </div>
+    }
+}
+
+/// Form creating a webhook, or editing `webhook`.
+///
+/// The secret isn't sent to the browser, leaving it empty keeps the current one.
+#[component]
+#[track_caller]
+fn OutboundWebhookForm(
+    save: ServerAction<SaveOutboundWebhook>,
+    webhook: Option<OutboundWebhook>,
+    has_secret: bool,
+) -> impl IntoView {
+    let alert: RwSignal<Alert> = use_context().unwrap();
+    let is_new = webhook.is_none();
+    let webhook_id = webhook
+        .as_ref()
+        .map(|w| w.webhook_id.to_string())
+        .unwrap_or_default();
+    let watched = webhook.as_ref().and_then(|w| w.field.clone());
+    let name = webhook.as_ref().map(|w| w.name.clone()).unwrap_or_default();
+    let url = webhook.as_ref().map(|w| w.url.clone()).unwrap_or_default();
+    let conditions = webhook
+        .as_ref()
+        .map(|w| {
+            w.conditions
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .unwrap_or_default();
+    let body = webhook.as_ref().map(|w| w.body.clone()).unwrap_or_default();
+    let fields = move || {
+        let watched = watched.clone();
+        alert.with(|a| {
+            a.fields
+                .iter()
+                .map(|(_, (name, _))| {
+                    let selected = watched.as_ref() == Some(name);
+                    view! { <option value=name.to_string() selected=selected>{name.to_string()}</option> }
+                })
+                .collect_view()
+        })
+    };
+
+    view! {
+        <ActionForm action=save>
+            <AlertIdInput/>
+            <input type="hidden" name="webhook_id" value=webhook_id/>
+            <div class="flex flex-col gap-2">
+                <div class="flex gap-2">
+                    <input
+                        class="w-32 border border-gray-300 rounded px-2 py-1"
+                        type="text"
+                        name="name"
+                        placeholder="name"
+                        value=name
+                    />
+                    <input
+                        class="flex-1 border border-gray-300 rounded px-2 py-1"
+                        type="url"
+                        name="url"
+                        placeholder="https://example.com/hook"
+                        value=url
+                    />
+                    <select class="border border-gray-300 rounded px-2" name="field">
+                        <option value="">"Any field"</option>
+                        {fields}
+                    </select>
+                </div>
+                <div class="flex gap-2">
+                    <input
+                        class="flex-1 border border-gray-300 rounded px-2 py-1"
+                        type="text"
+                        name="conditions"
+                        placeholder="conditions, e.g. value >= 100, previous < 100"
+                        value=conditions
+                    />
+                    <input
+                        class="w-48 border border-gray-300 rounded px-2 py-1"
+                        type="password"
+                        name="secret"
+                        placeholder=if has_secret { "secret (unchanged)" } else { "secret" }
+                    />
+                    <Show when=move || has_secret>
+                        <label class="flex items-center gap-1">
+                            <input type="checkbox" name="clear_secret" value="on"/>
+                            "remove secret"
+                        </label>
+                    </Show>
+                </div>
+                <textarea
+                    class="rounded-lg border border-gray-300 bg-gray-50 p-2 font-mono"
+                    name="body"
+                    rows="2"
+                    placeholder="{\"text\": \"$field is now $value\"}, empty for all variables"
+                >
+                    {body}
+                </textarea>
+                <input
+                    class="self-start cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded"
+                    type="submit"
+                    value={if is_new { "Add webhook" } else { "Save" }}
+                />
+            </div>
+        </ActionForm>
+    }
+}
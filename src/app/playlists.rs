@@ -0,0 +1,173 @@
+use leptos::prelude::*;
+
+use super::update::AlertIdInput;
+pub use crate::alerts::*;
+use crate::playlists::*;
+
+#[component]
+#[track_caller]
+pub fn AlertPlaylist() -> impl IntoView {
+    let alert: RwSignal<Alert> = use_context().unwrap();
+    let update = ServerAction::<UpdateAlertPlaylist>::new();
+    let save_item = ServerAction::<SavePlaylistItem>::new();
+    let remove_item = ServerAction::<RemovePlaylistItem>::new();
+
+    for action in [update.value(), save_item.value(), remove_item.value()] {
+        Effect::new(move || {
+            if let Some(Ok(new_alert)) = action.get() {
+                alert.update(|a| {
+                    a.playlist = new_alert.playlist;
+                    a.last_text = new_alert.last_text;
+                });
+            }
+        });
+    }
+    let error = move || {
+        [
+            update.value().get().and_then(Result::err),
+            save_item.value().get().and_then(Result::err),
+            remove_item.value().get().and_then(Result::err),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+    };
+    let playlist = move || alert.with(|a| a.playlist.clone());
+
+    let items = move || {
+        let playlist = playlist();
+        let running = playlist.is_running();
+        let position = playlist.position;
+        playlist
+            .items
+            .into_iter()
+            .enumerate()
+            .map(|(index, item)| {
+                let current = running && index == position;
+                view! {
+                    <li class="flex items-start gap-2">
+                        <ActionForm action=remove_item>
+                            <AlertIdInput/>
+                            <input type="hidden" name="index" value=index/>
+                            <input
+                                class="cursor-pointer rounded border-2 border-red-500 px-1 hover:border-red-900"
+                                type="submit"
+                                value="𐄂"
+                            />
+                        </ActionForm>
+                        <div class="flex-1">
+                            <ActionForm action=save_item>
+                                <AlertIdInput/>
+                                <input type="hidden" name="index" value=index/>
+                                <div class="flex gap-2">
+                                    <textarea
+                                        class="flex-1 rounded border border-gray-300 px-2 py-1"
+                                        class:border-blue-500=current
+                                        name="text"
+                                        rows="2"
+                                    >
+                                        {item.text}
+                                    </textarea>
+                                    <input
+                                        class="w-16 border border-gray-300 rounded px-2 py-1"
+                                        type="number"
+                                        name="weight"
+                                        min="1"
+                                        max=MAX_WEIGHT
+                                        title="weight"
+                                        value=item.weight
+                                    />
+                                    <input
+                                        class="self-start cursor-pointer rounded bg-blue-500 hover:bg-blue-700 text-white px-2"
+                                        type="submit"
+                                        value="✓"
+                                    />
+                                </div>
+                            </ActionForm>
+                        </div>
+                    </li>
+                }
+            })
+            .collect_view()
+    };
+
+    view! {
+        <div class="space-y-2">
+            <h2 class="text-lg font-medium text-gray-700">"Playlist"</h2>
+            <p class="text-sm text-gray-600">
+                "Cycle the text through these messages. An item with weight 2 is shown twice as long, or picked twice as often when shuffling."
+            </p>
+            <pre class="text-sm text-red-500">{error}</pre>
+            <ActionForm action=update>
+                <AlertIdInput/>
+                <div class="flex gap-2 text-sm">
+                    <select class="border border-gray-300 rounded px-2" name="enabled">
+                        <option value="true" selected=move || playlist().enabled>
+                            "Running"
+                        </option>
+                        <option value="false" selected=move || !playlist().enabled>
+                            "Stopped"
+                        </option>
+                    </select>
+                    <select class="border border-gray-300 rounded px-2" name="order">
+                        <option
+                            value="sequence"
+                            selected=move || playlist().order == PlaylistOrder::Sequence
+                        >
+                            "In order"
+                        </option>
+                        <option
+                            value="shuffle"
+                            selected=move || playlist().order == PlaylistOrder::Shuffle
+                        >
+                            "Shuffle"
+                        </option>
+                    </select>
+                    <input
+                        class="w-24 border border-gray-300 rounded px-2 py-1"
+                        type="number"
+                        name="interval_secs"
+                        min=MIN_INTERVAL_SECS
+                        title="seconds per message"
+                        value=move || playlist().interval_secs
+                    />
+                    <span class="self-center text-gray-600">"seconds"</span>
+                    <input
+                        class="cursor-pointer rounded bg-blue-500 hover:bg-blue-700 text-white px-2"
+                        type="submit"
+                        value="✓"
+                    />
+                </div>
+            </ActionForm>
+            <ul class="space-y-1 text-sm">{items}</ul>
+            <ActionForm action=save_item>
+                <AlertIdInput/>
+                <input type="hidden" name="index" value=""/>
+                <div class="flex gap-2 text-sm">
+                    <textarea
+                        class="flex-1 rounded border border-gray-300 px-2 py-1"
+                        name="text"
+                        rows="2"
+                        placeholder="**Sponsor** of the day, or $field"
+                    ></textarea>
+                    <input
+                        class="w-16 border border-gray-300 rounded px-2 py-1"
+                        type="number"
+                        name="weight"
+                        min="1"
+                        max=MAX_WEIGHT
+                        title="weight"
+                        value="1"
+                    />
+                    <input
+                        class="self-start cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded"
+                        type="submit"
+                        value="Add message"
+                    />
+                </div>
+            </ActionForm>
+        </div>
+    }
+}
@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use leptos::prelude::*;
+
+pub use crate::alerts::*;
+
+/// Wait for typing to pause before rendering.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Unsaved text and style rendered like the overlay, with the current fields and layers.
+#[component]
+#[track_caller]
+pub fn AlertPreview(
+    #[prop(into)] text: Signal<String>,
+    #[prop(into)] style: Signal<String>,
+) -> impl IntoView {
+    let alert: RwSignal<Alert> = use_context().unwrap();
+    let debounced = RwSignal::new((text.get_untracked(), style.get_untracked()));
+    let pending = StoredValue::new(None::<TimeoutHandle>);
+
+    Effect::new(move || {
+        let draft = (text.get(), style.get());
+        if let Some(handle) = pending.get_value() {
+            handle.clear();
+        }
+        pending.set_value(set_timeout_with_handle(move || debounced.set(draft), DEBOUNCE).ok());
+    });
+    on_cleanup(move || {
+        if let Some(Some(handle)) = pending.try_get_value() {
+            handle.clear();
+        }
+    });
+
+    let preview = Memo::new(move |_| {
+        let (text, style) = debounced.get();
+        alert.with(|a| {
+            let mut errors = a.template_errors(&text);
+            errors.extend(a.template_errors(&style));
+            if let Err(css_errors) = crate::css::validate(&style) {
+                errors.extend(css_errors.iter().map(|e| format!("style {e}")));
+            }
+            let html = a.to_html(&a.render_template(&AlertText::from(text), &[]));
+            let srcdoc = format!(
+                r#"<!DOCTYPE html><html><head><link rel="stylesheet" href="/static/alert-style.css"/><style>{style}
+{layers_style}</style></head><body><div class="alert-canvas" style="width: {width}px; height: {height}px">{layers}</div><div class="alert-text">{html}</div></body></html>"#,
+                style = a.render_style_template(
+                    &match &a.theme {
+                        Some(theme) => format!("{}\n{style}", theme.style),
+                        None => style,
+                    },
+                    &[]
+                ),
+                layers_style = a.layers_style(),
+                width = a.canvas.width,
+                height = a.canvas.height,
+                layers = a.render_layers_html(),
+            );
+            (srcdoc, errors)
+        })
+    });
+
+    view! {
+        <div class="space-y-2">
+            <h2 class="text-lg font-medium text-gray-700">"Preview"</h2>
+            // no scripts in the preview, the overlay runtime isn't needed to show the render
+            <iframe
+                class="w-full h-96 rounded-lg bg-gray-800"
+                sandbox=""
+                srcdoc=move || preview.with(|(srcdoc, _)| srcdoc.clone())
+            ></iframe>
+            <ul class="text-sm text-red-500">
+                {move || {
+                    preview
+                        .with(|(_, errors)| {
+                            errors
+                                .iter()
+                                .map(|error| view! { <li>{error.clone()}</li> })
+                                .collect_view()
+                        })
+                }}
+            </ul>
+        </div>
+    }
+}
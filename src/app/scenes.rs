@@ -0,0 +1,203 @@
+use leptos::prelude::*;
+
+pub use crate::alerts::*;
+use crate::scenes::*;
+
+#[component]
+#[track_caller]
+pub fn Scenes() -> impl IntoView {
+    let create = ServerAction::<CreateScene>::new();
+    let update = ServerAction::<UpdateScene>::new();
+    let save_item = ServerAction::<SaveSceneItem>::new();
+    let remove_item = ServerAction::<RemoveSceneItem>::new();
+    let delete = ServerAction::<DeleteScene>::new();
+    let scenes = Resource::new(
+        move || {
+            (
+                create.version().get(),
+                update.version().get(),
+                save_item.version().get(),
+                remove_item.version().get(),
+                delete.version().get(),
+            )
+        },
+        |_| list_scenes(),
+    );
+    let alerts = Resource::new(|| (), |_| read_all_alerts());
+    let alert_name = move |alert_id: &AlertId| {
+        alerts
+            .get()
+            .and_then(Result::ok)
+            .and_then(|alerts| {
+                alerts
+                    .into_iter()
+                    .find(|(id, _)| id == alert_id)
+                    .map(|(_, a)| a.name.to_string())
+            })
+            .unwrap_or_else(|| alert_id.to_string())
+    };
+
+    let error = move || {
+        [
+            update.value().get().and_then(Result::err),
+            save_item.value().get().and_then(Result::err),
+            remove_item.value().get().and_then(Result::err),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+    };
+
+    view! {
+        <div class="w-full max-w-4xl bg-white shadow rounded-xl p-8 space-y-6">
+            <h1 class="text-2xl font-semibold">"Scenes"</h1>
+            <p class="text-sm text-gray-600">
+                "A scene shows several alerts on one page, use it as a single browser source."
+            </p>
+            <ActionForm action=create>
+                <div class="flex gap-2">
+                    <input
+                        class="w-64 border border-gray-300 rounded px-4 py-2"
+                        type="text"
+                        name="name"
+                        placeholder="name"
+                    />
+                    <input
+                        class="cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded text-sm"
+                        type="submit"
+                        value="New scene"
+                    />
+                </div>
+            </ActionForm>
+            <pre class="text-sm text-red-500">{error}</pre>
+            <Suspense fallback=move || view! { <p>"loading"</p> }>
+                {move || {
+                    scenes
+                        .get()
+                        .map(|scenes| match scenes {
+                            Ok(scenes) => {
+                                scenes
+                                    .into_iter()
+                                    .map(|scene| {
+                                        let scene_id = scene.scene_id.to_string();
+                                        let items = scene
+                                            .items
+                                            .iter()
+                                            .cloned()
+                                            .map(|item| {
+                                                let name = alert_name(&item.alert_id);
+                                                let alert_id = item.alert_id.to_string();
+                                                let (remove_scene_id, save_scene_id) = (scene_id.clone(), scene_id.clone());
+                                                let remove_alert_id = alert_id.clone();
+                                                view! {
+                                                    <li class="flex items-center gap-2 text-sm">
+                                                        <ActionForm action=remove_item>
+                                                            <input type="hidden" name="scene_id" value=remove_scene_id/>
+                                                            <input type="hidden" name="alert_id" value=remove_alert_id/>
+                                                            <input
+                                                                class="cursor-pointer rounded border-2 border-red-500 px-1 hover:border-red-900"
+                                                                type="submit"
+                                                                value="𐄂"
+                                                            />
+                                                        </ActionForm>
+                                                        <span class="w-40 font-semibold truncate">
+                                                            {name}
+                                                        </span>
+                                                        <ActionForm action=save_item>
+                                                            <input type="hidden" name="scene_id" value=save_scene_id/>
+                                                            <input type="hidden" name="alert_id" value=alert_id/>
+                                                            <SceneItemGeometry item=Some(item)/>
+                                                        </ActionForm>
+                                                    </li>
+                                                }
+                                            })
+                                            .collect_view();
+                                        let href = format!("/scene/{scene_id}?preview");
+                                        let (delete_id, update_id) = (scene_id.clone(), scene_id.clone());
+                                        view! {
+                                            <div class="space-y-2 border-t border-gray-200 pt-4">
+                                                <div class="flex items-center gap-2">
+                                                    <ActionForm action=delete>
+                                                        <input type="hidden" name="scene_id" value=delete_id/>
+                                                        <input
+                                                            class="cursor-pointer rounded border-2 border-red-500 px-1 hover:border-red-900"
+                                                            type="submit"
+                                                            value="𐄂"
+                                                        />
+                                                    </ActionForm>
+                                                    <ActionForm action=update>
+                                                        <input type="hidden" name="scene_id" value=update_id/>
+                                                        <div class="flex gap-1 text-sm">
+                                                            <input class="w-48 border border-gray-300 rounded px-2" type="text" name="name" value=scene.name.clone()/>
+                                                            <input class="w-20 border border-gray-300 rounded px-2" type="number" name="width" title="width" min="1" value=scene.canvas.width/>
+                                                            <input class="w-20 border border-gray-300 rounded px-2" type="number" name="height" title="height" min="1" value=scene.canvas.height/>
+                                                            <input class="rounded bg-blue-500 hover:bg-blue-700 text-white px-2" type="submit" value="✓"/>
+                                                        </div>
+                                                    </ActionForm>
+                                                    <a
+                                                        class="text-blue-600 hover:underline text-sm"
+                                                        href=href
+                                                    >
+                                                        "View"
+                                                    </a>
+                                                </div>
+                                                <ul class="space-y-1">{items}</ul>
+                                                <ActionForm action=save_item>
+                                                    <input type="hidden" name="scene_id" value=scene_id/>
+                                                    <div class="flex gap-1 text-sm">
+                                                        <select class="border border-gray-300 rounded px-2" name="alert_id">
+                                                            {move || {
+                                                                alerts
+                                                                    .get()
+                                                                    .and_then(Result::ok)
+                                                                    .unwrap_or_default()
+                                                                    .into_iter()
+                                                                    .map(|(id, alert)| {
+                                                                        view! {
+                                                                            <option value=id.to_string()>{alert.name.to_string()}</option>
+                                                                        }
+                                                                    })
+                                                                    .collect_view()
+                                                            }}
+                                                        </select>
+                                                        <SceneItemGeometry item=None/>
+                                                    </div>
+                                                </ActionForm>
+                                            </div>
+                                        }
+                                    })
+                                    .collect_view()
+                                    .into_any()
+                            }
+                            Err(e) => {
+                                view! { <p class="text-red-500">{format!("Error: {e}")}</p> }
+                                    .into_any()
+                            }
+                        })
+                }}
+            </Suspense>
+        </div>
+    }
+}
+
+/// Position inputs for a [`SceneItem`], with a submit button.
+#[component]
+#[track_caller]
+fn SceneItemGeometry(item: Option<SceneItem>) -> impl IntoView {
+    let (x, y, width, height, z_index) = match item {
+        Some(item) => (item.x, item.y, item.width, item.height, item.z_index),
+        None => (0, 0, 1920, 1080, 0),
+    };
+    view! {
+        <div class="flex gap-1">
+            <input class="w-20 border border-gray-300 rounded px-2" type="number" name="x" title="x" value=x/>
+            <input class="w-20 border border-gray-300 rounded px-2" type="number" name="y" title="y" value=y/>
+            <input class="w-20 border border-gray-300 rounded px-2" type="number" name="width" title="width" min="0" value=width/>
+            <input class="w-20 border border-gray-300 rounded px-2" type="number" name="height" title="height" min="0" value=height/>
+            <input class="w-16 border border-gray-300 rounded px-2" type="number" name="z_index" title="z-index" value=z_index/>
+            <input class="rounded bg-blue-500 hover:bg-blue-700 text-white px-2" type="submit" value="✓"/>
+        </div>
+    }
+}
@@ -0,0 +1,266 @@
+use leptos::prelude::*;
+
+use super::actions::{action_summary, AlertActionInputs};
+pub use crate::alerts::*;
+use crate::schedules::*;
+
+#[component]
+#[track_caller]
+pub fn Schedules() -> impl IntoView {
+    let save = ServerAction::<SaveSchedule>::new();
+    let delete = ServerAction::<DeleteSchedule>::new();
+    let add_action = ServerAction::<AddScheduleAction>::new();
+    let remove_action = ServerAction::<RemoveScheduleAction>::new();
+    let run = ServerAction::<RunSchedule>::new();
+    let schedules = Resource::new(
+        move || {
+            (
+                save.version().get(),
+                delete.version().get(),
+                add_action.version().get(),
+                remove_action.version().get(),
+                run.version().get(),
+            )
+        },
+        |_| list_schedules(),
+    );
+
+    let error = move || {
+        [
+            save.value().get().and_then(Result::err),
+            delete.value().get().and_then(Result::err),
+            add_action.value().get().and_then(Result::err),
+            remove_action.value().get().and_then(Result::err),
+            run.value().get().and_then(Result::err),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+    };
+
+    view! {
+        <div class="w-full max-w-4xl bg-white shadow rounded-xl p-8 space-y-6">
+            <h1 class="text-2xl font-semibold">"Schedules"</h1>
+            <p class="text-sm text-gray-600">
+                "Schedules run their actions on a cron expression like "
+                <code>"0 0 * * *"</code>
+                " (midnight) in their timezone, or on an interval like "
+                <code>"10m"</code>
+                ". Lines to rotate through are available as "
+                <code>"$rotation"</code>
+                " in the actions."
+            </p>
+            <pre class="text-sm text-red-500">{error}</pre>
+            <Suspense fallback=move || view! { <p>"loading"</p> }>
+                {move || {
+                    schedules
+                        .get()
+                        .map(|schedules| match schedules {
+                            Ok(schedules) => {
+                                schedules
+                                    .into_iter()
+                                    .map(|overview| {
+                                        view! {
+                                            <ScheduleSettings
+                                                overview
+                                                save
+                                                delete
+                                                add_action
+                                                remove_action
+                                                run
+                                            />
+                                        }
+                                    })
+                                    .collect_view()
+                                    .into_any()
+                            }
+                            Err(e) => {
+                                view! { <p class="text-red-500">{format!("Error: {e}")}</p> }
+                                    .into_any()
+                            }
+                        })
+                }}
+            </Suspense>
+            <h2 class="text-lg font-medium text-gray-700">"New schedule"</h2>
+            <ScheduleForm save schedule=None/>
+        </div>
+    }
+}
+
+#[component]
+#[track_caller]
+fn ScheduleSettings(
+    overview: ScheduleOverview,
+    save: ServerAction<SaveSchedule>,
+    delete: ServerAction<DeleteSchedule>,
+    add_action: ServerAction<AddScheduleAction>,
+    remove_action: ServerAction<RemoveScheduleAction>,
+    run: ServerAction<RunSchedule>,
+) -> impl IntoView {
+    let ScheduleOverview { schedule, next_run } = overview;
+    let schedule_id = schedule.schedule_id.to_string();
+    let next_run = match (schedule.enabled, next_run) {
+        (false, _) => view! { <span class="text-gray-600">"disabled"</span> }.into_any(),
+        (true, Ok(next_run)) => {
+            view! { <span class="text-gray-600">{format!("next run {next_run}")}</span> }.into_any()
+        }
+        (true, Err(e)) => view! { <span class="text-red-500">{e}</span> }.into_any(),
+    };
+    let actions = schedule
+        .actions
+        .iter()
+        .enumerate()
+        .map(|(index, action)| {
+            let schedule_id = schedule_id.clone();
+            view! {
+                <li class="flex items-center gap-2">
+                    <ActionForm action=remove_action>
+                        <input type="hidden" name="schedule_id" value=schedule_id.clone()/>
+                        <input type="hidden" name="index" value=index/>
+                        <input
+                            class="cursor-pointer rounded border-2 border-red-500 px-1 hover:border-red-900"
+                            type="submit"
+                            value="𐄂"
+                        />
+                    </ActionForm>
+                    <span>{action_summary(action)}</span>
+                </li>
+            }
+        })
+        .collect_view();
+    let failed = schedule
+        .last_outcomes
+        .iter()
+        .filter_map(|outcome| {
+            let error = outcome.error.as_ref()?;
+            Some(view! { <li class="text-red-500">{format!("{}: {error}", outcome.action)}</li> })
+        })
+        .collect_view();
+    let (delete_id, run_id) = (schedule_id.clone(), schedule_id.clone());
+
+    view! {
+        <div class="space-y-2 border-t border-gray-200 pt-4 text-sm">
+            <div class="flex items-center gap-2">
+                <ActionForm action=delete>
+                    <input type="hidden" name="schedule_id" value=delete_id/>
+                    <input
+                        class="cursor-pointer rounded border-2 border-red-500 px-1 hover:border-red-900"
+                        type="submit"
+                        value="𐄂"
+                    />
+                </ActionForm>
+                <ActionForm action=run>
+                    <input type="hidden" name="schedule_id" value=run_id/>
+                    <input
+                        class="cursor-pointer rounded bg-blue-500 hover:bg-blue-700 text-white px-2"
+                        type="submit"
+                        value="Run now"
+                    />
+                </ActionForm>
+                <span class="text-lg font-semibold">{schedule.name.clone()}</span>
+                <code class="text-gray-600">{schedule.timing.to_string()}</code>
+                {next_run}
+            </div>
+            <ul>{failed}</ul>
+            <ScheduleForm save schedule=Some(schedule)/>
+            <ul class="space-y-1">{actions}</ul>
+            <ActionForm action=add_action>
+                <input type="hidden" name="schedule_id" value=schedule_id/>
+                <div class="flex gap-2">
+                    <div class="flex-1">
+                        <AlertActionInputs/>
+                    </div>
+                    <input
+                        class="cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded"
+                        type="submit"
+                        value="Add action"
+                    />
+                </div>
+            </ActionForm>
+        </div>
+    }
+}
+
+/// Form creating a schedule, or editing `schedule`.
+#[component]
+#[track_caller]
+fn ScheduleForm(save: ServerAction<SaveSchedule>, schedule: Option<Schedule>) -> impl IntoView {
+    let is_new = schedule.is_none();
+    let schedule_id = schedule
+        .as_ref()
+        .map(|s| s.schedule_id.to_string())
+        .unwrap_or_default();
+    let name = schedule
+        .as_ref()
+        .map(|s| s.name.clone())
+        .unwrap_or_default();
+    let enabled = schedule.as_ref().map_or(true, |s| s.enabled);
+    let (is_interval, timing) = match schedule.as_ref().map(|s| &s.timing) {
+        Some(Timing::Cron { expression }) => (false, expression.clone()),
+        Some(Timing::Interval { seconds }) => (true, format!("{seconds}s")),
+        None => (false, String::new()),
+    };
+    let timezone = schedule
+        .as_ref()
+        .map(|s| s.timezone.clone())
+        .unwrap_or_default();
+    let rotation = schedule
+        .as_ref()
+        .map(|s| s.rotation.join("\n"))
+        .unwrap_or_default();
+
+    view! {
+        <ActionForm action=save>
+            <input type="hidden" name="schedule_id" value=schedule_id/>
+            <div class="flex flex-col gap-2 text-sm">
+                <div class="flex gap-2">
+                    <input
+                        class="w-32 border border-gray-300 rounded px-2 py-1"
+                        type="text"
+                        name="name"
+                        placeholder="name"
+                        value=name
+                    />
+                    <select class="border border-gray-300 rounded px-2" name="enabled">
+                        <option value="true" selected=enabled>"Enabled"</option>
+                        <option value="false" selected=!enabled>"Disabled"</option>
+                    </select>
+                    <select class="border border-gray-300 rounded px-2" name="kind">
+                        <option value="cron" selected=!is_interval>"Cron"</option>
+                        <option value="interval" selected=is_interval>"Interval"</option>
+                    </select>
+                    <input
+                        class="flex-1 border border-gray-300 rounded px-2 py-1"
+                        type="text"
+                        name="timing"
+                        placeholder="0 0 * * * or 10m"
+                        value=timing
+                    />
+                    <input
+                        class="w-40 border border-gray-300 rounded px-2 py-1"
+                        type="text"
+                        name="timezone"
+                        placeholder="UTC"
+                        title="IANA timezone, e.g. Europe/Stockholm"
+                        value=timezone
+                    />
+                </div>
+                <textarea
+                    class="rounded-lg border border-gray-300 bg-gray-50 p-2"
+                    name="rotation"
+                    rows="2"
+                    placeholder="lines to rotate through, one per line"
+                >
+                    {rotation}
+                </textarea>
+                <input
+                    class="self-start cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded"
+                    type="submit"
+                    value={if is_new { "Add schedule" } else { "Save" }}
+                />
+            </div>
+        </ActionForm>
+    }
+}
@@ -0,0 +1,174 @@
+use leptos::prelude::*;
+
+use super::library::{MediaSelect, VolumeInput};
+use super::update::AlertIdInput;
+pub use crate::alerts::*;
+use crate::themes::*;
+
+#[component]
+#[track_caller]
+pub fn Themes() -> impl IntoView {
+    let save = ServerAction::<SaveTheme>::new();
+    let delete = ServerAction::<DeleteTheme>::new();
+    let themes = Resource::new(
+        move || (save.version().get(), delete.version().get()),
+        |_| list_themes(),
+    );
+    // theme shown in the form
+    let editing = RwSignal::new(None::<Theme>);
+
+    view! {
+        <div class="w-full max-w-4xl bg-white shadow rounded-xl p-8 space-y-6">
+            <h1 class="text-2xl font-semibold">"Themes"</h1>
+            <Suspense fallback=move || view! { <p>"loading"</p> }>
+                {move || {
+                    themes
+                        .get()
+                        .map(|themes| match themes {
+                            Ok(themes) => {
+                                view! {
+                                    <ul class="space-y-2">
+                                        {themes
+                                            .into_iter()
+                                            .map(|theme| {
+                                                let name = theme.name.to_string();
+                                                let delete_name = name.clone();
+                                                view! {
+                                                    <li class="flex items-center gap-4 border-b border-gray-200 py-2">
+                                                        <ActionForm action=delete>
+                                                            <input type="hidden" name="name" value=delete_name/>
+                                                            <input
+                                                                class="cursor-pointer rounded border-2 border-red-500 px-1 hover:border-red-900"
+                                                                type="submit"
+                                                                value="𐄂"
+                                                            />
+                                                        </ActionForm>
+                                                        <button
+                                                            class="text-blue-600 hover:underline"
+                                                            on:click=move |_| editing.set(Some(theme.clone()))
+                                                        >
+                                                            {name}
+                                                        </button>
+                                                    </li>
+                                                }
+                                            })
+                                            .collect_view()}
+                                    </ul>
+                                }
+                                    .into_any()
+                            }
+                            Err(e) => {
+                                view! { <p class="text-red-500">{format!("Error: {e}")}</p> }
+                                    .into_any()
+                            }
+                        })
+                }}
+            </Suspense>
+            <ActionForm action=save>
+                <div class="flex flex-col gap-2">
+                    <input
+                        class="w-64 border border-gray-300 rounded px-4 py-2"
+                        type="text"
+                        name="name"
+                        placeholder="name"
+                        value=move || editing.with(|t| t.as_ref().map(|t| t.name.to_string()))
+                    />
+                    <div class="flex gap-2">
+                        <textarea
+                            class="flex-1 rounded-lg border border-gray-300 bg-gray-50 p-3 text-sm"
+                            name="style"
+                            rows="12"
+                            placeholder="style"
+                            prop:value=move || {
+                                editing.with(|t| t.as_ref().map(|t| t.style.clone()).unwrap_or_default())
+                            }
+                        ></textarea>
+                        <textarea
+                            class="w-64 rounded-lg border border-gray-300 bg-gray-50 p-3 text-sm"
+                            name="fields"
+                            rows="12"
+                            placeholder="default fields, one `name: value` per line"
+                            prop:value=move || {
+                                editing
+                                    .with(|t| t.as_ref().map(Theme::fields_string).unwrap_or_default())
+                            }
+                        ></textarea>
+                    </div>
+                    <div class="flex gap-2">
+                        <MediaSelect allow_none=true/>
+                        <VolumeInput/>
+                        <input
+                            class="cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded text-sm"
+                            type="submit"
+                            value="Save theme"
+                        />
+                    </div>
+                    <pre class="text-sm text-red-500">
+                        {move || save.value().get().and_then(Result::err).map(|e| e.to_string())}
+                    </pre>
+                </div>
+            </ActionForm>
+        </div>
+    }
+}
+
+#[component]
+#[track_caller]
+pub fn AlertTheme() -> impl IntoView {
+    let alert: RwSignal<Alert> = use_context().unwrap();
+    let set_theme = ServerAction::<SetAlertTheme>::new();
+    let themes = Resource::new(|| (), |_| list_themes());
+
+    Effect::new(move || {
+        if let Some(Ok(new_alert)) = set_theme.value().get() {
+            alert.update(|a| a.theme = new_alert.theme);
+        }
+    });
+
+    let current = move || alert.with(|a| a.theme.as_ref().map(|t| t.name.to_string()));
+
+    view! {
+        <div class="space-y-2">
+            <h2 class="text-lg font-medium text-gray-700">"Theme"</h2>
+            <ActionForm action=set_theme>
+                <AlertIdInput/>
+                <div class="flex items-center gap-2 text-sm">
+                    <select class="border border-gray-300 rounded px-4 py-2" name="theme">
+                        <option value="">"no theme"</option>
+                        <Suspense fallback=|| ()>
+                            {move || {
+                                themes
+                                    .get()
+                                    .and_then(Result::ok)
+                                    .unwrap_or_default()
+                                    .into_iter()
+                                    .map(|t| {
+                                        let name = t.name.to_string();
+                                        let selected = {
+                                            let name = name.clone();
+                                            move || current().as_deref() == Some(name.as_str())
+                                        };
+                                        let value = name.clone();
+                                        view! {
+                                            <option value=value selected=selected>
+                                                {name}
+                                            </option>
+                                        }
+                                    })
+                                    .collect_view()
+                            }}
+                        </Suspense>
+                    </select>
+                    <input
+                        class="cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded text-sm"
+                        type="submit"
+                        value="Save"
+                    />
+                    <a class="text-blue-600 hover:underline" href="/themes">
+                        "Edit themes"
+                    </a>
+                </div>
+            </ActionForm>
+        </div>
+    }
+}
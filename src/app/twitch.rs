@@ -0,0 +1,187 @@
+use leptos::prelude::*;
+
+use super::actions::{action_summary, AlertActionInputs};
+pub use crate::alerts::*;
+use crate::twitch::*;
+
+#[component]
+#[track_caller]
+pub fn Twitch() -> impl IntoView {
+    let save = ServerAction::<SaveEventSubMapping>::new();
+    let delete = ServerAction::<DeleteEventSubMapping>::new();
+    let replay = ServerAction::<ReplayEventSubFixture>::new();
+    let eventsub = Resource::new(
+        move || (save.version().get(), delete.version().get()),
+        |_| read_eventsub(),
+    );
+
+    let error = move || {
+        [
+            save.value().get().and_then(Result::err),
+            delete.value().get().and_then(Result::err),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+    };
+    let outcomes = move || {
+        replay.value().get().map(|outcomes| match outcomes {
+            Ok(outcomes) if outcomes.is_empty() => {
+                view! { <p class="text-sm text-gray-600">"No mapping matched"</p> }.into_any()
+            }
+            Ok(outcomes) => view! {
+                <ul class="text-sm">
+                    {outcomes
+                        .into_iter()
+                        .map(|outcome| match outcome.error {
+                            Some(error) => view! {
+                                <li class="text-red-500">{format!("{}: {error}", outcome.action)}</li>
+                            }
+                            .into_any(),
+                            None => view! { <li>{format!("{}: ok", outcome.action)}</li> }.into_any(),
+                        })
+                        .collect_view()}
+                </ul>
+            }
+            .into_any(),
+            Err(e) => view! { <p class="text-sm text-red-500">{e.to_string()}</p> }.into_any(),
+        })
+    };
+
+    view! {
+        <div class="w-full max-w-4xl bg-white shadow rounded-xl p-8 space-y-6">
+            <h1 class="text-2xl font-semibold">"Twitch"</h1>
+            <p class="text-sm text-gray-600">
+                "Subscribe to EventSub with the webhook callback "
+                <code>"/twitch/eventsub"</code>
+                " and the secret given with "
+                <code>"--twitch-eventsub-secret"</code>
+                ", or give "
+                <code>"--twitch-client-id"</code>
+                ", "
+                <code>"--twitch-access-token"</code>
+                " and "
+                <code>"--twitch-broadcaster-id"</code>
+                " to receive events over a websocket. Fields of the event, e.g. "
+                <code>"$user_name"</code>
+                ", can be used in the action."
+            </p>
+            <pre class="text-sm text-red-500">{error}</pre>
+            <Suspense fallback=move || view! { <p>"loading"</p> }>
+                {move || {
+                    eventsub
+                        .get()
+                        .map(|eventsub| match eventsub {
+                            Ok(eventsub) => {
+                                let fixtures = eventsub.fixtures.clone();
+                                let configured = eventsub.configured;
+                                view! {
+                                    <Show when=move || !configured>
+                                        <p class="text-sm text-red-500">
+                                            "No secret is set, the webhook is disabled."
+                                        </p>
+                                    </Show>
+                                    <p class="text-sm text-gray-600">
+                                        {match eventsub.websocket {
+                                            Some(status) => format!("Websocket: {status}"),
+                                            None => "The websocket client is disabled.".to_owned(),
+                                        }}
+                                    </p>
+                                    <ul class="space-y-1">
+                                        {eventsub
+                                            .mappings
+                                            .into_iter()
+                                            .map(|mapping| {
+                                                let conditions = mapping
+                                                    .conditions
+                                                    .iter()
+                                                    .map(ToString::to_string)
+                                                    .collect::<Vec<_>>()
+                                                    .join(", ");
+                                                view! {
+                                                    <li class="flex items-center gap-2 text-sm">
+                                                        <ActionForm action=delete>
+                                                            <input type="hidden" name="mapping_id" value=mapping.mapping_id.to_string()/>
+                                                            <input
+                                                                class="cursor-pointer rounded border-2 border-red-500 px-1 hover:border-red-900"
+                                                                type="submit"
+                                                                value="𐄂"
+                                                            />
+                                                        </ActionForm>
+                                                        <span class="font-semibold">{mapping.subscription_type.clone()}</span>
+                                                        <span class="text-gray-600">{conditions}</span>
+                                                        <span>{action_summary(&mapping.action)}</span>
+                                                    </li>
+                                                }
+                                            })
+                                            .collect_view()}
+                                    </ul>
+                                    <datalist id="eventsub-types">
+                                        {fixtures
+                                            .iter()
+                                            .map(|name| view! { <option value=name.clone()></option> })
+                                            .collect_view()}
+                                    </datalist>
+                                    <div class="space-y-2 border-t border-gray-200 pt-4">
+                                        <h2 class="font-medium text-gray-700">"Replay a recorded event"</h2>
+                                        <ActionForm action=replay>
+                                            <div class="flex gap-2 text-sm">
+                                                <select class="border border-gray-300 rounded px-2" name="name">
+                                                    {fixtures
+                                                        .into_iter()
+                                                        .map(|name| {
+                                                            let value = name.clone();
+                                                            view! { <option value=value>{name}</option> }
+                                                        })
+                                                        .collect_view()}
+                                                </select>
+                                                <input
+                                                    class="cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded text-sm"
+                                                    type="submit"
+                                                    value="Replay"
+                                                />
+                                            </div>
+                                        </ActionForm>
+                                        {outcomes}
+                                    </div>
+                                }
+                                    .into_any()
+                            }
+                            Err(e) => {
+                                view! { <p class="text-red-500">{format!("Error: {e}")}</p> }
+                                    .into_any()
+                            }
+                        })
+                }}
+            </Suspense>
+            <ActionForm action=save>
+                <div class="flex flex-col gap-2 border-t border-gray-200 pt-4 text-sm">
+                    <h2 class="font-medium text-gray-700">"New mapping"</h2>
+                    <div class="flex gap-2">
+                        <input
+                            class="w-56 border border-gray-300 rounded px-2 py-1"
+                            type="text"
+                            name="subscription_type"
+                            list="eventsub-types"
+                            placeholder="channel.follow"
+                        />
+                        <input
+                            class="flex-1 border border-gray-300 rounded px-2 py-1"
+                            type="text"
+                            name="conditions"
+                            placeholder="bits >= 100"
+                        />
+                    </div>
+                    <AlertActionInputs/>
+                    <input
+                        class="self-start cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded"
+                        type="submit"
+                        value="Add mapping"
+                    />
+                </div>
+            </ActionForm>
+        </div>
+    }
+}
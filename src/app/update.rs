@@ -4,6 +4,15 @@ use leptos::prelude::*;
 use leptos_meta::*;
 use leptos_router::{components::A, *};
 
+use super::events::AlertEvents;
+use super::layers::AlertLayers;
+use super::library::AlertMedia;
+use super::new::AlertSaveTemplate;
+use super::obs::AlertObsSource;
+use super::outbound::AlertOutboundWebhooks;
+use super::playlists::AlertPlaylist;
+use super::preview::AlertPreview;
+use super::themes::AlertTheme;
 pub use super::login::*;
 
 #[track_caller]
@@ -33,6 +42,8 @@ pub fn UpdateAlert() -> impl IntoView {
                     {move || {
                         match alert.read().as_ref() {
                             Some(Ok(alert)) => {
+                                let draft_text = RwSignal::new(alert.last_text.to_string());
+                                let draft_style = RwSignal::new(alert.last_style.clone());
                                 let alert = RwSignal::new(alert.clone());
                                 provide_context(alert);
 
@@ -42,7 +53,7 @@ pub fn UpdateAlert() -> impl IntoView {
                                         <div class="flex items-center justify-between">
                                             <h1 class="text-2xl font-semibold">"Update Alert"</h1>
                                             <div class="text-blue-600 hover:underline text-sm">
-                                                <A href=move || format!("/alert/{}", alert.get().alert_id)>
+                                                <A href=move || format!("/alert/{}?preview", alert.get().alert_id)>
                                                     "View"
                                                 </A>
                                             </div>
@@ -55,6 +66,7 @@ pub fn UpdateAlert() -> impl IntoView {
                                                            focus:outline-none focus:ring-2 focus:ring-blue-500"
                                                 />
                                             </ActionForm>
+                                            <AlertTrustedHtml/>
                                         </div>
 
                                         <div>
@@ -91,6 +103,7 @@ pub fn UpdateAlert() -> impl IntoView {
                                                     <textarea
                                                         id="alert_text"
                                                         name="text"
+                                                        on:input=move |ev| draft_text.set(event_target_value(&ev))
                                                         rows="20"
                                                         class="w-full resize-y rounded-lg border border-gray-300 bg-gray-50 p-3 text-sm text-gray-900 focus:border-blue-500 focus:ring-blue-500"
                                                     >
@@ -114,11 +127,30 @@ pub fn UpdateAlert() -> impl IntoView {
                                                     <textarea
                                                         id="alert_style"
                                                         name="style"
+                                                        on:input=move |ev| draft_style.set(event_target_value(&ev))
                                                         rows="20"
                                                         class="w-full resize-y rounded-lg border border-gray-300 bg-gray-50 p-3 text-sm text-gray-900 focus:border-blue-500 focus:ring-blue-500"
                                                     >
                                                         {move || alert.with(|a| a.last_style.to_string())}
                                                     </textarea>
+                                                    <label class="flex items-center gap-1 text-sm text-gray-700">
+                                                        <input
+                                                            type="checkbox"
+                                                            name="scope_style"
+                                                            value="on"
+                                                            checked=move || alert.with(|a| a.scope_style)
+                                                        />
+                                                        "only apply to the alert"
+                                                    </label>
+                                                    <pre class="text-sm text-red-500">
+                                                        {move || {
+                                                            update_alert_style
+                                                                .value()
+                                                                .get()
+                                                                .and_then(Result::err)
+                                                                .map(|e| e.to_string())
+                                                        }}
+                                                    </pre>
                                                     <input
                                                         type="submit"
                                                         value="Submit"
@@ -129,7 +161,27 @@ pub fn UpdateAlert() -> impl IntoView {
                                             </div>
                                         </div>
 
+                                        <AlertPreview text=draft_text style=draft_style/>
+
                                         <AlertFields/>
+
+                                        <AlertTheme/>
+
+                                        <AlertTransition/>
+
+                                        <AlertPlaylist/>
+
+                                        <AlertLayers/>
+
+                                        <AlertMedia/>
+
+                                        <AlertEvents/>
+
+                                        <AlertOutboundWebhooks/>
+
+                                        <AlertObsSource/>
+
+                                        <AlertSaveTemplate/>
                                     </div>
                                 }.into_any()
                             }
@@ -270,6 +322,110 @@ pub fn AlertField(
     }
 }
 
+/// Toggle for [`Alert::trusted_html`], only admins can change it.
+#[component]
+#[track_caller]
+pub fn AlertTrustedHtml() -> impl IntoView {
+    let alert: RwSignal<Alert> = use_context().unwrap();
+    let update_trusted = ServerAction::<UpdateAlertTrustedHtml>::new();
+
+    Effect::new(move || {
+        if let Some(Ok(new_alert)) = update_trusted.value().get() {
+            alert.update(|a| a.trusted_html = new_alert.trusted_html);
+        }
+    });
+
+    view! {
+        <ActionForm action=update_trusted>
+            <AlertIdInput/>
+            <div class="flex items-center gap-2 text-sm">
+                <label class="flex items-center gap-1" title="render html as is, admin only">
+                    <input
+                        type="checkbox"
+                        name="trusted_html"
+                        value="on"
+                        checked=move || alert.with(|a| a.trusted_html)
+                    />
+                    "trusted html"
+                </label>
+                <input
+                    type="submit"
+                    value="Save"
+                    class="text-sm cursor-pointer bg-blue-600 px-3 py-1 rounded text-white hover:bg-blue-700"
+                />
+                {move || {
+                    update_trusted
+                        .value()
+                        .get()
+                        .and_then(Result::err)
+                        .map(|e| view! { <span class="text-red-500">{e.to_string()}</span> })
+                }}
+            </div>
+        </ActionForm>
+    }
+}
+
+#[component]
+#[track_caller]
+pub fn AlertTransition() -> impl IntoView {
+    let alert: RwSignal<Alert> = use_context().unwrap();
+    let update_transition = ServerAction::<UpdateAlertTransition>::new();
+
+    Effect::new(move || {
+        if let Some(Ok(new_alert)) = update_transition.value().get() {
+            alert.update(|a| a.transition = new_alert.transition);
+        }
+    });
+
+    let transition = move || alert.with(|a| a.transition.clone());
+
+    view! {
+        <div class="space-y-2">
+            <h2 class="text-lg font-medium text-gray-700">"Transition"</h2>
+            <ActionForm action=update_transition>
+                <AlertIdInput/>
+                <div class="flex items-center gap-2 text-sm">
+                    <select class="border border-gray-300 rounded px-4 py-2" name="kind">
+                        {TransitionKind::ALL
+                            .into_iter()
+                            .map(|kind| {
+                                view! {
+                                    <option
+                                        value=kind.as_str()
+                                        selected=move || transition().kind == kind
+                                    >
+                                        {kind.as_str()}
+                                    </option>
+                                }
+                            })
+                            .collect_view()}
+                    </select>
+                    <input
+                        class="w-24 border border-gray-300 rounded px-4 py-2"
+                        type="number"
+                        name="duration_ms"
+                        title="duration ms"
+                        min="0"
+                        value=move || transition().duration_ms
+                    />
+                    <input
+                        class="w-40 border border-gray-300 rounded px-4 py-2"
+                        type="text"
+                        name="easing"
+                        title="easing"
+                        value=move || transition().easing
+                    />
+                    <input
+                        class="cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded text-sm"
+                        type="submit"
+                        value="Save"
+                    />
+                </div>
+            </ActionForm>
+        </div>
+    }
+}
+
 #[component]
 pub fn AlertIdInput() -> impl IntoView {
     let alert = use_context::<RwSignal<Alert>>().unwrap();
@@ -284,13 +440,12 @@ pub fn AlertIdInput() -> impl IntoView {
 #[server(UpdateAlertRefresh, "/backend")]
 #[tracing::instrument(err)]
 pub async fn update_alert_refresh(alert_id: AlertId) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
     let Some(manager): Option<AlertManager> = use_context() else {
         return Err(ServerFnError::ServerError("Missing manager".to_owned()));
     };
 
-    manager
-        .sender.send(AlertMessage::Update { alert_id })?;
-    Ok(())
+    manager.refresh_alert(&alert_id).await
 }
 
 #[server(UpdateAlertName, "/backend")]
@@ -319,8 +474,9 @@ pub async fn update_alert_text(alert_id: AlertId, text: String) -> Result<Alert,
     };
 
     manager
-        .edit_alert(&alert_id, move |alert| {
+        .edit_alert_html(&alert_id, move |alert| {
             alert.last_text = text.into();
+            Ok(())
         })
         .await?;
 
@@ -331,14 +487,21 @@ pub async fn update_alert_text(alert_id: AlertId, text: String) -> Result<Alert,
 
 #[server(UpdateAlertStyle, "/backend")]
 #[tracing::instrument(err)]
-pub async fn update_alert_style(alert_id: AlertId, style: String) -> Result<Alert, ServerFnError> {
+pub async fn update_alert_style(
+    alert_id: AlertId,
+    style: String,
+    scope_style: Option<String>,
+) -> Result<Alert, ServerFnError> {
     let Some(manager): Option<AlertManager> = use_context() else {
         return Err(ServerFnError::ServerError("Missing manager".to_owned()));
     };
+    crate::css::validate(&style).map_err(crate::css::server_error)?;
 
     manager
-        .edit_alert(&alert_id, move |alert| {
+        .edit_alert_html(&alert_id, move |alert| {
             alert.last_style = style;
+            alert.scope_style = scope_style.is_some();
+            Ok(())
         })
         .await?;
 
@@ -347,6 +510,65 @@ pub async fn update_alert_style(alert_id: AlertId, style: String) -> Result<Aler
     Ok(alert.clone())
 }
 
+#[server(UpdateAlertTransition, "/backend")]
+#[tracing::instrument(err)]
+pub async fn update_alert_transition(
+    alert_id: AlertId,
+    kind: TransitionKind,
+    duration_ms: u32,
+    easing: String,
+) -> Result<Alert, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    let easing = match easing.trim() {
+        "" => Transition::default().easing,
+        // the easing ends up in the overlay script, keep it to what css allows
+        easing if Transition::is_valid_easing(easing) => easing.to_owned(),
+        _ => {
+            return Err(ServerFnError::ServerError(
+                "Invalid easing function".to_owned(),
+            ))
+        }
+    };
+
+    manager
+        .edit_alert(&alert_id, move |alert| {
+            alert.transition = Transition {
+                kind,
+                duration_ms,
+                easing,
+            };
+        })
+        .await?;
+
+    manager.get_alert(&alert_id).await
+}
+
+#[server(UpdateAlertTrustedHtml, "/backend")]
+#[tracing::instrument(err)]
+pub async fn update_alert_trusted_html(
+    alert_id: AlertId,
+    trusted_html: Option<String>,
+) -> Result<Alert, ServerFnError> {
+    let auth = leptos_axum::extract::<crate::auth::AuthSession>().await?;
+    if !auth.user.as_ref().is_some_and(|user| user.is_admin()) {
+        return Err(ServerFnError::ServerError("Unauthorized".to_owned()));
+    }
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+
+    manager
+        .edit_alert(&alert_id, move |alert| {
+            alert.trusted_html = trusted_html.is_some();
+        })
+        .await?;
+
+    manager.get_alert(&alert_id).await
+}
+
 #[server(UpdateAlertField, "/backend")]
 #[tracing::instrument(err)]
 pub async fn update_alert_field(
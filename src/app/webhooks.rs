@@ -0,0 +1,317 @@
+use leptos::prelude::*;
+
+use super::actions::{action_summary, AlertActionInputs};
+pub use crate::alerts::*;
+use crate::webhooks::*;
+
+#[component]
+#[track_caller]
+pub fn Webhooks() -> impl IntoView {
+    let create = ServerAction::<CreateWebhook>::new();
+    let delete = ServerAction::<DeleteWebhook>::new();
+    let verification = ServerAction::<UpdateWebhookVerification>::new();
+    let save_extract = ServerAction::<SaveWebhookExtract>::new();
+    let remove_extract = ServerAction::<RemoveWebhookExtract>::new();
+    let add_mapping = ServerAction::<AddWebhookMapping>::new();
+    let remove_mapping = ServerAction::<RemoveWebhookMapping>::new();
+    let test = ServerAction::<TestWebhook>::new();
+    let webhooks = Resource::new(
+        move || {
+            (
+                create.version().get(),
+                delete.version().get(),
+                verification.version().get(),
+                save_extract.version().get(),
+                remove_extract.version().get(),
+                add_mapping.version().get(),
+                remove_mapping.version().get(),
+            )
+        },
+        |_| list_webhooks(),
+    );
+
+    let error = move || {
+        [
+            create.value().get().and_then(Result::err),
+            verification.value().get().and_then(Result::err),
+            save_extract.value().get().and_then(Result::err),
+            add_mapping.value().get().and_then(Result::err),
+            test.value().get().and_then(Result::err),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+    };
+    let outcomes = move || {
+        test.value().get().and_then(Result::ok).map(|outcomes| {
+            if outcomes.is_empty() {
+                return view! { <p class="text-sm text-gray-600">"No mapping matched"</p> }
+                    .into_any();
+            }
+            view! {
+                <ul class="text-sm">
+                    {outcomes
+                        .into_iter()
+                        .map(|outcome| match outcome.error {
+                            Some(error) => view! {
+                                <li class="text-red-500">{format!("{}: {error}", outcome.action)}</li>
+                            }
+                            .into_any(),
+                            None => view! { <li>{format!("{}: ok", outcome.action)}</li> }.into_any(),
+                        })
+                        .collect_view()}
+                </ul>
+            }
+            .into_any()
+        })
+    };
+
+    view! {
+        <div class="w-full max-w-4xl bg-white shadow rounded-xl p-8 space-y-6">
+            <h1 class="text-2xl font-semibold">"Webhooks"</h1>
+            <p class="text-sm text-gray-600">
+                "Webhooks receive JSON or form posts at "
+                <code>"/hook/<id>"</code>
+                ". Extract values with a JSON pointer like "
+                <code>"/data/amount"</code>
+                " or a path like "
+                <code>"data.items[0].name"</code>
+                " and use them as "
+                <code>"$name"</code>
+                " in conditions and actions, every value is also available by its path, e.g. "
+                <code>"$data.amount"</code>
+                "."
+            </p>
+            <ActionForm action=create>
+                <div class="flex gap-2">
+                    <input
+                        class="w-64 border border-gray-300 rounded px-4 py-2"
+                        type="text"
+                        name="name"
+                        placeholder="name"
+                    />
+                    <input
+                        class="cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded text-sm"
+                        type="submit"
+                        value="New webhook"
+                    />
+                </div>
+            </ActionForm>
+            <pre class="text-sm text-red-500">{error}</pre>
+            {outcomes}
+            <Suspense fallback=move || view! { <p>"loading"</p> }>
+                {move || {
+                    webhooks
+                        .get()
+                        .map(|webhooks| match webhooks {
+                            Ok(webhooks) => {
+                                webhooks
+                                    .into_iter()
+                                    .map(|ListedInboundWebhook { webhook, has_secret }| {
+                                        view! {
+                                            <WebhookSettings
+                                                webhook
+                                                has_secret
+                                                delete
+                                                verification
+                                                save_extract
+                                                remove_extract
+                                                add_mapping
+                                                remove_mapping
+                                                test
+                                            />
+                                        }
+                                    })
+                                    .collect_view()
+                                    .into_any()
+                            }
+                            Err(e) => {
+                                view! { <p class="text-red-500">{format!("Error: {e}")}</p> }
+                                    .into_any()
+                            }
+                        })
+                }}
+            </Suspense>
+        </div>
+    }
+}
+
+#[component]
+#[track_caller]
+#[allow(clippy::too_many_arguments)]
+fn WebhookSettings(
+    webhook: InboundWebhook,
+    has_secret: bool,
+    delete: ServerAction<DeleteWebhook>,
+    verification: ServerAction<UpdateWebhookVerification>,
+    save_extract: ServerAction<SaveWebhookExtract>,
+    remove_extract: ServerAction<RemoveWebhookExtract>,
+    add_mapping: ServerAction<AddWebhookMapping>,
+    remove_mapping: ServerAction<RemoveWebhookMapping>,
+    test: ServerAction<TestWebhook>,
+) -> impl IntoView {
+    let webhook_id = StoredValue::new(webhook.webhook_id.to_string());
+    let (kind, value, prefix) = match webhook.verification.clone() {
+        WebhookVerification::None => ("none", String::new(), String::new()),
+        WebhookVerification::Header { header, .. } => ("header", header, String::new()),
+        WebhookVerification::Hmac { header, prefix, .. } => ("hmac", header, prefix),
+        WebhookVerification::Payload { path, .. } => ("payload", path, String::new()),
+    };
+    let extract = webhook
+        .extract
+        .iter()
+        .map(|extract| {
+            let name = extract.name.clone();
+            view! {
+                <li class="flex items-center gap-2">
+                    <ActionForm action=remove_extract>
+                        <input type="hidden" name="webhook_id" value=webhook_id.get_value()/>
+                        <input type="hidden" name="name" value=name/>
+                        <input
+                            class="cursor-pointer rounded border-2 border-red-500 px-1 hover:border-red-900"
+                            type="submit"
+                            value="𐄂"
+                        />
+                    </ActionForm>
+                    <span class="font-semibold">{format!("${}", extract.name)}</span>
+                    <code class="text-gray-600">{extract.path.clone()}</code>
+                </li>
+            }
+        })
+        .collect_view();
+    let mappings = webhook
+        .mappings
+        .iter()
+        .map(|mapping| {
+            let conditions = mapping
+                .conditions
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            let mapping_id = mapping.mapping_id.to_string();
+            view! {
+                <li class="flex items-center gap-2">
+                    <ActionForm action=remove_mapping>
+                        <input type="hidden" name="webhook_id" value=webhook_id.get_value()/>
+                        <input type="hidden" name="mapping_id" value=mapping_id/>
+                        <input
+                            class="cursor-pointer rounded border-2 border-red-500 px-1 hover:border-red-900"
+                            type="submit"
+                            value="𐄂"
+                        />
+                    </ActionForm>
+                    <span class="text-gray-600">{conditions}</span>
+                    <span>{action_summary(&mapping.action)}</span>
+                </li>
+            }
+        })
+        .collect_view();
+
+    view! {
+        <div class="space-y-3 border-t border-gray-200 pt-4 text-sm">
+            <div class="flex items-center gap-2">
+                <ActionForm action=delete>
+                    <input type="hidden" name="webhook_id" value=webhook_id.get_value()/>
+                    <input
+                        class="cursor-pointer rounded border-2 border-red-500 px-1 hover:border-red-900"
+                        type="submit"
+                        value="𐄂"
+                    />
+                </ActionForm>
+                <span class="text-lg font-semibold">{webhook.name.clone()}</span>
+                <code class="text-gray-600">{format!("/hook/{}", webhook_id.get_value())}</code>
+            </div>
+            <ActionForm action=verification>
+                <input type="hidden" name="webhook_id" value=webhook_id.get_value()/>
+                <div class="flex gap-2">
+                    <select class="border border-gray-300 rounded px-2" name="kind">
+                        <option value="none" selected={kind == "none"}>"No verification"</option>
+                        <option value="header" selected={kind == "header"}>"Secret header"</option>
+                        <option value="hmac" selected={kind == "hmac"}>"HMAC-SHA256 header"</option>
+                        <option value="payload" selected={kind == "payload"}>"Secret in payload"</option>
+                    </select>
+                    <input
+                        class="w-48 border border-gray-300 rounded px-2 py-1"
+                        type="text"
+                        name="value"
+                        placeholder="header or payload path"
+                        value=value
+                    />
+                    <input
+                        class="w-48 border border-gray-300 rounded px-2 py-1"
+                        type="password"
+                        name="secret"
+                        placeholder=if has_secret { "secret (unchanged)" } else { "secret" }
+                    />
+                    <input
+                        class="w-24 border border-gray-300 rounded px-2 py-1"
+                        type="text"
+                        name="prefix"
+                        placeholder="sha256="
+                        title="prefix of the signature"
+                        value=prefix
+                    />
+                    <input class="rounded bg-blue-500 hover:bg-blue-700 text-white px-2" type="submit" value="✓"/>
+                </div>
+            </ActionForm>
+            <h3 class="font-medium text-gray-700">"Values"</h3>
+            <ul class="space-y-1">{extract}</ul>
+            <ActionForm action=save_extract>
+                <input type="hidden" name="webhook_id" value=webhook_id.get_value()/>
+                <div class="flex gap-2">
+                    <input
+                        class="w-32 border border-gray-300 rounded px-2 py-1"
+                        type="text"
+                        name="name"
+                        placeholder="amount"
+                    />
+                    <input
+                        class="flex-1 border border-gray-300 rounded px-2 py-1"
+                        type="text"
+                        name="path"
+                        placeholder="/data/amount"
+                    />
+                    <input class="rounded bg-blue-500 hover:bg-blue-700 text-white px-2" type="submit" value="Add value"/>
+                </div>
+            </ActionForm>
+            <h3 class="font-medium text-gray-700">"Mappings"</h3>
+            <ul class="space-y-1">{mappings}</ul>
+            <ActionForm action=add_mapping>
+                <input type="hidden" name="webhook_id" value=webhook_id.get_value()/>
+                <div class="flex flex-col gap-2">
+                    <input
+                        class="border border-gray-300 rounded px-2 py-1"
+                        type="text"
+                        name="conditions"
+                        placeholder="conditions, e.g. type == Donation, amount >= 5"
+                    />
+                    <AlertActionInputs/>
+                    <input
+                        class="self-start cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded"
+                        type="submit"
+                        value="Add mapping"
+                    />
+                </div>
+            </ActionForm>
+            <ActionForm action=test>
+                <input type="hidden" name="webhook_id" value=webhook_id.get_value()/>
+                <div class="flex gap-2">
+                    <textarea
+                        class="flex-1 rounded-lg border border-gray-300 bg-gray-50 p-2 font-mono"
+                        name="payload"
+                        rows="3"
+                        placeholder="{\"data\": {\"amount\": 5}}"
+                    ></textarea>
+                    <input
+                        class="self-start cursor-pointer bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded"
+                        type="submit"
+                        value="Test"
+                    />
+                </div>
+            </ActionForm>
+        </div>
+    }
+}
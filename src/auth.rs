@@ -8,6 +8,9 @@ use crate::opts::Opts;
 #[cfg(feature = "ssr")]
 pub const COOKIE_AUTH_USER_LOGIN: &str = "user_login";
 
+/// Id of the admin user created on startup.
+pub const ADMIN_USER_ID: i64 = 0;
+
 #[cfg(feature = "ssr")]
 pub type AuthContext = axum_login::AuthManager<User, SessionStore>;
 #[cfg(feature = "ssr")]
@@ -50,6 +53,10 @@ impl User {
         })
     }
 
+    pub fn is_admin(&self) -> bool {
+        self.id == ADMIN_USER_ID
+    }
+
     #[cfg(feature = "ssr")]
     pub fn password(&self) -> scrypt::password_hash::PasswordHash {
         scrypt::password_hash::PasswordHash::new(&self.password_hash).unwrap()
@@ -156,10 +163,10 @@ pub async fn setup(
     let user_store = Users::empty();
 
     user_store.write().await.insert(
-        0,
+        ADMIN_USER_ID,
         User::new(
             "admin".to_owned(),
-            0,
+            ADMIN_USER_ID,
             opts.admin_password.secret().as_bytes(),
         )?,
     );
@@ -175,4 +182,24 @@ pub async fn setup(
     Ok(auth_layer)
 }
 
+/// Fail server functions called without logging in.
+#[cfg(feature = "ssr")]
+pub async fn require_user() -> Result<(), leptos::server_fn::ServerFnError> {
+    let auth = leptos_axum::extract::<AuthSession>().await?;
+    if auth.user.is_none() {
+        return Err(leptos::server_fn::ServerFnError::ServerError(
+            "Unauthorized".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+/// Whether the current server function is called by the admin.
+#[cfg(feature = "ssr")]
+pub async fn is_admin() -> bool {
+    leptos_axum::extract::<AuthSession>()
+        .await
+        .is_ok_and(|auth| auth.user.as_ref().is_some_and(User::is_admin))
+}
+
 //
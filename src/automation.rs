@@ -0,0 +1,678 @@
+//! Automation rules, running actions when the fields of an alert start matching conditions.
+//!
+//! Rules are evaluated after every edit of their alert in [`AlertManager::try_edit_alert`]. The
+//! fields of the alert are available as variables, condition values can refer to other fields,
+//! e.g. `subs >= $goal`. A rule fires only when its conditions go from not matching to matching,
+//! so `subs >= $goal` with an action bumping `goal` fires once per goal.
+//!
+//! Actions editing alerts evaluate rules again, chains deeper than [`MAX_DEPTH`] are stopped.
+//! Rules in dry run mode only log what they would have done. Rules are stored under
+//! `db_path/automation`.
+
+use leptos::{prelude::*, server};
+
+use crate::actions::{ActionOutcome, AlertAction};
+use crate::alerts::*;
+use crate::events::Condition;
+#[cfg(feature = "ssr")]
+use std::{
+    collections::{BTreeMap, VecDeque},
+    path::PathBuf,
+    sync::Arc,
+};
+#[cfg(feature = "ssr")]
+use tokio::sync::RwLock;
+
+/// Rules fired by actions of other rules, deeper chains are stopped.
+pub const MAX_DEPTH: u32 = 8;
+/// Runs kept in the log.
+#[cfg(feature = "ssr")]
+const LOG_SIZE: usize = 50;
+
+#[cfg(feature = "ssr")]
+tokio::task_local! {
+    /// How many rules are running above the current edit.
+    static DEPTH: u32;
+}
+
+#[aliri_braid::braid(serde)]
+pub struct RuleId;
+
+impl RuleId {
+    pub fn new_id() -> Self {
+        Self(nanoid::nanoid!(8))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleMode {
+    #[default]
+    Enabled,
+    /// Log the actions without running them.
+    DryRun,
+    Disabled,
+}
+
+impl RuleMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RuleMode::Enabled => "enabled",
+            RuleMode::DryRun => "dry_run",
+            RuleMode::Disabled => "disabled",
+        }
+    }
+}
+
+impl std::str::FromStr for RuleMode {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "enabled" => Ok(RuleMode::Enabled),
+            "dry_run" => Ok(RuleMode::DryRun),
+            "disabled" => Ok(RuleMode::Disabled),
+            _ => eyre::bail!("unknown rule mode `{s}`"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct AutomationRule {
+    pub rule_id: RuleId,
+    pub name: String,
+    #[serde(default)]
+    pub mode: RuleMode,
+    /// Alert whose fields are checked.
+    pub alert_id: AlertId,
+    pub conditions: Vec<Condition>,
+    /// Run in order, on any alert.
+    #[serde(default)]
+    pub actions: Vec<AlertAction>,
+}
+
+impl AutomationRule {
+    /// Check the conditions, substituting variables in their values first.
+    pub fn matches(&self, vars: &[(String, String)]) -> bool {
+        !self.conditions.is_empty()
+            && self.conditions.iter().all(|c| {
+                Condition {
+                    value: crate::actions::substitute_vars(&c.value, vars),
+                    ..c.clone()
+                }
+                .matches(vars)
+            })
+    }
+}
+
+/// A rule that fired.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct RuleRun {
+    /// RFC 3339 timestamp
+    pub at: String,
+    pub rule_id: RuleId,
+    pub rule_name: String,
+    pub depth: u32,
+    pub dry_run: bool,
+    pub outcomes: Vec<ActionOutcome>,
+}
+
+/// Variables rules are evaluated with, every field by name plus `alert_id` and `alert_name`.
+pub fn rule_vars(alert: &Alert) -> Vec<(String, String)> {
+    let mut vars = vec![
+        ("alert_id".to_owned(), alert.alert_id.to_string()),
+        ("alert_name".to_owned(), alert.name.to_string()),
+    ];
+    vars.extend(
+        alert
+            .fields
+            .iter()
+            .map(|(_, (name, value))| (name.to_string(), value.to_string())),
+    );
+    vars
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Clone)]
+pub struct Automation {
+    path: PathBuf,
+    rules: Arc<RwLock<BTreeMap<RuleId, AutomationRule>>>,
+    log: Arc<RwLock<VecDeque<RuleRun>>>,
+}
+
+#[cfg(feature = "ssr")]
+impl Automation {
+    pub async fn load(db_path: &std::path::Path) -> Result<Self, eyre::Report> {
+        let path = db_path.join("automation");
+        tokio::fs::create_dir_all(&path).await?;
+        let mut rules = BTreeMap::new();
+        let mut dir = tokio::fs::read_dir(&path).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                let rule: AutomationRule = serde_json::from_slice(&tokio::fs::read(&path).await?)?;
+                rules.insert(rule.rule_id.clone(), rule);
+            }
+        }
+        Ok(Self {
+            path,
+            rules: Arc::new(RwLock::new(rules)),
+            log: Default::default(),
+        })
+    }
+
+    fn rule_path(&self, rule_id: &RuleIdRef) -> PathBuf {
+        self.path.join(format!("{rule_id}.json"))
+    }
+
+    pub async fn list(&self) -> Vec<AutomationRule> {
+        self.rules.read().await.values().cloned().collect()
+    }
+
+    pub async fn for_alert(&self, alert_id: &AlertId) -> Vec<AutomationRule> {
+        self.rules
+            .read()
+            .await
+            .values()
+            .filter(|r| &r.alert_id == alert_id && r.mode != RuleMode::Disabled)
+            .cloned()
+            .collect()
+    }
+
+    pub async fn save(&self, rule: AutomationRule) -> Result<(), eyre::Report> {
+        let mut rules = self.rules.write().await;
+        self.write(&rule).await?;
+        rules.insert(rule.rule_id.clone(), rule);
+        Ok(())
+    }
+
+    /// Edit and save a rule.
+    pub async fn edit(
+        &self,
+        rule_id: &RuleIdRef,
+        f: impl FnOnce(&mut AutomationRule) -> Result<(), eyre::Report>,
+    ) -> Result<(), ServerFnError> {
+        // held until the rule is written, so concurrent edits don't undo each other
+        let mut rules = self.rules.write().await;
+        let Some(mut rule) = rules.get(rule_id).cloned() else {
+            return Err(ServerFnError::ServerError("no such rule".to_owned()));
+        };
+        f(&mut rule).map_err(|e| {
+            ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+        })?;
+        self.write(&rule).await.map_err(|e| {
+            ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+        })?;
+        rules.insert(rule.rule_id.clone(), rule);
+        Ok(())
+    }
+
+    async fn write(&self, rule: &AutomationRule) -> Result<(), eyre::Report> {
+        tokio::fs::write(self.rule_path(&rule.rule_id), serde_json::to_vec(rule)?).await?;
+        Ok(())
+    }
+
+    pub async fn remove(&self, rule_id: &RuleIdRef) -> Result<(), eyre::Report> {
+        if self.rules.write().await.remove(rule_id).is_some() {
+            tokio::fs::remove_file(self.rule_path(rule_id)).await?;
+        }
+        Ok(())
+    }
+
+    /// Latest runs first.
+    pub async fn runs(&self) -> Vec<RuleRun> {
+        self.log.read().await.iter().rev().cloned().collect()
+    }
+
+    async fn record(&self, run: RuleRun) {
+        let mut log = self.log.write().await;
+        if log.len() >= LOG_SIZE {
+            log.pop_front();
+        }
+        log.push_back(run);
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl AlertManager {
+    /// Fire the rules of an alert whose conditions match `after` but not `before`.
+    ///
+    /// Boxed, as actions edit alerts which runs automation again.
+    pub(crate) fn run_automation<'a>(
+        &'a self,
+        alert_id: &'a AlertId,
+        before: &'a [(String, String)],
+        after: &'a [(String, String)],
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'a>> {
+        Box::pin(self.run_automation_inner(alert_id, before, after))
+    }
+
+    async fn run_automation_inner(
+        &self,
+        alert_id: &AlertId,
+        before: &[(String, String)],
+        after: &[(String, String)],
+    ) {
+        let depth = DEPTH.try_with(|d| *d).unwrap_or(0);
+        for rule in self.automation.for_alert(alert_id).await {
+            if rule.matches(before) || !rule.matches(after) {
+                continue;
+            }
+            let dry_run = rule.mode == RuleMode::DryRun;
+            let outcomes = if depth >= MAX_DEPTH {
+                tracing::warn!(rule_id = %rule.rule_id, depth, "stopped automation loop");
+                vec![ActionOutcome {
+                    action: "loop protection".to_owned(),
+                    error: Some(format!("stopped after {MAX_DEPTH} chained rules")),
+                }]
+            } else if dry_run {
+                rule.actions
+                    .iter()
+                    .map(|action| ActionOutcome {
+                        action: action.to_string(),
+                        error: None,
+                    })
+                    .collect()
+            } else {
+                tracing::info!(rule_id = %rule.rule_id, depth, "running automation rule");
+                DEPTH
+                    .scope(depth + 1, self.run_actions(&rule.actions, after))
+                    .await
+            };
+            self.automation
+                .record(RuleRun {
+                    at: chrono::Utc::now().to_rfc3339(),
+                    rule_id: rule.rule_id.clone(),
+                    rule_name: rule.name.clone(),
+                    depth,
+                    dry_run,
+                    outcomes,
+                })
+                .await;
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct AutomationOverview {
+    pub rules: Vec<AutomationRule>,
+    pub runs: Vec<RuleRun>,
+}
+
+#[server(ReadAutomation, "/backend")]
+pub async fn read_automation() -> Result<AutomationOverview, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    Ok(AutomationOverview {
+        rules: manager.automation.list().await,
+        runs: manager.automation.runs().await,
+    })
+}
+
+/// Create a rule, or update it if `rule_id` isn't empty.
+#[server(SaveRule, "/backend")]
+#[tracing::instrument(err)]
+pub async fn save_rule(
+    rule_id: String,
+    name: String,
+    mode: String,
+    trigger_alert_id: AlertId,
+    conditions: String,
+) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager.get_alert(&trigger_alert_id).await?;
+    let mode: RuleMode = mode.parse().map_err(|e: eyre::Report| {
+        ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+    })?;
+    let conditions = crate::events::parse_conditions(&conditions).map_err(|e| {
+        ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+    })?;
+    if conditions.is_empty() {
+        return Err(ServerFnError::ServerError(
+            "a rule needs at least one condition".to_owned(),
+        ));
+    }
+    if rule_id.trim().is_empty() {
+        return manager
+            .automation
+            .save(AutomationRule {
+                rule_id: RuleId::new_id(),
+                name,
+                mode,
+                alert_id: trigger_alert_id,
+                conditions,
+                actions: vec![],
+            })
+            .await
+            .map_err(|e| ServerFnError::ServerError(e.to_string()));
+    }
+    manager
+        .automation
+        .edit(RuleIdRef::from_str(rule_id.trim()), move |rule| {
+            rule.name = name;
+            rule.mode = mode;
+            rule.alert_id = trigger_alert_id;
+            rule.conditions = conditions;
+            Ok(())
+        })
+        .await
+}
+
+#[server(DeleteRule, "/backend")]
+#[tracing::instrument(err)]
+pub async fn delete_rule(rule_id: RuleId) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager
+        .automation
+        .remove(&rule_id)
+        .await
+        .map_err(|e| ServerFnError::ServerError(e.to_string()))
+}
+
+#[server(AddRuleAction, "/backend")]
+#[tracing::instrument(err)]
+pub async fn add_rule_action(
+    rule_id: RuleId,
+    kind: String,
+    alert_id: AlertId,
+    field: String,
+    value: String,
+) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager.get_alert(&alert_id).await?;
+    let action = AlertAction::from_form(&kind, alert_id, &field, value).map_err(|e| {
+        ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+    })?;
+    manager
+        .automation
+        .edit(&rule_id, move |rule| {
+            rule.actions.push(action);
+            Ok(())
+        })
+        .await
+}
+
+#[server(RemoveRuleAction, "/backend")]
+#[tracing::instrument(err)]
+pub async fn remove_rule_action(rule_id: RuleId, index: usize) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager
+        .automation
+        .edit(&rule_id, move |rule| {
+            if index >= rule.actions.len() {
+                eyre::bail!("no such action");
+            }
+            rule.actions.remove(index);
+            Ok(())
+        })
+        .await
+}
+
+/// Which rules would fire if `field` of an alert was set to `value`, without changing anything.
+///
+/// Only rules of the alert itself are checked, not the ones their actions would trigger.
+#[server(DryRunAutomation, "/backend")]
+#[tracing::instrument(err)]
+pub async fn dry_run_automation(
+    alert_id: AlertId,
+    field: AlertFieldName,
+    value: String,
+) -> Result<Vec<RuleRun>, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    let alert = manager.get_alert(&alert_id).await?;
+    let mut edited = alert.clone();
+    match edited.entry_field_name(field) {
+        Some((_, (_, field))) => field.set(value).map_err(|e| {
+            ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+        })?,
+        None => return Err(ServerFnError::ServerError("no such field".to_owned())),
+    }
+    let (before, after) = (rule_vars(&alert), rule_vars(&edited));
+    let at = chrono::Utc::now().to_rfc3339();
+    Ok(manager
+        .automation
+        .for_alert(&alert_id)
+        .await
+        .into_iter()
+        .filter(|rule| !rule.matches(&before) && rule.matches(&after))
+        .map(|rule| RuleRun {
+            at: at.clone(),
+            outcomes: rule
+                .actions
+                .iter()
+                .map(|action| ActionOutcome {
+                    action: action.to_string(),
+                    error: None,
+                })
+                .collect(),
+            rule_id: rule.rule_id,
+            rule_name: rule.name,
+            depth: 0,
+            dry_run: true,
+        })
+        .collect())
+}
+
+#[cfg(all(test, feature = "ssr"))]
+mod tests {
+    use super::*;
+
+    /// Add an alert with counters.
+    async fn counters(manager: &AlertManager, fields: &[(&str, i32)]) -> AlertId {
+        let mut alert = Alert::new(
+            AlertId::new_id(),
+            AlertText::from(""),
+            AlertName::from("test"),
+        );
+        for (name, value) in fields {
+            alert.fields.push((
+                AlertFieldId::new_id(),
+                (AlertFieldName::from(*name), AlertField::Counter(*value)),
+            ));
+        }
+        manager.new_alert(alert.clone()).await.unwrap();
+        alert.alert_id
+    }
+
+    fn increment(alert_id: &AlertId, field: &str) -> AlertAction {
+        AlertAction::IncrementField {
+            alert_id: alert_id.clone(),
+            field: AlertFieldName::from(field),
+            amount: "1".to_owned(),
+        }
+    }
+
+    async fn rule(
+        manager: &AlertManager,
+        mode: RuleMode,
+        alert_id: &AlertId,
+        conditions: &str,
+        actions: Vec<AlertAction>,
+    ) {
+        manager
+            .automation
+            .save(AutomationRule {
+                rule_id: RuleId::new_id(),
+                name: conditions.to_owned(),
+                mode,
+                alert_id: alert_id.clone(),
+                conditions: crate::events::parse_conditions(conditions).unwrap(),
+                actions,
+            })
+            .await
+            .unwrap();
+    }
+
+    async fn field(manager: &AlertManager, alert_id: &AlertId, name: &str) -> String {
+        let alert = manager.get_alert(alert_id).await.unwrap();
+        rule_vars(&alert)
+            .into_iter()
+            .find(|(n, _)| n == name)
+            .unwrap()
+            .1
+    }
+
+    #[tokio::test]
+    async fn fires_when_starting_to_match() {
+        let (_dir, manager) = crate::alerts::test_manager(&[]).await;
+        let alert_id = counters(&manager, &[("subs", 0), ("fired", 0)]).await;
+        rule(
+            &manager,
+            RuleMode::Enabled,
+            &alert_id,
+            "subs >= 2",
+            vec![increment(&alert_id, "fired")],
+        )
+        .await;
+
+        for _ in 0..3 {
+            manager
+                .run_action(&increment(&alert_id, "subs"), &[])
+                .await
+                .unwrap();
+        }
+        assert_eq!(field(&manager, &alert_id, "fired").await, "1");
+
+        let reset = AlertAction::SetField {
+            alert_id: alert_id.clone(),
+            field: AlertFieldName::from("subs"),
+            value: "0".to_owned(),
+        };
+        manager.run_action(&reset, &[]).await.unwrap();
+        assert_eq!(field(&manager, &alert_id, "fired").await, "1");
+        for _ in 0..2 {
+            manager
+                .run_action(&increment(&alert_id, "subs"), &[])
+                .await
+                .unwrap();
+        }
+        assert_eq!(field(&manager, &alert_id, "fired").await, "2");
+        assert_eq!(manager.automation.runs().await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn stops_chains_at_max_depth() {
+        let (_dir, manager) = crate::alerts::test_manager(&[]).await;
+        let alert_id = counters(&manager, &[("count", 0), ("goal", 1)]).await;
+        // moves the goal and reaches it again, so it fires itself forever
+        rule(
+            &manager,
+            RuleMode::Enabled,
+            &alert_id,
+            "count >= $goal",
+            vec![increment(&alert_id, "goal"), increment(&alert_id, "count")],
+        )
+        .await;
+
+        manager
+            .run_action(&increment(&alert_id, "count"), &[])
+            .await
+            .unwrap();
+
+        // runs are logged when their chain is done, the deepest first
+        let runs = manager.automation.runs().await;
+        assert_eq!(runs.len(), MAX_DEPTH as usize + 1);
+        assert!(runs
+            .iter()
+            .enumerate()
+            .all(|(depth, run)| run.depth == depth as u32));
+        let (stopped, ran) = runs.split_last().unwrap();
+        assert_eq!(stopped.outcomes[0].action, "loop protection");
+        assert!(stopped.outcomes[0].error.is_some());
+        assert!(ran
+            .iter()
+            .all(|run| run.outcomes.iter().all(|o| o.error.is_none())));
+        assert_eq!(
+            field(&manager, &alert_id, "count").await,
+            (1 + MAX_DEPTH).to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn dry_run_only_logs() {
+        let (_dir, manager) = crate::alerts::test_manager(&[]).await;
+        let alert_id = counters(&manager, &[("subs", 0), ("fired", 0)]).await;
+        rule(
+            &manager,
+            RuleMode::DryRun,
+            &alert_id,
+            "subs >= 1",
+            vec![increment(&alert_id, "fired")],
+        )
+        .await;
+
+        manager
+            .run_action(&increment(&alert_id, "subs"), &[])
+            .await
+            .unwrap();
+
+        assert_eq!(field(&manager, &alert_id, "fired").await, "0");
+        let runs = manager.automation.runs().await;
+        assert_eq!(runs.len(), 1);
+        assert!(runs[0].dry_run);
+        assert_eq!(
+            runs[0].outcomes,
+            [ActionOutcome {
+                action: increment(&alert_id, "fired").to_string(),
+                error: None,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn concurrent_edits_are_kept() {
+        let (_dir, manager) = crate::alerts::test_manager(&[]).await;
+        let alert_id = counters(&manager, &[("subs", 0)]).await;
+        let rule_id = RuleId::new_id();
+        manager
+            .automation
+            .save(AutomationRule {
+                rule_id: rule_id.clone(),
+                name: "test".to_owned(),
+                mode: RuleMode::Disabled,
+                alert_id: alert_id.clone(),
+                conditions: vec![],
+                actions: vec![],
+            })
+            .await
+            .unwrap();
+
+        let edits = (0..20).map(|_| {
+            let automation = manager.automation.clone();
+            let rule_id = rule_id.clone();
+            let action = increment(&alert_id, "subs");
+            tokio::spawn(async move {
+                automation
+                    .edit(&rule_id, move |rule| {
+                        rule.actions.push(action);
+                        Ok(())
+                    })
+                    .await
+                    .unwrap();
+            })
+        });
+        for edit in edits.collect::<Vec<_>>() {
+            edit.await.unwrap();
+        }
+
+        let reloaded = Automation::load(&manager.db_path).await.unwrap();
+        assert_eq!(reloaded.list().await[0].actions.len(), 20);
+    }
+}
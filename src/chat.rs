@@ -0,0 +1,736 @@
+//! Chat commands over Twitch IRC, enabled with `--twitch-chat-channel`.
+//!
+//! A message like `!death -1` runs the actions of the command `death` with the words after the
+//! command as variables, `$args` for all of them and `$1`, `$2`, ... for each. Commands without
+//! arguments use the default arguments of the command, so `!death` with the default `1` and an
+//! action incrementing `deaths` by `$1` counts up, while `!death -1` counts down.
+//!
+//! Commands are limited to a role read from the badges of the sender, and have a cooldown shared
+//! by everyone. The reply is sent after the actions succeeded, with the fields of the alerts the
+//! actions changed as variables, e.g. `Deaths: $deaths`. Commands are stored under `db_path/chat`.
+
+use leptos::{prelude::*, server};
+
+use crate::actions::{ActionOutcome, AlertAction};
+use crate::alerts::*;
+#[cfg(feature = "ssr")]
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+#[cfg(feature = "ssr")]
+use tokio::sync::{mpsc, Mutex, RwLock};
+
+#[cfg(feature = "ssr")]
+pub mod irc;
+
+/// Runs kept in the log.
+#[cfg(feature = "ssr")]
+const LOG_SIZE: usize = 50;
+/// Twitch drops longer messages.
+#[cfg(feature = "ssr")]
+const MAX_REPLY_LEN: usize = 500;
+
+#[aliri_braid::braid(serde)]
+pub struct ChatCommandId;
+
+impl ChatCommandId {
+    pub fn new_id() -> Self {
+        Self(nanoid::nanoid!(8))
+    }
+}
+
+/// Who may use a command, each role includes the ones above it.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    serde::Deserialize,
+    serde::Serialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatRole {
+    #[default]
+    Everyone,
+    Vip,
+    Moderator,
+    Broadcaster,
+}
+
+impl ChatRole {
+    pub const ALL: [ChatRole; 4] = [
+        ChatRole::Everyone,
+        ChatRole::Vip,
+        ChatRole::Moderator,
+        ChatRole::Broadcaster,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChatRole::Everyone => "everyone",
+            ChatRole::Vip => "vip",
+            ChatRole::Moderator => "moderator",
+            ChatRole::Broadcaster => "broadcaster",
+        }
+    }
+}
+
+impl std::str::FromStr for ChatRole {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "everyone" => Ok(ChatRole::Everyone),
+            "vip" => Ok(ChatRole::Vip),
+            "moderator" => Ok(ChatRole::Moderator),
+            "broadcaster" => Ok(ChatRole::Broadcaster),
+            _ => eyre::bail!("unknown role `{s}`"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct ChatCommand {
+    pub command_id: ChatCommandId,
+    /// Used as `!name`, lowercase.
+    pub name: String,
+    #[serde(default)]
+    pub role: ChatRole,
+    #[serde(default)]
+    pub cooldown_secs: u64,
+    /// Arguments used when the command is sent without any.
+    #[serde(default)]
+    pub default_args: String,
+    /// Run in order, on any alert.
+    #[serde(default)]
+    pub actions: Vec<AlertAction>,
+    /// Sent in chat after the actions succeeded, nothing is sent if empty.
+    #[serde(default)]
+    pub reply: String,
+}
+
+/// Sender of a chat message.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct ChatUser {
+    pub name: String,
+    pub role: ChatRole,
+}
+
+/// A command sent in chat.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct ChatRun {
+    /// RFC 3339 timestamp
+    pub at: String,
+    pub user: String,
+    pub message: String,
+    pub outcomes: Vec<ActionOutcome>,
+    pub reply: Option<String>,
+    /// Why the command didn't run, e.g. the cooldown.
+    pub skipped: Option<String>,
+}
+
+/// Split `!name args` into the lowercase name and the arguments.
+pub fn parse_command(message: &str) -> Option<(String, String)> {
+    let message = message.trim().strip_prefix('!')?;
+    let (name, args) = message
+        .split_once(char::is_whitespace)
+        .unwrap_or((message, ""));
+    if name.is_empty() {
+        return None;
+    }
+    Some((name.to_lowercase(), args.trim().to_owned()))
+}
+
+/// `user`, `args` and every argument as `1`, `2`, ...
+pub fn command_vars(user: &ChatUser, args: &str) -> Vec<(String, String)> {
+    let mut vars = vec![
+        ("user".to_owned(), user.name.clone()),
+        ("args".to_owned(), args.to_owned()),
+    ];
+    vars.extend(
+        args.split_whitespace()
+            .enumerate()
+            .map(|(i, arg)| ((i + 1).to_string(), arg.to_owned())),
+    );
+    vars
+}
+
+#[cfg(feature = "ssr")]
+fn reply_line(text: &str) -> String {
+    let line = text.replace(['\r', '\n'], " ");
+    let line = line.trim();
+    match line.char_indices().nth(MAX_REPLY_LEN) {
+        Some((end, _)) => line[..end].to_owned(),
+        None => line.to_owned(),
+    }
+}
+
+/// A reply waiting to be sent over the connection.
+#[cfg(feature = "ssr")]
+pub(crate) struct ChatReply {
+    /// Message replied to, from its `id` tag.
+    pub parent_id: Option<String>,
+    pub text: String,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Clone)]
+pub struct Chat {
+    configured: bool,
+    path: PathBuf,
+    commands: Arc<RwLock<BTreeMap<ChatCommandId, ChatCommand>>>,
+    last_used: Arc<Mutex<HashMap<ChatCommandId, Instant>>>,
+    log: Arc<RwLock<VecDeque<ChatRun>>>,
+    status: Arc<RwLock<String>>,
+    /// Replies for the current connection, `None` while disconnected or reading anonymously.
+    replies: Arc<RwLock<Option<mpsc::UnboundedSender<ChatReply>>>>,
+}
+
+#[cfg(feature = "ssr")]
+impl Chat {
+    pub async fn load(opts: &crate::opts::Opts) -> Result<Self, eyre::Report> {
+        let path = opts.db_path.join("chat");
+        tokio::fs::create_dir_all(&path).await?;
+        let mut commands = BTreeMap::new();
+        let mut dir = tokio::fs::read_dir(&path).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                let command: ChatCommand = serde_json::from_slice(&tokio::fs::read(&path).await?)?;
+                commands.insert(command.command_id.clone(), command);
+            }
+        }
+        Ok(Self {
+            configured: opts.twitch_chat_channel.is_some(),
+            path,
+            commands: Arc::new(RwLock::new(commands)),
+            last_used: Default::default(),
+            log: Default::default(),
+            status: Arc::new(RwLock::new("disconnected".to_owned())),
+            replies: Default::default(),
+        })
+    }
+
+    fn command_path(&self, command_id: &ChatCommandIdRef) -> PathBuf {
+        self.path.join(format!("{command_id}.json"))
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.configured
+    }
+
+    pub async fn status(&self) -> String {
+        self.status.read().await.clone()
+    }
+
+    async fn set_status(&self, status: impl Into<String>) {
+        *self.status.write().await = status.into();
+    }
+
+    pub async fn list(&self) -> Vec<ChatCommand> {
+        self.commands.read().await.values().cloned().collect()
+    }
+
+    pub async fn by_name(&self, name: &str) -> Option<ChatCommand> {
+        self.commands
+            .read()
+            .await
+            .values()
+            .find(|c| c.name == name)
+            .cloned()
+    }
+
+    pub async fn save(&self, command: ChatCommand) -> Result<(), eyre::Report> {
+        let mut commands = self.commands.write().await;
+        self.insert(&mut commands, command).await
+    }
+
+    /// Edit and save a command.
+    pub async fn edit(
+        &self,
+        command_id: &ChatCommandIdRef,
+        f: impl FnOnce(&mut ChatCommand) -> Result<(), eyre::Report>,
+    ) -> Result<(), ServerFnError> {
+        // held until the command is written, so concurrent edits don't undo each other
+        let mut commands = self.commands.write().await;
+        let Some(mut command) = commands.get(command_id).cloned() else {
+            return Err(ServerFnError::ServerError("no such command".to_owned()));
+        };
+        f(&mut command).map_err(|e| {
+            ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+        })?;
+        self.insert(&mut commands, command)
+            .await
+            .map_err(|e| ServerFnError::ServerError(e.to_string()))
+    }
+
+    /// Write and add a command, its name has to be unique.
+    async fn insert(
+        &self,
+        commands: &mut BTreeMap<ChatCommandId, ChatCommand>,
+        command: ChatCommand,
+    ) -> Result<(), eyre::Report> {
+        if commands
+            .values()
+            .any(|c| c.name == command.name && c.command_id != command.command_id)
+        {
+            eyre::bail!("there's already a command !{}", command.name);
+        }
+        tokio::fs::write(
+            self.command_path(&command.command_id),
+            serde_json::to_vec(&command)?,
+        )
+        .await?;
+        commands.insert(command.command_id.clone(), command);
+        Ok(())
+    }
+
+    pub async fn remove(&self, command_id: &ChatCommandIdRef) -> Result<(), eyre::Report> {
+        if self.commands.write().await.remove(command_id).is_some() {
+            tokio::fs::remove_file(self.command_path(command_id)).await?;
+        }
+        Ok(())
+    }
+
+    /// Latest runs first.
+    pub async fn runs(&self) -> Vec<ChatRun> {
+        self.log.read().await.iter().rev().cloned().collect()
+    }
+
+    async fn record(&self, run: ChatRun) {
+        let mut log = self.log.write().await;
+        if log.len() >= LOG_SIZE {
+            log.pop_front();
+        }
+        log.push_back(run);
+    }
+
+    /// Start the cooldown of a command, or the time left if it's still cooling down.
+    async fn start_cooldown(&self, command: &ChatCommand) -> Result<(), Duration> {
+        let cooldown = Duration::from_secs(command.cooldown_secs);
+        let mut last_used = self.last_used.lock().await;
+        let now = Instant::now();
+        if let Some(used) = last_used.get(&command.command_id) {
+            let elapsed = now.duration_since(*used);
+            if elapsed < cooldown {
+                return Err(cooldown - elapsed);
+            }
+        }
+        last_used.insert(command.command_id.clone(), now);
+        Ok(())
+    }
+
+    /// Send a reply in chat, dropped while disconnected.
+    pub(crate) async fn reply(&self, parent_id: Option<String>, text: &str) {
+        let Some(replies) = self.replies.read().await.clone() else {
+            tracing::debug!("not replying, chat is read only or disconnected");
+            return;
+        };
+        let _ = replies.send(ChatReply {
+            parent_id,
+            text: reply_line(text),
+        });
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl AlertManager {
+    /// Run the command in a chat message, if it is one.
+    pub async fn handle_chat_message(&self, user: &ChatUser, message: &str) -> Option<ChatRun> {
+        let (name, args) = parse_command(message)?;
+        let command = self.chat.by_name(&name).await?;
+        let mut run = ChatRun {
+            at: chrono::Utc::now().to_rfc3339(),
+            user: user.name.clone(),
+            message: message.trim().to_owned(),
+            outcomes: vec![],
+            reply: None,
+            skipped: None,
+        };
+        if user.role < command.role {
+            run.skipped = Some(format!("only for {}", command.role.as_str()));
+        } else if let Err(left) = self.chat.start_cooldown(&command).await {
+            run.skipped = Some(format!("cooling down, {}s left", left.as_secs() + 1));
+        } else {
+            let args = if args.is_empty() {
+                command.default_args.clone()
+            } else {
+                args
+            };
+            let mut vars = command_vars(user, &args);
+            tracing::info!(command = %command.name, user = %user.name, "running chat command");
+            run.outcomes = self.run_actions(&command.actions, &vars).await;
+            if run.outcomes.iter().all(|o| o.error.is_none()) && !command.reply.trim().is_empty() {
+                let mut seen = vec![];
+                for alert_id in command.actions.iter().filter_map(AlertAction::alert_id) {
+                    if seen.contains(&alert_id) {
+                        continue;
+                    }
+                    seen.push(alert_id);
+                    if let Ok(alert) = self.get_alert(alert_id).await {
+                        vars.extend(crate::automation::rule_vars(&alert));
+                    }
+                }
+                run.reply = Some(reply_line(&crate::actions::substitute_vars(
+                    &command.reply,
+                    &vars,
+                )));
+            }
+        }
+        self.chat.record(run.clone()).await;
+        Some(run)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct ChatOverview {
+    pub configured: bool,
+    pub status: String,
+    pub commands: Vec<ChatCommand>,
+    pub runs: Vec<ChatRun>,
+}
+
+#[server(ReadChat, "/backend")]
+pub async fn read_chat() -> Result<ChatOverview, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    Ok(ChatOverview {
+        configured: manager.chat.is_configured(),
+        status: manager.chat.status().await,
+        commands: manager.chat.list().await,
+        runs: manager.chat.runs().await,
+    })
+}
+
+/// Create a command, or update it if `command_id` isn't empty.
+#[server(SaveChatCommand, "/backend")]
+#[tracing::instrument(err)]
+pub async fn save_chat_command(
+    command_id: String,
+    name: String,
+    role: String,
+    cooldown_secs: u64,
+    default_args: String,
+    reply: String,
+) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    let name = name.trim().trim_start_matches('!').to_lowercase();
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(ServerFnError::ServerError(
+            "a command needs a name without spaces".to_owned(),
+        ));
+    }
+    let role: ChatRole = role.parse().map_err(|e: eyre::Report| {
+        ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+    })?;
+    let default_args = default_args.trim().to_owned();
+    if command_id.trim().is_empty() {
+        return manager
+            .chat
+            .save(ChatCommand {
+                command_id: ChatCommandId::new_id(),
+                name,
+                role,
+                cooldown_secs,
+                default_args,
+                actions: vec![],
+                reply,
+            })
+            .await
+            .map_err(|e| ServerFnError::ServerError(e.to_string()));
+    }
+    manager
+        .chat
+        .edit(
+            ChatCommandIdRef::from_str(command_id.trim()),
+            move |command| {
+                command.name = name;
+                command.role = role;
+                command.cooldown_secs = cooldown_secs;
+                command.default_args = default_args;
+                command.reply = reply;
+                Ok(())
+            },
+        )
+        .await
+}
+
+#[server(DeleteChatCommand, "/backend")]
+#[tracing::instrument(err)]
+pub async fn delete_chat_command(command_id: ChatCommandId) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager
+        .chat
+        .remove(&command_id)
+        .await
+        .map_err(|e| ServerFnError::ServerError(e.to_string()))
+}
+
+#[server(AddChatCommandAction, "/backend")]
+#[tracing::instrument(err)]
+pub async fn add_chat_command_action(
+    command_id: ChatCommandId,
+    kind: String,
+    alert_id: AlertId,
+    field: String,
+    value: String,
+) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager.get_alert(&alert_id).await?;
+    let action = AlertAction::from_form(&kind, alert_id, &field, value).map_err(|e| {
+        ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+    })?;
+    manager
+        .chat
+        .edit(&command_id, move |command| {
+            command.actions.push(action);
+            Ok(())
+        })
+        .await
+}
+
+#[server(RemoveChatCommandAction, "/backend")]
+#[tracing::instrument(err)]
+pub async fn remove_chat_command_action(
+    command_id: ChatCommandId,
+    index: usize,
+) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager
+        .chat
+        .edit(&command_id, move |command| {
+            if index >= command.actions.len() {
+                eyre::bail!("no such action");
+            }
+            command.actions.remove(index);
+            Ok(())
+        })
+        .await
+}
+
+/// Handle a message as if it was sent in chat by `user`, without replying in chat.
+///
+/// The actions run for real and the cooldown starts, like for a message from chat.
+#[server(SimulateChatMessage, "/backend")]
+#[tracing::instrument(err)]
+pub async fn simulate_chat_message(
+    user: String,
+    role: String,
+    message: String,
+) -> Result<Option<ChatRun>, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    let role: ChatRole = role.parse().map_err(|e: eyre::Report| {
+        ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+    })?;
+    let user = ChatUser {
+        name: Some(user.trim().to_owned())
+            .filter(|u| !u.is_empty())
+            .unwrap_or_else(|| "tester".to_owned()),
+        role,
+    };
+    Ok(manager.handle_chat_message(&user, &message).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(
+            parse_command("!Death -1"),
+            Some(("death".to_owned(), "-1".to_owned()))
+        );
+        assert_eq!(
+            parse_command("  !so  someone else  "),
+            Some(("so".to_owned(), "someone else".to_owned()))
+        );
+        assert_eq!(
+            parse_command("!death"),
+            Some(("death".to_owned(), String::new()))
+        );
+        assert_eq!(parse_command("death"), None);
+        assert_eq!(parse_command("!"), None);
+        assert_eq!(parse_command("! death"), None);
+    }
+
+    #[test]
+    fn vars() {
+        let user = ChatUser {
+            name: "Someone".to_owned(),
+            role: ChatRole::Everyone,
+        };
+        assert_eq!(
+            command_vars(&user, "a  b"),
+            [
+                ("user".to_owned(), "Someone".to_owned()),
+                ("args".to_owned(), "a  b".to_owned()),
+                ("1".to_owned(), "a".to_owned()),
+                ("2".to_owned(), "b".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn roles_include_the_ones_above() {
+        assert!(ChatRole::Broadcaster > ChatRole::Moderator);
+        assert!(ChatRole::Moderator > ChatRole::Vip);
+        assert!(ChatRole::Vip > ChatRole::Everyone);
+        for role in ChatRole::ALL {
+            assert_eq!(role.as_str().parse::<ChatRole>().unwrap(), role);
+        }
+    }
+
+    #[cfg(feature = "ssr")]
+    #[test]
+    fn reply_lines() {
+        assert_eq!(reply_line(" one\r\ntwo\n"), "one  two");
+        assert_eq!(reply_line(&"é".repeat(600)).chars().count(), MAX_REPLY_LEN);
+    }
+
+    #[cfg(feature = "ssr")]
+    #[tokio::test]
+    async fn cooldown() {
+        let (_dir, manager) = crate::alerts::test_manager(&[]).await;
+        let command = |cooldown_secs| ChatCommand {
+            command_id: ChatCommandId::new_id(),
+            name: "hi".to_owned(),
+            role: ChatRole::Everyone,
+            cooldown_secs,
+            default_args: String::new(),
+            actions: vec![],
+            reply: String::new(),
+        };
+        let cooling = command(60);
+        assert_eq!(manager.chat.start_cooldown(&cooling).await, Ok(()));
+        let left = manager.chat.start_cooldown(&cooling).await.unwrap_err();
+        assert!(left > Duration::from_secs(58) && left <= Duration::from_secs(60));
+        // each command has its own cooldown
+        let other = command(60);
+        assert_eq!(manager.chat.start_cooldown(&other).await, Ok(()));
+
+        let none = command(0);
+        assert_eq!(manager.chat.start_cooldown(&none).await, Ok(()));
+        assert_eq!(manager.chat.start_cooldown(&none).await, Ok(()));
+    }
+
+    #[cfg(feature = "ssr")]
+    #[tokio::test]
+    async fn skipped_runs() {
+        let (_dir, manager) = crate::alerts::test_manager(&[]).await;
+        let user = |role| ChatUser {
+            name: "Someone".to_owned(),
+            role,
+        };
+        manager
+            .chat
+            .save(ChatCommand {
+                command_id: ChatCommandId::new_id(),
+                name: "hi".to_owned(),
+                role: ChatRole::Vip,
+                cooldown_secs: 60,
+                default_args: String::new(),
+                actions: vec![],
+                reply: "Hi $user".to_owned(),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            manager
+                .handle_chat_message(&user(ChatRole::Vip), "!unknown")
+                .await,
+            None
+        );
+        let run = manager
+            .handle_chat_message(&user(ChatRole::Everyone), "!hi")
+            .await
+            .unwrap();
+        assert_eq!(run.skipped.as_deref(), Some("only for vip"));
+        let run = manager
+            .handle_chat_message(&user(ChatRole::Moderator), "!HI")
+            .await
+            .unwrap();
+        assert_eq!(run.skipped, None);
+        assert_eq!(run.reply.as_deref(), Some("Hi Someone"));
+        let run = manager
+            .handle_chat_message(&user(ChatRole::Vip), "!hi")
+            .await
+            .unwrap();
+        assert_eq!(run.skipped.as_deref(), Some("cooling down, 60s left"));
+        assert_eq!(manager.chat.runs().await.len(), 3);
+    }
+
+    #[cfg(feature = "ssr")]
+    #[tokio::test]
+    async fn concurrent_edits_are_kept() {
+        let (_dir, manager) = crate::alerts::test_manager(&[]).await;
+        let command = |name: &str| ChatCommand {
+            command_id: ChatCommandId::new_id(),
+            name: name.to_owned(),
+            role: ChatRole::Everyone,
+            cooldown_secs: 0,
+            default_args: String::new(),
+            actions: vec![],
+            reply: String::new(),
+        };
+        let hi = command("hi");
+        let command_id = hi.command_id.clone();
+        manager.chat.save(hi).await.unwrap();
+        manager.chat.save(command("bye")).await.unwrap();
+
+        let edits = (0..20).map(|_| {
+            let chat = manager.chat.clone();
+            let command_id = command_id.clone();
+            tokio::spawn(async move {
+                chat.edit(&command_id, |command| {
+                    command.cooldown_secs += 1;
+                    Ok(())
+                })
+                .await
+                .unwrap();
+            })
+        });
+        for edit in edits.collect::<Vec<_>>() {
+            edit.await.unwrap();
+        }
+        // names stay unique when editing
+        let renamed = manager
+            .chat
+            .edit(&command_id, |command| {
+                command.name = "bye".to_owned();
+                Ok(())
+            })
+            .await;
+        assert!(renamed.is_err());
+
+        let opts = crate::alerts::test_opts(&manager.db_path, &[]);
+        let reloaded = Chat::load(&opts).await.unwrap();
+        let hi = reloaded.by_name("hi").await.unwrap();
+        assert_eq!(hi.cooldown_secs, 20);
+    }
+}
@@ -0,0 +1,564 @@
+//! The Twitch chat client, IRC over a websocket or plain TCP.
+//!
+//! The server is configurable with `--twitch-chat-url`, so the client can be pointed at a local
+//! IRC server, e.g. `irc://127.0.0.1:6667`. Without a nick and token chat is read anonymously and
+//! replies aren't sent. See <https://dev.twitch.tv/docs/chat/irc/>
+
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use futures::{SinkExt, StreamExt};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+use super::{ChatReply, ChatRole, ChatUser};
+use crate::alerts::AlertManager;
+use crate::opts::{Opts, Secret};
+
+type WebSocket =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// Time the server has to welcome us after logging in.
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(10);
+/// Nick used to read chat without logging in.
+const ANONYMOUS_NICK: &str = "justinfan31415";
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    url: String,
+    /// Without the `#`, lowercase.
+    channel: String,
+    login: Option<(String, Secret)>,
+}
+
+impl Config {
+    pub fn from_opts(opts: &Opts) -> Option<Self> {
+        let channel = opts
+            .twitch_chat_channel
+            .as_deref()?
+            .trim()
+            .trim_start_matches('#')
+            .to_lowercase();
+        let token = opts
+            .twitch_chat_token
+            .clone()
+            .or_else(|| opts.twitch_access_token.clone());
+        let login = match (&opts.twitch_chat_nick, token) {
+            (Some(nick), Some(token)) => Some((nick.to_lowercase(), token)),
+            (None, _) => None,
+            (Some(_), None) => {
+                tracing::warn!("chat needs a token to log in, reading chat anonymously");
+                None
+            }
+        };
+        Some(Self {
+            url: opts.twitch_chat_url.clone(),
+            channel,
+            login,
+        })
+    }
+}
+
+/// A line of IRC, `@tags :prefix COMMAND params :trailing`
+#[derive(Debug, Default, PartialEq)]
+struct IrcMessage {
+    tags: HashMap<String, String>,
+    prefix: Option<String>,
+    command: String,
+    params: Vec<String>,
+}
+
+impl IrcMessage {
+    fn parse(line: &str) -> Option<Self> {
+        let mut rest = line.trim_end_matches(['\r', '\n']);
+        let mut message = IrcMessage::default();
+        if let Some(tags) = rest.strip_prefix('@') {
+            let (tags, tail) = tags.split_once(' ')?;
+            message.tags = tags
+                .split(';')
+                .map(|tag| {
+                    let (key, value) = tag.split_once('=').unwrap_or((tag, ""));
+                    (key.to_owned(), unescape_tag(value))
+                })
+                .collect();
+            rest = tail.trim_start();
+        }
+        if let Some(prefix) = rest.strip_prefix(':') {
+            let (prefix, tail) = prefix.split_once(' ')?;
+            message.prefix = Some(prefix.to_owned());
+            rest = tail.trim_start();
+        }
+        let (command, mut rest) = rest.split_once(' ').unwrap_or((rest, ""));
+        if command.is_empty() {
+            return None;
+        }
+        message.command = command.to_owned();
+        while !rest.is_empty() {
+            if let Some(trailing) = rest.strip_prefix(':') {
+                message.params.push(trailing.to_owned());
+                break;
+            }
+            let (param, tail) = rest.split_once(' ').unwrap_or((rest, ""));
+            message.params.push(param.to_owned());
+            rest = tail.trim_start();
+        }
+        Some(message)
+    }
+
+    /// Nick of the sender, from `nick!user@host`
+    fn nick(&self) -> Option<&str> {
+        self.prefix.as_deref()?.split('!').next()
+    }
+
+    fn tag(&self, key: &str) -> Option<&str> {
+        self.tags
+            .get(key)
+            .map(String::as_str)
+            .filter(|v| !v.is_empty())
+    }
+
+    /// The sender of a `PRIVMSG`, with the highest role of their badges.
+    fn chat_user(&self) -> Option<ChatUser> {
+        let badges: Vec<&str> = self
+            .tag("badges")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|badge| badge.split('/').next())
+            .collect();
+        let role = if badges.contains(&"broadcaster") {
+            ChatRole::Broadcaster
+        } else if badges.contains(&"moderator") || self.tag("mod") == Some("1") {
+            ChatRole::Moderator
+        } else if badges.contains(&"vip") || self.tags.contains_key("vip") {
+            ChatRole::Vip
+        } else {
+            ChatRole::Everyone
+        };
+        Some(ChatUser {
+            name: self.tag("display-name").or_else(|| self.nick())?.to_owned(),
+            role,
+        })
+    }
+}
+
+fn unescape_tag(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(':') => unescaped.push(';'),
+            Some('s') => unescaped.push(' '),
+            Some('r') => unescaped.push('\r'),
+            Some('n') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+/// Lines of IRC, over a websocket or TCP.
+enum Connection {
+    WebSocket {
+        ws: Box<WebSocket>,
+        /// A websocket message can hold several lines.
+        buffered: VecDeque<String>,
+    },
+    Tcp {
+        stream: BufReader<tokio::net::TcpStream>,
+        /// Kept between reads, as a read can be cancelled halfway through a line.
+        partial: Vec<u8>,
+    },
+}
+
+impl Connection {
+    async fn connect(url: &str) -> Result<Self, eyre::Report> {
+        if let Some(addr) = url.strip_prefix("irc://") {
+            let stream = tokio::net::TcpStream::connect(addr.trim_end_matches('/')).await?;
+            return Ok(Connection::Tcp {
+                stream: BufReader::new(stream),
+                partial: vec![],
+            });
+        }
+        let (ws, _) = tokio_tungstenite::connect_async(url).await?;
+        Ok(Connection::WebSocket {
+            ws: Box::new(ws),
+            buffered: VecDeque::new(),
+        })
+    }
+
+    async fn send(&mut self, line: &str) -> Result<(), eyre::Report> {
+        match self {
+            Connection::WebSocket { ws, .. } => ws.send(Message::Text(line.to_owned())).await?,
+            Connection::Tcp { stream, .. } => {
+                stream
+                    .get_mut()
+                    .write_all(format!("{line}\r\n").as_bytes())
+                    .await?
+            }
+        }
+        Ok(())
+    }
+
+    async fn next_line(&mut self) -> Result<String, eyre::Report> {
+        match self {
+            Connection::WebSocket { ws, buffered } => loop {
+                if let Some(line) = buffered.pop_front() {
+                    return Ok(line);
+                }
+                match ws.next().await {
+                    Some(Ok(Message::Text(text))) => buffered.extend(
+                        text.split("\r\n")
+                            .filter(|line| !line.is_empty())
+                            .map(str::to_owned),
+                    ),
+                    Some(Ok(Message::Close(_))) | None => eyre::bail!("connection closed"),
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => return Err(e.into()),
+                }
+            },
+            Connection::Tcp { stream, partial } => {
+                if stream.read_until(b'\n', partial).await? == 0 {
+                    eyre::bail!("connection closed");
+                }
+                let line = String::from_utf8_lossy(partial).into_owned();
+                partial.clear();
+                Ok(line)
+            }
+        }
+    }
+
+    /// The next message, answering pings.
+    async fn next_message(&mut self) -> Result<IrcMessage, eyre::Report> {
+        loop {
+            let line = self.next_line().await?;
+            let Some(message) = IrcMessage::parse(&line) else {
+                continue;
+            };
+            if message.command == "PING" {
+                let token = message.params.first().map(String::as_str).unwrap_or("");
+                self.send(&format!("PONG :{token}")).await?;
+                continue;
+            }
+            return Ok(message);
+        }
+    }
+}
+
+/// Run the client forever, reconnecting with a backoff when the connection is lost.
+pub async fn run(manager: AlertManager, config: Config) {
+    let mut attempts = 0u32;
+    loop {
+        manager.chat.set_status("connecting").await;
+        let error = match session(&manager, &config, &mut attempts).await {
+            Ok(()) => eyre::eyre!("server asked to reconnect"),
+            Err(error) => error,
+        };
+        *manager.chat.replies.write().await = None;
+        tracing::debug!(%error, "chat disconnected");
+        manager
+            .chat
+            .set_status(format!("disconnected: {error}"))
+            .await;
+        let delay = Duration::from_secs(1 << attempts.min(6)).min(MAX_BACKOFF);
+        attempts += 1;
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Handle one connection, until it's lost or the server asks to reconnect.
+async fn session(
+    manager: &AlertManager,
+    config: &Config,
+    attempts: &mut u32,
+) -> Result<(), eyre::Report> {
+    let mut connection = Connection::connect(&config.url).await?;
+    tokio::time::timeout(LOGIN_TIMEOUT, login(&mut connection, config))
+        .await
+        .map_err(|_| eyre::eyre!("chat server didn't welcome us"))??;
+    connection
+        .send(&format!("JOIN #{}", config.channel))
+        .await?;
+    *attempts = 0;
+    tracing::info!(channel = %config.channel, "connected to chat");
+    manager
+        .chat
+        .set_status(match config.login {
+            Some(_) => "connected",
+            None => "connected, read only",
+        })
+        .await;
+
+    let (sender, mut replies) = mpsc::unbounded_channel::<ChatReply>();
+    if config.login.is_some() {
+        *manager.chat.replies.write().await = Some(sender);
+    }
+    let channel = format!("#{}", config.channel);
+    loop {
+        tokio::select! {
+            Some(reply) = replies.recv() => {
+                let line = match reply.parent_id {
+                    Some(id) => format!("@reply-parent-msg-id={id} PRIVMSG {channel} :{}", reply.text),
+                    None => format!("PRIVMSG {channel} :{}", reply.text),
+                };
+                connection.send(&line).await?;
+            }
+            message = connection.next_message() => {
+                let message = message?;
+                match message.command.as_str() {
+                    "PRIVMSG" if message.params.first() == Some(&channel) => {
+                        let Some(text) = message.params.get(1) else {
+                            continue;
+                        };
+                        if !text.starts_with('!') {
+                            continue;
+                        }
+                        let Some(user) = message.chat_user() else {
+                            continue;
+                        };
+                        let parent_id = message.tag("id").map(str::to_owned);
+                        let text = text.clone();
+                        let manager = manager.clone();
+                        tokio::spawn(async move {
+                            let Some(run) = manager.handle_chat_message(&user, &text).await else {
+                                return;
+                            };
+                            for outcome in &run.outcomes {
+                                if let Some(error) = &outcome.error {
+                                    tracing::warn!(message = %run.message, action = %outcome.action, %error, "chat command failed");
+                                }
+                            }
+                            if let Some(reply) = run.reply {
+                                manager.chat.reply(parent_id, &reply).await;
+                            }
+                        });
+                    }
+                    "NOTICE" => {
+                        let notice = message.params.last().cloned().unwrap_or_default();
+                        tracing::info!(%notice, "chat notice");
+                    }
+                    "RECONNECT" => return Ok(()),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// Log in and wait for the welcome, Twitch sends a notice instead when the token is rejected.
+async fn login(connection: &mut Connection, config: &Config) -> Result<(), eyre::Report> {
+    connection
+        .send("CAP REQ :twitch.tv/tags twitch.tv/commands")
+        .await?;
+    match &config.login {
+        Some((nick, token)) => {
+            let token = token.secret().trim_start_matches("oauth:");
+            connection.send(&format!("PASS oauth:{token}")).await?;
+            connection.send(&format!("NICK {nick}")).await?;
+        }
+        None => connection.send(&format!("NICK {ANONYMOUS_NICK}")).await?,
+    }
+    loop {
+        let message = connection.next_message().await?;
+        match message.command.as_str() {
+            "001" => return Ok(()),
+            "NOTICE" => eyre::bail!(
+                "login failed: {}",
+                message.params.last().cloned().unwrap_or_default()
+            ),
+            _ => continue,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::AlertAction;
+    use crate::alerts::{AlertField, AlertFieldName};
+    use crate::chat::{ChatCommand, ChatCommandId};
+
+    #[test]
+    fn parse_privmsg() {
+        let message = IrcMessage::parse(
+            "@badge-info=;badges=moderator/1;display-name=Mod;id=abc-1 \
+             :mod!mod@mod.tmi.twitch.tv PRIVMSG #channel :!death  2\r\n",
+        )
+        .unwrap();
+        assert_eq!(message.command, "PRIVMSG");
+        assert_eq!(message.prefix.as_deref(), Some("mod!mod@mod.tmi.twitch.tv"));
+        assert_eq!(message.nick(), Some("mod"));
+        assert_eq!(message.params, ["#channel", "!death  2"]);
+        assert_eq!(message.tag("id"), Some("abc-1"));
+        assert_eq!(message.tag("badge-info"), None);
+    }
+
+    #[test]
+    fn parse_without_tags_or_prefix() {
+        let message = IrcMessage::parse("PING :tmi.twitch.tv").unwrap();
+        assert_eq!(message.command, "PING");
+        assert_eq!(message.prefix, None);
+        assert_eq!(message.params, ["tmi.twitch.tv"]);
+
+        let message = IrcMessage::parse(":tmi.twitch.tv 001 bot :Welcome, GLHF!").unwrap();
+        assert_eq!(message.command, "001");
+        assert_eq!(message.params, ["bot", "Welcome, GLHF!"]);
+
+        let message = IrcMessage::parse("RECONNECT").unwrap();
+        assert_eq!(message.command, "RECONNECT");
+        assert!(message.params.is_empty());
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert_eq!(IrcMessage::parse(""), None);
+        assert_eq!(IrcMessage::parse("@tags-without-a-command"), None);
+        assert_eq!(IrcMessage::parse(":prefix-only"), None);
+    }
+
+    #[test]
+    fn unescape_tags() {
+        assert_eq!(unescape_tag(r"hello\sworld"), "hello world");
+        assert_eq!(unescape_tag(r"a\:b"), "a;b");
+        assert_eq!(unescape_tag(r"back\\slash"), r"back\slash");
+        assert_eq!(unescape_tag(r"line\r\n"), "line\r\n");
+        assert_eq!(unescape_tag(r"unknown\x"), "unknownx");
+        assert_eq!(unescape_tag(r"trailing\"), "trailing");
+    }
+
+    fn role(tags: &str) -> ChatRole {
+        IrcMessage::parse(&format!("@{tags} :user!user@host PRIVMSG #channel :!hi"))
+            .unwrap()
+            .chat_user()
+            .unwrap()
+            .role
+    }
+
+    #[test]
+    fn chat_user_roles() {
+        assert_eq!(
+            role("badges=broadcaster/1,subscriber/0"),
+            ChatRole::Broadcaster
+        );
+        assert_eq!(role("badges=moderator/1"), ChatRole::Moderator);
+        assert_eq!(role("badges=;mod=1"), ChatRole::Moderator);
+        assert_eq!(role("badges=vip/1"), ChatRole::Vip);
+        assert_eq!(role("badges=;vip=1"), ChatRole::Vip);
+        assert_eq!(role("badges=subscriber/12;mod=0"), ChatRole::Everyone);
+        assert_eq!(role("badges="), ChatRole::Everyone);
+    }
+
+    #[test]
+    fn chat_user_name() {
+        let message =
+            IrcMessage::parse("@display-name=Someone :someone!someone@host PRIVMSG #c :!hi")
+                .unwrap();
+        assert_eq!(message.chat_user().unwrap().name, "Someone");
+        let message =
+            IrcMessage::parse("@display-name= :someone!someone@host PRIVMSG #c :!hi").unwrap();
+        assert_eq!(message.chat_user().unwrap().name, "someone");
+    }
+
+    async fn next_line(
+        lines: &mut tokio::io::Lines<BufReader<tokio::net::tcp::OwnedReadHalf>>,
+    ) -> String {
+        tokio::time::timeout(Duration::from_secs(5), lines.next_line())
+            .await
+            .expect("the client sent a line")
+            .unwrap()
+            .expect("the client is connected")
+    }
+
+    #[tokio::test]
+    async fn command_over_tcp() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("irc://{}", listener.local_addr().unwrap());
+        // starts the client
+        let (_dir, manager) = crate::alerts::test_manager(&[
+            "--twitch-chat-channel",
+            "#Channel",
+            "--twitch-chat-nick",
+            "Bot",
+            "--twitch-chat-token",
+            "token",
+            "--twitch-chat-url",
+            &url,
+        ])
+        .await;
+        let alert_id = crate::alerts::test_alert(&manager, "deaths").await;
+        manager
+            .chat
+            .save(ChatCommand {
+                command_id: ChatCommandId::new_id(),
+                name: "death".to_owned(),
+                role: ChatRole::Moderator,
+                cooldown_secs: 0,
+                default_args: "1".to_owned(),
+                actions: vec![AlertAction::IncrementField {
+                    alert_id: alert_id.clone(),
+                    field: AlertFieldName::from("deaths"),
+                    amount: "$1".to_owned(),
+                }],
+                reply: "Deaths: $deaths".to_owned(),
+            })
+            .await
+            .unwrap();
+        let mut changes = manager.changes.subscribe();
+
+        let (stream, _) = tokio::time::timeout(Duration::from_secs(5), listener.accept())
+            .await
+            .expect("the client connected")
+            .unwrap();
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+
+        assert_eq!(
+            next_line(&mut lines).await,
+            "CAP REQ :twitch.tv/tags twitch.tv/commands"
+        );
+        assert_eq!(next_line(&mut lines).await, "PASS oauth:token");
+        assert_eq!(next_line(&mut lines).await, "NICK bot");
+        write
+            .write_all(b":tmi.twitch.tv 001 bot :Welcome, GLHF!\r\n")
+            .await
+            .unwrap();
+        assert_eq!(next_line(&mut lines).await, "JOIN #channel");
+
+        write.write_all(b"PING :tmi.twitch.tv\r\n").await.unwrap();
+        assert_eq!(next_line(&mut lines).await, "PONG :tmi.twitch.tv");
+
+        // not a moderator, and not a command
+        write
+            .write_all(
+                b"@badges=;display-name=Viewer;id=m1 :viewer!viewer@host PRIVMSG #channel :!death\r\n\
+                  @badges=moderator/1;display-name=Mod;id=m2 :mod!mod@host PRIVMSG #channel :hello\r\n\
+                  @badges=moderator/1;display-name=Mod;id=m3 :mod!mod@host PRIVMSG #channel :!death 2\r\n",
+            )
+            .await
+            .unwrap();
+        let change = tokio::time::timeout(Duration::from_secs(5), changes.recv())
+            .await
+            .expect("the command ran")
+            .unwrap();
+        assert_eq!(change.alert_id, alert_id);
+        assert_eq!(change.fields[0].value, Some(AlertField::Counter(2)));
+        assert_eq!(
+            next_line(&mut lines).await,
+            "@reply-parent-msg-id=m3 PRIVMSG #channel :Deaths: 2"
+        );
+
+        let runs = manager.chat.runs().await;
+        assert_eq!(runs.len(), 2);
+        let viewer = runs.iter().find(|run| run.user == "Viewer").unwrap();
+        assert_eq!(viewer.skipped.as_deref(), Some("only for moderator"));
+        let moderator = runs.iter().find(|run| run.user == "Mod").unwrap();
+        assert_eq!(moderator.reply.as_deref(), Some("Deaths: 2"));
+        assert!(moderator.outcomes.iter().all(|o| o.error.is_none()));
+    }
+}
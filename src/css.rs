@@ -0,0 +1,577 @@
+//! Validation and scoping of alert css.
+//!
+//! This is not a full css parser, it checks the structure of the stylesheet: balanced blocks,
+//! terminated strings and comments, and declarations in the form `name: value`.
+
+use std::fmt;
+
+/// Selector of the element wrapping everything on the overlay page.
+pub const ALERT_ROOT: &str = "#alert-root";
+
+/// Ids of the elements on the overlay page. A scene shows several alerts on one page, so there
+/// they are only marked with `data-part`.
+pub const PARTS: &[&str] = &[
+    "alert-root",
+    "layers",
+    "text",
+    "media",
+    "event",
+    "event-media",
+    "dynamic-style",
+    "event-style",
+];
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct CssError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CssError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// All errors as one [`ServerFnError`], one error per line.
+pub fn server_error(errors: Vec<CssError>) -> leptos::prelude::ServerFnError {
+    leptos::prelude::ServerFnError::ServerError(
+        errors
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n"),
+    )
+}
+
+/// At-rules that contain rules, which are scoped like top level rules.
+const NESTING_AT_RULES: &[&str] = &["media", "supports", "container", "layer", "document"];
+
+/// Check the structure of `css`, returning all errors found.
+///
+/// Anything that would end the surrounding `<style>` element is an error, as are `@import` and
+/// `javascript:` urls.
+pub fn validate(css: &str) -> Result<(), Vec<CssError>> {
+    let mut errors = vec![];
+    let mut error = |pos: usize, message: String| {
+        let before = &css[..pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+        errors.push(CssError {
+            line,
+            column,
+            message,
+        });
+    };
+
+    // the html parser ends the element anywhere, also in css strings and comments
+    for (pos, _) in css.match_indices('<') {
+        if starts_with_ignore_case(&css[pos..], "</style") {
+            error(pos, "`</style` is not allowed".to_owned());
+        } else if css[pos..].starts_with("<!--") {
+            error(pos, "`<!--` is not allowed".to_owned());
+        }
+    }
+
+    // open brackets and blocks, with their position
+    let mut stack: Vec<(char, usize)> = vec![];
+    // start of the current statement, declaration or prelude
+    let mut segment_start = 0;
+    let mut chars = css.char_indices().peekable();
+    while let Some((pos, c)) = chars.next() {
+        match c {
+            '@' if starts_with_ignore_case(&css[pos..], "@import") => {
+                error(pos, "`@import` is not allowed".to_owned());
+            }
+            'u' | 'U'
+                if starts_with_ignore_case(&css[pos..], "url(")
+                    && is_script_url(&css[pos + 4..]) =>
+            {
+                error(pos, "`javascript:` urls are not allowed".to_owned());
+            }
+            '\\' => {
+                chars.next();
+            }
+            '/' if chars.peek().map(|(_, c)| *c) == Some('*') => {
+                chars.next();
+                let mut closed = false;
+                while let Some((_, c)) = chars.next() {
+                    if c == '*' && chars.peek().map(|(_, c)| *c) == Some('/') {
+                        chars.next();
+                        closed = true;
+                        break;
+                    }
+                }
+                if !closed {
+                    error(pos, "unterminated comment".to_owned());
+                }
+            }
+            '"' | '\'' => {
+                let mut closed = false;
+                while let Some((_, s)) = chars.next() {
+                    match s {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '\n' => break,
+                        s if s == c => {
+                            closed = true;
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+                if !closed {
+                    error(pos, "unterminated string".to_owned());
+                }
+            }
+            '(' | '[' => stack.push((c, pos)),
+            ')' | ']' => {
+                let open = if c == ')' { '(' } else { '[' };
+                match stack.last() {
+                    Some((o, _)) if *o == open => {
+                        stack.pop();
+                    }
+                    _ => error(pos, format!("unexpected `{c}`")),
+                }
+            }
+            '{' if !in_brackets(&stack) => {
+                if css[segment_start..pos].trim().is_empty() {
+                    error(pos, "missing selector".to_owned());
+                }
+                stack.push(('{', pos));
+                segment_start = pos + 1;
+            }
+            ';' | '}' if !in_brackets(&stack) => {
+                let segment = strip_comments(&css[segment_start..pos]);
+                let segment = segment.trim();
+                let in_block = stack.last().is_some_and(|(o, _)| *o == '{');
+                if !segment.is_empty() && !segment.starts_with('@') {
+                    if !in_block {
+                        error(segment_start, format!("unexpected `{c}`"));
+                    } else if !segment.contains(':') {
+                        error(
+                            segment_start,
+                            format!("expected `name: value`, found `{segment}`"),
+                        );
+                    }
+                }
+                if c == '}' {
+                    if in_block {
+                        stack.pop();
+                    } else {
+                        error(pos, "unexpected `}`".to_owned());
+                    }
+                }
+                segment_start = pos + 1;
+            }
+            _ => {}
+        }
+    }
+    for (open, pos) in stack {
+        error(pos, format!("unclosed `{open}`"));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn starts_with_ignore_case(css: &str, prefix: &str) -> bool {
+    css.get(..prefix.len())
+        .is_some_and(|s| s.eq_ignore_ascii_case(prefix))
+}
+
+/// Whether the argument of a `url(` has a `javascript:` scheme.
+fn is_script_url(args: &str) -> bool {
+    let url: String = args
+        .trim_start_matches(|c: char| c.is_whitespace() || c == '"' || c == '\'')
+        .chars()
+        .filter(|c| *c != '\\' && !c.is_whitespace())
+        .take("javascript:".len())
+        .collect();
+    url.eq_ignore_ascii_case("javascript:")
+}
+
+fn in_brackets(stack: &[(char, usize)]) -> bool {
+    stack.last().is_some_and(|(o, _)| *o != '{')
+}
+
+fn strip_comments(css: &str) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("/*") {
+        out.push_str(&rest[..start]);
+        rest = match rest[start + 2..].find("*/") {
+            Some(end) => &rest[start + 2 + end + 2..],
+            None => "",
+        };
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Replace `asset("name")` with the url of the asset, see [`crate::media::assets_router`].
+pub fn expand_assets(css: &str) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("asset(") {
+        let args = &rest[start + "asset(".len()..];
+        let quote = args.chars().next().filter(|c| *c == '"' || *c == '\'');
+        let name_end = quote.and_then(|q| args[1..].find(q));
+        // `asset(` as part of another identifier, e.g. `my-asset(`
+        let is_ident = rest[..start]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_alphanumeric() || c == '-' || c == '_');
+        match name_end {
+            Some(end) if !is_ident && args[1 + end + 1..].trim_start().starts_with(')') => {
+                let name = &args[1..1 + end];
+                let close = 1 + end + 1 + args[1 + end + 1..].find(')').unwrap_or(0);
+                out.push_str(&rest[..start]);
+                out.push_str(&format!("url(\"/assets/{}\")", encode_path_segment(name)));
+                rest = &args[close + 1..];
+            }
+            _ => {
+                out.push_str(&rest[..start + "asset(".len()]);
+                rest = args;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn encode_path_segment(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            b => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// Make `css` safe to put in a `<style>` element, even if it wasn't validated.
+pub fn neutralize(css: &str) -> String {
+    // `\/` is `/` in css, but doesn't end the element
+    css.replace("</", "<\\/")
+}
+
+/// Index of the first `{` or `;` outside of strings, comments and brackets.
+fn next_boundary(css: &str) -> Option<(usize, char)> {
+    let mut depth = 0usize;
+    let mut chars = css.char_indices().peekable();
+    while let Some((pos, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '/' if chars.peek().map(|(_, c)| *c) == Some('*') => {
+                chars.next();
+                while let Some((_, c)) = chars.next() {
+                    if c == '*' && chars.peek().map(|(_, c)| *c) == Some('/') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            '"' | '\'' => {
+                while let Some((_, s)) = chars.next() {
+                    if s == '\\' {
+                        chars.next();
+                    } else if s == c || s == '\n' {
+                        break;
+                    }
+                }
+            }
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            '{' | ';' if depth == 0 => return Some((pos, c)),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Index of the `}` closing the block `css` starts in, or the end of `css`.
+fn block_end(css: &str) -> usize {
+    let mut depth = 1usize;
+    let mut chars = css.char_indices().peekable();
+    while let Some((pos, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '/' if chars.peek().map(|(_, c)| *c) == Some('*') => {
+                chars.next();
+                while let Some((_, c)) = chars.next() {
+                    if c == '*' && chars.peek().map(|(_, c)| *c) == Some('/') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            '"' | '\'' => {
+                while let Some((_, s)) = chars.next() {
+                    if s == '\\' {
+                        chars.next();
+                    } else if s == c || s == '\n' {
+                        break;
+                    }
+                }
+            }
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return pos;
+                }
+            }
+            _ => {}
+        }
+    }
+    css.len()
+}
+
+/// Prefix every rule in `css` with `scope`, so it only applies inside that element.
+///
+/// `html`, `body` and `:root` are replaced with `scope`, rules in `@media`, `@supports` and
+/// similar are scoped as well, other at-rules like `@keyframes` are left as is.
+pub fn scope(css: &str, scope: &str) -> String {
+    scope_with(css, scope, false)
+}
+
+/// Like [`scope`], also replacing id selectors of [`PARTS`] with their `data-part` attribute, for
+/// styles of alerts in a scene.
+pub fn scope_parts(css: &str, scope: &str) -> String {
+    scope_with(css, scope, true)
+}
+
+fn scope_with(css: &str, scope: &str, parts: bool) -> String {
+    let mut out = String::with_capacity(css.len() + 64);
+    let mut rest = css;
+    while let Some((pos, c)) = next_boundary(rest) {
+        let prelude = &rest[..pos];
+        if c == ';' {
+            out.push_str(&rest[..=pos]);
+            rest = &rest[pos + 1..];
+            continue;
+        }
+        let body_start = pos + 1;
+        let body_end = body_start + block_end(&rest[body_start..]);
+        let body = &rest[body_start..body_end.min(rest.len())];
+        let trimmed = prelude.trim();
+        if let Some(at_rule) = trimmed.strip_prefix('@') {
+            let name: String = at_rule
+                .chars()
+                .take_while(|c| c.is_alphanumeric() || *c == '-')
+                .collect();
+            out.push_str(prelude);
+            out.push('{');
+            if NESTING_AT_RULES.contains(&name.to_ascii_lowercase().as_str()) {
+                out.push_str(&scope_with(body, scope, parts));
+            } else {
+                out.push_str(body);
+            }
+        } else {
+            let leading = &prelude[..prelude.len() - prelude.trim_start().len()];
+            out.push_str(leading);
+            let selectors = scope_selectors(trimmed, scope);
+            if parts {
+                out.push_str(&part_attributes(&selectors));
+            } else {
+                out.push_str(&selectors);
+            }
+            out.push_str(" {");
+            out.push_str(body);
+        }
+        if body_end < rest.len() {
+            out.push('}');
+            rest = &rest[body_end + 1..];
+        } else {
+            rest = "";
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn scope_selectors(selectors: &str, scope: &str) -> String {
+    split_selectors(selectors)
+        .into_iter()
+        .map(|selector| {
+            let selector = selector.trim();
+            for root in ["html", "body", ":root"] {
+                if selector == root {
+                    return scope.to_owned();
+                }
+                if let Some(rest) = selector.strip_prefix(root) {
+                    if rest.starts_with([' ', '>', '+', '~', '.', ':', '[', '#']) {
+                        return format!("{scope}{rest}");
+                    }
+                }
+            }
+            if selector.starts_with(scope) {
+                selector.to_owned()
+            } else {
+                format!("{scope} {selector}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Replace `#text` and the other [`PARTS`] in `selectors` with `[data-part="text"]`
+fn part_attributes(selectors: &str) -> String {
+    let mut out = String::with_capacity(selectors.len());
+    let mut quote = None;
+    let mut rest = selectors;
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => {
+                let len = rest
+                    .find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))
+                    .unwrap_or(rest.len());
+                let id = &rest[..len];
+                if PARTS.contains(&id) {
+                    out.push_str(&format!("[data-part=\"{id}\"]"));
+                    rest = &rest[len..];
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Split a selector list on commas outside of brackets and strings.
+fn split_selectors(selectors: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut start = 0;
+    for (pos, c) in selectors.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(' | '[') => depth += 1,
+            (None, ')' | ']') => depth = depth.saturating_sub(1),
+            (None, ',') if depth == 0 => {
+                parts.push(&selectors[start..pos]);
+                start = pos + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&selectors[start..]);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scope_parts_replaces_ids() {
+        let scoped = scope_parts(
+            "#text, #event-media > img {color: #fff;}\n#textual a[href=\"#text\"] {top: 0;}",
+            "[data-alert-id=\"a\"]",
+        );
+        assert_eq!(
+            scoped,
+            "[data-alert-id=\"a\"] [data-part=\"text\"], [data-alert-id=\"a\"] [data-part=\"event-media\"] > img {color: #fff;}\n[data-alert-id=\"a\"] #textual a[href=\"#text\"] {top: 0;}"
+        );
+    }
+
+    #[test]
+    fn scope_keeps_ids() {
+        assert_eq!(
+            scope("body #text {top: 0;}", ALERT_ROOT),
+            "#alert-root #text {top: 0;}"
+        );
+    }
+
+    fn messages(css: &str) -> Vec<String> {
+        validate(css)
+            .unwrap_err()
+            .into_iter()
+            .map(|e| e.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn validate_allows_css() {
+        for css in [
+            "",
+            "#text { color: red; }",
+            "@media (max-width: 600px) { #text { font-size: 2em } }",
+            "/* comment } */ a[href=\"{\"]::after { content: \"}\"; }",
+            "#text { background: url(\"/assets/bg.png\") no-repeat; }",
+            "@keyframes fade { from { opacity: 0 } to { opacity: 1 } }",
+            "#text { font-family: 'Comic Sans', sans-serif }",
+        ] {
+            assert_eq!(validate(css), Ok(()), "{css}");
+        }
+    }
+
+    #[test]
+    fn validate_unbalanced_braces() {
+        assert_eq!(messages("#text { color: red;"), ["1:7: unclosed `{`"]);
+        assert_eq!(
+            messages("#text { color: red; }\n}"),
+            ["2:1: unexpected `}`"]
+        );
+        assert_eq!(
+            messages("#text { width: calc(1px + 2px; }"),
+            ["1:7: unclosed `{`", "1:20: unclosed `(`"]
+        );
+    }
+
+    #[test]
+    fn validate_style_injection() {
+        assert_eq!(
+            messages("#text { color: red; }</style><script>alert(1)</script>"),
+            ["1:22: `</style` is not allowed"]
+        );
+        assert!(validate("#text { content: \"</STYLE>\" }").is_err());
+        assert!(validate("/* </style> */").is_err());
+        assert!(validate("<!-- #text { color: red } -->").is_err());
+    }
+
+    #[test]
+    fn validate_import() {
+        assert_eq!(
+            messages("@import url(\"https://evil.example/x.css\");"),
+            ["1:1: `@import` is not allowed"]
+        );
+        assert!(validate("@IMPORT 'x.css';").is_err());
+    }
+
+    #[test]
+    fn validate_script_urls() {
+        for css in [
+            "#text { background: url(javascript:alert(1)) }",
+            "#text { background: URL( \"JavaScript:alert(1)\") }",
+            "#text { background: url('java\\script:alert(1)') }",
+        ] {
+            assert_eq!(
+                messages(css),
+                ["1:21: `javascript:` urls are not allowed"],
+                "{css}"
+            );
+        }
+    }
+}
@@ -0,0 +1,867 @@
+//! Transient alert events, e.g. "X followed!".
+//!
+//! Events are queued per alert and played one at a time to the overlay with
+//! [`AlertMessage::EventShow`] and [`AlertMessage::EventHide`].
+
+use std::collections::{BTreeMap, VecDeque};
+
+use leptos::{prelude::*, server};
+
+use crate::alerts::*;
+use crate::media::MediaAttachment;
+#[cfg(feature = "ssr")]
+use crate::media::MediaId;
+#[cfg(feature = "ssr")]
+use axum::{extract, http::StatusCode, response::IntoResponse, Extension};
+#[cfg(feature = "ssr")]
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+#[cfg(feature = "ssr")]
+use tokio::sync::{watch, Mutex, Notify};
+
+#[aliri_braid::braid(serde)]
+pub struct AlertEventId;
+
+impl AlertEventId {
+    pub fn new_id() -> Self {
+        Self(nanoid::nanoid!(8))
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct AlertEvent {
+    pub event_id: AlertEventId,
+    /// Type of the event, e.g. `follow`, used to pick a [`AlertVariant`]
+    #[serde(default)]
+    pub event_type: Option<String>,
+    /// Markdown template, rendered like [`Alert::last_text`] with the payload as extra variables.
+    pub template: AlertText,
+    /// Extra style for the event, rendered like [`Alert::last_style`].
+    #[serde(default)]
+    pub style: String,
+    #[serde(default)]
+    pub payload: BTreeMap<String, serde_json::Value>,
+    #[serde(default)]
+    pub media: Vec<MediaAttachment>,
+    pub duration_ms: u64,
+}
+
+impl AlertEvent {
+    /// Payload flattened into `$name` variables, nested objects are joined with `.`
+    pub fn vars(&self) -> Vec<(String, String)> {
+        let mut vars = vec![];
+        for (key, value) in &self.payload {
+            flatten_value(key.clone(), value, &mut vars);
+        }
+        vars
+    }
+}
+
+pub(crate) fn flatten_value(
+    key: String,
+    value: &serde_json::Value,
+    vars: &mut Vec<(String, String)>,
+) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (k, v) in map {
+                flatten_value(format!("{key}.{k}"), v, vars);
+            }
+        }
+        serde_json::Value::String(s) => vars.push((key, s.clone())),
+        serde_json::Value::Null => vars.push((key, String::new())),
+        value => vars.push((key, value.to_string())),
+    }
+}
+
+/// Body of `POST /alert/:id/events`
+///
+/// Either `type` or `template` has to be given, a `template` overrides the text of the variant.
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct NewAlertEvent {
+    #[serde(default, rename = "type")]
+    pub event_type: Option<String>,
+    #[serde(default)]
+    pub template: Option<AlertText>,
+    #[serde(default)]
+    pub payload: BTreeMap<String, serde_json::Value>,
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
+}
+
+const DEFAULT_DURATION_MS: u64 = 5000;
+
+/// A named event template, selected by event type and conditions on the payload.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct AlertVariant {
+    pub name: String,
+    pub event_type: String,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    pub text: AlertText,
+    #[serde(default)]
+    pub style: String,
+    #[serde(default)]
+    pub media: Vec<MediaAttachment>,
+    pub duration_ms: u64,
+}
+
+impl AlertVariant {
+    pub fn matches(&self, vars: &[(String, String)]) -> bool {
+        self.conditions.iter().all(|c| c.matches(vars))
+    }
+}
+
+/// A comparison like `amount >= 100`
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Condition {
+    pub key: String,
+    pub op: ConditionOp,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConditionOp {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Contains,
+}
+
+impl ConditionOp {
+    // longer operators first so `>=` isn't parsed as `>`
+    const PARSE_ORDER: [(&'static str, ConditionOp); 7] = [
+        (" contains ", ConditionOp::Contains),
+        ("==", ConditionOp::Eq),
+        ("!=", ConditionOp::Ne),
+        (">=", ConditionOp::Ge),
+        ("<=", ConditionOp::Le),
+        (">", ConditionOp::Gt),
+        ("<", ConditionOp::Lt),
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConditionOp::Eq => "==",
+            ConditionOp::Ne => "!=",
+            ConditionOp::Gt => ">",
+            ConditionOp::Ge => ">=",
+            ConditionOp::Lt => "<",
+            ConditionOp::Le => "<=",
+            ConditionOp::Contains => "contains",
+        }
+    }
+}
+
+impl Condition {
+    /// Check the condition against `vars`, a missing key never matches.
+    ///
+    /// Values are compared as numbers if both sides are numbers.
+    pub fn matches(&self, vars: &[(String, String)]) -> bool {
+        let Some((_, actual)) = vars.iter().find(|(k, _)| k == &self.key) else {
+            return false;
+        };
+        let numbers = actual
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .zip(self.value.trim().parse::<f64>().ok());
+        match (self.op, numbers) {
+            (ConditionOp::Contains, _) => actual.contains(&self.value),
+            (ConditionOp::Eq, Some((a, b))) => a == b,
+            (ConditionOp::Ne, Some((a, b))) => a != b,
+            (ConditionOp::Eq, None) => actual == &self.value,
+            (ConditionOp::Ne, None) => actual != &self.value,
+            (ConditionOp::Gt, Some((a, b))) => a > b,
+            (ConditionOp::Ge, Some((a, b))) => a >= b,
+            (ConditionOp::Lt, Some((a, b))) => a < b,
+            (ConditionOp::Le, Some((a, b))) => a <= b,
+            (_, None) => false,
+        }
+    }
+}
+
+impl std::str::FromStr for Condition {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (op_str, op) = ConditionOp::PARSE_ORDER
+            .into_iter()
+            .find(|(op_str, _)| s.contains(op_str))
+            .ok_or_else(|| eyre::eyre!("no operator in condition `{s}`"))?;
+        let (key, value) = s.split_once(op_str).expect("operator was found");
+        let key = key.trim();
+        if key.is_empty() {
+            eyre::bail!("no key in condition `{s}`");
+        }
+        Ok(Self {
+            key: key.to_owned(),
+            op,
+            value: value.trim().to_owned(),
+        })
+    }
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.key, self.op.as_str(), self.value)
+    }
+}
+
+/// Parse a comma separated list of conditions.
+pub fn parse_conditions(s: &str) -> Result<Vec<Condition>, eyre::Report> {
+    s.split(',')
+        .filter(|c| !c.trim().is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+impl Alert {
+    /// Build an event from a request, picking a variant if a type is given.
+    pub fn new_event(&self, new: NewAlertEvent) -> Result<AlertEvent, eyre::Report> {
+        let mut event = AlertEvent {
+            event_id: AlertEventId::new_id(),
+            event_type: new.event_type,
+            template: AlertText::from(""),
+            style: String::new(),
+            payload: new.payload,
+            media: vec![],
+            duration_ms: DEFAULT_DURATION_MS,
+        };
+        let variant = match &event.event_type {
+            Some(event_type) => self.select_variant(event_type, &event.vars()),
+            None => None,
+        };
+        match (variant, new.template) {
+            (_, Some(template)) => event.template = template,
+            (Some(variant), None) => event.template = variant.text.clone(),
+            (None, None) => eyre::bail!(
+                "no variant matches event type {:?} and no template given",
+                event.event_type
+            ),
+        }
+        if let Some(variant) = variant {
+            event.style = variant.style.clone();
+            event.media = variant.media.clone();
+            event.duration_ms = variant.duration_ms;
+        }
+        if let Some(duration_ms) = new.duration_ms {
+            event.duration_ms = duration_ms;
+        }
+        Ok(event)
+    }
+}
+
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct EventQueue {
+    pub pending: VecDeque<AlertEvent>,
+    /// Event currently shown on the overlay.
+    pub current: Option<AlertEvent>,
+    /// Last event that finished playing, used for replay.
+    pub last: Option<AlertEvent>,
+    pub paused: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueControl {
+    Pause,
+    Resume,
+    Skip,
+    Replay,
+    Clear,
+}
+
+impl QueueControl {
+    pub const ALL: [QueueControl; 5] = [
+        QueueControl::Pause,
+        QueueControl::Resume,
+        QueueControl::Skip,
+        QueueControl::Replay,
+        QueueControl::Clear,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueueControl::Pause => "pause",
+            QueueControl::Resume => "resume",
+            QueueControl::Skip => "skip",
+            QueueControl::Replay => "replay",
+            QueueControl::Clear => "clear",
+        }
+    }
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Clone, Default)]
+pub struct EventQueues {
+    queues: Arc<std::sync::Mutex<HashMap<AlertId, Arc<QueueHandle>>>>,
+}
+
+#[cfg(feature = "ssr")]
+struct QueueHandle {
+    queue: Mutex<EventQueue>,
+    /// Wakes the player when the queue has changed.
+    wake: Notify,
+    /// Ends the event with this id early, reset when an event starts so a stale skip
+    /// can't end the next one.
+    skip: watch::Sender<Option<AlertEventId>>,
+}
+
+#[cfg(feature = "ssr")]
+impl AlertManager {
+    fn events_path(&self) -> PathBuf {
+        self.db_path.join("events")
+    }
+
+    /// Get the queue for an alert, starting its player if needed.
+    fn event_queue(&self, alert_id: &AlertId) -> Arc<QueueHandle> {
+        self.event_queue_with(alert_id, EventQueue::default)
+    }
+
+    fn event_queue_with(
+        &self,
+        alert_id: &AlertId,
+        init: impl FnOnce() -> EventQueue,
+    ) -> Arc<QueueHandle> {
+        let mut queues = self.events.queues.lock().unwrap();
+        if let Some(handle) = queues.get(alert_id) {
+            return handle.clone();
+        }
+        let handle = Arc::new(QueueHandle {
+            queue: Mutex::new(init()),
+            wake: Notify::new(),
+            skip: watch::Sender::new(None),
+        });
+        queues.insert(alert_id.clone(), handle.clone());
+        tokio::spawn(self.clone().play_events(alert_id.clone(), handle.clone()));
+        handle
+    }
+
+    /// Load persisted queues, an event that was playing when we stopped is played again.
+    pub(crate) async fn load_event_queues(&self) -> Result<(), eyre::Report> {
+        let path = self.events_path();
+        tokio::fs::create_dir_all(&path).await?;
+        let mut dir = tokio::fs::read_dir(path).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            if !entry.file_type().await?.is_file() {
+                continue;
+            }
+            let Some(alert_id) = entry
+                .path()
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .map(AlertId::from)
+            else {
+                continue;
+            };
+            let mut queue: EventQueue =
+                serde_json::from_slice(&tokio::fs::read(entry.path()).await?)?;
+            if let Some(current) = queue.current.take() {
+                queue.pending.push_front(current);
+            }
+            self.event_queue_with(&alert_id, || queue);
+        }
+        Ok(())
+    }
+
+    async fn save_event_queue(&self, alert_id: &AlertId, queue: &EventQueue) {
+        let path = self.events_path().join(format!("{alert_id}.json"));
+        let res = match serde_json::to_vec(queue) {
+            Ok(json) => tokio::fs::write(path, json)
+                .await
+                .map_err(eyre::Report::from),
+            Err(e) => Err(e.into()),
+        };
+        if let Err(error) = res {
+            tracing::error!(%error, ?alert_id, "could not save event queue");
+        }
+    }
+
+    pub async fn read_event_queue(&self, alert_id: &AlertId) -> EventQueue {
+        self.event_queue(alert_id).queue.lock().await.clone()
+    }
+
+    /// Resolve and enqueue an event for an alert.
+    pub async fn enqueue_new_event(
+        &self,
+        alert_id: &AlertId,
+        new: NewAlertEvent,
+    ) -> Result<AlertEventId, ServerFnError> {
+        let alert = self.get_alert(alert_id).await?;
+        let mut event = alert.new_event(new).map_err(|e| {
+            ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+        })?;
+        self.attach_tts(&alert, &mut event).await;
+        self.enqueue_event(alert_id, event).await
+    }
+
+    pub async fn enqueue_event(
+        &self,
+        alert_id: &AlertId,
+        event: AlertEvent,
+    ) -> Result<AlertEventId, ServerFnError> {
+        // make sure the alert exists
+        self.get_alert(alert_id).await?;
+        let event_id = event.event_id.clone();
+        let handle = self.event_queue(alert_id);
+        let mut queue = handle.queue.lock().await;
+        queue.pending.push_back(event);
+        self.save_event_queue(alert_id, &queue).await;
+        handle.wake.notify_one();
+        tracing::info!(?alert_id, ?event_id, "enqueued event");
+        Ok(event_id)
+    }
+
+    pub async fn control_event_queue(&self, alert_id: &AlertId, control: QueueControl) {
+        let handle = self.event_queue(alert_id);
+        let mut queue = handle.queue.lock().await;
+        match control {
+            QueueControl::Pause => queue.paused = true,
+            QueueControl::Resume => queue.paused = false,
+            QueueControl::Skip => {
+                if let Some(current) = &queue.current {
+                    handle.skip.send_replace(Some(current.event_id.clone()));
+                }
+            }
+            QueueControl::Replay => {
+                if let Some(last) = queue.last.clone() {
+                    queue.pending.push_front(last);
+                }
+            }
+            QueueControl::Clear => queue.pending.clear(),
+        }
+        self.save_event_queue(alert_id, &queue).await;
+        handle.wake.notify_one();
+        tracing::info!(?alert_id, ?control, "controlled event queue");
+    }
+
+    async fn play_events(self, alert_id: AlertId, handle: Arc<QueueHandle>) {
+        loop {
+            let event = {
+                let mut queue = handle.queue.lock().await;
+                let event = match queue.paused {
+                    true => None,
+                    false => queue.pending.pop_front(),
+                };
+                if let Some(event) = &event {
+                    queue.current = Some(event.clone());
+                    handle.skip.send_replace(None);
+                    self.save_event_queue(&alert_id, &queue).await;
+                }
+                event
+            };
+            let Some(event) = event else {
+                handle.wake.notified().await;
+                continue;
+            };
+
+            match self.get_alert(&alert_id).await {
+                Ok(alert) => {
+                    let mut skip = handle.skip.subscribe();
+                    let vars = event.vars();
+                    let _ = self.sender.send(AlertMessage::EventShow {
+                        alert_id: alert_id.clone(),
+                        event_id: event.event_id.clone(),
+                        text: alert.to_html(&alert.render_template(&event.template, &vars)),
+                        style: alert.render_style_template(&event.style, &vars),
+                        media: self.media.resolve(&event.media).await,
+                        duration_ms: event.duration_ms,
+                        transition: alert.transition.clone(),
+                    });
+                    tokio::select! {
+                        _ = tokio::time::sleep(std::time::Duration::from_millis(event.duration_ms)) => {}
+                        _ = skip.wait_for(|id| id.as_ref() == Some(&event.event_id)) => {
+                            tracing::debug!(?alert_id, event_id = ?event.event_id, "skipped event");
+                        }
+                    }
+                    let _ = self.sender.send(AlertMessage::EventHide {
+                        alert_id: alert_id.clone(),
+                        event_id: event.event_id.clone(),
+                    });
+                }
+                Err(error) => {
+                    tracing::warn!(%error, ?alert_id, "dropping event for missing alert");
+                }
+            }
+
+            let mut queue = handle.queue.lock().await;
+            queue.current = None;
+            queue.last = Some(event);
+            self.save_event_queue(&alert_id, &queue).await;
+        }
+    }
+}
+
+#[cfg(feature = "ssr")]
+pub(crate) async fn enqueue_event_handler(
+    extract::Path(alert_id): extract::Path<AlertId>,
+    Extension(manager): Extension<AlertManager>,
+    axum::Json(event): axum::Json<NewAlertEvent>,
+) -> axum::response::Response {
+    match manager.enqueue_new_event(&alert_id, event).await {
+        Ok(event_id) => (
+            StatusCode::OK,
+            axum::Json(serde_json::json!({ "event_id": event_id })),
+        )
+            .into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+#[cfg(feature = "ssr")]
+pub(crate) async fn control_event_queue_handler(
+    extract::Path((alert_id, control)): extract::Path<(AlertId, QueueControl)>,
+    Extension(manager): Extension<AlertManager>,
+) -> axum::response::Response {
+    if let Err(e) = manager.get_alert(&alert_id).await {
+        return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    }
+    manager.control_event_queue(&alert_id, control).await;
+    axum::Json(manager.read_event_queue(&alert_id).await).into_response()
+}
+
+#[cfg(feature = "ssr")]
+pub(crate) async fn read_event_queue_handler(
+    extract::Path(alert_id): extract::Path<AlertId>,
+    Extension(manager): Extension<AlertManager>,
+) -> axum::response::Response {
+    if let Err(e) = manager.get_alert(&alert_id).await {
+        return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    }
+    axum::Json(manager.read_event_queue(&alert_id).await).into_response()
+}
+
+#[server(ReadEventQueue, "/backend")]
+pub async fn read_event_queue(alert_id: AlertId) -> Result<EventQueue, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager.get_alert(&alert_id).await?;
+    Ok(manager.read_event_queue(&alert_id).await)
+}
+
+#[server(ControlEventQueue, "/backend")]
+#[tracing::instrument(err)]
+pub async fn control_event_queue(
+    alert_id: AlertId,
+    control: QueueControl,
+) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager.get_alert(&alert_id).await?;
+    manager.control_event_queue(&alert_id, control).await;
+    Ok(())
+}
+
+/// Enqueue an event from the editor, empty inputs are left to the variant.
+#[server(EnqueueAlertEvent, "/backend")]
+#[tracing::instrument(err)]
+pub async fn enqueue_alert_event(
+    alert_id: AlertId,
+    event_type: String,
+    template: String,
+    payload: String,
+    duration_ms: String,
+) -> Result<AlertEventId, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    let payload = match payload.trim() {
+        "" => Default::default(),
+        payload => serde_json::from_str(payload)?,
+    };
+    let duration_ms = match duration_ms.trim() {
+        "" => None,
+        duration_ms => Some(duration_ms.parse()?),
+    };
+    let non_empty = |s: String| (!s.trim().is_empty()).then_some(s);
+    manager
+        .enqueue_new_event(
+            &alert_id,
+            NewAlertEvent {
+                event_type: non_empty(event_type),
+                template: non_empty(template).map(Into::into),
+                payload,
+                duration_ms,
+            },
+        )
+        .await
+}
+
+#[server(SaveAlertVariant, "/backend")]
+#[tracing::instrument(err)]
+#[allow(clippy::too_many_arguments)]
+pub async fn save_alert_variant(
+    alert_id: AlertId,
+    name: String,
+    event_type: String,
+    conditions: String,
+    text: String,
+    style: String,
+    duration_ms: u64,
+    media_id: String,
+    volume: f32,
+) -> Result<Alert, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    crate::css::validate(&style).map_err(crate::css::server_error)?;
+    let media = match media_id.trim() {
+        "" => vec![],
+        media_id => vec![MediaAttachment {
+            media_id: MediaId::from(media_id),
+            volume,
+        }],
+    };
+    let variant = AlertVariant {
+        name,
+        event_type,
+        conditions: parse_conditions(&conditions).map_err(|e| {
+            ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+        })?,
+        text: text.into(),
+        style,
+        media,
+        duration_ms,
+    };
+
+    manager
+        .edit_alert_html(&alert_id, move |alert| {
+            match alert.variants.iter_mut().find(|v| v.name == variant.name) {
+                Some(existing) => *existing = variant,
+                None => alert.variants.push(variant),
+            }
+            Ok(())
+        })
+        .await?;
+
+    manager.get_alert(&alert_id).await
+}
+
+#[server(DeleteAlertVariant, "/backend")]
+#[tracing::instrument(err)]
+pub async fn delete_alert_variant(alert_id: AlertId, name: String) -> Result<Alert, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+
+    manager
+        .edit_alert(&alert_id, move |alert| {
+            alert.variants.retain(|v| v.name != name);
+        })
+        .await?;
+
+    manager.get_alert(&alert_id).await
+}
+
+#[cfg(all(test, feature = "ssr"))]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::sync::broadcast;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn event(template: &str, duration_ms: u64) -> NewAlertEvent {
+        NewAlertEvent {
+            template: Some(AlertText::from(template)),
+            duration_ms: Some(duration_ms),
+            ..NewAlertEvent::default()
+        }
+    }
+
+    /// The next event shown or hidden by the overlay, `true` if it was shown.
+    async fn next_event(messages: &mut broadcast::Receiver<AlertMessage>) -> (bool, AlertEventId) {
+        loop {
+            match messages.recv().await.unwrap() {
+                AlertMessage::EventShow { event_id, .. } => return (true, event_id),
+                AlertMessage::EventHide { event_id, .. } => return (false, event_id),
+                _ => continue,
+            }
+        }
+    }
+
+    async fn expect_event(
+        messages: &mut broadcast::Receiver<AlertMessage>,
+        shown: bool,
+        event_id: &AlertEventId,
+    ) {
+        let event = tokio::time::timeout(TIMEOUT, next_event(messages))
+            .await
+            .expect("an event was shown or hidden");
+        assert_eq!(event, (shown, event_id.clone()));
+    }
+
+    #[test]
+    fn parse_conditions_list() {
+        let conditions =
+            parse_conditions("amount >= 100, user != bob,,message contains hi").unwrap();
+        assert_eq!(
+            conditions
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["amount >= 100", "user != bob", "message contains hi"]
+        );
+        assert!(parse_conditions("").unwrap().is_empty());
+        assert!(parse_conditions("amount").is_err());
+        assert!(parse_conditions(">= 100").is_err());
+        // `>=` isn't read as `>` with a value of `= 100`
+        assert_eq!(
+            "amount >= 100".parse::<Condition>().unwrap().op,
+            ConditionOp::Ge
+        );
+    }
+
+    #[test]
+    fn condition_matches() {
+        let vars = [
+            ("amount".to_owned(), "100".to_owned()),
+            ("user".to_owned(), "bob".to_owned()),
+            ("message".to_owned(), "hi chat".to_owned()),
+        ];
+        let matches = |condition: &str| condition.parse::<Condition>().unwrap().matches(&vars);
+        assert!(matches("amount == 100.0"));
+        assert!(matches("amount >= 100"));
+        assert!(matches("amount > 99"));
+        assert!(!matches("amount < 100"));
+        assert!(matches("amount <= 100"));
+        assert!(matches("user == bob"));
+        assert!(matches("user != alice"));
+        assert!(matches("message contains chat"));
+        // text is only compared for equality
+        assert!(!matches("user > a"));
+        // a missing key never matches
+        assert!(!matches("missing != x"));
+    }
+
+    #[tokio::test]
+    async fn plays_in_order() {
+        let (_dir, manager) = crate::alerts::test_manager(&[]).await;
+        let alert_id = crate::alerts::test_alert(&manager, "count").await;
+        manager
+            .control_event_queue(&alert_id, QueueControl::Pause)
+            .await;
+        let first = manager
+            .enqueue_new_event(&alert_id, event("first", 50))
+            .await
+            .unwrap();
+        let second = manager
+            .enqueue_new_event(&alert_id, event("second", 50))
+            .await
+            .unwrap();
+
+        let mut messages = manager.sender.subscribe();
+        manager
+            .control_event_queue(&alert_id, QueueControl::Resume)
+            .await;
+        expect_event(&mut messages, true, &first).await;
+        expect_event(&mut messages, false, &first).await;
+        expect_event(&mut messages, true, &second).await;
+        expect_event(&mut messages, false, &second).await;
+        let queue = manager.read_event_queue(&alert_id).await;
+        assert!(queue.pending.is_empty());
+        assert_eq!(queue.last.map(|e| e.event_id), Some(second));
+    }
+
+    #[tokio::test]
+    async fn skip_only_ends_the_current_event() {
+        let (_dir, manager) = crate::alerts::test_manager(&[]).await;
+        let alert_id = crate::alerts::test_alert(&manager, "count").await;
+        let mut messages = manager.sender.subscribe();
+
+        // nothing is playing, so this does nothing
+        manager
+            .control_event_queue(&alert_id, QueueControl::Skip)
+            .await;
+        let first = manager
+            .enqueue_new_event(&alert_id, event("first", 60_000))
+            .await
+            .unwrap();
+        expect_event(&mut messages, true, &first).await;
+        manager
+            .control_event_queue(&alert_id, QueueControl::Skip)
+            .await;
+        manager
+            .control_event_queue(&alert_id, QueueControl::Skip)
+            .await;
+        expect_event(&mut messages, false, &first).await;
+
+        // the second skip didn't carry over
+        let second = manager
+            .enqueue_new_event(&alert_id, event("second", 60_000))
+            .await
+            .unwrap();
+        expect_event(&mut messages, true, &second).await;
+        assert!(
+            tokio::time::timeout(Duration::from_millis(300), next_event(&mut messages))
+                .await
+                .is_err(),
+            "the next event was skipped"
+        );
+    }
+
+    #[tokio::test]
+    async fn clear_drops_pending_events() {
+        let (_dir, manager) = crate::alerts::test_manager(&[]).await;
+        let alert_id = crate::alerts::test_alert(&manager, "count").await;
+        manager
+            .control_event_queue(&alert_id, QueueControl::Pause)
+            .await;
+        for template in ["first", "second"] {
+            manager
+                .enqueue_new_event(&alert_id, event(template, 50))
+                .await
+                .unwrap();
+        }
+        assert_eq!(manager.read_event_queue(&alert_id).await.pending.len(), 2);
+        manager
+            .control_event_queue(&alert_id, QueueControl::Clear)
+            .await;
+        let queue = manager.read_event_queue(&alert_id).await;
+        assert!(queue.pending.is_empty());
+        assert!(queue.paused);
+    }
+
+    #[tokio::test]
+    async fn queues_survive_a_restart() {
+        let (dir, manager) = crate::alerts::test_manager(&[]).await;
+        let alert_id = crate::alerts::test_alert(&manager, "count").await;
+        manager
+            .control_event_queue(&alert_id, QueueControl::Pause)
+            .await;
+        let mut ids = vec![];
+        for template in ["first", "second"] {
+            ids.push(
+                manager
+                    .enqueue_new_event(&alert_id, event(template, 50))
+                    .await
+                    .unwrap(),
+            );
+        }
+
+        let (_, restarted) = crate::alerts::setup::<()>(&crate::alerts::test_opts(dir.path(), &[]))
+            .await
+            .unwrap();
+        let queue = restarted.read_event_queue(&alert_id).await;
+        assert!(queue.paused);
+        assert_eq!(
+            queue
+                .pending
+                .into_iter()
+                .map(|e| e.event_id)
+                .collect::<Vec<_>>(),
+            ids
+        );
+    }
+}
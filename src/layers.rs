@@ -0,0 +1,343 @@
+//! Positioned layers on a fixed size overlay canvas.
+//!
+//! Layer content is sent to the overlay with [`AlertMessage::Layers`], positions are part of
+//! [`Alert::render_style`] and follow the style broadcast.
+
+use leptos::{prelude::*, server};
+
+use crate::alerts::*;
+use crate::media::MediaId;
+
+#[aliri_braid::braid(serde)]
+pub struct LayerId;
+
+impl LayerId {
+    pub fn new_id() -> Self {
+        Self(nanoid::nanoid!(8))
+    }
+}
+
+/// Resolution of the overlay, the browser source should have the same size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Canvas {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for Canvas {
+    fn default() -> Self {
+        Self {
+            width: 1920,
+            height: 1080,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Layer {
+    pub layer_id: LayerId,
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub z_index: i32,
+    pub content: LayerContent,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LayerContent {
+    /// Markdown, rendered like [`Alert::last_text`]
+    Text {
+        text: AlertText,
+    },
+    Image {
+        media_id: MediaId,
+    },
+    /// A bar filled to `value / max`, both may use fields, e.g. `$donations`
+    Progress {
+        value: String,
+        max: String,
+        color: String,
+    },
+    /// Counts down to `ends_at_ms`, in milliseconds since the unix epoch.
+    Timer {
+        ends_at_ms: u64,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayerKind {
+    Text,
+    Image,
+    Progress,
+    Timer,
+}
+
+impl LayerKind {
+    pub const ALL: [LayerKind; 4] = [
+        LayerKind::Text,
+        LayerKind::Image,
+        LayerKind::Progress,
+        LayerKind::Timer,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LayerKind::Text => "text",
+            LayerKind::Image => "image",
+            LayerKind::Progress => "progress",
+            LayerKind::Timer => "timer",
+        }
+    }
+}
+
+impl LayerContent {
+    pub fn kind(&self) -> LayerKind {
+        match self {
+            LayerContent::Text { .. } => LayerKind::Text,
+            LayerContent::Image { .. } => LayerKind::Image,
+            LayerContent::Progress { .. } => LayerKind::Progress,
+            LayerContent::Timer { .. } => LayerKind::Timer,
+        }
+    }
+}
+
+/// The html of a layer, as sent to the overlay.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct RenderedLayer {
+    pub layer_id: LayerId,
+    pub html: String,
+}
+
+impl Alert {
+    pub fn render_layers(&self) -> Vec<RenderedLayer> {
+        self.layers
+            .iter()
+            .map(|layer| RenderedLayer {
+                layer_id: layer.layer_id.clone(),
+                html: self.render_layer(layer),
+            })
+            .collect()
+    }
+
+    fn render_layer(&self, layer: &Layer) -> String {
+        match &layer.content {
+            LayerContent::Text { text } => self.to_html(&self.render_template(text, &[])),
+            LayerContent::Image { media_id } => {
+                format!(r#"<img class="alert-image" src="/media/{media_id}"/>"#)
+            }
+            LayerContent::Progress { value, max, .. } => {
+                let value = self.substitute(value, &[], false).trim().parse::<f64>();
+                let max = self.substitute(max, &[], false).trim().parse::<f64>();
+                let percent = match (value, max) {
+                    (Ok(value), Ok(max)) if max > 0.0 => (value / max * 100.0).clamp(0.0, 100.0),
+                    _ => 0.0,
+                };
+                format!(
+                    r#"<div class="alert-progress"><div class="alert-progress-bar" style="width: {percent:.2}%"></div></div>"#
+                )
+            }
+            LayerContent::Timer { ends_at_ms } => {
+                format!(r#"<span class="alert-timer" data-ends-at="{ends_at_ms}"></span>"#)
+            }
+        }
+    }
+
+    /// The full overlay markup of all layers, used for the initial page load.
+    pub fn render_layers_html(&self) -> String {
+        self.render_layers()
+            .into_iter()
+            .map(|layer| {
+                format!(
+                    r#"<div class="alert-layer" data-layer-id="{id}">{html}</div>"#,
+                    id = layer.layer_id,
+                    html = layer.html
+                )
+            })
+            .collect()
+    }
+
+    /// Positions of all layers, by `data-layer-id` as a scene can show the alert more than once.
+    pub fn layers_style(&self) -> String {
+        self.layers
+            .iter()
+            .map(|layer| {
+                let mut style = format!(
+                    "[data-layer-id=\"{}\"] {{ left: {}px; top: {}px; width: {}px; height: {}px; z-index: {}; }}\n",
+                    layer.layer_id, layer.x, layer.y, layer.width, layer.height, layer.z_index
+                );
+                if let LayerContent::Progress { color, .. } = &layer.content {
+                    style.push_str(&format!(
+                        "[data-layer-id=\"{}\"] .alert-progress-bar {{ background: {color}; }}\n",
+                        layer.layer_id
+                    ));
+                }
+                style
+            })
+            .collect()
+    }
+}
+
+/// Colors end up in the overlay style, only allow what css colors need.
+#[cfg(feature = "ssr")]
+fn valid_color(color: &str) -> bool {
+    color
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || " #%,.()".contains(c))
+}
+
+#[server(UpdateAlertCanvas, "/backend")]
+#[tracing::instrument(err)]
+pub async fn update_alert_canvas(
+    alert_id: AlertId,
+    width: u32,
+    height: u32,
+) -> Result<Alert, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    if width == 0 || height == 0 {
+        return Err(ServerFnError::ServerError("Invalid canvas size".to_owned()));
+    }
+
+    manager
+        .edit_alert(&alert_id, move |alert| {
+            alert.canvas = Canvas { width, height };
+        })
+        .await?;
+
+    manager.get_alert(&alert_id).await
+}
+
+/// Add a layer, or replace the content of the layer with the same name.
+///
+/// For timers `value` is the number of seconds to count down from.
+#[server(SaveAlertLayer, "/backend")]
+#[tracing::instrument(err)]
+#[allow(clippy::too_many_arguments)]
+pub async fn save_alert_layer(
+    alert_id: AlertId,
+    name: String,
+    kind: LayerKind,
+    text: String,
+    media_id: String,
+    value: String,
+    max: String,
+    color: String,
+) -> Result<Alert, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    if name.trim().is_empty() {
+        return Err(ServerFnError::ServerError("Missing layer name".to_owned()));
+    }
+    let content = match kind {
+        LayerKind::Text => LayerContent::Text { text: text.into() },
+        LayerKind::Image => {
+            let media_id = MediaId::from(media_id);
+            if manager.media.get(&media_id).await.is_none() {
+                return Err(ServerFnError::ServerError("No such media".to_owned()));
+            }
+            LayerContent::Image { media_id }
+        }
+        LayerKind::Progress => {
+            if !valid_color(&color) {
+                return Err(ServerFnError::ServerError("Invalid color".to_owned()));
+            }
+            LayerContent::Progress { value, max, color }
+        }
+        LayerKind::Timer => LayerContent::Timer {
+            ends_at_ms: value
+                .trim()
+                .parse::<u64>()
+                .map(|secs| crate::util::now_ms().saturating_add(secs.saturating_mul(1000)))
+                .unwrap_or_default(),
+        },
+    };
+
+    manager
+        .edit_alert_html(&alert_id, move |alert| {
+            match alert.layers.iter_mut().find(|l| l.name == name) {
+                Some(existing) => existing.content = content,
+                None => {
+                    let z_index = alert
+                        .layers
+                        .iter()
+                        .map(|l| l.z_index + 1)
+                        .max()
+                        .unwrap_or(0);
+                    alert.layers.push(Layer {
+                        layer_id: LayerId::new_id(),
+                        name,
+                        x: 0,
+                        y: 0,
+                        width: 400,
+                        height: 100,
+                        z_index,
+                        content,
+                    })
+                }
+            }
+            Ok(())
+        })
+        .await?;
+
+    manager.get_alert(&alert_id).await
+}
+
+#[server(UpdateAlertLayerGeometry, "/backend")]
+#[tracing::instrument(err)]
+#[allow(clippy::too_many_arguments)]
+pub async fn update_alert_layer_geometry(
+    alert_id: AlertId,
+    layer_id: LayerId,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    z_index: i32,
+) -> Result<Alert, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+
+    manager
+        .edit_alert(&alert_id, move |alert| {
+            if let Some(layer) = alert.layers.iter_mut().find(|l| l.layer_id == layer_id) {
+                layer.x = x;
+                layer.y = y;
+                layer.width = width;
+                layer.height = height;
+                layer.z_index = z_index;
+            }
+        })
+        .await?;
+
+    manager.get_alert(&alert_id).await
+}
+
+#[server(DeleteAlertLayer, "/backend")]
+#[tracing::instrument(err)]
+pub async fn delete_alert_layer(
+    alert_id: AlertId,
+    layer_id: LayerId,
+) -> Result<Alert, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+
+    manager
+        .edit_alert(&alert_id, move |alert| {
+            alert.layers.retain(|l| l.layer_id != layer_id);
+        })
+        .await?;
+
+    manager.get_alert(&alert_id).await
+}
@@ -1,13 +1,33 @@
 use cfg_if::cfg_if;
+pub mod actions;
 pub mod alerts;
+pub mod api;
 pub mod app;
+pub mod automation;
+pub mod chat;
+pub mod css;
 pub mod error_template;
+pub mod events;
 pub mod fileserv;
+pub mod layers;
+pub mod media;
+pub mod obs;
 pub mod opts;
+pub mod outbound;
+pub mod playlists;
+pub mod scenes;
+pub mod schedules;
+pub mod templates;
+pub mod themes;
+pub mod tts;
+pub mod twitch;
 pub mod util;
+pub mod webhooks;
 
 #[cfg(feature = "ssr")]
 pub mod ip;
+#[cfg(feature = "ssr")]
+pub mod overlay;
 
 pub mod auth;
 
@@ -87,6 +87,12 @@ async fn main() -> Result<(), eyre::Report> {
     // build our application with a route
     let app: Router<_> = Router::new()
         .nest("/alert", alert_router)
+        .nest("/media", stream_alerts::media::router())
+        .nest("/assets", stream_alerts::media::assets_router())
+        .nest("/scene", stream_alerts::scenes::router())
+        .nest("/twitch", stream_alerts::twitch::router())
+        .nest("/hook", stream_alerts::webhooks::router())
+        .nest("/api/v1", stream_alerts::api::router())
         .route(
             "/backend/*fn_name",
             post(
@@ -0,0 +1,537 @@
+//! Uploaded sounds, images, videos and fonts, stored under `db_path/media`.
+//!
+//! Styles reference media by name with `asset("name")`, see [`crate::css::expand_assets`].
+
+use leptos::{prelude::*, server};
+
+#[cfg(feature = "ssr")]
+use crate::alerts::AlertManager;
+#[cfg(feature = "ssr")]
+use axum::{
+    extract,
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    Extension,
+};
+#[cfg(feature = "ssr")]
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+#[cfg(feature = "ssr")]
+use tokio::sync::RwLock;
+
+#[aliri_braid::braid(serde)]
+pub struct MediaId;
+
+impl MediaId {
+    pub fn new_id() -> Self {
+        Self(nanoid::nanoid!())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Media {
+    pub media_id: MediaId,
+    pub name: String,
+    pub content_type: String,
+    /// Hex encoded sha256 of the content.
+    pub hash: String,
+    pub size: u64,
+    /// Created by the server, e.g. text-to-speech, and not shown in the library.
+    #[serde(default)]
+    pub generated: bool,
+    /// Milliseconds since the unix epoch.
+    #[serde(default)]
+    pub uploaded_at: u64,
+}
+
+impl Media {
+    pub fn url(&self) -> String {
+        format!("/media/{}", self.media_id)
+    }
+
+    /// Url that changes with the content, for [`serve_asset`] to redirect to.
+    pub fn hashed_url(&self) -> String {
+        format!(
+            "/media/{}?v={}",
+            self.media_id,
+            &self.hash[..self.hash.len().min(16)]
+        )
+    }
+
+    pub fn kind(&self) -> MediaKind {
+        MediaKind::from_content_type(&self.content_type)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaKind {
+    Audio,
+    Image,
+    Video,
+    Font,
+}
+
+impl MediaKind {
+    /// Kind of a content type, anything we can't play is treated as an image.
+    pub fn from_content_type(content_type: &str) -> Self {
+        match content_type.split('/').next() {
+            Some("audio") => MediaKind::Audio,
+            Some("video") => MediaKind::Video,
+            Some("font") => MediaKind::Font,
+            _ => MediaKind::Image,
+        }
+    }
+}
+
+/// Media referenced by an alert or variant.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct MediaAttachment {
+    pub media_id: MediaId,
+    /// Volume between 0 and 1
+    #[serde(default = "default_volume")]
+    pub volume: f32,
+}
+
+fn default_volume() -> f32 {
+    1.0
+}
+
+/// Media to be played by the overlay.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct MediaPlay {
+    pub url: String,
+    pub kind: MediaKind,
+    pub volume: f32,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Clone)]
+pub struct MediaLibrary {
+    path: PathBuf,
+    media: Arc<RwLock<HashMap<MediaId, Media>>>,
+}
+
+#[cfg(feature = "ssr")]
+impl MediaLibrary {
+    pub async fn load(db_path: &std::path::Path) -> Result<Self, eyre::Report> {
+        let path = db_path.join("media");
+        tokio::fs::create_dir_all(&path).await?;
+        let mut media = HashMap::new();
+        let mut dir = tokio::fs::read_dir(&path).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                let m: Media = serde_json::from_slice(&tokio::fs::read(&path).await?)?;
+                media.insert(m.media_id.clone(), m);
+            }
+        }
+        Ok(Self {
+            path,
+            media: Arc::new(RwLock::new(media)),
+        })
+    }
+
+    fn data_path(&self, media_id: &MediaIdRef) -> PathBuf {
+        self.path.join(media_id.as_str())
+    }
+
+    pub async fn list(&self) -> Vec<Media> {
+        let mut media: Vec<_> = self
+            .media
+            .read()
+            .await
+            .values()
+            .filter(|m| !m.generated)
+            .cloned()
+            .collect();
+        media.sort_by(|a, b| a.name.cmp(&b.name));
+        media
+    }
+
+    pub async fn get(&self, media_id: &MediaIdRef) -> Option<Media> {
+        self.media.read().await.get(media_id).cloned()
+    }
+
+    /// The latest upload with the given name.
+    pub async fn find_asset(&self, name: &str) -> Option<Media> {
+        self.media
+            .read()
+            .await
+            .values()
+            .filter(|m| !m.generated && m.name == name)
+            .max_by_key(|m| m.uploaded_at)
+            .cloned()
+    }
+
+    /// Generated media with the given name.
+    pub async fn find_generated(&self, name: &str) -> Option<Media> {
+        self.media
+            .read()
+            .await
+            .values()
+            .find(|m| m.generated && m.name == name)
+            .cloned()
+    }
+
+    pub async fn read(&self, media_id: &MediaIdRef) -> Result<Vec<u8>, eyre::Report> {
+        Ok(tokio::fs::read(self.data_path(media_id)).await?)
+    }
+
+    pub async fn add(
+        &self,
+        name: String,
+        content_type: String,
+        data: &[u8],
+    ) -> Result<Media, eyre::Report> {
+        self.insert(name, content_type, data, false).await
+    }
+
+    pub async fn add_generated(
+        &self,
+        name: String,
+        content_type: String,
+        data: &[u8],
+    ) -> Result<Media, eyre::Report> {
+        self.insert(name, content_type, data, true).await
+    }
+
+    async fn insert(
+        &self,
+        name: String,
+        content_type: String,
+        data: &[u8],
+        generated: bool,
+    ) -> Result<Media, eyre::Report> {
+        use sha2::Digest;
+
+        let media = Media {
+            media_id: MediaId::new_id(),
+            name,
+            content_type,
+            hash: format!("{:x}", sha2::Sha256::digest(data)),
+            size: data.len() as u64,
+            generated,
+            uploaded_at: crate::util::now_ms(),
+        };
+        tokio::fs::write(self.data_path(&media.media_id), data).await?;
+        tokio::fs::write(
+            self.path.join(format!("{}.json", media.media_id)),
+            serde_json::to_vec(&media)?,
+        )
+        .await?;
+        self.media
+            .write()
+            .await
+            .insert(media.media_id.clone(), media.clone());
+        tracing::info!(?media, "added media");
+        Ok(media)
+    }
+
+    pub async fn remove(&self, media_id: &MediaIdRef) -> Result<(), eyre::Report> {
+        if self.media.write().await.remove(media_id).is_some() {
+            tokio::fs::remove_file(self.path.join(format!("{media_id}.json"))).await?;
+            tokio::fs::remove_file(self.data_path(media_id)).await?;
+        }
+        Ok(())
+    }
+
+    /// Resolve attachments for the overlay, missing media is skipped.
+    pub async fn resolve(&self, attachments: &[MediaAttachment]) -> Vec<MediaPlay> {
+        let media = self.media.read().await;
+        attachments
+            .iter()
+            .filter_map(|a| {
+                let m = media
+                    .get(&a.media_id)
+                    .filter(|m| m.kind() != MediaKind::Font)?;
+                Some(MediaPlay {
+                    url: m.url(),
+                    kind: m.kind(),
+                    volume: a.volume.clamp(0.0, 1.0),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "ssr")]
+pub fn router<S: Clone + Send + Sync + 'static>() -> axum::Router<S> {
+    use axum::routing::{get, post};
+
+    axum::Router::new()
+        .route(
+            "/",
+            post(upload_media).layer(extract::DefaultBodyLimit::max(MAX_UPLOAD_SIZE)),
+        )
+        .route("/:id", get(serve_media).delete(delete_media_handler))
+}
+
+/// Assets by name, redirecting to the current content.
+#[cfg(feature = "ssr")]
+pub fn assets_router<S: Clone + Send + Sync + 'static>() -> axum::Router<S> {
+    axum::Router::new().route("/:name", axum::routing::get(serve_asset))
+}
+
+#[cfg(feature = "ssr")]
+async fn serve_asset(
+    extract::Path(name): extract::Path<String>,
+    Extension(manager): Extension<AlertManager>,
+) -> axum::response::Response {
+    let Some(media) = manager.media.find_asset(&name).await else {
+        return (StatusCode::NOT_FOUND, "no such asset").into_response();
+    };
+    // the name may point to a new upload later, the target is cached forever
+    (
+        StatusCode::TEMPORARY_REDIRECT,
+        [
+            (header::LOCATION, media.hashed_url()),
+            (header::CACHE_CONTROL, "no-cache".to_owned()),
+        ],
+    )
+        .into_response()
+}
+
+#[cfg(feature = "ssr")]
+const MAX_UPLOAD_SIZE: usize = 64 * 1024 * 1024;
+
+#[cfg(feature = "ssr")]
+async fn serve_media(
+    extract::Path(media_id): extract::Path<MediaId>,
+    Extension(manager): Extension<AlertManager>,
+    headers: HeaderMap,
+) -> axum::response::Response {
+    let Some(media) = manager.media.get(&media_id).await else {
+        return (StatusCode::NOT_FOUND, "no such media").into_response();
+    };
+    let etag = format!("\"{}\"", media.hash);
+    // content of an id never changes
+    let cache = (header::CACHE_CONTROL, "public, max-age=31536000, immutable");
+    if headers
+        .get(header::IF_NONE_MATCH)
+        .is_some_and(|v| v.as_bytes() == etag.as_bytes())
+    {
+        return (
+            StatusCode::NOT_MODIFIED,
+            [cache, (header::ETAG, etag.as_str())],
+        )
+            .into_response();
+    }
+    match tokio::fs::read(manager.media.data_path(&media.media_id)).await {
+        Ok(data) => (
+            StatusCode::OK,
+            [
+                cache,
+                (header::ETAG, etag.as_str()),
+                (header::CONTENT_TYPE, media.content_type.as_str()),
+                // the content type is given by the uploader, never let it run as a page
+                (header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+                (header::CONTENT_SECURITY_POLICY, "sandbox"),
+            ],
+            data,
+        )
+            .into_response(),
+        Err(error) => {
+            tracing::error!(%error, ?media, "could not read media");
+            (StatusCode::INTERNAL_SERVER_ERROR, "could not read media").into_response()
+        }
+    }
+}
+
+/// Upload media as `multipart/form-data` with a `file` field.
+///
+/// Responds with the media as json, or redirects if a `redirect` field is given.
+#[cfg(feature = "ssr")]
+async fn upload_media(
+    auth: crate::auth::AuthSession,
+    Extension(manager): Extension<AlertManager>,
+    mut multipart: extract::Multipart,
+) -> axum::response::Response {
+    if auth.user.is_none() {
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    }
+    let mut redirect = None;
+    let mut uploaded = None;
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        };
+        let field_name = field.name().map(ToOwned::to_owned);
+        match field_name.as_deref() {
+            Some("redirect") => redirect = field.text().await.ok(),
+            Some("file") => {
+                let name = field.file_name().unwrap_or("upload").to_owned();
+                let content_type = field
+                    .content_type()
+                    // fonts are often sent as `application/x-font-ttf` or similar
+                    .filter(|c| !c.starts_with("application/"))
+                    .map(ToOwned::to_owned)
+                    .unwrap_or_else(|| {
+                        mime_guess::from_path(&name)
+                            .first_or_octet_stream()
+                            .to_string()
+                    });
+                if !is_allowed_content_type(&content_type) {
+                    return (
+                        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                        format!("unsupported content type {content_type}"),
+                    )
+                        .into_response();
+                }
+                let data = match field.bytes().await {
+                    Ok(data) => data,
+                    Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+                };
+                uploaded = Some((name, content_type, data));
+            }
+            _ => {}
+        }
+    }
+    let Some((name, content_type, data)) = uploaded else {
+        return (StatusCode::BAD_REQUEST, "missing file").into_response();
+    };
+    match manager.media.add(name, content_type, &data).await {
+        Ok(media) => match redirect {
+            Some(redirect) if is_local_redirect(&redirect) => {
+                axum::response::Redirect::to(&redirect).into_response()
+            }
+            _ => axum::Json(media).into_response(),
+        },
+        Err(error) => {
+            tracing::error!(%error, "could not save media");
+            (StatusCode::INTERNAL_SERVER_ERROR, "could not save media").into_response()
+        }
+    }
+}
+
+/// Whether `redirect` stays on this site, browsers treat `//host` and `/\host` as other hosts.
+#[cfg(feature = "ssr")]
+fn is_local_redirect(redirect: &str) -> bool {
+    redirect.starts_with('/')
+        && !redirect.starts_with("//")
+        && !redirect.contains('\\')
+        // browsers drop tabs and newlines, so `/\t/host` would become `//host`
+        && !redirect.chars().any(char::is_control)
+        && redirect
+            .parse::<http::Uri>()
+            .is_ok_and(|uri| uri.scheme().is_none() && uri.authority().is_none())
+}
+
+#[cfg(feature = "ssr")]
+fn is_allowed_content_type(content_type: &str) -> bool {
+    // svg can run scripts when opened directly
+    !content_type.starts_with("image/svg")
+        && ["audio/", "image/", "video/", "font/"]
+            .iter()
+            .any(|prefix| content_type.starts_with(prefix))
+}
+
+#[cfg(feature = "ssr")]
+async fn delete_media_handler(
+    auth: crate::auth::AuthSession,
+    extract::Path(media_id): extract::Path<MediaId>,
+    Extension(manager): Extension<AlertManager>,
+) -> axum::response::Response {
+    if auth.user.is_none() {
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    }
+    match manager.media.remove(&media_id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+#[server(ListMedia, "/backend")]
+pub async fn list_media() -> Result<Vec<Media>, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    Ok(manager.media.list().await)
+}
+
+#[server(DeleteMedia, "/backend")]
+#[tracing::instrument(err)]
+pub async fn delete_media(media_id: MediaId) -> Result<(), ServerFnError> {
+    let auth = leptos_axum::extract::<crate::auth::AuthSession>().await?;
+    if auth.user.is_none() {
+        return Err(ServerFnError::ServerError("Unauthorized".to_owned()));
+    }
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager
+        .media
+        .remove(&media_id)
+        .await
+        .map_err(|e| ServerFnError::ServerError(e.to_string()))
+}
+
+#[server(AddAlertMedia, "/backend")]
+#[tracing::instrument(err)]
+pub async fn add_alert_media(
+    alert_id: crate::alerts::AlertId,
+    media_id: MediaId,
+    volume: f32,
+) -> Result<crate::alerts::Alert, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    if manager.media.get(&media_id).await.is_none() {
+        return Err(ServerFnError::ServerError("no such media".to_owned()));
+    }
+
+    manager
+        .edit_alert(&alert_id, move |alert| {
+            alert.media.push(MediaAttachment { media_id, volume });
+        })
+        .await?;
+
+    manager.get_alert(&alert_id).await
+}
+
+#[server(RemoveAlertMedia, "/backend")]
+#[tracing::instrument(err)]
+pub async fn remove_alert_media(
+    alert_id: crate::alerts::AlertId,
+    media_id: MediaId,
+) -> Result<crate::alerts::Alert, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+
+    manager
+        .edit_alert(&alert_id, move |alert| {
+            alert.media.retain(|m| m.media_id != media_id);
+        })
+        .await?;
+
+    manager.get_alert(&alert_id).await
+}
+
+#[cfg(all(test, feature = "ssr"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_redirects() {
+        for redirect in ["/", "/library", "/alert/abc/update?tab=media#files"] {
+            assert!(is_local_redirect(redirect), "{redirect}");
+        }
+        for redirect in [
+            "",
+            "library",
+            "//evil.example",
+            "/\\evil.example",
+            "/\t/evil.example",
+            "/\n/evil.example",
+            "https://evil.example/",
+            "javascript:alert(1)",
+        ] {
+            assert!(!is_local_redirect(redirect), "{redirect:?}");
+        }
+    }
+}
@@ -0,0 +1,361 @@
+//! OBS integration over obs-websocket v5, enabled with `--obs-websocket-url`.
+//!
+//! Alerts control OBS through the OBS actions, see [`AlertAction`]. OBS events run mappings like
+//! the Twitch integration, with the event data as variables, e.g. `StreamStateChanged` with
+//! `outputActive == true` when the stream starts. Mappings are stored in `db_path/obs`.
+//!
+//! See <https://github.com/obsproject/obs-websocket/blob/master/docs/generated/protocol.md>
+
+use leptos::{prelude::*, server};
+
+#[cfg(feature = "ssr")]
+use crate::actions::ActionOutcome;
+use crate::actions::AlertAction;
+use crate::alerts::*;
+use crate::events::Condition;
+#[cfg(feature = "ssr")]
+use std::{path::PathBuf, sync::Arc};
+#[cfg(feature = "ssr")]
+use tokio::sync::{mpsc, oneshot, RwLock};
+
+#[cfg(feature = "ssr")]
+pub mod client;
+
+/// Events shown as suggestions in the mapping form.
+pub const COMMON_EVENTS: &[&str] = &[
+    "StreamStateChanged",
+    "RecordStateChanged",
+    "CurrentProgramSceneChanged",
+    "SceneItemEnableStateChanged",
+    "InputMuteStateChanged",
+    "ReplayBufferSaved",
+];
+
+#[aliri_braid::braid(serde)]
+pub struct ObsMappingId;
+
+impl ObsMappingId {
+    pub fn new_id() -> Self {
+        Self(nanoid::nanoid!(8))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct ObsMapping {
+    pub mapping_id: ObsMappingId,
+    /// e.g. `StreamStateChanged`
+    pub event_type: String,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    pub action: AlertAction,
+}
+
+/// A request waiting to be sent over the connection.
+#[cfg(feature = "ssr")]
+pub(crate) struct PendingRequest {
+    pub request_type: String,
+    pub data: serde_json::Value,
+    pub reply: oneshot::Sender<Result<serde_json::Value, eyre::Report>>,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Clone)]
+pub struct Obs {
+    configured: bool,
+    path: PathBuf,
+    mappings: Arc<RwLock<Vec<ObsMapping>>>,
+    /// Requests for the current connection, `None` while disconnected.
+    connection: Arc<RwLock<Option<mpsc::UnboundedSender<PendingRequest>>>>,
+    status: Arc<RwLock<String>>,
+}
+
+#[cfg(feature = "ssr")]
+impl Obs {
+    /// How long to wait for OBS to answer a request.
+    const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+    pub async fn load(opts: &crate::opts::Opts) -> Result<Self, eyre::Report> {
+        let dir = opts.db_path.join("obs");
+        tokio::fs::create_dir_all(&dir).await?;
+        let path = dir.join("mappings.json");
+        let mappings = match tokio::fs::read(&path).await {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            configured: opts.obs_websocket_url.is_some(),
+            path,
+            mappings: Arc::new(RwLock::new(mappings)),
+            connection: Default::default(),
+            status: Arc::new(RwLock::new("disconnected".to_owned())),
+        })
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.configured
+    }
+
+    pub async fn list(&self) -> Vec<ObsMapping> {
+        self.mappings.read().await.clone()
+    }
+
+    pub async fn status(&self) -> String {
+        self.status.read().await.clone()
+    }
+
+    async fn set_status(&self, status: impl Into<String>) {
+        *self.status.write().await = status.into();
+    }
+
+    async fn store(&self, mappings: &[ObsMapping]) -> Result<(), eyre::Report> {
+        tokio::fs::write(&self.path, serde_json::to_vec(mappings)?).await?;
+        Ok(())
+    }
+
+    pub async fn save(&self, mapping: ObsMapping) -> Result<(), eyre::Report> {
+        let mut mappings = self.mappings.write().await;
+        match mappings
+            .iter_mut()
+            .find(|m| m.mapping_id == mapping.mapping_id)
+        {
+            Some(existing) => *existing = mapping,
+            None => mappings.push(mapping),
+        }
+        self.store(&mappings).await
+    }
+
+    pub async fn remove(&self, mapping_id: &ObsMappingIdRef) -> Result<(), eyre::Report> {
+        let mut mappings = self.mappings.write().await;
+        mappings.retain(|m| &*m.mapping_id != mapping_id);
+        self.store(&mappings).await
+    }
+
+    /// Send a request to OBS, returning the response data.
+    pub async fn request(
+        &self,
+        request_type: &str,
+        data: serde_json::Value,
+    ) -> Result<serde_json::Value, eyre::Report> {
+        if !self.configured {
+            eyre::bail!("OBS is not configured");
+        }
+        let Some(connection) = self.connection.read().await.clone() else {
+            eyre::bail!("not connected to OBS");
+        };
+        let (reply, response) = oneshot::channel();
+        connection
+            .send(PendingRequest {
+                request_type: request_type.to_owned(),
+                data,
+                reply,
+            })
+            .map_err(|_| eyre::eyre!("not connected to OBS"))?;
+        tokio::time::timeout(Self::REQUEST_TIMEOUT, response)
+            .await
+            .map_err(|_| eyre::eyre!("OBS didn't answer {request_type}"))?
+            .map_err(|_| eyre::eyre!("connection to OBS lost"))?
+    }
+
+    pub async fn set_scene(&self, scene: &str) -> Result<(), eyre::Report> {
+        self.request(
+            "SetCurrentProgramScene",
+            serde_json::json!({ "sceneName": scene }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Show or hide a source, `None` toggles it.
+    pub async fn set_source_visible(
+        &self,
+        scene: Option<String>,
+        source: &str,
+        visible: Option<bool>,
+    ) -> Result<(), eyre::Report> {
+        let scene = match scene {
+            Some(scene) => scene,
+            None => {
+                let current = self
+                    .request("GetCurrentProgramScene", serde_json::json!({}))
+                    .await?;
+                current["currentProgramSceneName"]
+                    .as_str()
+                    .ok_or_else(|| eyre::eyre!("OBS has no program scene"))?
+                    .to_owned()
+            }
+        };
+        let item = self
+            .request(
+                "GetSceneItemId",
+                serde_json::json!({ "sceneName": scene, "sourceName": source }),
+            )
+            .await?;
+        let item_id = item["sceneItemId"]
+            .as_i64()
+            .ok_or_else(|| eyre::eyre!("no source {source} in {scene}"))?;
+        let visible = match visible {
+            Some(visible) => visible,
+            None => {
+                let enabled = self
+                    .request(
+                        "GetSceneItemEnabled",
+                        serde_json::json!({ "sceneName": scene, "sceneItemId": item_id }),
+                    )
+                    .await?;
+                !enabled["sceneItemEnabled"].as_bool().unwrap_or_default()
+            }
+        };
+        self.request(
+            "SetSceneItemEnabled",
+            serde_json::json!({
+                "sceneName": scene,
+                "sceneItemId": item_id,
+                "sceneItemEnabled": visible,
+            }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Reload a browser source without its cache, like its "Refresh cache of current page" button.
+    pub async fn refresh_browser_source(&self, source: &str) -> Result<(), eyre::Report> {
+        self.request(
+            "PressInputPropertiesButton",
+            serde_json::json!({ "inputName": source, "propertyName": "refreshnocache" }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Run the mappings matching an event.
+    pub async fn dispatch(
+        &self,
+        manager: &AlertManager,
+        event_type: &str,
+        data: &serde_json::Value,
+    ) -> Vec<ActionOutcome> {
+        let mut vars = vec![("event_type".to_owned(), event_type.to_owned())];
+        if let serde_json::Value::Object(data) = data {
+            for (key, value) in data {
+                crate::events::flatten_value(key.clone(), value, &mut vars);
+            }
+        }
+        let actions: Vec<_> = self
+            .list()
+            .await
+            .into_iter()
+            .filter(|m| m.event_type == event_type && m.conditions.iter().all(|c| c.matches(&vars)))
+            .map(|m| m.action)
+            .collect();
+        manager.run_actions(&actions, &vars).await
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct ObsOverview {
+    pub configured: bool,
+    pub status: String,
+    pub mappings: Vec<ObsMapping>,
+    /// Scenes in OBS, empty while disconnected.
+    pub scenes: Vec<String>,
+}
+
+#[server(ReadObs, "/backend")]
+pub async fn read_obs() -> Result<ObsOverview, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    let scenes = match manager
+        .obs
+        .request("GetSceneList", serde_json::json!({}))
+        .await
+    {
+        Ok(list) => list["scenes"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|scene| scene["sceneName"].as_str().map(str::to_owned))
+            .collect(),
+        Err(_) => vec![],
+    };
+    Ok(ObsOverview {
+        configured: manager.obs.is_configured(),
+        status: manager.obs.status().await,
+        mappings: manager.obs.list().await,
+        scenes,
+    })
+}
+
+#[server(SaveObsMapping, "/backend")]
+#[tracing::instrument(err)]
+pub async fn save_obs_mapping(
+    event_type: String,
+    conditions: String,
+    kind: String,
+    alert_id: AlertId,
+    field: String,
+    value: String,
+) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager.get_alert(&alert_id).await?;
+    let event_type = event_type.trim().to_owned();
+    if event_type.is_empty() {
+        return Err(ServerFnError::ServerError("missing event type".to_owned()));
+    }
+    let action = AlertAction::from_form(&kind, alert_id, &field, value).map_err(|e| {
+        ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+    })?;
+    let mapping = ObsMapping {
+        mapping_id: ObsMappingId::new_id(),
+        event_type,
+        conditions: crate::events::parse_conditions(&conditions).map_err(|e| {
+            ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+        })?,
+        action,
+    };
+    manager
+        .obs
+        .save(mapping)
+        .await
+        .map_err(|e| ServerFnError::ServerError(e.to_string()))
+}
+
+#[server(DeleteObsMapping, "/backend")]
+#[tracing::instrument(err)]
+pub async fn delete_obs_mapping(mapping_id: ObsMappingId) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager
+        .obs
+        .remove(&mapping_id)
+        .await
+        .map_err(|e| ServerFnError::ServerError(e.to_string()))
+}
+
+/// Set the OBS browser source showing an alert, refreshed with the connected overlays.
+#[server(UpdateAlertObsSource, "/backend")]
+#[tracing::instrument(err)]
+pub async fn update_alert_obs_source(
+    alert_id: AlertId,
+    source: String,
+) -> Result<Alert, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+
+    manager
+        .edit_alert(&alert_id, move |alert| {
+            alert.obs_source = Some(source.trim().to_owned()).filter(|s| !s.is_empty());
+        })
+        .await?;
+
+    manager.get_alert(&alert_id).await
+}
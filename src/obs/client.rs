@@ -0,0 +1,429 @@
+//! The obs-websocket v5 client, reconnecting with a backoff while OBS isn't running.
+//!
+//! The url is configurable, so the client can be pointed at a local mock server speaking the
+//! same protocol.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use futures::{SinkExt, StreamExt};
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::tungstenite::Message;
+
+use super::PendingRequest;
+use crate::alerts::AlertManager;
+use crate::opts::{Opts, Secret};
+
+type WebSocket =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+const RPC_VERSION: u32 = 1;
+/// All events except the high volume ones.
+const EVENT_SUBSCRIPTIONS: u32 = (1 << 11) - 1;
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// Time OBS has to answer the identify.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+mod op {
+    pub const HELLO: u8 = 0;
+    pub const IDENTIFY: u8 = 1;
+    pub const IDENTIFIED: u8 = 2;
+    pub const EVENT: u8 = 5;
+    pub const REQUEST: u8 = 6;
+    pub const REQUEST_RESPONSE: u8 = 7;
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    url: String,
+    password: Option<Secret>,
+}
+
+impl Config {
+    pub fn from_opts(opts: &Opts) -> Option<Self> {
+        Some(Self {
+            url: opts.obs_websocket_url.clone()?,
+            password: opts.obs_websocket_password.clone(),
+        })
+    }
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct ObsMessage {
+    op: u8,
+    d: serde_json::Value,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Hello {
+    #[serde(default)]
+    obs_web_socket_version: Option<String>,
+    rpc_version: u32,
+    #[serde(default)]
+    authentication: Option<Authentication>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Authentication {
+    challenge: String,
+    salt: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Event {
+    event_type: String,
+    #[serde(default)]
+    event_data: serde_json::Value,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RequestResponse {
+    request_id: String,
+    request_status: RequestStatus,
+    #[serde(default)]
+    response_data: serde_json::Value,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct RequestStatus {
+    result: bool,
+    code: u32,
+    #[serde(default)]
+    comment: Option<String>,
+}
+
+/// `base64(sha256(base64(sha256(password + salt)) + challenge))`
+fn authentication_string(password: &str, auth: &Authentication) -> String {
+    use base64::Engine;
+    use sha2::Digest;
+
+    let engine = base64::engine::general_purpose::STANDARD;
+    let secret = engine.encode(sha2::Sha256::digest(format!("{password}{}", auth.salt)));
+    engine.encode(sha2::Sha256::digest(format!("{secret}{}", auth.challenge)))
+}
+
+/// Run the client forever, reconnecting with a backoff when the connection is lost.
+pub async fn run(manager: AlertManager, config: Config) {
+    let mut attempts = 0u32;
+    loop {
+        manager.obs.set_status("connecting").await;
+        let error = match session(&manager, &config, &mut attempts).await {
+            Ok(()) => eyre::eyre!("connection closed"),
+            Err(error) => error,
+        };
+        *manager.obs.connection.write().await = None;
+        tracing::debug!(%error, "obs websocket disconnected");
+        manager
+            .obs
+            .set_status(format!("disconnected: {error}"))
+            .await;
+        let delay = Duration::from_secs(1 << attempts.min(6)).min(MAX_BACKOFF);
+        attempts += 1;
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Handle one connection, until it's lost.
+async fn session(
+    manager: &AlertManager,
+    config: &Config,
+    attempts: &mut u32,
+) -> Result<(), eyre::Report> {
+    let (mut ws, _) = tokio_tungstenite::connect_async(&config.url).await?;
+    tokio::time::timeout(HANDSHAKE_TIMEOUT, identify(&mut ws, config))
+        .await
+        .map_err(|_| eyre::eyre!("OBS didn't finish the handshake"))??;
+    *attempts = 0;
+    tracing::info!(url = %config.url, "connected to obs");
+    manager.obs.set_status("connected").await;
+
+    let (sender, mut requests) = mpsc::unbounded_channel::<PendingRequest>();
+    *manager.obs.connection.write().await = Some(sender);
+    let mut pending: HashMap<String, oneshot::Sender<Result<serde_json::Value, eyre::Report>>> =
+        HashMap::new();
+    loop {
+        tokio::select! {
+            Some(request) = requests.recv() => {
+                let request_id = nanoid::nanoid!();
+                let message = ObsMessage {
+                    op: op::REQUEST,
+                    d: serde_json::json!({
+                        "requestType": request.request_type,
+                        "requestId": request_id,
+                        "requestData": request.data,
+                    }),
+                };
+                ws.send(Message::Text(serde_json::to_string(&message)?)).await?;
+                pending.insert(request_id, request.reply);
+            }
+            message = next_message(&mut ws) => {
+                let message = message?;
+                match message.op {
+                    op::EVENT => {
+                        let event: Event = serde_json::from_value(message.d)?;
+                        tracing::debug!(event_type = %event.event_type, "obs event");
+                        let manager = manager.clone();
+                        tokio::spawn(async move {
+                            let outcomes = manager
+                                .obs
+                                .dispatch(&manager, &event.event_type, &event.event_data)
+                                .await;
+                            for outcome in outcomes {
+                                if let Some(error) = outcome.error {
+                                    tracing::warn!(event_type = %event.event_type, action = %outcome.action, %error, "obs mapping failed");
+                                }
+                            }
+                        });
+                    }
+                    op::REQUEST_RESPONSE => {
+                        let response: RequestResponse = serde_json::from_value(message.d)?;
+                        let Some(reply) = pending.remove(&response.request_id) else {
+                            continue;
+                        };
+                        let status = response.request_status;
+                        let _ = reply.send(if status.result {
+                            Ok(response.response_data)
+                        } else {
+                            Err(eyre::eyre!(
+                                "OBS refused the request ({}): {}",
+                                status.code,
+                                status.comment.unwrap_or_default()
+                            ))
+                        });
+                    }
+                    op => tracing::trace!(op, "unhandled obs message"),
+                }
+            }
+        }
+    }
+}
+
+/// Answer the hello, authenticating if OBS asks for it.
+async fn identify(ws: &mut WebSocket, config: &Config) -> Result<(), eyre::Report> {
+    let hello = next_message(ws).await?;
+    if hello.op != op::HELLO {
+        eyre::bail!("expected a hello, got op {}", hello.op);
+    }
+    let hello: Hello = serde_json::from_value(hello.d)?;
+    if hello.rpc_version < RPC_VERSION {
+        eyre::bail!("unsupported rpc version {}", hello.rpc_version);
+    }
+    tracing::debug!(version = ?hello.obs_web_socket_version, "obs hello");
+    let mut identify = serde_json::json!({
+        "rpcVersion": RPC_VERSION,
+        "eventSubscriptions": EVENT_SUBSCRIPTIONS,
+    });
+    if let Some(auth) = &hello.authentication {
+        let Some(password) = &config.password else {
+            eyre::bail!("OBS requires a password, set --obs-websocket-password");
+        };
+        identify["authentication"] = authentication_string(password.secret(), auth).into();
+    }
+    let message = ObsMessage {
+        op: op::IDENTIFY,
+        d: identify,
+    };
+    ws.send(Message::Text(serde_json::to_string(&message)?))
+        .await?;
+    let identified = next_message(ws).await?;
+    if identified.op != op::IDENTIFIED {
+        eyre::bail!("expected identified, got op {}", identified.op);
+    }
+    Ok(())
+}
+
+/// Read the next message, OBS closes the connection with a reason when identifying fails.
+async fn next_message(ws: &mut WebSocket) -> Result<ObsMessage, eyre::Report> {
+    loop {
+        match ws.next().await {
+            Some(Ok(Message::Text(text))) => return Ok(serde_json::from_str(&text)?),
+            Some(Ok(Message::Close(Some(frame)))) => {
+                eyre::bail!(
+                    "closed by OBS ({}): {}",
+                    u16::from(frame.code),
+                    frame.reason
+                )
+            }
+            Some(Ok(Message::Close(None))) => eyre::bail!("closed by OBS"),
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => return Err(e.into()),
+            None => eyre::bail!("connection closed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{ObsMapping, ObsMappingId};
+    use super::*;
+    use crate::alerts::AlertField;
+
+    type ServerSocket = tokio_tungstenite::WebSocketStream<tokio::net::TcpStream>;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+    /// The example in the obs-websocket protocol documentation.
+    const PASSWORD: &str = "supersecretpassword";
+    const SALT: &str = "lM1GncleQOaCu9lT1yeUZhFYnqhsLLP1G5lAGo3ixaI=";
+    const CHALLENGE: &str = "+IxH4CnCiqpX1rM9scsNynZzbOe4KhDeYcTNS3PDaeY=";
+    const AUTHENTICATION: &str = "1Ct943GAT+6YQUUX47Ia/ncufilbe6+oD6lY+5kaCu4=";
+
+    #[test]
+    fn documented_authentication_string() {
+        let auth = Authentication {
+            challenge: CHALLENGE.to_owned(),
+            salt: SALT.to_owned(),
+        };
+        assert_eq!(authentication_string(PASSWORD, &auth), AUTHENTICATION);
+    }
+
+    async fn send(ws: &mut ServerSocket, op: u8, d: serde_json::Value) {
+        let message = serde_json::to_string(&ObsMessage { op, d }).unwrap();
+        ws.send(Message::Text(message)).await.unwrap();
+    }
+
+    async fn receive(ws: &mut ServerSocket) -> ObsMessage {
+        loop {
+            let message = tokio::time::timeout(TIMEOUT, ws.next())
+                .await
+                .expect("the client sent a message")
+                .unwrap()
+                .unwrap();
+            if let Message::Text(text) = message {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn mock_obs() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (_dir, manager) = crate::alerts::test_manager(&[
+            "--obs-websocket-url",
+            &url,
+            "--obs-websocket-password",
+            PASSWORD,
+        ])
+        .await;
+        let alert_id = crate::alerts::test_alert(&manager, "streams").await;
+        manager
+            .obs
+            .save(ObsMapping {
+                mapping_id: ObsMappingId::new_id(),
+                event_type: "StreamStateChanged".to_owned(),
+                conditions: vec![],
+                action: crate::actions::AlertAction::IncrementField {
+                    alert_id: alert_id.clone(),
+                    field: "streams".into(),
+                    amount: "1".to_owned(),
+                },
+            })
+            .await
+            .unwrap();
+        let mut changes = manager.changes.subscribe();
+
+        // hello with an auth challenge, identify, identified
+        let (stream, _) = tokio::time::timeout(TIMEOUT, listener.accept())
+            .await
+            .expect("the client connected")
+            .unwrap();
+        let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+        send(
+            &mut ws,
+            op::HELLO,
+            serde_json::json!({
+                "obsWebSocketVersion": "5.1.0",
+                "rpcVersion": 1,
+                "authentication": { "challenge": CHALLENGE, "salt": SALT },
+            }),
+        )
+        .await;
+        let identify = receive(&mut ws).await;
+        assert_eq!(identify.op, op::IDENTIFY);
+        assert_eq!(identify.d["rpcVersion"], RPC_VERSION);
+        assert_eq!(identify.d["authentication"], AUTHENTICATION);
+        assert_eq!(identify.d["eventSubscriptions"], EVENT_SUBSCRIPTIONS);
+        send(
+            &mut ws,
+            op::IDENTIFIED,
+            serde_json::json!({ "negotiatedRpcVersion": 1 }),
+        )
+        .await;
+        tokio::time::timeout(TIMEOUT, async {
+            while manager.obs.connection.read().await.is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the client identified");
+
+        // responses are matched to requests by id, not by order
+        let obs = manager.obs.clone();
+        let scenes =
+            tokio::spawn(async move { obs.request("GetSceneList", serde_json::json!({})).await });
+        let first = receive(&mut ws).await;
+        let obs = manager.obs.clone();
+        let version = tokio::spawn(async move {
+            obs.request("GetVersion", serde_json::json!({ "x": 1 }))
+                .await
+        });
+        let second = receive(&mut ws).await;
+        assert_eq!(first.op, op::REQUEST);
+        assert_eq!(first.d["requestType"], "GetSceneList");
+        assert_eq!(second.d["requestType"], "GetVersion");
+        assert_eq!(second.d["requestData"], serde_json::json!({ "x": 1 }));
+        assert_ne!(first.d["requestId"], second.d["requestId"]);
+        send(
+            &mut ws,
+            op::REQUEST_RESPONSE,
+            serde_json::json!({
+                "requestType": "GetVersion",
+                "requestId": second.d["requestId"],
+                "requestStatus": { "result": true, "code": 100 },
+                "responseData": { "obsVersion": "30.0.0" },
+            }),
+        )
+        .await;
+        send(
+            &mut ws,
+            op::REQUEST_RESPONSE,
+            serde_json::json!({
+                "requestType": "GetSceneList",
+                "requestId": first.d["requestId"],
+                "requestStatus": { "result": false, "code": 600, "comment": "no scenes" },
+            }),
+        )
+        .await;
+        let version = version.await.unwrap().unwrap();
+        assert_eq!(version["obsVersion"], "30.0.0");
+        let error = scenes.await.unwrap().unwrap_err().to_string();
+        assert!(
+            error.contains("600") && error.contains("no scenes"),
+            "{error}"
+        );
+
+        // events run the mappings
+        send(
+            &mut ws,
+            op::EVENT,
+            serde_json::json!({
+                "eventType": "StreamStateChanged",
+                "eventIntent": 64,
+                "eventData": {
+                    "outputActive": true,
+                    "outputState": "OBS_WEBSOCKET_OUTPUT_STARTED",
+                },
+            }),
+        )
+        .await;
+        let change = tokio::time::timeout(TIMEOUT, changes.recv())
+            .await
+            .expect("the mapping ran")
+            .unwrap();
+        assert_eq!(change.alert_id, alert_id);
+        assert_eq!(change.fields[0].value, Some(AlertField::Counter(1)));
+    }
+}
@@ -9,6 +9,68 @@ pub struct Opts {
     pub db_path: PathBuf,
     #[clap(long, env, hide_env = true)]
     pub admin_password: Secret,
+    /// Command used for text-to-speech, e.g. `espeak-ng -v {voice} -s {rate} --stdin --stdout`
+    #[clap(long, env, hide_env = true)]
+    pub tts_command: Option<String>,
+    /// Content type of the audio produced by the tts command
+    #[clap(long, env, hide_env = true, default_value = "audio/wav")]
+    pub tts_content_type: String,
+    /// Voices the tts command accepts, comma separated, alerts can only pick one of these
+    #[clap(long, env, hide_env = true, value_delimiter = ',')]
+    pub tts_voices: Vec<String>,
+    /// Secret of the Twitch EventSub webhook subscriptions, enables `/twitch/eventsub`
+    #[clap(long, env, hide_env = true)]
+    pub twitch_eventsub_secret: Option<Secret>,
+    /// Client id of the Twitch application used by the EventSub websocket client
+    #[clap(long, env, hide_env = true)]
+    pub twitch_client_id: Option<String>,
+    /// User access token for the EventSub websocket client, with the scopes the mapped events need
+    #[clap(long, env, hide_env = true)]
+    pub twitch_access_token: Option<Secret>,
+    /// Id of the channel to receive events of over the EventSub websocket
+    #[clap(long, env, hide_env = true)]
+    pub twitch_broadcaster_id: Option<String>,
+    /// EventSub websocket endpoint, e.g. `ws://127.0.0.1:8080/ws` for the Twitch CLI
+    #[clap(
+        long,
+        env,
+        hide_env = true,
+        default_value = "wss://eventsub.wss.twitch.tv/ws"
+    )]
+    pub twitch_eventsub_ws_url: String,
+    /// Twitch API used to manage EventSub subscriptions, e.g. `http://127.0.0.1:8080` for the Twitch CLI
+    #[clap(
+        long,
+        env,
+        hide_env = true,
+        default_value = "https://api.twitch.tv/helix"
+    )]
+    pub twitch_api_url: String,
+    /// OBS websocket server to connect to, e.g. `ws://127.0.0.1:4455`
+    #[clap(long, env, hide_env = true)]
+    pub obs_websocket_url: Option<String>,
+    /// Password of the OBS websocket server, if authentication is enabled
+    #[clap(long, env, hide_env = true)]
+    pub obs_websocket_password: Option<Secret>,
+    /// Twitch channel to run chat commands in, enables the chat client
+    #[clap(long, env, hide_env = true)]
+    pub twitch_chat_channel: Option<String>,
+    /// Login of the account replying in chat, chat is read anonymously without one
+    #[clap(long, env, hide_env = true)]
+    pub twitch_chat_nick: Option<String>,
+    /// User access token of the chat account with the `chat:read` and `chat:edit` scopes,
+    /// defaults to the EventSub access token
+    #[clap(long, env, hide_env = true)]
+    pub twitch_chat_token: Option<Secret>,
+    /// Chat server, `wss://` or `ws://` for IRC over a websocket or `irc://host:port` for plain
+    /// IRC, e.g. `irc://127.0.0.1:6667` for a local server
+    #[clap(
+        long,
+        env,
+        hide_env = true,
+        default_value = "wss://irc-ws.chat.twitch.tv:443"
+    )]
+    pub twitch_chat_url: String,
 }
 
 #[derive(Clone)]
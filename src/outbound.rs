@@ -0,0 +1,634 @@
+//! Outbound webhooks, posting to a url when fields of an alert change.
+//!
+//! The body is a JSON template with the variables `$alert_id`, `$alert_name`, `$field`, `$value`
+//! and `$previous`, escaped for use inside JSON strings. With a secret, requests are signed with
+//! [`SIGNATURE_HEADER`]. Failed deliveries are retried with a backoff.
+//!
+//! Webhooks are stored under `db_path/outbound`, the delivery log is only kept in memory.
+
+use leptos::{prelude::*, server};
+
+use crate::alerts::*;
+use crate::events::Condition;
+#[cfg(feature = "ssr")]
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+#[cfg(feature = "ssr")]
+use tokio::sync::RwLock;
+
+/// Header with `sha256=<hex encoded hmac-sha256 of the body>`
+pub const SIGNATURE_HEADER: &str = "X-Stream-Alerts-Signature";
+
+#[cfg(feature = "ssr")]
+const MAX_ATTEMPTS: u32 = 5;
+/// Deliveries kept per webhook.
+#[cfg(feature = "ssr")]
+const LOG_SIZE: usize = 20;
+
+#[aliri_braid::braid(serde)]
+pub struct OutboundWebhookId;
+
+impl OutboundWebhookId {
+    pub fn new_id() -> Self {
+        Self(nanoid::nanoid!(8))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct OutboundWebhook {
+    pub webhook_id: OutboundWebhookId,
+    pub name: String,
+    pub url: String,
+    pub alert_id: AlertId,
+    /// Field to watch, `None` for all fields.
+    #[serde(default)]
+    pub field: Option<AlertFieldName>,
+    /// e.g. `value >= 100, previous < 100` to fire when crossing 100.
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    /// JSON template, empty for a body with all variables.
+    #[serde(default)]
+    pub body: String,
+    #[serde(default)]
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Delivery {
+    /// RFC 3339 timestamp of the first attempt
+    pub at: String,
+    /// What caused the delivery, e.g. `deaths: 4 → 5`
+    pub trigger: String,
+    pub attempts: u32,
+    /// Status of the last response
+    pub status: Option<u16>,
+    pub error: Option<String>,
+}
+
+/// A webhook as listed in the editor, the secret stays on the server.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct ListedOutboundWebhook {
+    /// The webhook, without its secret.
+    pub webhook: OutboundWebhook,
+    pub has_secret: bool,
+    /// Latest deliveries first.
+    pub deliveries: Vec<Delivery>,
+}
+
+/// Escape a value for use inside a JSON string.
+fn json_escape(value: &str) -> String {
+    let quoted = serde_json::Value::String(value.to_owned()).to_string();
+    quoted[1..quoted.len() - 1].to_owned()
+}
+
+impl OutboundWebhook {
+    pub fn render_body(&self, vars: &[(String, String)]) -> String {
+        if self.body.trim().is_empty() {
+            return serde_json::Value::Object(
+                vars.iter()
+                    .map(|(k, v)| (k.clone(), serde_json::Value::String(v.clone())))
+                    .collect(),
+            )
+            .to_string();
+        }
+        let vars: Vec<_> = vars
+            .iter()
+            .map(|(k, v)| (k.clone(), json_escape(v)))
+            .collect();
+        crate::actions::substitute_vars(&self.body, &vars)
+    }
+}
+
+/// Variables of a field change.
+pub fn change_vars(
+    alert_id: &AlertId,
+    alert_name: &AlertName,
+    field: &AlertFieldName,
+    value: Option<&AlertField>,
+    previous: Option<&AlertField>,
+) -> Vec<(String, String)> {
+    vec![
+        ("alert_id".to_owned(), alert_id.to_string()),
+        ("alert_name".to_owned(), alert_name.to_string()),
+        ("field".to_owned(), field.to_string()),
+        (
+            "value".to_owned(),
+            value.map(ToString::to_string).unwrap_or_default(),
+        ),
+        (
+            "previous".to_owned(),
+            previous.map(ToString::to_string).unwrap_or_default(),
+        ),
+    ]
+}
+
+#[cfg(feature = "ssr")]
+fn sign(secret: &str, body: &str) -> String {
+    use hmac::Mac;
+
+    let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(secret.as_bytes())
+        .expect("hmac accepts keys of any length");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Clone)]
+pub struct OutboundWebhooks {
+    path: PathBuf,
+    webhooks: Arc<RwLock<BTreeMap<OutboundWebhookId, OutboundWebhook>>>,
+    log: Arc<RwLock<HashMap<OutboundWebhookId, VecDeque<Delivery>>>>,
+    http: reqwest::Client,
+}
+
+#[cfg(feature = "ssr")]
+impl OutboundWebhooks {
+    pub async fn load(db_path: &std::path::Path) -> Result<Self, eyre::Report> {
+        let path = db_path.join("outbound");
+        tokio::fs::create_dir_all(&path).await?;
+        let mut webhooks = BTreeMap::new();
+        let mut dir = tokio::fs::read_dir(&path).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                let webhook: OutboundWebhook =
+                    serde_json::from_slice(&tokio::fs::read(&path).await?)?;
+                webhooks.insert(webhook.webhook_id.clone(), webhook);
+            }
+        }
+        Ok(Self {
+            path,
+            webhooks: Arc::new(RwLock::new(webhooks)),
+            log: Default::default(),
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()?,
+        })
+    }
+
+    fn webhook_path(&self, webhook_id: &OutboundWebhookIdRef) -> PathBuf {
+        self.path.join(format!("{webhook_id}.json"))
+    }
+
+    pub async fn for_alert(&self, alert_id: &AlertId) -> Vec<OutboundWebhook> {
+        self.webhooks
+            .read()
+            .await
+            .values()
+            .filter(|w| &w.alert_id == alert_id)
+            .cloned()
+            .collect()
+    }
+
+    pub async fn get(&self, webhook_id: &OutboundWebhookIdRef) -> Option<OutboundWebhook> {
+        self.webhooks.read().await.get(webhook_id).cloned()
+    }
+
+    pub async fn save(&self, webhook: OutboundWebhook) -> Result<(), eyre::Report> {
+        tokio::fs::write(
+            self.webhook_path(&webhook.webhook_id),
+            serde_json::to_vec(&webhook)?,
+        )
+        .await?;
+        self.webhooks
+            .write()
+            .await
+            .insert(webhook.webhook_id.clone(), webhook);
+        Ok(())
+    }
+
+    pub async fn remove(&self, webhook_id: &OutboundWebhookIdRef) -> Result<(), eyre::Report> {
+        if self.webhooks.write().await.remove(webhook_id).is_some() {
+            tokio::fs::remove_file(self.webhook_path(webhook_id)).await?;
+        }
+        self.log.write().await.remove(webhook_id);
+        Ok(())
+    }
+
+    /// Latest deliveries first.
+    pub async fn deliveries(&self, webhook_id: &OutboundWebhookIdRef) -> Vec<Delivery> {
+        self.log
+            .read()
+            .await
+            .get(webhook_id)
+            .map(|log| log.iter().rev().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    async fn record(&self, webhook_id: &OutboundWebhookId, delivery: Delivery) {
+        let mut log = self.log.write().await;
+        let log = log.entry(webhook_id.clone()).or_default();
+        if log.len() >= LOG_SIZE {
+            log.pop_front();
+        }
+        log.push_back(delivery);
+    }
+
+    /// Post to the webhook, retrying server errors and failed connections.
+    pub async fn deliver(
+        &self,
+        webhook: &OutboundWebhook,
+        trigger: String,
+        vars: &[(String, String)],
+    ) -> Delivery {
+        let body = webhook.render_body(vars);
+        let mut delivery = Delivery {
+            at: chrono::Utc::now().to_rfc3339(),
+            trigger,
+            attempts: 0,
+            status: None,
+            error: None,
+        };
+        for attempt in 1..=MAX_ATTEMPTS {
+            delivery.attempts = attempt;
+            let mut request = self
+                .http
+                .post(&webhook.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone());
+            if let Some(secret) = &webhook.secret {
+                request = request.header(SIGNATURE_HEADER, sign(secret, &body));
+            }
+            let retry = match request.send().await {
+                Ok(response) => {
+                    let status = response.status();
+                    delivery.status = Some(status.as_u16());
+                    if status.is_success() {
+                        delivery.error = None;
+                        break;
+                    }
+                    let text = response.text().await.unwrap_or_default();
+                    delivery.error = Some(text.chars().take(200).collect());
+                    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                }
+                Err(e) => {
+                    delivery.status = None;
+                    delivery.error = Some(e.to_string());
+                    true
+                }
+            };
+            if !retry || attempt == MAX_ATTEMPTS {
+                break;
+            }
+            tokio::time::sleep(Duration::from_secs(1 << (attempt - 1))).await;
+        }
+        if let Some(error) = &delivery.error {
+            tracing::warn!(webhook_id = %webhook.webhook_id, %error, "outbound webhook failed");
+        }
+        self.record(&webhook.webhook_id, delivery.clone()).await;
+        delivery
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl AlertManager {
+    /// Fire outbound webhooks on field changes, runs forever.
+    pub(crate) async fn deliver_outbound_webhooks(self) {
+        use tokio::sync::broadcast::error::RecvError;
+
+        let mut changes = self.changes.subscribe();
+        loop {
+            let change = match changes.recv().await {
+                Ok(change) => change,
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::warn!(missed, "outbound webhooks missed field changes");
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            let webhooks = self.outbound.for_alert(&change.alert_id).await;
+            if webhooks.is_empty() {
+                continue;
+            }
+            for delta in &change.fields {
+                let vars = change_vars(
+                    &change.alert_id,
+                    &change.alert_name,
+                    &delta.name,
+                    delta.value.as_ref(),
+                    delta.previous.as_ref(),
+                );
+                let trigger = match (&delta.previous, &delta.value) {
+                    (Some(previous), Some(value)) => {
+                        format!("{}: {previous} → {value}", delta.name)
+                    }
+                    (None, Some(value)) => format!("{}: {value}", delta.name),
+                    (_, None) => format!("{}: removed", delta.name),
+                };
+                for webhook in webhooks.iter().filter(|w| {
+                    w.field.as_ref().map_or(true, |f| f == &delta.name)
+                        && w.conditions.iter().all(|c| c.matches(&vars))
+                }) {
+                    let outbound = self.outbound.clone();
+                    let webhook = webhook.clone();
+                    let vars = vars.clone();
+                    let trigger = trigger.clone();
+                    tokio::spawn(async move { outbound.deliver(&webhook, trigger, &vars).await });
+                }
+            }
+        }
+    }
+}
+
+/// Webhooks of an alert with their latest deliveries.
+#[server(ListOutboundWebhooks, "/backend")]
+pub async fn list_outbound_webhooks(
+    alert_id: AlertId,
+) -> Result<Vec<ListedOutboundWebhook>, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    let mut webhooks = vec![];
+    for mut webhook in manager.outbound.for_alert(&alert_id).await {
+        let deliveries = manager.outbound.deliveries(&webhook.webhook_id).await;
+        webhooks.push(ListedOutboundWebhook {
+            has_secret: webhook.secret.take().is_some(),
+            webhook,
+            deliveries,
+        });
+    }
+    Ok(webhooks)
+}
+
+/// Create a webhook, or update it if `webhook_id` isn't empty.
+///
+/// An empty `secret` keeps the secret of an existing webhook, unless `clear_secret` is set.
+#[server(SaveOutboundWebhook, "/backend")]
+#[tracing::instrument(skip(secret), err)]
+#[allow(clippy::too_many_arguments)]
+pub async fn save_outbound_webhook(
+    webhook_id: String,
+    alert_id: AlertId,
+    name: String,
+    url: String,
+    field: String,
+    conditions: String,
+    body: String,
+    secret: String,
+    clear_secret: Option<String>,
+) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    let alert = manager.get_alert(&alert_id).await?;
+    let url = url.trim().to_owned();
+    match reqwest::Url::parse(&url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => {}
+        _ => {
+            return Err(ServerFnError::ServerError(
+                "url has to be http or https".to_owned(),
+            ))
+        }
+    }
+    let field = match field.trim() {
+        "" => None,
+        field => Some(AlertFieldName::from(field)),
+    };
+    // the id ends up in a file name, only ids of existing webhooks are accepted
+    let existing = match webhook_id.trim() {
+        "" => None,
+        id => match manager
+            .outbound
+            .get(OutboundWebhookIdRef::from_str(id))
+            .await
+        {
+            Some(existing) => Some(existing),
+            None => return Err(ServerFnError::ServerError("no such webhook".to_owned())),
+        },
+    };
+    let secret = match &existing {
+        _ if !secret.is_empty() => Some(secret),
+        Some(existing) if clear_secret.is_none() => existing.secret.clone(),
+        _ => None,
+    };
+    let webhook_id = existing
+        .map(|existing| existing.webhook_id)
+        .unwrap_or_else(OutboundWebhookId::new_id);
+    let webhook = OutboundWebhook {
+        webhook_id,
+        name,
+        url,
+        alert_id,
+        field,
+        conditions: crate::events::parse_conditions(&conditions).map_err(|e| {
+            ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+        })?,
+        body,
+        secret,
+    };
+    // catch broken templates before they fail on every change
+    let sample = change_vars(
+        &alert.alert_id,
+        &alert.name,
+        &AlertFieldName::from("field"),
+        Some(&AlertField::Counter(1)),
+        Some(&AlertField::Counter(0)),
+    );
+    serde_json::from_str::<serde_json::Value>(&webhook.render_body(&sample)).map_err(|e| {
+        ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(format!(
+            "body is not valid JSON: {e}"
+        ))
+    })?;
+    manager
+        .outbound
+        .save(webhook)
+        .await
+        .map_err(|e| ServerFnError::ServerError(e.to_string()))
+}
+
+#[server(DeleteOutboundWebhook, "/backend")]
+#[tracing::instrument(err)]
+pub async fn delete_outbound_webhook(webhook_id: OutboundWebhookId) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager
+        .outbound
+        .remove(&webhook_id)
+        .await
+        .map_err(|e| ServerFnError::ServerError(e.to_string()))
+}
+
+/// Deliver with the current value of the watched field, ignoring conditions.
+#[server(TestOutboundWebhook, "/backend")]
+#[tracing::instrument(err)]
+pub async fn test_outbound_webhook(
+    webhook_id: OutboundWebhookId,
+) -> Result<Delivery, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    let Some(webhook) = manager.outbound.get(&webhook_id).await else {
+        return Err(ServerFnError::ServerError("no such webhook".to_owned()));
+    };
+    let alert = manager.get_alert(&webhook.alert_id).await?;
+    let (_, (name, value)) = alert
+        .fields
+        .iter()
+        .find(|(_, (name, _))| webhook.field.as_ref().map_or(true, |f| f == name))
+        .ok_or_else(|| {
+            ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(
+                "the alert has no such field".to_owned(),
+            )
+        })?;
+    let vars = change_vars(&alert.alert_id, &alert.name, name, Some(value), Some(value));
+    Ok(manager
+        .outbound
+        .deliver(&webhook, "test".to_owned(), &vars)
+        .await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn webhook(body: &str) -> OutboundWebhook {
+        OutboundWebhook {
+            webhook_id: OutboundWebhookId::new_id(),
+            name: "test".to_owned(),
+            url: String::new(),
+            alert_id: AlertId::from("alert"),
+            field: None,
+            conditions: vec![],
+            body: body.to_owned(),
+            secret: None,
+        }
+    }
+
+    fn vars() -> Vec<(String, String)> {
+        change_vars(
+            &AlertId::from("alert"),
+            &AlertName::from("Deaths \"today\""),
+            &AlertFieldName::from("deaths"),
+            Some(&AlertField::Counter(5)),
+            None,
+        )
+    }
+
+    #[test]
+    fn change_vars_of_a_field() {
+        assert_eq!(
+            vars(),
+            [
+                ("alert_id", "alert"),
+                ("alert_name", "Deaths \"today\""),
+                ("field", "deaths"),
+                ("value", "5"),
+                ("previous", ""),
+            ]
+            .map(|(k, v)| (k.to_owned(), v.to_owned()))
+        );
+    }
+
+    #[test]
+    fn render_body_template() {
+        let body = webhook(r#"{"text": "$alert_name: $field is now $value", "n": $value}"#)
+            .render_body(&vars());
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!({ "text": "Deaths \"today\": deaths is now 5", "n": 5 })
+        );
+    }
+
+    #[test]
+    fn render_body_default() {
+        let body = webhook(" ").render_body(&vars());
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!({
+                "alert_id": "alert",
+                "alert_name": "Deaths \"today\"",
+                "field": "deaths",
+                "value": "5",
+                "previous": "",
+            })
+        );
+    }
+
+    #[cfg(feature = "ssr")]
+    #[test]
+    fn sign_body() {
+        // the hmac-sha256 example from wikipedia
+        assert_eq!(
+            sign("key", "The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    /// Signature headers of the received requests.
+    #[cfg(feature = "ssr")]
+    type Received = Arc<std::sync::Mutex<Vec<Option<String>>>>;
+
+    /// Respond with the statuses in order, returning the url to post to.
+    #[cfg(feature = "ssr")]
+    async fn serve(statuses: &[u16]) -> (String, Received) {
+        use axum::{extract::State, http::HeaderMap, http::StatusCode};
+
+        let received = Received::default();
+        let statuses = Arc::new(statuses.to_vec());
+        let hook = |State((statuses, received)): State<(Arc<Vec<u16>>, Received)>,
+                    headers: HeaderMap| async move {
+            let mut received = received.lock().unwrap();
+            received.push(
+                headers
+                    .get(SIGNATURE_HEADER)
+                    .map(|v| v.to_str().unwrap().to_owned()),
+            );
+            StatusCode::from_u16(statuses[received.len() - 1]).unwrap()
+        };
+        let app = axum::Router::new()
+            .route("/hook", axum::routing::post(hook))
+            .with_state((statuses, received.clone()));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, received)
+    }
+
+    #[cfg(feature = "ssr")]
+    #[tokio::test]
+    async fn deliver_retries_server_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let outbound = OutboundWebhooks::load(dir.path()).await.unwrap();
+        let (url, received) = serve(&[503, 200]).await;
+        let webhook = OutboundWebhook {
+            url,
+            secret: Some("s3cr3t".to_owned()),
+            ..webhook("")
+        };
+
+        let delivery = outbound.deliver(&webhook, "test".to_owned(), &vars()).await;
+        assert_eq!(delivery.attempts, 2);
+        assert_eq!(delivery.status, Some(200));
+        assert_eq!(delivery.error, None);
+        let signature = sign("s3cr3t", &webhook.render_body(&vars()));
+        assert_eq!(
+            *received.lock().unwrap(),
+            [Some(signature.clone()), Some(signature)]
+        );
+        assert_eq!(outbound.deliveries(&webhook.webhook_id).await, [delivery]);
+    }
+
+    #[cfg(feature = "ssr")]
+    #[tokio::test]
+    async fn deliver_gives_up_on_client_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let outbound = OutboundWebhooks::load(dir.path()).await.unwrap();
+        let (url, received) = serve(&[404, 200]).await;
+        let webhook = OutboundWebhook { url, ..webhook("") };
+
+        let delivery = outbound.deliver(&webhook, "test".to_owned(), &vars()).await;
+        assert_eq!(delivery.attempts, 1);
+        assert_eq!(delivery.status, Some(404));
+        assert!(delivery.error.is_some());
+        assert_eq!(*received.lock().unwrap(), [None]);
+    }
+}
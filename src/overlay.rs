@@ -0,0 +1,72 @@
+//! The overlay runtime, the script driving `/alert/:id`.
+//!
+//! The script is embedded in the binary and served at a url containing a hash of its content, so
+//! browser sources never run a stale runtime against a newer server.
+
+use axum::{
+    extract,
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+use once_cell::sync::Lazy;
+
+/// Version of the websocket protocol, the runtime sends the versions it supports on connect.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Close code sent when the runtime doesn't support [`PROTOCOL_VERSION`].
+pub const CLOSE_UNSUPPORTED_PROTOCOL: u16 = 4001;
+
+const RUNTIME: &str = include_str!("../overlay/runtime.js");
+
+/// Hash of the runtime, changes with every change to the script.
+pub static RUNTIME_VERSION: Lazy<String> = Lazy::new(|| {
+    use sha2::Digest;
+    let hash = format!("{:x}", sha2::Sha256::digest(RUNTIME.as_bytes()));
+    hash[..16].to_owned()
+});
+
+/// Messages for the connection, not about a specific alert.
+#[derive(Clone, serde::Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OverlayMessage {
+    /// Reply to [`crate::alerts::AlertMessageRecv::Init`]
+    Hello {
+        protocol: u32,
+        /// Version of the runtime the overlay should be running.
+        runtime: String,
+    },
+    /// The page changed, e.g. alerts were added to a scene.
+    Reload,
+}
+
+impl OverlayMessage {
+    pub(crate) fn to_message(&self) -> Result<axum::extract::ws::Message, eyre::Report> {
+        Ok(axum::extract::ws::Message::Text(serde_json::to_string(
+            self,
+        )?))
+    }
+}
+
+pub fn runtime_url() -> String {
+    format!("/alert/runtime/overlay.{}.js", *RUNTIME_VERSION)
+}
+
+pub(crate) async fn serve_runtime(
+    extract::Path(file): extract::Path<String>,
+) -> axum::response::Response {
+    let cache = if file == format!("overlay.{}.js", *RUNTIME_VERSION) {
+        "public, max-age=31536000, immutable"
+    } else {
+        // an old page, it reloads when the server reports a different version
+        "no-cache"
+    };
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, "text/javascript; charset=utf-8"),
+            (header::CACHE_CONTROL, cache),
+        ],
+        RUNTIME,
+    )
+        .into_response()
+}
@@ -0,0 +1,384 @@
+//! Playlists cycling the text of an alert through markdown messages, e.g. for sponsor tickers.
+//!
+//! While a playlist runs the alert shows its current item instead of the alert text. The server
+//! broadcasts every rotation, so all overlays show the same message and reconnecting overlays get
+//! the current one. An item with weight `n` gets `n` times the airtime of an item with weight 1:
+//! it's shown for `n` intervals in a row, or picked `n` times as often when shuffling.
+
+use leptos::{prelude::*, server};
+
+use crate::alerts::*;
+
+/// Shortest interval between messages.
+pub const MIN_INTERVAL_SECS: u64 = 5;
+/// Highest weight of an item.
+pub const MAX_WEIGHT: u32 = 100;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaylistOrder {
+    #[default]
+    Sequence,
+    Shuffle,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct PlaylistItem {
+    /// Markdown, may use fields like the alert text.
+    pub text: String,
+    pub weight: u32,
+}
+
+impl PlaylistItem {
+    /// The weight, within `1..=MAX_WEIGHT`.
+    pub fn airtime(&self) -> u32 {
+        self.weight.clamp(1, MAX_WEIGHT)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Playlist {
+    pub enabled: bool,
+    pub interval_secs: u64,
+    pub order: PlaylistOrder,
+    pub items: Vec<PlaylistItem>,
+    /// Index of the current item
+    pub position: usize,
+    /// Intervals the current item has been shown for
+    pub shown: u32,
+}
+
+impl Default for Playlist {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_secs: 30,
+            order: PlaylistOrder::default(),
+            items: vec![],
+            position: 0,
+            shown: 0,
+        }
+    }
+}
+
+impl Playlist {
+    pub fn is_running(&self) -> bool {
+        self.enabled && !self.items.is_empty()
+    }
+
+    /// Text of the current item, while the playlist is running.
+    pub fn current(&self) -> Option<&str> {
+        if !self.is_running() {
+            return None;
+        }
+        let item = &self.items[self.position % self.items.len()];
+        Some(&item.text)
+    }
+
+    /// Move to the next item and return its text.
+    #[cfg(feature = "ssr")]
+    pub fn advance(&mut self) -> Option<String> {
+        use rand::Rng;
+
+        if self.items.is_empty() {
+            return None;
+        }
+        let current = self.position % self.items.len();
+        let weight = self.items[current].airtime();
+        match self.order {
+            PlaylistOrder::Sequence if self.shown == 0 || self.shown < weight => {
+                self.position = current;
+            }
+            PlaylistOrder::Sequence => {
+                self.position = (current + 1) % self.items.len();
+                self.shown = 0;
+            }
+            PlaylistOrder::Shuffle => {
+                // don't show the same item twice in a row
+                let candidates: Vec<_> = (0..self.items.len())
+                    .filter(|&i| i != current || self.items.len() == 1 || self.shown == 0)
+                    .collect();
+                let total: u64 = candidates
+                    .iter()
+                    .map(|&i| u64::from(self.items[i].airtime()))
+                    .sum();
+                let mut pick = rand::thread_rng().gen_range(0..total);
+                for i in candidates {
+                    let weight = u64::from(self.items[i].airtime());
+                    if pick < weight {
+                        self.position = i;
+                        break;
+                    }
+                    pick -= weight;
+                }
+                self.shown = 0;
+            }
+        }
+        self.shown += 1;
+        Some(self.items[self.position].text.clone())
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl AlertManager {
+    /// Rotate the text of alerts with a running playlist, runs forever.
+    pub(crate) async fn run_playlists(self) {
+        use std::collections::HashMap;
+        use tokio::time::{Duration, Instant, MissedTickBehavior};
+
+        let mut next: HashMap<AlertId, Instant> = HashMap::new();
+        let mut tick = tokio::time::interval(Duration::from_secs(1));
+        tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tick.tick().await;
+            let now = Instant::now();
+            let running: Vec<_> = self
+                .read_alerts()
+                .await
+                .values()
+                .filter(|alert| alert.playlist.is_running())
+                .map(|alert| (alert.alert_id.clone(), alert.playlist.interval_secs))
+                .collect();
+            // forget stopped playlists, so they start from the current item when enabled again
+            next.retain(|alert_id, _| running.iter().any(|(id, _)| id == alert_id));
+            for (alert_id, interval_secs) in running {
+                if next.get(&alert_id).is_some_and(|at| *at > now) {
+                    continue;
+                }
+                let interval = Duration::from_secs(interval_secs.max(MIN_INTERVAL_SECS));
+                next.insert(alert_id.clone(), now + interval);
+                self.rotate_playlist(&alert_id).await;
+            }
+        }
+    }
+
+    /// Show the next item of a playlist on the overlays.
+    ///
+    /// Only the position changes, it's saved with the next edit of the alert instead of on every
+    /// rotation.
+    async fn rotate_playlist(&self, alert_id: &AlertId) {
+        let mut alerts = self.alerts.write().await;
+        let Some(alert) = alerts.get_mut(alert_id) else {
+            return;
+        };
+        let old = alert.to_html(&alert.render());
+        if alert.playlist.advance().is_none() {
+            return;
+        }
+        let html = alert.to_html(&alert.render());
+        if html != old {
+            let _ = self.sender.send(AlertMessage::new_message(
+                alert_id.clone(),
+                html,
+                alert.transition.clone(),
+            ));
+        }
+    }
+}
+
+#[server(UpdateAlertPlaylist, "/backend")]
+#[tracing::instrument(err)]
+pub async fn update_alert_playlist(
+    alert_id: AlertId,
+    enabled: bool,
+    interval_secs: u64,
+    order: PlaylistOrder,
+) -> Result<Alert, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    if interval_secs < MIN_INTERVAL_SECS {
+        return Err(ServerFnError::ServerError(format!(
+            "Interval has to be at least {MIN_INTERVAL_SECS} seconds"
+        )));
+    }
+
+    manager
+        .edit_alert(&alert_id, move |alert| {
+            alert.playlist.enabled = enabled;
+            alert.playlist.interval_secs = interval_secs;
+            alert.playlist.order = order;
+        })
+        .await?;
+
+    manager.get_alert(&alert_id).await
+}
+
+/// Add an item, or replace the item at `index` if it isn't empty.
+#[server(SavePlaylistItem, "/backend")]
+#[tracing::instrument(err)]
+pub async fn save_playlist_item(
+    alert_id: AlertId,
+    index: String,
+    text: String,
+    weight: u32,
+) -> Result<Alert, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    if text.trim().is_empty() {
+        return Err(ServerFnError::ServerError("Missing text".to_owned()));
+    }
+    let index = match index.trim() {
+        "" => None,
+        index => Some(index.parse::<usize>().map_err(|e| {
+            ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(format!(
+                "Invalid index: {e}"
+            ))
+        })?),
+    };
+    let item = PlaylistItem {
+        text,
+        weight: weight.clamp(1, MAX_WEIGHT),
+    };
+
+    manager
+        .edit_alert_html(&alert_id, move |alert| {
+            match index {
+                Some(index) => {
+                    let Some(existing) = alert.playlist.items.get_mut(index) else {
+                        return Err(ServerFnError::ServerError("No such item".to_owned()));
+                    };
+                    *existing = item;
+                }
+                None => alert.playlist.items.push(item),
+            }
+            Ok(())
+        })
+        .await?;
+
+    manager.get_alert(&alert_id).await
+}
+
+#[server(RemovePlaylistItem, "/backend")]
+#[tracing::instrument(err)]
+pub async fn remove_playlist_item(alert_id: AlertId, index: usize) -> Result<Alert, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+
+    manager
+        .try_edit_alert(&alert_id, move |alert| {
+            if index >= alert.playlist.items.len() {
+                return Err(
+                    ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(
+                        "No such item".to_owned(),
+                    ),
+                );
+            }
+            alert.playlist.items.remove(index);
+            if alert.playlist.position > index {
+                alert.playlist.position -= 1;
+            }
+            Ok(())
+        })
+        .await??;
+
+    manager.get_alert(&alert_id).await
+}
+
+#[cfg(all(test, feature = "ssr"))]
+mod tests {
+    use super::*;
+
+    fn playlist(order: PlaylistOrder, weights: &[u32]) -> Playlist {
+        Playlist {
+            enabled: true,
+            order,
+            items: weights
+                .iter()
+                .enumerate()
+                .map(|(i, &weight)| PlaylistItem {
+                    text: i.to_string(),
+                    weight,
+                })
+                .collect(),
+            ..Playlist::default()
+        }
+    }
+
+    fn rotate(playlist: &mut Playlist, times: usize) -> Vec<String> {
+        (0..times).map(|_| playlist.advance().unwrap()).collect()
+    }
+
+    #[test]
+    fn sequence_airtime() {
+        let mut playlist = playlist(PlaylistOrder::Sequence, &[1, 3, 0]);
+        assert_eq!(
+            rotate(&mut playlist, 10),
+            ["0", "1", "1", "1", "2", "0", "1", "1", "1", "2"]
+        );
+    }
+
+    #[test]
+    fn sequence_clamps_weight() {
+        let mut playlist = playlist(PlaylistOrder::Sequence, &[u32::MAX, 1]);
+        let shown = rotate(&mut playlist, MAX_WEIGHT as usize + 1);
+        assert!(shown[..MAX_WEIGHT as usize].iter().all(|text| text == "0"));
+        assert_eq!(shown[MAX_WEIGHT as usize], "1");
+    }
+
+    #[test]
+    fn shuffle_never_repeats() {
+        let mut playlist = playlist(PlaylistOrder::Shuffle, &[1, 50, 2]);
+        let shown = rotate(&mut playlist, 1000);
+        assert!(shown.windows(2).all(|pair| pair[0] != pair[1]));
+        for item in ["0", "1", "2"] {
+            assert!(shown.iter().any(|text| text == item));
+        }
+    }
+
+    #[test]
+    fn shuffle_weight_airtime() {
+        // weights that would overflow a u32 sum
+        let mut playlist = playlist(PlaylistOrder::Shuffle, &[u32::MAX, u32::MAX, 1, 1]);
+        let shown = rotate(&mut playlist, 2000);
+        let heavy = shown
+            .iter()
+            .filter(|text| *text == "0" || *text == "1")
+            .count();
+        // the light items get about 1 in 100 of the picks each
+        assert!(heavy > 1800, "{heavy} of 2000");
+    }
+
+    #[test]
+    fn shuffle_single_item() {
+        let mut playlist = playlist(PlaylistOrder::Shuffle, &[2]);
+        assert_eq!(rotate(&mut playlist, 3), ["0", "0", "0"]);
+    }
+
+    #[tokio::test]
+    async fn rotation_is_broadcast_not_saved() {
+        let (dir, manager) = crate::alerts::test_manager(&[]).await;
+        let alert_id = crate::alerts::test_alert(&manager, "count").await;
+        manager
+            .edit_alert(&alert_id, |alert| {
+                alert.playlist = playlist(PlaylistOrder::Sequence, &[1, 1]);
+            })
+            .await
+            .unwrap();
+        let mut messages = manager.sender.subscribe();
+
+        // the first rotation shows the current item, which the overlays already have
+        manager.rotate_playlist(&alert_id).await;
+        manager.rotate_playlist(&alert_id).await;
+        let text = match messages.try_recv().unwrap() {
+            AlertMessage::MessageMarkdown { text, .. } => text,
+            message => panic!("unexpected message {message:?}"),
+        };
+        assert_eq!(text.trim(), "<p>1</p>");
+        assert!(messages.try_recv().is_err());
+
+        let alert = manager.get_alert(&alert_id).await.unwrap();
+        assert_eq!(alert.last_text.as_str(), "$count");
+        assert_eq!(alert.playlist.position, 1);
+        let opts = crate::alerts::test_opts(dir.path(), &[]);
+        let (_, restarted) = crate::alerts::setup::<()>(&opts).await.unwrap();
+        let saved = restarted.get_alert(&alert_id).await.unwrap();
+        assert_eq!(saved.playlist.position, 0);
+    }
+}
@@ -0,0 +1,442 @@
+//! Scenes, several alerts laid out on one overlay page at `/scene/:id`.
+//!
+//! The page receives the messages of all its alerts over one websocket, styles are scoped to the
+//! element of each alert with [`scope_selector`]. Scenes are stored under `db_path/scenes`.
+
+use leptos::{prelude::*, server};
+
+use crate::alerts::*;
+use crate::layers::Canvas;
+#[cfg(feature = "ssr")]
+use askama::Template;
+#[cfg(feature = "ssr")]
+use axum::{
+    extract::{self, ws},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension,
+};
+#[cfg(feature = "ssr")]
+use rand::Rng;
+#[cfg(feature = "ssr")]
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+#[cfg(feature = "ssr")]
+use tokio::sync::{broadcast, RwLock};
+
+#[aliri_braid::braid(serde)]
+pub struct SceneId;
+
+impl SceneId {
+    pub fn new_id() -> Self {
+        Self(nanoid::nanoid!())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Scene {
+    pub scene_id: SceneId,
+    pub name: String,
+    #[serde(default)]
+    pub canvas: Canvas,
+    #[serde(default)]
+    pub items: Vec<SceneItem>,
+}
+
+/// An alert placed in a scene, each alert is in a scene at most once.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct SceneItem {
+    pub alert_id: AlertId,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub z_index: i32,
+}
+
+/// Selector of the element of an alert in a scene.
+pub fn scope_selector(alert_id: &AlertIdRef) -> String {
+    format!("[data-alert-id=\"{alert_id}\"]")
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Clone)]
+pub struct Scenes {
+    path: PathBuf,
+    scenes: Arc<RwLock<BTreeMap<SceneId, Scene>>>,
+    /// Scenes that were saved or deleted, their pages reload.
+    changes: broadcast::Sender<SceneId>,
+}
+
+#[cfg(feature = "ssr")]
+impl Scenes {
+    pub async fn load(db_path: &std::path::Path) -> Result<Self, eyre::Report> {
+        let path = db_path.join("scenes");
+        tokio::fs::create_dir_all(&path).await?;
+        let mut scenes = BTreeMap::new();
+        let mut dir = tokio::fs::read_dir(&path).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                let scene: Scene = serde_json::from_slice(&tokio::fs::read(&path).await?)?;
+                scenes.insert(scene.scene_id.clone(), scene);
+            }
+        }
+        Ok(Self {
+            path,
+            scenes: Arc::new(RwLock::new(scenes)),
+            changes: broadcast::channel(16).0,
+        })
+    }
+
+    fn scene_path(&self, scene_id: &SceneIdRef) -> PathBuf {
+        self.path.join(format!("{scene_id}.json"))
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<SceneId> {
+        self.changes.subscribe()
+    }
+
+    pub async fn list(&self) -> Vec<Scene> {
+        self.scenes.read().await.values().cloned().collect()
+    }
+
+    pub async fn get(&self, scene_id: &SceneIdRef) -> Option<Scene> {
+        self.scenes.read().await.get(scene_id).cloned()
+    }
+
+    pub async fn save(&self, scene: Scene) -> Result<(), eyre::Report> {
+        let mut scenes = self.scenes.write().await;
+        self.write(&scene).await?;
+        let scene_id = scene.scene_id.clone();
+        scenes.insert(scene_id.clone(), scene);
+        let _ = self.changes.send(scene_id);
+        Ok(())
+    }
+
+    /// Edit and save a scene, returning the new scene.
+    pub async fn edit(
+        &self,
+        scene_id: &SceneIdRef,
+        f: impl FnOnce(&mut Scene),
+    ) -> Result<Scene, ServerFnError> {
+        // held until the scene is written, so concurrent edits don't undo each other
+        let mut scenes = self.scenes.write().await;
+        let Some(mut scene) = scenes.get(scene_id).cloned() else {
+            return Err(ServerFnError::ServerError("no such scene".to_owned()));
+        };
+        f(&mut scene);
+        self.write(&scene).await.map_err(|e| {
+            ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+        })?;
+        scenes.insert(scene.scene_id.clone(), scene.clone());
+        let _ = self.changes.send(scene.scene_id.clone());
+        Ok(scene)
+    }
+
+    async fn write(&self, scene: &Scene) -> Result<(), eyre::Report> {
+        tokio::fs::write(self.scene_path(&scene.scene_id), serde_json::to_vec(scene)?).await?;
+        Ok(())
+    }
+
+    pub async fn remove(&self, scene_id: &SceneIdRef) -> Result<(), eyre::Report> {
+        if self.scenes.write().await.remove(scene_id).is_some() {
+            tokio::fs::remove_file(self.scene_path(scene_id)).await?;
+            let _ = self.changes.send(scene_id.to_owned());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "ssr")]
+pub fn router<S: Clone + Send + Sync + 'static>() -> axum::Router<S> {
+    use axum::routing::get;
+
+    axum::Router::new()
+        .route("/ws/:id", get(scene_ws))
+        .route("/:id", get(serve_scene))
+}
+
+#[derive(Template)]
+#[template(path = "scene.html", escape = "none")]
+#[cfg(feature = "ssr")]
+struct SceneSite {
+    scene_id: SceneId,
+    /// Html escaped
+    scene_name: String,
+    canvas: Canvas,
+    items: Vec<SceneSiteItem>,
+    cache_bust: String,
+    /// json list of [`crate::media::MediaPlay`] for the overlay to preload
+    preload: String,
+    runtime_url: String,
+    runtime_version: String,
+}
+
+#[cfg(feature = "ssr")]
+struct SceneSiteItem {
+    item: SceneItem,
+    /// Scoped to the element of the alert
+    style: String,
+    /// Rendered html
+    last_text: String,
+    canvas: Canvas,
+    layers: String,
+}
+
+#[cfg(feature = "ssr")]
+async fn serve_scene(
+    extract::Path(scene_id): extract::Path<SceneId>,
+    Extension(manager): Extension<AlertManager>,
+) -> axum::response::Response {
+    let Some(scene) = manager.scenes.get(&scene_id).await else {
+        return (StatusCode::NOT_FOUND, "no such scene").into_response();
+    };
+    let mut items = vec![];
+    let mut attachments = vec![];
+    {
+        let alerts = manager.read_alerts().await;
+        for item in &scene.items {
+            // alerts may have been deleted since
+            let Some(alert) = alerts.get(&item.alert_id) else {
+                continue;
+            };
+            attachments.extend(alert.change_media().iter().cloned());
+            attachments.extend(alert.variants.iter().flat_map(|v| v.media.iter().cloned()));
+            items.push(SceneSiteItem {
+                item: item.clone(),
+                style: crate::css::scope_parts(
+                    &alert.render_style(),
+                    &scope_selector(&item.alert_id),
+                ),
+                last_text: alert.to_html(&alert.render()),
+                canvas: alert.canvas,
+                layers: alert.render_layers_html(),
+            });
+        }
+    }
+    let preload = manager.media.resolve(&attachments).await;
+
+    axum::response::Html(
+        SceneSite {
+            scene_id: scene.scene_id,
+            scene_name: crate::alerts::escape_html(&scene.name),
+            canvas: scene.canvas,
+            items,
+            cache_bust: rand::thread_rng()
+                .sample_iter(&rand::distributions::Alphanumeric)
+                .take(7)
+                .map(char::from)
+                .collect(),
+            preload: serde_json::to_string(&preload).unwrap_or_else(|_| "[]".to_owned()),
+            runtime_url: crate::overlay::runtime_url(),
+            runtime_version: crate::overlay::RUNTIME_VERSION.clone(),
+        }
+        .render()
+        .unwrap_or_default(),
+    )
+    .into_response()
+}
+
+#[cfg(feature = "ssr")]
+async fn scene_ws(
+    ws: ws::WebSocketUpgrade,
+    extract::Path(scene_id): extract::Path<SceneId>,
+    Extension(manager): Extension<AlertManager>,
+) -> axum::response::Response {
+    let Some(scene) = manager.scenes.get(&scene_id).await else {
+        return (StatusCode::NOT_FOUND, "no such scene").into_response();
+    };
+    ws.on_upgrade(|socket| async move {
+        let subscription = Subscription {
+            alert_ids: scene.items.into_iter().map(|i| i.alert_id).collect(),
+            scene: Some(scene_id.clone()),
+        };
+        if let Err(error) =
+            handle_socket(socket, manager.sender.clone(), subscription, manager).await
+        {
+            tracing::error!(%error, ?scene_id, "error occured");
+        }
+    })
+}
+
+#[server(ListScenes, "/backend")]
+pub async fn list_scenes() -> Result<Vec<Scene>, ServerFnError> {
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    Ok(manager.scenes.list().await)
+}
+
+#[server(CreateScene, "/backend")]
+#[tracing::instrument(err)]
+pub async fn create_scene(name: String) -> Result<Scene, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    let scene = Scene {
+        scene_id: SceneId::new_id(),
+        name,
+        canvas: Default::default(),
+        items: vec![],
+    };
+    manager.scenes.save(scene.clone()).await.map_err(|e| {
+        ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+    })?;
+    Ok(scene)
+}
+
+#[server(UpdateScene, "/backend")]
+#[tracing::instrument(err)]
+pub async fn update_scene(
+    scene_id: SceneId,
+    name: String,
+    width: u32,
+    height: u32,
+) -> Result<Scene, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager
+        .scenes
+        .edit(&scene_id, move |scene| {
+            scene.name = name;
+            scene.canvas = Canvas { width, height };
+        })
+        .await
+}
+
+/// Add an alert to a scene, or move it if it's already in the scene.
+#[server(SaveSceneItem, "/backend")]
+#[tracing::instrument(err)]
+pub async fn save_scene_item(
+    scene_id: SceneId,
+    alert_id: AlertId,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    z_index: i32,
+) -> Result<Scene, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager.get_alert(&alert_id).await?;
+    let item = SceneItem {
+        alert_id,
+        x,
+        y,
+        width,
+        height,
+        z_index,
+    };
+    manager
+        .scenes
+        .edit(&scene_id, move |scene| {
+            match scene.items.iter_mut().find(|i| i.alert_id == item.alert_id) {
+                Some(existing) => *existing = item,
+                None => scene.items.push(item),
+            }
+        })
+        .await
+}
+
+#[server(RemoveSceneItem, "/backend")]
+#[tracing::instrument(err)]
+pub async fn remove_scene_item(
+    scene_id: SceneId,
+    alert_id: AlertId,
+) -> Result<Scene, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager
+        .scenes
+        .edit(&scene_id, move |scene| {
+            scene.items.retain(|i| i.alert_id != alert_id);
+        })
+        .await
+}
+
+#[server(DeleteScene, "/backend")]
+#[tracing::instrument(err)]
+pub async fn delete_scene(scene_id: SceneId) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager
+        .scenes
+        .remove(&scene_id)
+        .await
+        .map_err(|e| ServerFnError::ServerError(e.to_string()))
+}
+
+#[cfg(all(test, feature = "ssr"))]
+mod tests {
+    use super::*;
+
+    async fn scene(manager: &AlertManager, name: &str) -> SceneId {
+        let scene_id = SceneId::new_id();
+        manager
+            .scenes
+            .save(Scene {
+                scene_id: scene_id.clone(),
+                name: name.to_owned(),
+                canvas: Canvas::default(),
+                items: vec![],
+            })
+            .await
+            .unwrap();
+        scene_id
+    }
+
+    #[tokio::test]
+    async fn scene_name_is_escaped() {
+        let (_dir, manager) = crate::alerts::test_manager(&[]).await;
+        let scene_id = scene(&manager, "</title><script>alert(1)</script>").await;
+
+        let response = serve_scene(extract::Path(scene_id), Extension(manager)).await;
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(
+            body.contains(
+                "<title>Scene View - &lt;/title&gt;&lt;script&gt;alert(1)&lt;/script&gt;</title>"
+            ),
+            "{body}"
+        );
+    }
+
+    #[tokio::test]
+    async fn concurrent_edits_are_kept() {
+        let (_dir, manager) = crate::alerts::test_manager(&[]).await;
+        let scene_id = scene(&manager, "test").await;
+
+        let edits = (0..20).map(|_| {
+            let scenes = manager.scenes.clone();
+            let scene_id = scene_id.clone();
+            tokio::spawn(async move {
+                scenes
+                    .edit(&scene_id, |scene| scene.canvas.width += 1)
+                    .await
+                    .unwrap();
+            })
+        });
+        for edit in edits.collect::<Vec<_>>() {
+            edit.await.unwrap();
+        }
+
+        let width = Canvas::default().width + 20;
+        assert_eq!(
+            manager.scenes.get(&scene_id).await.unwrap().canvas.width,
+            width
+        );
+        let reloaded = Scenes::load(&manager.db_path).await.unwrap();
+        assert_eq!(reloaded.get(&scene_id).await.unwrap().canvas.width, width);
+    }
+}
@@ -0,0 +1,647 @@
+//! Scheduled actions on alerts, running on cron expressions or fixed intervals.
+//!
+//! Cron expressions are evaluated in the timezone of the schedule and take 5 fields
+//! (`0 0 * * *` for midnight) or 6 with seconds first. The time of the last run is stored, after a
+//! restart schedules that missed runs while the server was down run once.
+//!
+//! Schedules may rotate through a list of lines, available as `$rotation` in their actions, e.g.
+//! for sponsor messages. They are stored under `db_path/schedules`.
+
+use leptos::{prelude::*, server};
+
+use crate::actions::{ActionOutcome, AlertAction};
+use crate::alerts::*;
+#[cfg(feature = "ssr")]
+use chrono::{DateTime, Utc};
+#[cfg(feature = "ssr")]
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+#[cfg(feature = "ssr")]
+use tokio::sync::{watch, RwLock};
+
+/// Longest interval, a year.
+pub const MAX_INTERVAL_SECS: u64 = 366 * 24 * 60 * 60;
+
+#[aliri_braid::braid(serde)]
+pub struct ScheduleId;
+
+impl ScheduleId {
+    pub fn new_id() -> Self {
+        Self(nanoid::nanoid!(8))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Timing {
+    Cron { expression: String },
+    Interval { seconds: u64 },
+}
+
+impl std::fmt::Display for Timing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Timing::Cron { expression } => write!(f, "{expression}"),
+            Timing::Interval { seconds } => write!(f, "every {seconds}s"),
+        }
+    }
+}
+
+impl Timing {
+    /// Parse a cron expression, or an interval like `600`, `90s`, `10m` or `1h`.
+    pub fn from_form(kind: &str, value: &str) -> Result<Self, eyre::Report> {
+        let value = value.trim();
+        match kind {
+            "cron" => Ok(Timing::Cron {
+                expression: value.to_owned(),
+            }),
+            "interval" => {
+                let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+                    Some(i) => value.split_at(i),
+                    None => (value, "s"),
+                };
+                let number: u64 = number
+                    .parse()
+                    .map_err(|_| eyre::eyre!("invalid interval `{value}`"))?;
+                let unit_seconds = match unit.trim() {
+                    "s" => 1,
+                    "m" => 60,
+                    "h" => 60 * 60,
+                    _ => eyre::bail!("invalid interval unit `{unit}`, use s, m or h"),
+                };
+                let seconds = number
+                    .checked_mul(unit_seconds)
+                    .filter(|seconds| *seconds <= MAX_INTERVAL_SECS)
+                    .ok_or_else(|| eyre::eyre!("interval can't be longer than 366 days"))?;
+                if seconds == 0 {
+                    eyre::bail!("interval can't be zero");
+                }
+                Ok(Timing::Interval { seconds })
+            }
+            _ => eyre::bail!("invalid schedule kind `{kind}`"),
+        }
+    }
+
+    /// The first run after `after`.
+    #[cfg(feature = "ssr")]
+    pub fn next_after(
+        &self,
+        after: DateTime<Utc>,
+        timezone: chrono_tz::Tz,
+    ) -> Result<DateTime<Utc>, eyre::Report> {
+        match self {
+            Timing::Cron { expression } => {
+                let expression = match expression.split_whitespace().count() {
+                    5 => format!("0 {expression}"),
+                    _ => expression.clone(),
+                };
+                let schedule: cron::Schedule = expression
+                    .parse()
+                    .map_err(|e| eyre::eyre!("invalid cron expression: {e}"))?;
+                schedule
+                    .after(&after.with_timezone(&timezone))
+                    .next()
+                    .map(|next| next.with_timezone(&Utc))
+                    .ok_or_else(|| eyre::eyre!("cron expression never runs again"))
+            }
+            Timing::Interval { seconds } => i64::try_from(*seconds)
+                .ok()
+                .and_then(chrono::Duration::try_seconds)
+                .and_then(|interval| after.checked_add_signed(interval))
+                .ok_or_else(|| eyre::eyre!("interval of {seconds}s is out of range")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Schedule {
+    pub schedule_id: ScheduleId,
+    pub name: String,
+    pub enabled: bool,
+    pub timing: Timing,
+    /// IANA timezone, e.g. `Europe/Stockholm`
+    pub timezone: String,
+    #[serde(default)]
+    pub actions: Vec<AlertAction>,
+    /// Lines used in turn as `$rotation`
+    #[serde(default)]
+    pub rotation: Vec<String>,
+    #[serde(default)]
+    pub rotation_index: usize,
+    /// RFC 3339 timestamp of the last run, or when the schedule was saved.
+    pub last_run: String,
+    #[serde(default)]
+    pub last_outcomes: Vec<ActionOutcome>,
+}
+
+#[cfg(feature = "ssr")]
+impl Schedule {
+    pub fn timezone(&self) -> Result<chrono_tz::Tz, eyre::Report> {
+        self.timezone
+            .parse()
+            .map_err(|_| eyre::eyre!("unknown timezone `{}`", self.timezone))
+    }
+
+    pub fn next_run(&self) -> Result<DateTime<Utc>, eyre::Report> {
+        let last_run = DateTime::parse_from_rfc3339(&self.last_run)?.with_timezone(&Utc);
+        self.timing.next_after(last_run, self.timezone()?)
+    }
+
+    /// When a run due at `due` counts as run, the next run follows from it.
+    ///
+    /// That's `due`, so runs don't drift by how late they start, unless later runs were missed
+    /// too, e.g. while the server was down. Those aren't caught up, the schedule continues from
+    /// `now`.
+    pub fn ran_at(&self, due: DateTime<Utc>, now: DateTime<Utc>) -> DateTime<Utc> {
+        match self
+            .timezone()
+            .and_then(|timezone| self.timing.next_after(due, timezone))
+        {
+            Ok(next) if next > now => due,
+            _ => now,
+        }
+    }
+
+    fn vars(&self) -> Vec<(String, String)> {
+        let mut vars = vec![("schedule_name".to_owned(), self.name.clone())];
+        if let Some(line) = self
+            .rotation
+            .get(self.rotation_index % self.rotation.len().max(1))
+        {
+            vars.push(("rotation".to_owned(), line.clone()));
+        }
+        vars
+    }
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Clone)]
+pub struct Schedules {
+    path: PathBuf,
+    schedules: Arc<RwLock<BTreeMap<ScheduleId, Schedule>>>,
+    /// Bumped when schedules change, the scheduler recomputes when to wake up.
+    changes: watch::Sender<()>,
+}
+
+#[cfg(feature = "ssr")]
+impl Schedules {
+    pub async fn load(db_path: &std::path::Path) -> Result<Self, eyre::Report> {
+        let path = db_path.join("schedules");
+        tokio::fs::create_dir_all(&path).await?;
+        let mut schedules = BTreeMap::new();
+        let mut dir = tokio::fs::read_dir(&path).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                let schedule: Schedule = serde_json::from_slice(&tokio::fs::read(&path).await?)?;
+                schedules.insert(schedule.schedule_id.clone(), schedule);
+            }
+        }
+        Ok(Self {
+            path,
+            schedules: Arc::new(RwLock::new(schedules)),
+            changes: watch::channel(()).0,
+        })
+    }
+
+    fn schedule_path(&self, schedule_id: &ScheduleIdRef) -> PathBuf {
+        self.path.join(format!("{schedule_id}.json"))
+    }
+
+    pub async fn list(&self) -> Vec<Schedule> {
+        self.schedules.read().await.values().cloned().collect()
+    }
+
+    pub async fn get(&self, schedule_id: &ScheduleIdRef) -> Option<Schedule> {
+        self.schedules.read().await.get(schedule_id).cloned()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<()> {
+        self.changes.subscribe()
+    }
+
+    pub async fn save(&self, schedule: Schedule) -> Result<(), eyre::Report> {
+        let mut schedules = self.schedules.write().await;
+        self.write(&schedule).await?;
+        schedules.insert(schedule.schedule_id.clone(), schedule);
+        self.changes.send_replace(());
+        Ok(())
+    }
+
+    /// Edit and save a schedule.
+    pub async fn edit(
+        &self,
+        schedule_id: &ScheduleIdRef,
+        f: impl FnOnce(&mut Schedule) -> Result<(), eyre::Report>,
+    ) -> Result<(), ServerFnError> {
+        // held until the schedule is written, so concurrent edits don't undo each other
+        let mut schedules = self.schedules.write().await;
+        let Some(mut schedule) = schedules.get(schedule_id).cloned() else {
+            return Err(ServerFnError::ServerError("no such schedule".to_owned()));
+        };
+        f(&mut schedule).map_err(|e| {
+            ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+        })?;
+        self.write(&schedule).await.map_err(|e| {
+            ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+        })?;
+        schedules.insert(schedule.schedule_id.clone(), schedule);
+        self.changes.send_replace(());
+        Ok(())
+    }
+
+    async fn write(&self, schedule: &Schedule) -> Result<(), eyre::Report> {
+        tokio::fs::write(
+            self.schedule_path(&schedule.schedule_id),
+            serde_json::to_vec(schedule)?,
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn remove(&self, schedule_id: &ScheduleIdRef) -> Result<(), eyre::Report> {
+        if self.schedules.write().await.remove(schedule_id).is_some() {
+            tokio::fs::remove_file(self.schedule_path(schedule_id)).await?;
+        }
+        self.changes.send_replace(());
+        Ok(())
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl AlertManager {
+    /// Run schedules when they're due, runs forever.
+    pub(crate) async fn run_schedules(self) {
+        // wake up regularly, so changes of the system clock are noticed
+        const MAX_SLEEP: std::time::Duration = std::time::Duration::from_secs(60);
+
+        let mut changes = self.schedules.subscribe();
+        loop {
+            let now = Utc::now();
+            let mut next: Option<DateTime<Utc>> = None;
+            for schedule in self.schedules.list().await {
+                if !schedule.enabled {
+                    continue;
+                }
+                let at = match schedule.next_run() {
+                    Ok(at) if at <= now => {
+                        self.run_schedule(&schedule.schedule_id, schedule.ran_at(at, now))
+                            .await;
+                        match self.schedules.get(&schedule.schedule_id).await {
+                            Some(schedule) => schedule.next_run(),
+                            None => continue,
+                        }
+                    }
+                    at => at,
+                };
+                match at {
+                    Ok(at) => next = Some(next.map_or(at, |next| next.min(at))),
+                    Err(error) => {
+                        tracing::warn!(schedule_id = %schedule.schedule_id, %error, "invalid schedule")
+                    }
+                }
+            }
+            let sleep = next
+                .and_then(|next| (next - Utc::now()).to_std().ok())
+                .map_or(MAX_SLEEP, |sleep| sleep.min(MAX_SLEEP));
+            tokio::select! {
+                _ = tokio::time::sleep(sleep) => {}
+                changed = changes.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Run the actions of a schedule and advance it, the next run is computed from `ran_at`.
+    pub async fn run_schedule(
+        &self,
+        schedule_id: &ScheduleIdRef,
+        ran_at: DateTime<Utc>,
+    ) -> Vec<ActionOutcome> {
+        let Some(schedule) = self.schedules.get(schedule_id).await else {
+            return vec![];
+        };
+        tracing::info!(%schedule_id, "running schedule");
+        let outcomes = self.run_actions(&schedule.actions, &schedule.vars()).await;
+        for outcome in &outcomes {
+            if let Some(error) = &outcome.error {
+                tracing::warn!(%schedule_id, action = %outcome.action, %error, "scheduled action failed");
+            }
+        }
+        let saved = outcomes.clone();
+        let result = self
+            .schedules
+            .edit(schedule_id, move |schedule| {
+                schedule.last_run = ran_at.to_rfc3339();
+                schedule.rotation_index = match schedule.rotation.len() {
+                    0 => 0,
+                    len => (schedule.rotation_index + 1) % len,
+                };
+                schedule.last_outcomes = saved;
+                Ok(())
+            })
+            .await;
+        if let Err(error) = result {
+            tracing::error!(%schedule_id, %error, "couldn't save schedule");
+        }
+        outcomes
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct ScheduleOverview {
+    pub schedule: Schedule,
+    /// Next run in the timezone of the schedule, or why there is none.
+    pub next_run: Result<String, String>,
+}
+
+#[server(ListSchedules, "/backend")]
+pub async fn list_schedules() -> Result<Vec<ScheduleOverview>, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    Ok(manager
+        .schedules
+        .list()
+        .await
+        .into_iter()
+        .map(|schedule| {
+            let next_run = schedule
+                .timezone()
+                .and_then(|tz| {
+                    Ok(schedule
+                        .next_run()?
+                        .with_timezone(&tz)
+                        .format("%Y-%m-%d %H:%M:%S %Z")
+                        .to_string())
+                })
+                .map_err(|e| e.to_string());
+            ScheduleOverview { schedule, next_run }
+        })
+        .collect())
+}
+
+/// Create a schedule, or update it if `schedule_id` isn't empty.
+///
+/// Saving counts as a run, so a changed schedule doesn't fire right away.
+#[server(SaveSchedule, "/backend")]
+#[tracing::instrument(err)]
+#[allow(clippy::too_many_arguments)]
+pub async fn save_schedule(
+    schedule_id: String,
+    name: String,
+    enabled: bool,
+    kind: String,
+    timing: String,
+    timezone: String,
+    rotation: String,
+) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    let err = |e: eyre::Report| ServerFnError::ServerError(e.to_string());
+    let timing = Timing::from_form(&kind, &timing).map_err(err)?;
+    let timezone = match timezone.trim() {
+        "" => "UTC".to_owned(),
+        timezone => timezone.to_owned(),
+    };
+    let now = Utc::now().to_rfc3339();
+    let mut schedule = match schedule_id.trim() {
+        "" => Schedule {
+            schedule_id: ScheduleId::new_id(),
+            name: String::new(),
+            enabled,
+            timing: timing.clone(),
+            timezone: String::new(),
+            actions: vec![],
+            rotation: vec![],
+            rotation_index: 0,
+            last_run: now.clone(),
+            last_outcomes: vec![],
+        },
+        id => manager
+            .schedules
+            .get(ScheduleIdRef::from_str(id))
+            .await
+            .ok_or_else(|| {
+                ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(
+                    "no such schedule".to_owned(),
+                )
+            })?,
+    };
+    schedule.name = name;
+    schedule.enabled = enabled;
+    schedule.timing = timing;
+    schedule.timezone = timezone;
+    schedule.rotation = rotation
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(str::to_owned)
+        .collect();
+    schedule.last_run = now;
+    // catch invalid cron expressions and timezones when saving
+    schedule.next_run().map_err(err)?;
+    manager.schedules.save(schedule).await.map_err(err)
+}
+
+#[server(DeleteSchedule, "/backend")]
+#[tracing::instrument(err)]
+pub async fn delete_schedule(schedule_id: ScheduleId) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager
+        .schedules
+        .remove(&schedule_id)
+        .await
+        .map_err(|e| ServerFnError::ServerError(e.to_string()))
+}
+
+#[server(AddScheduleAction, "/backend")]
+#[tracing::instrument(err)]
+pub async fn add_schedule_action(
+    schedule_id: ScheduleId,
+    kind: String,
+    alert_id: AlertId,
+    field: String,
+    value: String,
+) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager.get_alert(&alert_id).await?;
+    let action = AlertAction::from_form(&kind, alert_id, &field, value).map_err(|e| {
+        ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+    })?;
+    manager
+        .schedules
+        .edit(&schedule_id, move |schedule| {
+            schedule.actions.push(action);
+            Ok(())
+        })
+        .await
+}
+
+#[server(RemoveScheduleAction, "/backend")]
+#[tracing::instrument(err)]
+pub async fn remove_schedule_action(
+    schedule_id: ScheduleId,
+    index: usize,
+) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager
+        .schedules
+        .edit(&schedule_id, move |schedule| {
+            if index >= schedule.actions.len() {
+                eyre::bail!("no such action");
+            }
+            schedule.actions.remove(index);
+            Ok(())
+        })
+        .await
+}
+
+/// Run a schedule now, it then continues from now.
+#[server(RunSchedule, "/backend")]
+#[tracing::instrument(err)]
+pub async fn run_schedule(schedule_id: ScheduleId) -> Result<Vec<ActionOutcome>, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    if manager.schedules.get(&schedule_id).await.is_none() {
+        return Err(ServerFnError::ServerError("no such schedule".to_owned()));
+    }
+    Ok(manager.run_schedule(&schedule_id, Utc::now()).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intervals() {
+        let interval = |value| match Timing::from_form("interval", value) {
+            Ok(Timing::Interval { seconds }) => Some(seconds),
+            _ => None,
+        };
+        assert_eq!(interval("600"), Some(600));
+        assert_eq!(interval("90s"), Some(90));
+        assert_eq!(interval("10m"), Some(600));
+        assert_eq!(interval("2h"), Some(7200));
+        assert_eq!(interval("8784h"), Some(MAX_INTERVAL_SECS));
+        assert_eq!(interval("8785h"), None);
+        assert_eq!(interval("0"), None);
+        assert_eq!(interval("10d"), None);
+        // would overflow a u64 when multiplied
+        assert_eq!(interval("18446744073709551615h"), None);
+        assert_eq!(interval("99999999999999999999"), None);
+    }
+
+    #[cfg(feature = "ssr")]
+    fn schedule(timing: Timing, last_run: DateTime<Utc>) -> Schedule {
+        Schedule {
+            schedule_id: ScheduleId::new_id(),
+            name: "test".to_owned(),
+            enabled: true,
+            timing,
+            timezone: "UTC".to_owned(),
+            actions: vec![],
+            rotation: vec![],
+            rotation_index: 0,
+            last_run: last_run.to_rfc3339(),
+            last_outcomes: vec![],
+        }
+    }
+
+    #[cfg(feature = "ssr")]
+    #[test]
+    fn runs_dont_drift() {
+        let due = "2026-01-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let late = due + chrono::Duration::seconds(5);
+        let interval = schedule(Timing::Interval { seconds: 60 }, due);
+        assert_eq!(interval.ran_at(due, late), due);
+        let cron = schedule(
+            Timing::Cron {
+                expression: "*/10 * * * *".to_owned(),
+            },
+            due,
+        );
+        assert_eq!(cron.ran_at(due, late), due);
+        // missed runs aren't caught up
+        let much_later = due + chrono::Duration::minutes(30);
+        assert_eq!(interval.ran_at(due, much_later), much_later);
+        assert_eq!(cron.ran_at(due, much_later), much_later);
+    }
+
+    #[cfg(feature = "ssr")]
+    #[tokio::test]
+    async fn run_schedule_continues_from_the_due_time() {
+        let (_dir, manager) = crate::alerts::test_manager(&[]).await;
+        let due = Utc::now() - chrono::Duration::seconds(5);
+        let saved = schedule(
+            Timing::Interval { seconds: 60 },
+            due - chrono::Duration::seconds(60),
+        );
+        let schedule_id = saved.schedule_id.clone();
+        manager.schedules.save(saved).await.unwrap();
+
+        manager.run_schedule(&schedule_id, due).await;
+
+        let ran = manager.schedules.get(&schedule_id).await.unwrap();
+        assert_eq!(ran.last_run, due.to_rfc3339());
+        assert_eq!(ran.next_run().unwrap(), due + chrono::Duration::seconds(60));
+    }
+
+    #[cfg(feature = "ssr")]
+    #[test]
+    fn next_after_out_of_range() {
+        let timing = Timing::Interval { seconds: u64::MAX };
+        assert!(timing.next_after(Utc::now(), chrono_tz::UTC).is_err());
+        let timing = Timing::Interval { seconds: 60 };
+        let now = Utc::now();
+        assert_eq!(
+            timing.next_after(now, chrono_tz::UTC).unwrap(),
+            now + chrono::Duration::seconds(60)
+        );
+    }
+
+    #[cfg(feature = "ssr")]
+    #[tokio::test]
+    async fn concurrent_edits_are_kept() {
+        let (_dir, manager) = crate::alerts::test_manager(&[]).await;
+        let saved = schedule(Timing::Interval { seconds: 60 }, Utc::now());
+        let schedule_id = saved.schedule_id.clone();
+        manager.schedules.save(saved).await.unwrap();
+
+        let edits = (0..20).map(|_| {
+            let schedules = manager.schedules.clone();
+            let schedule_id = schedule_id.clone();
+            tokio::spawn(async move {
+                schedules
+                    .edit(&schedule_id, |schedule| {
+                        schedule.rotation_index += 1;
+                        Ok(())
+                    })
+                    .await
+                    .unwrap();
+            })
+        });
+        for edit in edits.collect::<Vec<_>>() {
+            edit.await.unwrap();
+        }
+
+        let reloaded = Schedules::load(&manager.db_path).await.unwrap();
+        assert_eq!(reloaded.get(&schedule_id).await.unwrap().rotation_index, 20);
+    }
+}
@@ -0,0 +1,349 @@
+//! Starting points for new alerts, built-in or saved from an existing alert to `db_path/templates`.
+
+use leptos::{prelude::*, server};
+
+use crate::alerts::*;
+use crate::events::AlertVariant;
+use crate::layers::{Canvas, Layer, LayerContent, LayerId};
+use crate::media::MediaAttachment;
+use crate::themes::ThemeName;
+#[cfg(feature = "ssr")]
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+#[cfg(feature = "ssr")]
+use tokio::sync::RwLock;
+
+#[aliri_braid::braid(serde)]
+pub struct TemplateName;
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct AlertTemplate {
+    pub name: TemplateName,
+    #[serde(default)]
+    pub description: String,
+    /// Shipped with the application, can't be replaced or deleted.
+    #[serde(default)]
+    pub builtin: bool,
+    pub text: AlertText,
+    #[serde(default)]
+    pub style: String,
+    #[serde(default)]
+    pub fields: Vec<(AlertFieldName, AlertField)>,
+    #[serde(default)]
+    pub variants: Vec<AlertVariant>,
+    #[serde(default)]
+    pub media: Vec<MediaAttachment>,
+    #[serde(default)]
+    pub transition: Transition,
+    /// Looked up by name when the alert is created.
+    #[serde(default)]
+    pub theme: Option<ThemeName>,
+    #[serde(default)]
+    pub scope_style: bool,
+    #[serde(default)]
+    pub canvas: Canvas,
+    /// Timers hold the remaining time in milliseconds instead of the end time.
+    #[serde(default)]
+    pub layers: Vec<Layer>,
+}
+
+impl AlertTemplate {
+    fn new(name: &str, description: &str, text: &str, style: &str) -> Self {
+        Self {
+            name: name.into(),
+            description: description.to_owned(),
+            builtin: true,
+            text: AlertText::from(text),
+            style: style.to_owned(),
+            fields: vec![],
+            variants: vec![],
+            media: vec![],
+            transition: Default::default(),
+            theme: None,
+            scope_style: false,
+            canvas: Default::default(),
+            layers: vec![],
+        }
+    }
+
+    fn field(mut self, name: &str, value: AlertField) -> Self {
+        self.fields.push((name.into(), value));
+        self
+    }
+
+    fn transition(mut self, kind: TransitionKind) -> Self {
+        self.transition.kind = kind;
+        self
+    }
+
+    fn layer(
+        mut self,
+        name: &str,
+        (x, y, width, height): (i32, i32, u32, u32),
+        content: LayerContent,
+    ) -> Self {
+        self.layers.push(Layer {
+            layer_id: LayerId::new_id(),
+            name: name.to_owned(),
+            x,
+            y,
+            width,
+            height,
+            z_index: self.layers.len() as i32,
+            content,
+        });
+        self
+    }
+
+    /// Templates shipped with the application.
+    pub fn builtin() -> Vec<AlertTemplate> {
+        vec![
+            AlertTemplate::new(
+                "Death counter",
+                "A counter to bump from chat or a hotkey.",
+                "Deaths: $deaths",
+                "#text {\n  font-size: 64px;\n  font-weight: bold;\n  color: white;\n  text-shadow: 0 0 8px black;\n}\n",
+            )
+            .field("deaths", AlertField::Counter(0)),
+            AlertTemplate::new(
+                "Sub goal bar",
+                "A progress bar filled to `$subs / $goal`.",
+                "Sub goal: $subs / $goal",
+                "#text {\n  font-size: 32px;\n  color: white;\n  text-shadow: 0 0 4px black;\n}\n",
+            )
+            .field("subs", AlertField::Counter(0))
+            .field("goal", AlertField::Counter(50))
+            .layer(
+                "goal",
+                (40, 80, 600, 32),
+                LayerContent::Progress {
+                    value: "$subs".to_owned(),
+                    max: "$goal".to_owned(),
+                    color: "#9146ff".to_owned(),
+                },
+            ),
+            AlertTemplate::new(
+                "Now playing",
+                "The current song, update the fields when it changes.",
+                "🎵 **$title**  \n$artist",
+                "#text {\n  display: inline-block;\n  padding: 12px 20px;\n  border-radius: 12px;\n  background: rgba(0, 0, 0, 0.6);\n  color: white;\n  font-size: 28px;\n}\n",
+            )
+            .field("title", AlertField::Text("Song".to_owned()))
+            .field("artist", AlertField::Text("Artist".to_owned()))
+            .transition(TransitionKind::Fade),
+            AlertTemplate::new(
+                "Countdown",
+                "A five minute timer, restart it from the layers.",
+                "Starting soon",
+                "#text {\n  font-size: 48px;\n  color: white;\n  text-align: center;\n}\n.alert-timer {\n  font-size: 96px;\n  color: white;\n}\n",
+            )
+            .layer(
+                "timer",
+                (760, 440, 400, 120),
+                LayerContent::Timer {
+                    ends_at_ms: 5 * 60 * 1000,
+                },
+            ),
+            AlertTemplate::new(
+                "Chat ticker",
+                "A line of text scrolling across the screen.",
+                "$message",
+                "#text {\n  white-space: nowrap;\n  font-size: 32px;\n  color: white;\n  text-shadow: 0 0 4px black;\n  animation: ticker 15s linear infinite;\n}\n@keyframes ticker {\n  from { transform: translateX(100vw); }\n  to { transform: translateX(-100%); }\n}\n",
+            )
+            .field(
+                "message",
+                AlertField::Text("Welcome to the stream!".to_owned()),
+            )
+            .transition(TransitionKind::Fade),
+        ]
+    }
+
+    /// A template with everything but the name and id of `alert`.
+    #[cfg(feature = "ssr")]
+    pub fn from_alert(name: TemplateName, description: String, alert: &Alert) -> Self {
+        let now = crate::util::now_ms();
+        let layers = alert
+            .layers
+            .iter()
+            .cloned()
+            .map(|mut layer| {
+                if let LayerContent::Timer { ends_at_ms } = &mut layer.content {
+                    *ends_at_ms = ends_at_ms.saturating_sub(now);
+                }
+                layer
+            })
+            .collect();
+        Self {
+            name,
+            description,
+            builtin: false,
+            text: alert.last_text.clone(),
+            style: alert.last_style.clone(),
+            fields: alert.fields.iter().map(|(_, f)| f.clone()).collect(),
+            variants: alert.variants.clone(),
+            media: alert.media.clone(),
+            transition: alert.transition.clone(),
+            theme: alert.theme.as_ref().map(|t| t.name.clone()),
+            scope_style: alert.scope_style,
+            canvas: alert.canvas,
+            layers,
+        }
+    }
+
+    /// A new alert with the contents of this template.
+    #[cfg(feature = "ssr")]
+    pub fn to_alert(&self, alert_id: AlertId, name: AlertName) -> Alert {
+        let now = crate::util::now_ms();
+        let mut alert = Alert::new(alert_id, self.text.clone(), name);
+        alert.last_style = self.style.clone();
+        alert.fields = self
+            .fields
+            .iter()
+            .map(|f| (AlertFieldId::new_id(), f.clone()))
+            .collect();
+        alert.variants = self.variants.clone();
+        alert.media = self.media.clone();
+        alert.transition = self.transition.clone();
+        alert.scope_style = self.scope_style;
+        alert.canvas = self.canvas;
+        alert.layers = self
+            .layers
+            .iter()
+            .cloned()
+            .map(|mut layer| {
+                layer.layer_id = LayerId::new_id();
+                if let LayerContent::Timer { ends_at_ms } = &mut layer.content {
+                    *ends_at_ms += now;
+                }
+                layer
+            })
+            .collect();
+        alert
+    }
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Clone)]
+pub struct Templates {
+    path: PathBuf,
+    templates: Arc<RwLock<BTreeMap<TemplateName, AlertTemplate>>>,
+}
+
+#[cfg(feature = "ssr")]
+impl Templates {
+    pub async fn load(db_path: &std::path::Path) -> Result<Self, eyre::Report> {
+        let path = db_path.join("templates");
+        tokio::fs::create_dir_all(&path).await?;
+        let mut templates = BTreeMap::new();
+        let mut dir = tokio::fs::read_dir(&path).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                let template: AlertTemplate =
+                    serde_json::from_slice(&tokio::fs::read(&path).await?)?;
+                templates.insert(template.name.clone(), template);
+            }
+        }
+        Ok(Self {
+            path,
+            templates: Arc::new(RwLock::new(templates)),
+        })
+    }
+
+    fn template_path(&self, name: &TemplateNameRef) -> PathBuf {
+        use sha2::Digest;
+        self.path.join(format!(
+            "{:x}.json",
+            sha2::Sha256::digest(name.as_str().as_bytes())
+        ))
+    }
+
+    /// Built-in templates first, then saved ones.
+    pub async fn list(&self) -> Vec<AlertTemplate> {
+        let mut templates = AlertTemplate::builtin();
+        templates.extend(self.templates.read().await.values().cloned());
+        templates
+    }
+
+    pub async fn get(&self, name: &TemplateNameRef) -> Option<AlertTemplate> {
+        if let Some(template) = AlertTemplate::builtin()
+            .into_iter()
+            .find(|t| *t.name == *name)
+        {
+            return Some(template);
+        }
+        self.templates.read().await.get(name).cloned()
+    }
+
+    pub async fn save(&self, template: AlertTemplate) -> Result<(), eyre::Report> {
+        if AlertTemplate::builtin()
+            .iter()
+            .any(|t| t.name == template.name)
+        {
+            eyre::bail!("`{}` is a built-in template", template.name);
+        }
+        tokio::fs::write(
+            self.template_path(&template.name),
+            serde_json::to_vec(&template)?,
+        )
+        .await?;
+        self.templates
+            .write()
+            .await
+            .insert(template.name.clone(), template);
+        Ok(())
+    }
+
+    pub async fn remove(&self, name: &TemplateNameRef) -> Result<(), eyre::Report> {
+        if self.templates.write().await.remove(name).is_some() {
+            tokio::fs::remove_file(self.template_path(name)).await?;
+        }
+        Ok(())
+    }
+}
+
+#[server(ListAlertTemplates, "/backend")]
+pub async fn list_alert_templates() -> Result<Vec<AlertTemplate>, ServerFnError> {
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    Ok(manager.templates.list().await)
+}
+
+#[server(SaveAlertTemplate, "/backend")]
+#[tracing::instrument(err)]
+pub async fn save_alert_template(
+    alert_id: AlertId,
+    name: TemplateName,
+    description: String,
+) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    if name.as_str().trim().is_empty() {
+        return Err(ServerFnError::ServerError(
+            "Missing template name".to_owned(),
+        ));
+    }
+    let alert = manager.get_alert(&alert_id).await?;
+
+    manager
+        .templates
+        .save(AlertTemplate::from_alert(name, description, &alert))
+        .await
+        .map_err(|e| ServerFnError::ServerError(e.to_string()))
+}
+
+#[server(DeleteAlertTemplate, "/backend")]
+#[tracing::instrument(err)]
+pub async fn delete_alert_template(name: TemplateName) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager
+        .templates
+        .remove(&name)
+        .await
+        .map_err(|e| ServerFnError::ServerError(e.to_string()))
+}
@@ -0,0 +1,239 @@
+//! Named themes shared by alerts, stored under `db_path/themes`.
+//!
+//! An alert using a theme keeps a copy of it in [`Alert::theme`], which is updated when the theme
+//! is saved. The style of the theme comes before the alert's own style, and fields of the alert
+//! override the default fields of the theme.
+
+use leptos::{prelude::*, server};
+
+use crate::alerts::*;
+use crate::media::MediaAttachment;
+#[cfg(feature = "ssr")]
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+#[cfg(feature = "ssr")]
+use tokio::sync::RwLock;
+
+#[aliri_braid::braid(serde)]
+pub struct ThemeName;
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Theme {
+    pub name: ThemeName,
+    pub style: String,
+    /// Default values for fields the alert doesn't have.
+    #[serde(default)]
+    pub fields: Vec<(AlertFieldName, AlertField)>,
+    /// Played on change if the alert has no media of its own.
+    #[serde(default)]
+    pub media: Vec<MediaAttachment>,
+}
+
+impl Theme {
+    /// Fields in the form `name: value`, one per line, numbers become counters.
+    pub fn parse_fields(fields: &str) -> Vec<(AlertFieldName, AlertField)> {
+        fields
+            .lines()
+            .filter_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                let (name, value) = (name.trim(), value.trim());
+                if name.is_empty() {
+                    return None;
+                }
+                let value = match value.parse::<i32>() {
+                    Ok(counter) => AlertField::Counter(counter),
+                    Err(_) => AlertField::Text(value.to_owned()),
+                };
+                Some((AlertFieldName::from(name), value))
+            })
+            .collect()
+    }
+
+    pub fn fields_string(&self) -> String {
+        self.fields
+            .iter()
+            .map(|(name, value)| format!("{name}: {value}"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Clone)]
+pub struct Themes {
+    path: PathBuf,
+    themes: Arc<RwLock<BTreeMap<ThemeName, Theme>>>,
+}
+
+#[cfg(feature = "ssr")]
+impl Themes {
+    pub async fn load(db_path: &std::path::Path) -> Result<Self, eyre::Report> {
+        let path = db_path.join("themes");
+        tokio::fs::create_dir_all(&path).await?;
+        let mut themes = BTreeMap::new();
+        let mut dir = tokio::fs::read_dir(&path).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                let theme: Theme = serde_json::from_slice(&tokio::fs::read(&path).await?)?;
+                themes.insert(theme.name.clone(), theme);
+            }
+        }
+        Ok(Self {
+            path,
+            themes: Arc::new(RwLock::new(themes)),
+        })
+    }
+
+    fn theme_path(&self, name: &ThemeNameRef) -> PathBuf {
+        // names are user input, don't let them pick the path
+        use sha2::Digest;
+        self.path.join(format!(
+            "{:x}.json",
+            sha2::Sha256::digest(name.as_str().as_bytes())
+        ))
+    }
+
+    pub async fn list(&self) -> Vec<Theme> {
+        self.themes.read().await.values().cloned().collect()
+    }
+
+    pub async fn get(&self, name: &ThemeNameRef) -> Option<Theme> {
+        self.themes.read().await.get(name).cloned()
+    }
+
+    async fn save(&self, theme: Theme) -> Result<(), eyre::Report> {
+        tokio::fs::write(self.theme_path(&theme.name), serde_json::to_vec(&theme)?).await?;
+        self.themes.write().await.insert(theme.name.clone(), theme);
+        Ok(())
+    }
+
+    async fn remove(&self, name: &ThemeNameRef) -> Result<(), eyre::Report> {
+        if self.themes.write().await.remove(name).is_some() {
+            tokio::fs::remove_file(self.theme_path(name)).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl AlertManager {
+    async fn alerts_with_theme(&self, name: &ThemeNameRef) -> Vec<AlertId> {
+        self.read_alerts()
+            .await
+            .values()
+            .filter(|a| a.theme.as_ref().is_some_and(|t| *t.name == *name))
+            .map(|a| a.alert_id.clone())
+            .collect()
+    }
+
+    /// Save a theme and update every alert using it.
+    pub async fn save_theme(&self, theme: Theme) -> Result<(), ServerFnError> {
+        self.themes.save(theme.clone()).await.map_err(|e| {
+            ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+        })?;
+        for alert_id in self.alerts_with_theme(&theme.name).await {
+            let theme = theme.clone();
+            self.edit_alert(&alert_id, move |alert| alert.theme = Some(theme))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Delete a theme, alerts using it go back to only their own style.
+    pub async fn delete_theme(&self, name: &ThemeNameRef) -> Result<(), ServerFnError> {
+        for alert_id in self.alerts_with_theme(name).await {
+            self.edit_alert(&alert_id, move |alert| alert.theme = None)
+                .await?;
+        }
+        self.themes
+            .remove(name)
+            .await
+            .map_err(|e| ServerFnError::ServerError(e.to_string()))
+    }
+}
+
+#[server(ListThemes, "/backend")]
+pub async fn list_themes() -> Result<Vec<Theme>, ServerFnError> {
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    Ok(manager.themes.list().await)
+}
+
+#[server(SaveTheme, "/backend")]
+#[tracing::instrument(err)]
+pub async fn save_theme(
+    name: ThemeName,
+    style: String,
+    fields: String,
+    media_id: String,
+    volume: f32,
+) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    if name.as_str().trim().is_empty() {
+        return Err(ServerFnError::ServerError("Missing theme name".to_owned()));
+    }
+    crate::css::validate(&style).map_err(crate::css::server_error)?;
+    let media = match media_id.trim() {
+        "" => vec![],
+        media_id => vec![MediaAttachment {
+            media_id: media_id.into(),
+            volume,
+        }],
+    };
+
+    manager
+        .save_theme(Theme {
+            name,
+            style,
+            fields: Theme::parse_fields(&fields),
+            media,
+        })
+        .await
+}
+
+#[server(DeleteTheme, "/backend")]
+#[tracing::instrument(err)]
+pub async fn delete_theme(name: ThemeName) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager.delete_theme(&name).await
+}
+
+/// Use a theme for an alert, an empty `theme` removes it.
+#[server(SetAlertTheme, "/backend")]
+#[tracing::instrument(err)]
+pub async fn set_alert_theme(alert_id: AlertId, theme: String) -> Result<Alert, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    let theme = match theme.trim() {
+        "" => None,
+        name => Some(
+            manager
+                .themes
+                .get(ThemeNameRef::from_str(name))
+                .await
+                .ok_or_else(|| {
+                    ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(
+                        "No such theme".to_owned(),
+                    )
+                })?,
+        ),
+    };
+
+    manager
+        .edit_alert_html(&alert_id, move |alert| {
+            alert.theme = theme;
+            Ok(())
+        })
+        .await?;
+
+    manager.get_alert(&alert_id).await
+}
@@ -0,0 +1,441 @@
+//! Text-to-speech for event payloads, synthesized by a local engine.
+
+use leptos::{prelude::*, server};
+
+use crate::alerts::*;
+#[cfg(feature = "ssr")]
+use crate::media::{Media, MediaAttachment};
+#[cfg(feature = "ssr")]
+use std::sync::Arc;
+
+/// Per alert text-to-speech settings.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct TtsSettings {
+    pub enabled: bool,
+    /// Payload key to speak, e.g. `message`.
+    pub field: String,
+    pub voice: String,
+    /// Speaking rate, passed as is to the engine, within [`TTS_RATES`].
+    pub rate: u32,
+    /// Longer text is cut off, in characters.
+    pub max_length: usize,
+    /// Words that are left out, matched case-insensitively.
+    pub blocked_words: Vec<String>,
+    pub volume: f32,
+}
+
+impl Default for TtsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            field: "message".to_owned(),
+            voice: String::new(),
+            rate: 175,
+            max_length: 200,
+            blocked_words: vec![],
+            volume: 1.0,
+        }
+    }
+}
+
+/// Rates accepted for [`TtsSettings::rate`].
+pub const TTS_RATES: std::ops::RangeInclusive<u32> = 20..=1000;
+
+impl TtsSettings {
+    /// Filter and shorten text before speaking, `None` if nothing is left to say.
+    pub fn prepare(&self, text: &str) -> Option<String> {
+        let blocked: Vec<String> = self
+            .blocked_words
+            .iter()
+            .map(|w| w.trim().to_lowercase())
+            .filter(|w| !w.is_empty())
+            .collect();
+        let text = text
+            .split_whitespace()
+            .filter(|word| {
+                let word = word
+                    .trim_matches(|c: char| !c.is_alphanumeric())
+                    .to_lowercase();
+                !blocked.contains(&word)
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let text: String = text.chars().take(self.max_length).collect();
+        (!text.trim().is_empty()).then_some(text)
+    }
+}
+
+#[cfg(feature = "ssr")]
+#[async_trait::async_trait]
+pub trait TtsBackend: Send + Sync {
+    /// Synthesize `text`, returning the audio.
+    async fn synthesize(&self, text: &str, voice: &str, rate: u32)
+        -> Result<Vec<u8>, eyre::Report>;
+
+    /// Content type of the audio returned by [`TtsBackend::synthesize`].
+    fn content_type(&self) -> &str;
+
+    /// Voices that can be passed to [`TtsBackend::synthesize`].
+    fn voices(&self) -> &[String];
+}
+
+/// Runs a command line engine like `espeak-ng` or `piper`.
+///
+/// The text is written to stdin. `{voice}` and `{rate}` in the arguments are replaced with the
+/// alert settings, and if `{output}` is given the audio is read from that file instead of stdout.
+/// Only the configured voices are accepted, they end up as arguments of the command.
+///
+/// ```text
+/// espeak-ng -v {voice} -s {rate} --stdin --stdout
+/// piper --model {voice} --output_file {output}
+/// ```
+#[cfg(feature = "ssr")]
+pub struct CommandBackend {
+    program: String,
+    args: Vec<String>,
+    content_type: String,
+    voices: Vec<String>,
+}
+
+#[cfg(feature = "ssr")]
+impl CommandBackend {
+    pub fn new(
+        command: &str,
+        content_type: String,
+        voices: Vec<String>,
+    ) -> Result<Self, eyre::Report> {
+        let mut parts = command.split_whitespace().map(ToOwned::to_owned);
+        let program = parts
+            .next()
+            .ok_or_else(|| eyre::eyre!("empty tts command"))?;
+        Ok(Self {
+            program,
+            args: parts.collect(),
+            content_type,
+            voices,
+        })
+    }
+}
+
+#[cfg(feature = "ssr")]
+const TTS_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+#[cfg(feature = "ssr")]
+#[async_trait::async_trait]
+impl TtsBackend for CommandBackend {
+    async fn synthesize(
+        &self,
+        text: &str,
+        voice: &str,
+        rate: u32,
+    ) -> Result<Vec<u8>, eyre::Report> {
+        use eyre::WrapErr;
+        use std::process::Stdio;
+        use tokio::io::AsyncWriteExt;
+
+        let output_path =
+            std::env::temp_dir().join(format!("stream_alerts_tts_{}", nanoid::nanoid!()));
+        let mut uses_output = false;
+        // `{output}` goes first, a voice containing it must not pick where the audio is written
+        let args = self.args.iter().map(|arg| {
+            if arg.contains("{output}") {
+                uses_output = true;
+            }
+            arg.replace("{output}", &output_path.to_string_lossy())
+                .replace("{voice}", voice)
+                .replace("{rate}", &rate.to_string())
+        });
+        let args: Vec<_> = args.collect();
+
+        let mut child = tokio::process::Command::new(&self.program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .wrap_err_with(|| format!("could not run tts command {}", self.program))?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        // write while reading the output, the engine can fill its stdout before reading all of stdin
+        let write = async move {
+            let written = stdin.write_all(text.as_bytes()).await;
+            drop(stdin);
+            written
+        };
+        let (written, output) = tokio::time::timeout(TTS_TIMEOUT, async move {
+            tokio::join!(write, child.wait_with_output())
+        })
+        .await
+        .wrap_err("tts command timed out")?;
+        let output = output?;
+        if let Err(e) = written {
+            // the engine may exit without reading everything, its status tells if that's a problem
+            tracing::debug!(error = %e, "could not write all text to the tts command");
+        }
+        if !output.status.success() {
+            eyre::bail!(
+                "tts command failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        if uses_output {
+            let audio = tokio::fs::read(&output_path).await;
+            let _ = tokio::fs::remove_file(&output_path).await;
+            Ok(audio.wrap_err("could not read tts output")?)
+        } else {
+            Ok(output.stdout)
+        }
+    }
+
+    fn content_type(&self) -> &str {
+        &self.content_type
+    }
+
+    fn voices(&self) -> &[String] {
+        &self.voices
+    }
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Clone)]
+pub struct Tts {
+    backend: Arc<dyn TtsBackend>,
+}
+
+#[cfg(feature = "ssr")]
+impl Tts {
+    pub fn new(backend: impl TtsBackend + 'static) -> Self {
+        Self {
+            backend: Arc::new(backend),
+        }
+    }
+
+    pub fn from_opts(opts: &crate::opts::Opts) -> Result<Option<Self>, eyre::Report> {
+        opts.tts_command
+            .as_deref()
+            .map(|command| {
+                CommandBackend::new(
+                    command,
+                    opts.tts_content_type.clone(),
+                    opts.tts_voices.clone(),
+                )
+                .map(Self::new)
+            })
+            .transpose()
+    }
+
+    /// Voices alerts can use, an empty voice leaves it to the engine.
+    pub fn voices(&self) -> &[String] {
+        self.backend.voices()
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl AlertManager {
+    /// Speak the configured payload field of an event, extending the event to fit the speech.
+    pub(crate) async fn attach_tts(&self, alert: &Alert, event: &mut crate::events::AlertEvent) {
+        let (Some(tts), true) = (&self.tts, alert.tts.enabled) else {
+            return;
+        };
+        let Some(text) = event
+            .vars()
+            .into_iter()
+            .find(|(k, _)| k == &alert.tts.field)
+            .and_then(|(_, text)| alert.tts.prepare(&text))
+        else {
+            return;
+        };
+        match self.synthesize_cached(tts, &alert.tts, &text).await {
+            Ok((media, audio)) => {
+                if let Some(speech_ms) = wav_duration_ms(&audio) {
+                    event.duration_ms = event.duration_ms.max(speech_ms + 500);
+                }
+                event.media.push(MediaAttachment {
+                    media_id: media.media_id,
+                    volume: alert.tts.volume,
+                });
+            }
+            Err(error) => {
+                tracing::error!(%error, alert_id = ?alert.alert_id, "could not synthesize speech");
+            }
+        }
+    }
+
+    async fn synthesize_cached(
+        &self,
+        tts: &Tts,
+        settings: &TtsSettings,
+        text: &str,
+    ) -> Result<(Media, Vec<u8>), eyre::Report> {
+        let key = cache_key(settings, text);
+        if let Some(media) = self.media.find_generated(&key).await {
+            if let Ok(audio) = self.media.read(&media.media_id).await {
+                return Ok((media, audio));
+            }
+        }
+        let audio = tts
+            .backend
+            .synthesize(text, &settings.voice, settings.rate)
+            .await?;
+        let media = self
+            .media
+            .add_generated(key, tts.backend.content_type().to_owned(), &audio)
+            .await?;
+        Ok((media, audio))
+    }
+}
+
+/// Name of the generated media for speaking `text` with `settings`.
+#[cfg(feature = "ssr")]
+fn cache_key(settings: &TtsSettings, text: &str) -> String {
+    use sha2::Digest;
+
+    format!(
+        "tts-{:x}",
+        sha2::Sha256::digest(format!("{}\n{}\n{}", settings.voice, settings.rate, text))
+    )
+}
+
+/// Duration of a PCM wav file, from the byte rate in the header.
+#[cfg(feature = "ssr")]
+fn wav_duration_ms(audio: &[u8]) -> Option<u64> {
+    if audio.len() < 44 || &audio[0..4] != b"RIFF" || &audio[8..12] != b"WAVE" {
+        return None;
+    }
+    let byte_rate = u32::from_le_bytes(audio[28..32].try_into().ok()?) as u64;
+    if byte_rate == 0 {
+        return None;
+    }
+    Some((audio.len() as u64 - 44) * 1000 / byte_rate)
+}
+
+#[server(UpdateAlertTts, "/backend")]
+#[tracing::instrument(err)]
+#[allow(clippy::too_many_arguments)]
+pub async fn update_alert_tts(
+    alert_id: AlertId,
+    enabled: Option<String>,
+    field: String,
+    voice: String,
+    rate: u32,
+    max_length: usize,
+    blocked_words: String,
+    volume: f32,
+) -> Result<Alert, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    let voice = voice.trim().to_owned();
+    let voices = manager.tts.as_ref().map(Tts::voices).unwrap_or_default();
+    if !voice.is_empty() && !voices.contains(&voice) {
+        return Err(ServerFnError::ServerError(format!(
+            "unknown voice {voice:?}, expected one of {voices:?}"
+        )));
+    }
+    if !TTS_RATES.contains(&rate) {
+        return Err(ServerFnError::ServerError(format!(
+            "rate must be between {} and {}",
+            TTS_RATES.start(),
+            TTS_RATES.end()
+        )));
+    }
+    let settings = TtsSettings {
+        enabled: enabled.is_some(),
+        field,
+        voice,
+        rate,
+        max_length,
+        blocked_words: blocked_words
+            .split(',')
+            .map(|w| w.trim().to_owned())
+            .filter(|w| !w.is_empty())
+            .collect(),
+        volume,
+    };
+
+    manager
+        .edit_alert(&alert_id, move |alert| {
+            alert.tts = settings;
+        })
+        .await?;
+
+    manager.get_alert(&alert_id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prepare() {
+        let settings = TtsSettings {
+            max_length: 20,
+            blocked_words: vec![" Bad ".to_owned(), String::new()],
+            ..Default::default()
+        };
+        assert_eq!(
+            settings.prepare("this is  BAD, really bad!").as_deref(),
+            Some("this is really")
+        );
+        assert_eq!(
+            settings
+                .prepare("a very long message that is cut off")
+                .as_deref(),
+            Some("a very long message ")
+        );
+        assert_eq!(settings.prepare("bad bad"), None);
+        assert_eq!(settings.prepare("  "), None);
+    }
+
+    #[cfg(feature = "ssr")]
+    #[test]
+    fn cache_key_covers_settings_and_text() {
+        let settings = TtsSettings::default();
+        let key = cache_key(&settings, "hello");
+        assert!(key.starts_with("tts-"));
+        assert_eq!(key, cache_key(&settings, "hello"));
+        assert_ne!(key, cache_key(&settings, "hello!"));
+        let voice = TtsSettings {
+            voice: "en".to_owned(),
+            ..Default::default()
+        };
+        assert_ne!(key, cache_key(&voice, "hello"));
+        let rate = TtsSettings {
+            rate: settings.rate + 1,
+            ..Default::default()
+        };
+        assert_ne!(key, cache_key(&rate, "hello"));
+    }
+
+    #[cfg(feature = "ssr")]
+    #[test]
+    fn wav_duration() {
+        fn wav(byte_rate: u32, data: usize) -> Vec<u8> {
+            let mut wav = vec![0; 44 + data];
+            wav[0..4].copy_from_slice(b"RIFF");
+            wav[8..12].copy_from_slice(b"WAVE");
+            wav[28..32].copy_from_slice(&byte_rate.to_le_bytes());
+            wav
+        }
+
+        assert_eq!(wav_duration_ms(&wav(16_000, 8_000)), Some(500));
+        assert_eq!(wav_duration_ms(&wav(16_000, 0)), Some(0));
+        assert_eq!(wav_duration_ms(&wav(0, 8_000)), None);
+        assert_eq!(wav_duration_ms(b"RIFF"), None);
+        let mut mp3 = wav(16_000, 8_000);
+        mp3[0..4].copy_from_slice(b"ID3\x04");
+        assert_eq!(wav_duration_ms(&mp3), None);
+    }
+
+    #[cfg(feature = "ssr")]
+    #[tokio::test]
+    async fn voice_cannot_name_the_output() {
+        let backend =
+            CommandBackend::new("echo -n {voice}", "text/plain".to_owned(), vec![]).unwrap();
+        let audio = backend.synthesize("hello", "{output}", 175).await.unwrap();
+        assert_eq!(audio, b"{output}");
+    }
+}
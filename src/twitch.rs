@@ -0,0 +1,673 @@
+//! Twitch EventSub webhooks at `/twitch/eventsub`.
+//!
+//! Notifications are matched against [`EventSubMapping`]s by subscription type and conditions on
+//! the event, e.g. `channel.cheer` with `bits >= 100`, and run an [`AlertAction`] with the fields
+//! of the event as variables. Mappings are stored in `db_path/twitch/eventsub.json`.
+//!
+//! Recorded notifications in `fixtures/eventsub` can be replayed from the ui to test mappings.
+//!
+//! Without a public url, notifications can instead be received over a websocket, see [`websocket`].
+
+use leptos::{prelude::*, server};
+
+use crate::actions::AlertAction;
+use crate::alerts::*;
+use crate::events::Condition;
+#[cfg(feature = "ssr")]
+use axum::{
+    body::Bytes,
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    Extension,
+};
+#[cfg(feature = "ssr")]
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+#[cfg(feature = "ssr")]
+use tokio::sync::{watch, Mutex, RwLock};
+
+#[cfg(feature = "ssr")]
+pub mod websocket;
+
+#[aliri_braid::braid(serde)]
+pub struct EventSubMappingId;
+
+impl EventSubMappingId {
+    pub fn new_id() -> Self {
+        Self(nanoid::nanoid!(8))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct EventSubMapping {
+    pub mapping_id: EventSubMappingId,
+    /// e.g. `channel.follow`
+    pub subscription_type: String,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    pub action: AlertAction,
+}
+
+/// Result of running the mappings for a notification.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct EventSubOutcome {
+    pub mapping_id: EventSubMappingId,
+    pub action: String,
+    pub error: Option<String>,
+}
+
+/// Body of a webhook request, see <https://dev.twitch.tv/docs/eventsub/handling-webhook-events/>
+#[cfg(feature = "ssr")]
+#[derive(Debug, serde::Deserialize)]
+struct EventSubMessage {
+    subscription: EventSubSubscription,
+    #[serde(default)]
+    challenge: Option<String>,
+    #[serde(default)]
+    event: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Debug, serde::Deserialize)]
+struct EventSubSubscription {
+    #[serde(rename = "type")]
+    subscription_type: String,
+    #[serde(default)]
+    status: Option<String>,
+}
+
+/// Recorded notifications, named by subscription type.
+#[cfg(feature = "ssr")]
+const FIXTURES: &[(&str, &str)] = &[
+    (
+        "channel.follow",
+        include_str!("../fixtures/eventsub/channel.follow.json"),
+    ),
+    (
+        "channel.subscribe",
+        include_str!("../fixtures/eventsub/channel.subscribe.json"),
+    ),
+    (
+        "channel.subscription.gift",
+        include_str!("../fixtures/eventsub/channel.subscription.gift.json"),
+    ),
+    (
+        "channel.cheer",
+        include_str!("../fixtures/eventsub/channel.cheer.json"),
+    ),
+    (
+        "channel.raid",
+        include_str!("../fixtures/eventsub/channel.raid.json"),
+    ),
+];
+
+/// Messages older than this are rejected, and ids are remembered this long.
+#[cfg(feature = "ssr")]
+const MAX_MESSAGE_AGE: Duration = Duration::from_secs(10 * 60);
+
+#[cfg(feature = "ssr")]
+#[derive(Clone)]
+pub struct EventSub {
+    secret: Option<crate::opts::Secret>,
+    path: PathBuf,
+    mappings: Arc<RwLock<Vec<EventSubMapping>>>,
+    /// Ids of handled messages, Twitch may deliver a message more than once.
+    seen: Arc<Mutex<HashMap<String, Instant>>>,
+    /// Bumped when mappings change, the websocket client updates its subscriptions.
+    changes: watch::Sender<()>,
+    /// State of the websocket client, if it's enabled.
+    websocket_status: Arc<RwLock<Option<String>>>,
+}
+
+#[cfg(feature = "ssr")]
+impl EventSub {
+    pub async fn load(opts: &crate::opts::Opts) -> Result<Self, eyre::Report> {
+        let dir = opts.db_path.join("twitch");
+        tokio::fs::create_dir_all(&dir).await?;
+        let path = dir.join("eventsub.json");
+        let mappings = match tokio::fs::read(&path).await {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            secret: opts.twitch_eventsub_secret.clone(),
+            path,
+            mappings: Arc::new(RwLock::new(mappings)),
+            seen: Default::default(),
+            changes: watch::channel(()).0,
+            websocket_status: Default::default(),
+        })
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.secret.is_some()
+    }
+
+    pub async fn list(&self) -> Vec<EventSubMapping> {
+        self.mappings.read().await.clone()
+    }
+
+    /// Subscription types used by the mappings.
+    pub async fn subscription_types(&self) -> std::collections::BTreeSet<String> {
+        self.mappings
+            .read()
+            .await
+            .iter()
+            .map(|m| m.subscription_type.clone())
+            .collect()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<()> {
+        self.changes.subscribe()
+    }
+
+    pub async fn websocket_status(&self) -> Option<String> {
+        self.websocket_status.read().await.clone()
+    }
+
+    async fn set_websocket_status(&self, status: impl Into<String>) {
+        *self.websocket_status.write().await = Some(status.into());
+    }
+
+    async fn store(&self, mappings: &[EventSubMapping]) -> Result<(), eyre::Report> {
+        tokio::fs::write(&self.path, serde_json::to_vec(mappings)?).await?;
+        self.changes.send_replace(());
+        Ok(())
+    }
+
+    pub async fn save(&self, mapping: EventSubMapping) -> Result<(), eyre::Report> {
+        let mut mappings = self.mappings.write().await;
+        match mappings
+            .iter_mut()
+            .find(|m| m.mapping_id == mapping.mapping_id)
+        {
+            Some(existing) => *existing = mapping,
+            None => mappings.push(mapping),
+        }
+        self.store(&mappings).await
+    }
+
+    pub async fn remove(&self, mapping_id: &EventSubMappingIdRef) -> Result<(), eyre::Report> {
+        let mut mappings = self.mappings.write().await;
+        mappings.retain(|m| &*m.mapping_id != mapping_id);
+        self.store(&mappings).await
+    }
+
+    /// Remember a message id, returns `false` if it was already handled.
+    async fn first_delivery(&self, message_id: &str) -> bool {
+        let mut seen = self.seen.lock().await;
+        seen.retain(|_, at| at.elapsed() < MAX_MESSAGE_AGE);
+        seen.insert(message_id.to_owned(), Instant::now()).is_none()
+    }
+
+    /// Run all mappings matching the notification.
+    pub async fn dispatch(
+        &self,
+        manager: &AlertManager,
+        subscription_type: &str,
+        event: &serde_json::Map<String, serde_json::Value>,
+    ) -> Vec<EventSubOutcome> {
+        let mut vars = vec![("subscription_type".to_owned(), subscription_type.to_owned())];
+        for (key, value) in event {
+            crate::events::flatten_value(key.clone(), value, &mut vars);
+        }
+        let mappings = self.list().await;
+        let mut outcomes = vec![];
+        for mapping in mappings.iter().filter(|m| {
+            m.subscription_type == subscription_type
+                && m.conditions.iter().all(|c| c.matches(&vars))
+        }) {
+            let result = manager.run_action(&mapping.action, &vars).await;
+            outcomes.push(EventSubOutcome {
+                mapping_id: mapping.mapping_id.clone(),
+                action: mapping.action.to_string(),
+                error: result.err().map(|e| e.to_string()),
+            });
+        }
+        outcomes
+    }
+
+    /// Run the mappings for a notification from Twitch, failed actions are logged.
+    async fn handle_notification(
+        &self,
+        manager: &AlertManager,
+        subscription_type: &str,
+        event: &serde_json::Map<String, serde_json::Value>,
+    ) {
+        for outcome in self.dispatch(manager, subscription_type, event).await {
+            if let Some(error) = outcome.error {
+                tracing::warn!(
+                    %subscription_type,
+                    mapping_id = %outcome.mapping_id,
+                    %error,
+                    "eventsub action failed"
+                );
+            }
+        }
+    }
+}
+
+/// Check `Twitch-Eventsub-Message-Signature`, a hmac of the message id, timestamp and body.
+#[cfg(feature = "ssr")]
+fn verify_signature(
+    secret: &str,
+    message_id: &str,
+    timestamp: &str,
+    body: &[u8],
+    signature: &str,
+) -> bool {
+    use hmac::Mac;
+
+    let Some(signature) = signature
+        .strip_prefix("sha256=")
+        .and_then(|s| hex::decode(s).ok())
+    else {
+        return false;
+    };
+    let Ok(mut mac) = hmac::Hmac::<sha2::Sha256>::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(message_id.as_bytes());
+    mac.update(timestamp.as_bytes());
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+#[cfg(feature = "ssr")]
+fn is_recent(timestamp: &str) -> bool {
+    let Ok(timestamp) = chrono::DateTime::parse_from_rfc3339(timestamp) else {
+        return false;
+    };
+    let age = chrono::Utc::now().signed_duration_since(timestamp);
+    age.abs().to_std().is_ok_and(|age| age < MAX_MESSAGE_AGE)
+}
+
+#[cfg(feature = "ssr")]
+pub fn router<S: Clone + Send + Sync + 'static>() -> axum::Router<S> {
+    use axum::routing::post;
+
+    axum::Router::new().route("/eventsub", post(eventsub_handler))
+}
+
+#[cfg(feature = "ssr")]
+async fn eventsub_handler(
+    Extension(manager): Extension<AlertManager>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> axum::response::Response {
+    let Some(secret) = manager.twitch.secret.clone() else {
+        return (StatusCode::NOT_FOUND, "eventsub is not configured").into_response();
+    };
+    let get_header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let (Some(message_id), Some(timestamp), Some(signature), Some(message_type)) = (
+        get_header("Twitch-Eventsub-Message-Id"),
+        get_header("Twitch-Eventsub-Message-Timestamp"),
+        get_header("Twitch-Eventsub-Message-Signature"),
+        get_header("Twitch-Eventsub-Message-Type"),
+    ) else {
+        return (StatusCode::BAD_REQUEST, "missing eventsub headers").into_response();
+    };
+    if !verify_signature(secret.secret(), message_id, timestamp, &body, signature) {
+        tracing::warn!(message_id, "invalid eventsub signature");
+        return (StatusCode::FORBIDDEN, "invalid signature").into_response();
+    }
+    if !is_recent(timestamp) {
+        tracing::warn!(message_id, timestamp, "stale eventsub message");
+        return (StatusCode::FORBIDDEN, "message too old").into_response();
+    }
+    if !manager.twitch.first_delivery(message_id).await {
+        tracing::debug!(message_id, "duplicate eventsub message");
+        return StatusCode::NO_CONTENT.into_response();
+    }
+    let message: EventSubMessage = match serde_json::from_slice(&body) {
+        Ok(message) => message,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    match message_type {
+        "webhook_callback_verification" => {
+            let Some(challenge) = message.challenge else {
+                return (StatusCode::BAD_REQUEST, "missing challenge").into_response();
+            };
+            tracing::info!(
+                subscription_type = %message.subscription.subscription_type,
+                "verified eventsub subscription"
+            );
+            (
+                StatusCode::OK,
+                [(header::CONTENT_TYPE, "text/plain")],
+                challenge,
+            )
+                .into_response()
+        }
+        "notification" => {
+            let Some(event) = message.event else {
+                return (StatusCode::BAD_REQUEST, "missing event").into_response();
+            };
+            // Twitch expects a response within a few seconds, run the actions after responding
+            tokio::spawn(async move {
+                manager
+                    .twitch
+                    .handle_notification(&manager, &message.subscription.subscription_type, &event)
+                    .await;
+            });
+            StatusCode::NO_CONTENT.into_response()
+        }
+        "revocation" => {
+            tracing::warn!(
+                subscription_type = %message.subscription.subscription_type,
+                status = ?message.subscription.status,
+                "eventsub subscription revoked"
+            );
+            StatusCode::NO_CONTENT.into_response()
+        }
+        _ => (StatusCode::BAD_REQUEST, "unknown message type").into_response(),
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct EventSubOverview {
+    /// If a secret is set, otherwise the endpoint is disabled.
+    pub configured: bool,
+    pub mappings: Vec<EventSubMapping>,
+    /// Names of the recorded notifications that can be replayed.
+    pub fixtures: Vec<String>,
+    /// State of the websocket client, `None` if it's not enabled.
+    pub websocket: Option<String>,
+}
+
+#[server(ReadEventSub, "/backend")]
+pub async fn read_eventsub() -> Result<EventSubOverview, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    Ok(EventSubOverview {
+        configured: manager.twitch.is_configured(),
+        mappings: manager.twitch.list().await,
+        fixtures: FIXTURES.iter().map(|(name, _)| name.to_string()).collect(),
+        websocket: manager.twitch.websocket_status().await,
+    })
+}
+
+/// Add a mapping, `value` is the amount, value, text or event type depending on `kind`.
+#[server(SaveEventSubMapping, "/backend")]
+#[tracing::instrument(err)]
+pub async fn save_eventsub_mapping(
+    subscription_type: String,
+    conditions: String,
+    kind: String,
+    alert_id: AlertId,
+    field: String,
+    value: String,
+) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager.get_alert(&alert_id).await?;
+    let subscription_type = subscription_type.trim().to_owned();
+    if subscription_type.is_empty() {
+        return Err(ServerFnError::ServerError(
+            "missing subscription type".to_owned(),
+        ));
+    }
+    let action = AlertAction::from_form(&kind, alert_id, &field, value).map_err(|e| {
+        ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+    })?;
+    let mapping = EventSubMapping {
+        mapping_id: EventSubMappingId::new_id(),
+        subscription_type,
+        conditions: crate::events::parse_conditions(&conditions).map_err(|e| {
+            ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+        })?,
+        action,
+    };
+    manager
+        .twitch
+        .save(mapping)
+        .await
+        .map_err(|e| ServerFnError::ServerError(e.to_string()))
+}
+
+#[server(DeleteEventSubMapping, "/backend")]
+#[tracing::instrument(err)]
+pub async fn delete_eventsub_mapping(mapping_id: EventSubMappingId) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager
+        .twitch
+        .remove(&mapping_id)
+        .await
+        .map_err(|e| ServerFnError::ServerError(e.to_string()))
+}
+
+/// Run the mappings for a recorded notification, as if Twitch sent it.
+#[server(ReplayEventSubFixture, "/backend")]
+#[tracing::instrument(err)]
+pub async fn replay_eventsub_fixture(name: String) -> Result<Vec<EventSubOutcome>, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    let Some((_, fixture)) = FIXTURES.iter().find(|(n, _)| *n == name) else {
+        return Err(ServerFnError::ServerError("no such fixture".to_owned()));
+    };
+    let message: EventSubMessage = serde_json::from_str(fixture).map_err(|e| {
+        ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+    })?;
+    Ok(manager
+        .twitch
+        .dispatch(
+            &manager,
+            &message.subscription.subscription_type,
+            &message.event.unwrap_or_default(),
+        )
+        .await)
+}
+
+#[cfg(all(test, feature = "ssr"))]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "s3cr3t-for-tests";
+
+    async fn manager() -> (tempfile::TempDir, AlertManager, AlertId) {
+        let (dir, manager) =
+            crate::alerts::test_manager(&["--twitch-eventsub-secret", SECRET]).await;
+        let alert_id = crate::alerts::test_alert(&manager, "follows").await;
+        manager
+            .twitch
+            .save(EventSubMapping {
+                mapping_id: EventSubMappingId::new_id(),
+                subscription_type: "channel.follow".to_owned(),
+                conditions: vec![],
+                action: AlertAction::IncrementField {
+                    alert_id: alert_id.clone(),
+                    field: AlertFieldName::from("follows"),
+                    amount: "1".to_owned(),
+                },
+            })
+            .await
+            .unwrap();
+        (dir, manager, alert_id)
+    }
+
+    fn sign(message_id: &str, timestamp: &str, body: &[u8]) -> String {
+        use hmac::Mac;
+
+        let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+        mac.update(message_id.as_bytes());
+        mac.update(timestamp.as_bytes());
+        mac.update(body);
+        format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+    }
+
+    fn headers(
+        message_type: &str,
+        message_id: &str,
+        timestamp: &str,
+        signature: &str,
+    ) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in [
+            ("Twitch-Eventsub-Message-Id", message_id),
+            ("Twitch-Eventsub-Message-Timestamp", timestamp),
+            ("Twitch-Eventsub-Message-Signature", signature),
+            ("Twitch-Eventsub-Message-Type", message_type),
+        ] {
+            let name = header::HeaderName::from_bytes(name.as_bytes()).unwrap();
+            headers.insert(name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    /// Send `body` signed with the test secret.
+    async fn send(
+        manager: &AlertManager,
+        message_type: &str,
+        message_id: &str,
+        timestamp: &str,
+        body: &str,
+    ) -> (StatusCode, String) {
+        let signature = sign(message_id, timestamp, body.as_bytes());
+        let response = eventsub_handler(
+            Extension(manager.clone()),
+            headers(message_type, message_id, timestamp, &signature),
+            Bytes::from(body.to_owned()),
+        )
+        .await;
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    fn follow_fixture() -> &'static str {
+        FIXTURES
+            .iter()
+            .find(|(name, _)| *name == "channel.follow")
+            .unwrap()
+            .1
+    }
+
+    fn now() -> String {
+        chrono::Utc::now().to_rfc3339()
+    }
+
+    #[tokio::test]
+    async fn bad_signature_is_forbidden() {
+        let (_dir, manager, _) = manager().await;
+        let body = follow_fixture();
+        let timestamp = now();
+        let mut signature = sign("msg-1", &timestamp, body.as_bytes());
+        // flip the last hex digit
+        let last = if signature.ends_with('0') { "1" } else { "0" };
+        signature.replace_range(signature.len() - 1.., last);
+        let response = eventsub_handler(
+            Extension(manager.clone()),
+            headers("notification", "msg-1", &timestamp, &signature),
+            Bytes::from(body),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = eventsub_handler(
+            Extension(manager),
+            headers("notification", "msg-1", &timestamp, "md5=abc"),
+            Bytes::from(body),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn stale_timestamp_is_forbidden() {
+        let (_dir, manager, _) = manager().await;
+        let timestamp = (chrono::Utc::now() - chrono::Duration::minutes(11)).to_rfc3339();
+        let (status, body) = send(
+            &manager,
+            "notification",
+            "msg-1",
+            &timestamp,
+            follow_fixture(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body, "message too old");
+    }
+
+    /// The next field changes sent to overlays.
+    async fn next_fields(
+        messages: &mut tokio::sync::broadcast::Receiver<AlertMessage>,
+    ) -> (AlertId, Vec<FieldDelta>) {
+        loop {
+            if let AlertMessage::Fields {
+                alert_id, fields, ..
+            } = messages.recv().await.unwrap()
+            {
+                return (alert_id, fields);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn duplicate_message_runs_once() {
+        let (_dir, manager, alert_id) = manager().await;
+        let mut messages = manager.sender.subscribe();
+
+        let (status, _) = send(&manager, "notification", "msg-1", &now(), follow_fixture()).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (changed, fields) =
+            tokio::time::timeout(Duration::from_secs(5), next_fields(&mut messages))
+                .await
+                .expect("the mapping ran");
+        assert_eq!(changed, alert_id);
+        assert_eq!(fields[0].value, Some(AlertField::Counter(1)));
+
+        // redelivered with a new timestamp and signature, but the same id
+        let (status, _) = send(&manager, "notification", "msg-1", &now(), follow_fixture()).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(300), next_fields(&mut messages))
+                .await
+                .is_err(),
+            "a duplicate ran the mapping again"
+        );
+        let alerts = manager.read_alerts().await;
+        assert_eq!(alerts[&alert_id].fields[0].1 .1, AlertField::Counter(1));
+    }
+
+    #[tokio::test]
+    async fn challenge_is_echoed() {
+        let (_dir, manager, _) = manager().await;
+        let body = serde_json::json!({
+            "challenge": "pogchamp-kappa-360noscope-vohiyo",
+            "subscription": {
+                "id": "f1c2a387-161a-49f9-a165-0f21d7a4e1c4",
+                "status": "webhook_callback_verification_pending",
+                "type": "channel.follow",
+                "version": "2",
+            },
+        })
+        .to_string();
+        let (status, body) = send(
+            &manager,
+            "webhook_callback_verification",
+            "msg-1",
+            &now(),
+            &body,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "pogchamp-kappa-360noscope-vohiyo");
+    }
+}
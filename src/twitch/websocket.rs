@@ -0,0 +1,639 @@
+//! EventSub over a websocket, for setups without a public url for the webhook.
+//!
+//! The client subscribes to the subscription types used by the mappings and keeps the
+//! subscriptions in sync when mappings change. See
+//! <https://dev.twitch.tv/docs/eventsub/handling-websocket-events/>
+//!
+//! The endpoints are configurable, e.g. `--twitch-eventsub-ws-url ws://127.0.0.1:8080/ws` and
+//! `--twitch-api-url http://127.0.0.1:8080` for `twitch event websocket start-server`.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use futures::StreamExt;
+use tokio_tungstenite::tungstenite::Message;
+
+use super::EventSubSubscription;
+use crate::alerts::AlertManager;
+use crate::opts::{Opts, Secret};
+
+type WebSocket =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// Used when the welcome message has no keepalive timeout.
+const DEFAULT_KEEPALIVE: Duration = Duration::from_secs(10);
+/// Extra time on top of the keepalive timeout before the connection is considered lost.
+const KEEPALIVE_GRACE: Duration = Duration::from_secs(5);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    client_id: String,
+    access_token: Secret,
+    broadcaster_id: String,
+    url: String,
+    api_url: String,
+}
+
+impl Config {
+    /// The client is enabled if the client id, access token and broadcaster id are all given.
+    pub fn from_opts(opts: &Opts) -> Option<Self> {
+        match (
+            &opts.twitch_client_id,
+            &opts.twitch_access_token,
+            &opts.twitch_broadcaster_id,
+        ) {
+            (Some(client_id), Some(access_token), Some(broadcaster_id)) => Some(Self {
+                client_id: client_id.clone(),
+                access_token: access_token.clone(),
+                broadcaster_id: broadcaster_id.clone(),
+                url: opts.twitch_eventsub_ws_url.clone(),
+                api_url: opts.twitch_api_url.trim_end_matches('/').to_owned(),
+            }),
+            (None, None, None) => None,
+            _ => {
+                tracing::warn!(
+                    "eventsub websocket needs a client id, access token and broadcaster id"
+                );
+                None
+            }
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct WsMessage {
+    metadata: Metadata,
+    #[serde(default)]
+    payload: Payload,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Metadata {
+    message_id: String,
+    message_type: String,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct Payload {
+    #[serde(default)]
+    session: Option<Session>,
+    #[serde(default)]
+    subscription: Option<EventSubSubscription>,
+    #[serde(default)]
+    event: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Session {
+    id: String,
+    #[serde(default)]
+    keepalive_timeout_seconds: Option<u64>,
+    #[serde(default)]
+    reconnect_url: Option<String>,
+}
+
+/// What to do after a connection ends.
+enum Next {
+    /// Twitch asked us to move to a new url, the old connection is kept until the new one is
+    /// welcomed so no notifications are lost.
+    Reconnect {
+        url: String,
+        old: WebSocket,
+        subscriptions: Subscriptions,
+    },
+}
+
+/// Run the client forever, reconnecting with a backoff when the connection is lost.
+pub async fn run(manager: AlertManager, config: Config) {
+    let http = reqwest::Client::new();
+    let mut attempts = 0u32;
+    let mut reconnect = None;
+    loop {
+        let url = match &reconnect {
+            Some(Next::Reconnect { url, .. }) => url.clone(),
+            None => config.url.clone(),
+        };
+        manager.twitch.set_websocket_status("connecting").await;
+        match session(
+            &manager,
+            &config,
+            &http,
+            &url,
+            reconnect.take(),
+            &mut attempts,
+        )
+        .await
+        {
+            Ok(next) => reconnect = Some(next),
+            Err(error) => {
+                tracing::warn!(%error, "eventsub websocket disconnected");
+                manager
+                    .twitch
+                    .set_websocket_status(format!("disconnected: {error}"))
+                    .await;
+                let delay = Duration::from_secs(1 << attempts.min(6)).min(MAX_BACKOFF);
+                attempts += 1;
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+/// Handle one connection, until Twitch asks us to reconnect or the connection is lost.
+async fn session(
+    manager: &AlertManager,
+    config: &Config,
+    http: &reqwest::Client,
+    url: &str,
+    previous: Option<Next>,
+    attempts: &mut u32,
+) -> Result<Next, eyre::Report> {
+    let (mut ws, _) = tokio_tungstenite::connect_async(url).await?;
+
+    // the first message is always the welcome, until then notifications still arrive on the old
+    // connection when reconnecting
+    let (mut old, mut subscriptions) = match previous {
+        Some(Next::Reconnect {
+            old, subscriptions, ..
+        }) => (Some(old), subscriptions),
+        // subscriptions are removed by Twitch when a session ends
+        None => (None, Subscriptions::default()),
+    };
+    let welcome = loop {
+        let step = tokio::select! {
+            message = next_message(&mut ws, DEFAULT_KEEPALIVE) => Ok(message?),
+            Some(message) = next_old_message(&mut old) => Err(message),
+        };
+        match step {
+            Ok(welcome) => break welcome,
+            Err(Ok(message)) => handle_message(manager, message).await,
+            // twitch closes the old connection by itself
+            Err(Err(_)) => old = None,
+        }
+    };
+    if let Some(mut old) = old {
+        let _ = old.close(None).await;
+    }
+    let Some(session) = welcome
+        .payload
+        .session
+        .filter(|_| welcome.metadata.message_type == "session_welcome")
+    else {
+        eyre::bail!("expected a welcome, got {}", welcome.metadata.message_type);
+    };
+    let keepalive = session
+        .keepalive_timeout_seconds
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_KEEPALIVE)
+        + KEEPALIVE_GRACE;
+    *attempts = 0;
+    tracing::info!(session_id = %session.id, "eventsub websocket connected");
+
+    let mut changes = manager.twitch.subscribe();
+    changes.mark_changed();
+    let url = loop {
+        tokio::select! {
+            Ok(()) = changes.changed() => {
+                subscriptions.sync(manager, config, http, &session.id).await;
+                manager
+                    .twitch
+                    .set_websocket_status(format!(
+                        "connected, {} subscriptions",
+                        subscriptions.ids.len()
+                    ))
+                    .await;
+            }
+            message = next_message(&mut ws, keepalive) => {
+                let message = message?;
+                if message.metadata.message_type == "session_reconnect" {
+                    let Some(url) = message.payload.session.and_then(|s| s.reconnect_url) else {
+                        eyre::bail!("reconnect without a url");
+                    };
+                    break url;
+                }
+                handle_message(manager, message).await;
+            }
+        }
+    };
+    tracing::info!("eventsub websocket reconnecting");
+    Ok(Next::Reconnect {
+        url,
+        old: ws,
+        subscriptions,
+    })
+}
+
+async fn next_old_message(old: &mut Option<WebSocket>) -> Option<Result<WsMessage, eyre::Report>> {
+    match old {
+        Some(old) => Some(next_message(old, DEFAULT_KEEPALIVE).await),
+        None => None,
+    }
+}
+
+/// Read the next eventsub message, failing if nothing arrives within `keepalive`.
+async fn next_message(ws: &mut WebSocket, keepalive: Duration) -> Result<WsMessage, eyre::Report> {
+    loop {
+        let message = tokio::time::timeout(keepalive, ws.next())
+            .await
+            .map_err(|_| eyre::eyre!("no keepalive from twitch"))?;
+        match message {
+            Some(Ok(Message::Text(text))) => return Ok(serde_json::from_str(&text)?),
+            Some(Ok(Message::Close(frame))) => eyre::bail!("closed by twitch: {frame:?}"),
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => return Err(e.into()),
+            None => eyre::bail!("connection closed"),
+        }
+    }
+}
+
+async fn handle_message(manager: &AlertManager, message: WsMessage) {
+    match message.metadata.message_type.as_str() {
+        "session_keepalive" | "session_welcome" => {}
+        "notification" => {
+            if !manager
+                .twitch
+                .first_delivery(&message.metadata.message_id)
+                .await
+            {
+                tracing::debug!(message_id = %message.metadata.message_id, "duplicate eventsub message");
+                return;
+            }
+            let (Some(subscription), Some(event)) =
+                (message.payload.subscription, message.payload.event)
+            else {
+                tracing::warn!("eventsub notification without an event");
+                return;
+            };
+            manager
+                .twitch
+                .handle_notification(manager, &subscription.subscription_type, &event)
+                .await;
+        }
+        "revocation" => {
+            if let Some(subscription) = message.payload.subscription {
+                tracing::warn!(
+                    subscription_type = %subscription.subscription_type,
+                    status = ?subscription.status,
+                    "eventsub subscription revoked"
+                );
+            }
+        }
+        message_type => tracing::debug!(message_type, "unknown eventsub message"),
+    }
+}
+
+/// Subscriptions of a session, by subscription type.
+#[derive(Default)]
+struct Subscriptions {
+    ids: HashMap<String, String>,
+}
+
+impl Subscriptions {
+    /// Subscribe to the types used by the mappings, and unsubscribe from unused types.
+    async fn sync(
+        &mut self,
+        manager: &AlertManager,
+        config: &Config,
+        http: &reqwest::Client,
+        session_id: &str,
+    ) {
+        let wanted = manager.twitch.subscription_types().await;
+        let unused: Vec<_> = self
+            .ids
+            .keys()
+            .filter(|t| !wanted.contains(*t))
+            .cloned()
+            .collect();
+        for subscription_type in unused {
+            let id = self.ids.remove(&subscription_type).expect("key exists");
+            if let Err(error) = delete_subscription(config, http, &id).await {
+                tracing::warn!(%error, %subscription_type, "could not unsubscribe");
+            }
+        }
+        for subscription_type in wanted {
+            if self.ids.contains_key(&subscription_type) {
+                continue;
+            }
+            match create_subscription(config, http, session_id, &subscription_type).await {
+                Ok(id) => {
+                    self.ids.insert(subscription_type, id);
+                }
+                Err(error) => tracing::warn!(%error, %subscription_type, "could not subscribe"),
+            }
+        }
+    }
+}
+
+/// Version and condition of a subscription, most types only need the broadcaster.
+fn subscription_request(
+    subscription_type: &str,
+    broadcaster_id: &str,
+) -> (&'static str, serde_json::Value) {
+    match subscription_type {
+        "channel.follow" => (
+            "2",
+            serde_json::json!({
+                "broadcaster_user_id": broadcaster_id,
+                "moderator_user_id": broadcaster_id,
+            }),
+        ),
+        "channel.raid" => (
+            "1",
+            serde_json::json!({ "to_broadcaster_user_id": broadcaster_id }),
+        ),
+        "channel.update" => (
+            "2",
+            serde_json::json!({ "broadcaster_user_id": broadcaster_id }),
+        ),
+        _ => (
+            "1",
+            serde_json::json!({ "broadcaster_user_id": broadcaster_id }),
+        ),
+    }
+}
+
+fn api_request(
+    config: &Config,
+    http: &reqwest::Client,
+    method: reqwest::Method,
+) -> reqwest::RequestBuilder {
+    http.request(method, format!("{}/eventsub/subscriptions", config.api_url))
+        .header("Client-Id", &config.client_id)
+        .bearer_auth(config.access_token.secret())
+}
+
+/// Create a subscription, returning its id.
+async fn create_subscription(
+    config: &Config,
+    http: &reqwest::Client,
+    session_id: &str,
+    subscription_type: &str,
+) -> Result<String, eyre::Report> {
+    #[derive(serde::Deserialize)]
+    struct Created {
+        data: Vec<CreatedSubscription>,
+    }
+    #[derive(serde::Deserialize)]
+    struct CreatedSubscription {
+        id: String,
+    }
+
+    let (version, condition) = subscription_request(subscription_type, &config.broadcaster_id);
+    let response = api_request(config, http, reqwest::Method::POST)
+        .json(&serde_json::json!({
+            "type": subscription_type,
+            "version": version,
+            "condition": condition,
+            "transport": { "method": "websocket", "session_id": session_id },
+        }))
+        .send()
+        .await?;
+    let status = response.status();
+    if !status.is_success() {
+        eyre::bail!("{status}: {}", response.text().await.unwrap_or_default());
+    }
+    let created: Created = response.json().await?;
+    created
+        .data
+        .into_iter()
+        .next()
+        .map(|s| s.id)
+        .ok_or_else(|| eyre::eyre!("no subscription in response"))
+}
+
+async fn delete_subscription(
+    config: &Config,
+    http: &reqwest::Client,
+    id: &str,
+) -> Result<(), eyre::Report> {
+    let response = api_request(config, http, reqwest::Method::DELETE)
+        .query(&[("id", id)])
+        .send()
+        .await?;
+    let status = response.status();
+    if !status.is_success() {
+        eyre::bail!("{status}: {}", response.text().await.unwrap_or_default());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{EventSubMapping, EventSubMappingId, FIXTURES};
+    use super::*;
+    use crate::alerts::{AlertField, AlertId, AlertMessage, FieldDelta};
+    use futures::SinkExt;
+    use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+
+    type ServerSocket = tokio_tungstenite::WebSocketStream<tokio::net::TcpStream>;
+
+    const TIMEOUT: Duration = Duration::from_secs(20);
+
+    /// The next field changes sent to overlays.
+    async fn next_fields(
+        messages: &mut tokio::sync::broadcast::Receiver<AlertMessage>,
+    ) -> (AlertId, Vec<FieldDelta>) {
+        loop {
+            if let AlertMessage::Fields {
+                alert_id, fields, ..
+            } = messages.recv().await.unwrap()
+            {
+                return (alert_id, fields);
+            }
+        }
+    }
+
+    /// Accept the next connection, with the path it connected to.
+    #[allow(clippy::result_large_err)]
+    async fn accept(listener: &tokio::net::TcpListener) -> (String, ServerSocket) {
+        let (stream, _) = tokio::time::timeout(TIMEOUT, listener.accept())
+            .await
+            .expect("the client connected")
+            .unwrap();
+        let mut path = String::new();
+        let callback = |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+            path = request.uri().path().to_owned();
+            Ok(response)
+        };
+        let ws = tokio_tungstenite::accept_hdr_async(stream, callback)
+            .await
+            .unwrap();
+        (path, ws)
+    }
+
+    async fn send(ws: &mut ServerSocket, message: serde_json::Value) {
+        ws.send(Message::Text(message.to_string())).await.unwrap();
+    }
+
+    fn welcome(session_id: &str) -> serde_json::Value {
+        serde_json::json!({
+            "metadata": {
+                "message_id": format!("welcome-{session_id}"),
+                "message_type": "session_welcome",
+                "message_timestamp": "2023-07-19T14:56:51.634234626Z",
+            },
+            "payload": {
+                "session": {
+                    "id": session_id,
+                    "status": "connected",
+                    "connected_at": "2023-07-19T14:56:51.616329898Z",
+                    "keepalive_timeout_seconds": 1,
+                    "reconnect_url": null,
+                },
+            },
+        })
+    }
+
+    fn follow(message_id: &str) -> serde_json::Value {
+        let fixture: serde_json::Value = serde_json::from_str(
+            FIXTURES
+                .iter()
+                .find(|(name, _)| *name == "channel.follow")
+                .unwrap()
+                .1,
+        )
+        .unwrap();
+        serde_json::json!({
+            "metadata": {
+                "message_id": message_id,
+                "message_type": "notification",
+                "subscription_type": "channel.follow",
+            },
+            "payload": fixture,
+        })
+    }
+
+    async fn next_post(
+        posts: &mut tokio::sync::mpsc::UnboundedReceiver<(
+            axum::http::HeaderMap,
+            serde_json::Value,
+        )>,
+    ) -> (axum::http::HeaderMap, serde_json::Value) {
+        tokio::time::timeout(TIMEOUT, posts.recv())
+            .await
+            .expect("a subscription was created")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn welcome_notification_reconnect_and_keepalive() {
+        let (_dir, manager) = crate::alerts::test_manager(&[]).await;
+        let alert_id = crate::alerts::test_alert(&manager, "follows").await;
+        manager
+            .twitch
+            .save(EventSubMapping {
+                mapping_id: EventSubMappingId::new_id(),
+                subscription_type: "channel.follow".to_owned(),
+                conditions: vec![],
+                action: crate::actions::AlertAction::IncrementField {
+                    alert_id: alert_id.clone(),
+                    field: "follows".into(),
+                    amount: "1".to_owned(),
+                },
+            })
+            .await
+            .unwrap();
+        let mut messages = manager.sender.subscribe();
+
+        // stub of the twitch api, every created subscription is sent to `posts`
+        let (posts_sender, mut posts) = tokio::sync::mpsc::unbounded_channel();
+        let api = axum::Router::new().route(
+            "/eventsub/subscriptions",
+            axum::routing::post(
+                move |headers: axum::http::HeaderMap,
+                      axum::Json(body): axum::Json<serde_json::Value>| {
+                    let posts_sender = posts_sender.clone();
+                    async move {
+                        let session_id = body["transport"]["session_id"].as_str().unwrap_or("");
+                        let id = format!("sub-{session_id}");
+                        posts_sender.send((headers, body)).unwrap();
+                        axum::Json(serde_json::json!({ "data": [{ "id": id }] }))
+                    }
+                },
+            ),
+        );
+        let api_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api_url = format!("http://{}", api_listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(api_listener, api).await.unwrap() });
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = Config {
+            client_id: "client".to_owned(),
+            access_token: "token".parse().unwrap(),
+            broadcaster_id: "1337".to_owned(),
+            url: format!("ws://{addr}/ws"),
+            api_url,
+        };
+        tokio::spawn(run(manager.clone(), config));
+
+        // subscribes after the welcome
+        let (path, mut first) = accept(&listener).await;
+        assert_eq!(path, "/ws");
+        send(&mut first, welcome("first")).await;
+        let (headers, body) = next_post(&mut posts).await;
+        assert_eq!(headers["client-id"], "client");
+        assert_eq!(headers["authorization"], "Bearer token");
+        assert_eq!(body["type"], "channel.follow");
+        assert_eq!(body["version"], "2");
+        assert_eq!(body["condition"]["broadcaster_user_id"], "1337");
+        assert_eq!(body["transport"]["method"], "websocket");
+        assert_eq!(body["transport"]["session_id"], "first");
+
+        // notifications run the mappings
+        send(&mut first, follow("notification-1")).await;
+        let (changed, fields) = tokio::time::timeout(TIMEOUT, next_fields(&mut messages))
+            .await
+            .expect("the mapping ran");
+        assert_eq!(changed, alert_id);
+        assert_eq!(fields[0].value, Some(AlertField::Counter(1)));
+
+        // moves to the reconnect url, keeping the subscriptions
+        send(
+            &mut first,
+            serde_json::json!({
+                "metadata": {
+                    "message_id": "reconnect-1",
+                    "message_type": "session_reconnect",
+                },
+                "payload": {
+                    "session": {
+                        "id": "first",
+                        "status": "reconnecting",
+                        "keepalive_timeout_seconds": null,
+                        "reconnect_url": format!("ws://{addr}/reconnect"),
+                    },
+                },
+            }),
+        )
+        .await;
+        let (path, mut second) = accept(&listener).await;
+        assert_eq!(path, "/reconnect");
+        send(&mut second, welcome("second")).await;
+        // the old connection is closed once the new one is welcomed
+        loop {
+            match tokio::time::timeout(TIMEOUT, first.next()).await.unwrap() {
+                Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                Some(Ok(_)) => continue,
+            }
+        }
+        send(&mut second, follow("notification-2")).await;
+        let (_, fields) = tokio::time::timeout(TIMEOUT, next_fields(&mut messages))
+            .await
+            .expect("the mapping ran on the new connection");
+        assert_eq!(fields[0].value, Some(AlertField::Counter(2)));
+        assert!(
+            posts.try_recv().is_err(),
+            "subscriptions survive a reconnect"
+        );
+
+        // without keepalives the client starts over on the configured url
+        let (path, mut third) = accept(&listener).await;
+        assert_eq!(path, "/ws");
+        send(&mut third, welcome("third")).await;
+        let (_, body) = next_post(&mut posts).await;
+        assert_eq!(body["transport"]["session_id"], "third");
+        drop(second);
+    }
+}
@@ -114,3 +114,12 @@ pub fn install_tracing() {
         .with(ErrorLayer::default())
         .init();
 }
+
+/// Milliseconds since the unix epoch.
+#[cfg(feature = "ssr")]
+pub fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,818 @@
+//! User defined inbound webhooks at `/hook/:id`, e.g. for donation platforms or GitHub.
+//!
+//! Values are extracted from the JSON payload with JSON pointers (`/data/amount`) or paths
+//! (`data.amount`, `items[0].name`) into `$name` variables, all leaves of the payload are also
+//! available as `$data.amount`. Matching mappings then run their [`AlertAction`].
+//!
+//! Form encoded bodies are accepted too, fields containing a JSON object (like Ko-fi's `data`)
+//! are parsed. Webhooks are stored under `db_path/webhooks`.
+
+use leptos::{prelude::*, server};
+
+use crate::actions::{ActionOutcome, AlertAction};
+use crate::alerts::*;
+use crate::events::Condition;
+#[cfg(feature = "ssr")]
+use axum::{
+    body::Bytes,
+    extract,
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    Extension,
+};
+#[cfg(feature = "ssr")]
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+#[cfg(feature = "ssr")]
+use tokio::sync::RwLock;
+
+#[aliri_braid::braid(serde)]
+pub struct WebhookId;
+
+impl WebhookId {
+    pub fn new_id() -> Self {
+        Self(nanoid::nanoid!())
+    }
+}
+
+#[aliri_braid::braid(serde)]
+pub struct WebhookMappingId;
+
+impl WebhookMappingId {
+    pub fn new_id() -> Self {
+        Self(nanoid::nanoid!(8))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct InboundWebhook {
+    pub webhook_id: WebhookId,
+    pub name: String,
+    #[serde(default)]
+    pub verification: WebhookVerification,
+    /// Named values extracted from the payload.
+    #[serde(default)]
+    pub extract: Vec<WebhookExtract>,
+    #[serde(default)]
+    pub mappings: Vec<WebhookMapping>,
+}
+
+/// How a request proves it's from the sender.
+#[derive(Debug, Clone, PartialEq, Default, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum WebhookVerification {
+    /// Anyone knowing the url can send.
+    #[default]
+    None,
+    /// A header containing the secret, e.g. `Authorization`.
+    Header { header: String, secret: String },
+    /// A header containing a hex encoded hmac-sha256 of the body, e.g. GitHub's
+    /// `X-Hub-Signature-256` with the prefix `sha256=`.
+    Hmac {
+        header: String,
+        secret: String,
+        #[serde(default)]
+        prefix: String,
+    },
+    /// A value in the payload containing the secret, e.g. Ko-fi's `/data/verification_token`.
+    Payload { path: String, secret: String },
+}
+
+impl WebhookVerification {
+    pub fn secret_mut(&mut self) -> Option<&mut String> {
+        match self {
+            WebhookVerification::None => None,
+            WebhookVerification::Header { secret, .. }
+            | WebhookVerification::Hmac { secret, .. }
+            | WebhookVerification::Payload { secret, .. } => Some(secret),
+        }
+    }
+
+    /// Fill in an empty secret from the `current` verification of the same kind.
+    pub fn keep_secret(&mut self, current: &WebhookVerification) -> Result<(), eyre::Report> {
+        let same_kind = std::mem::discriminant(self) == std::mem::discriminant(current);
+        let Some(secret) = self.secret_mut().filter(|s| s.is_empty()) else {
+            return Ok(());
+        };
+        match current.clone().secret_mut() {
+            Some(existing) if same_kind && !existing.is_empty() => {
+                *secret = std::mem::take(existing);
+                Ok(())
+            }
+            _ => eyre::bail!("missing secret"),
+        }
+    }
+}
+
+/// A webhook as listed in the editor, the secret stays on the server.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct ListedInboundWebhook {
+    /// The webhook, without its secret.
+    pub webhook: InboundWebhook,
+    pub has_secret: bool,
+}
+
+impl From<InboundWebhook> for ListedInboundWebhook {
+    fn from(mut webhook: InboundWebhook) -> Self {
+        let has_secret = webhook
+            .verification
+            .secret_mut()
+            .is_some_and(|secret| !std::mem::take(secret).is_empty());
+        Self {
+            webhook,
+            has_secret,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct WebhookExtract {
+    pub name: String,
+    /// JSON pointer or path
+    pub path: String,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct WebhookMapping {
+    pub mapping_id: WebhookMappingId,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    pub action: AlertAction,
+}
+
+/// Turn a path like `$.data.items[0].name` into a JSON pointer, pointers are returned as is.
+pub fn to_pointer(path: &str) -> String {
+    let path = path.trim();
+    if path.is_empty() || path.starts_with('/') {
+        return path.to_owned();
+    }
+    let path = path.strip_prefix('$').unwrap_or(path);
+    let mut pointer = String::new();
+    for segment in path.split('.').filter(|s| !s.is_empty()) {
+        let mut parts = segment.split('[');
+        let key = parts.next().unwrap_or_default();
+        if !key.is_empty() {
+            pointer.push('/');
+            pointer.push_str(&key.replace('~', "~0").replace('/', "~1"));
+        }
+        for index in parts {
+            pointer.push('/');
+            pointer.push_str(index.trim_end_matches(']'));
+        }
+    }
+    pointer
+}
+
+#[cfg(feature = "ssr")]
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[cfg(feature = "ssr")]
+impl WebhookVerification {
+    fn verify(&self, headers: &HeaderMap, body: &[u8], payload: &serde_json::Value) -> bool {
+        let get_header = |name: &str| headers.get(name.trim()).map(|v| v.as_bytes());
+        match self {
+            WebhookVerification::None => true,
+            WebhookVerification::Header {
+                header: name,
+                secret,
+            } => get_header(name).is_some_and(|v| constant_time_eq(v, secret.as_bytes())),
+            WebhookVerification::Hmac {
+                header: name,
+                secret,
+                prefix,
+            } => {
+                use hmac::Mac;
+
+                let Some(signature) = get_header(name)
+                    .and_then(|v| std::str::from_utf8(v).ok())
+                    .and_then(|v| v.strip_prefix(prefix.as_str()))
+                    .and_then(|v| hex::decode(v).ok())
+                else {
+                    return false;
+                };
+                let Ok(mut mac) = hmac::Hmac::<sha2::Sha256>::new_from_slice(secret.as_bytes())
+                else {
+                    return false;
+                };
+                mac.update(body);
+                mac.verify_slice(&signature).is_ok()
+            }
+            WebhookVerification::Payload { path, secret } => payload
+                .pointer(&to_pointer(path))
+                .and_then(|v| v.as_str())
+                .is_some_and(|v| constant_time_eq(v.as_bytes(), secret.as_bytes())),
+        }
+    }
+}
+
+/// Parse a JSON or form encoded body.
+#[cfg(feature = "ssr")]
+fn parse_body(headers: &HeaderMap, body: &[u8]) -> Result<serde_json::Value, eyre::Report> {
+    let form = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/x-www-form-urlencoded"));
+    if !form {
+        return Ok(serde_json::from_slice(body)?);
+    }
+    let fields: Vec<(String, String)> = serde_urlencoded::from_bytes(body)?;
+    Ok(serde_json::Value::Object(
+        fields
+            .into_iter()
+            .map(|(key, value)| {
+                let value = match serde_json::from_str(&value) {
+                    Ok(object @ serde_json::Value::Object(_)) => object,
+                    _ => serde_json::Value::String(value),
+                };
+                (key, value)
+            })
+            .collect(),
+    ))
+}
+
+impl InboundWebhook {
+    /// Variables for the actions, the extracted values and all leaves of the payload.
+    #[cfg(feature = "ssr")]
+    pub fn vars(&self, payload: &serde_json::Value) -> Vec<(String, String)> {
+        let mut vars = vec![];
+        for extract in &self.extract {
+            let value = match payload.pointer(&to_pointer(&extract.path)) {
+                Some(serde_json::Value::String(s)) => s.clone(),
+                Some(serde_json::Value::Null) | None => String::new(),
+                Some(value) => value.to_string(),
+            };
+            vars.push((extract.name.clone(), value));
+        }
+        if let serde_json::Value::Object(map) = payload {
+            for (key, value) in map {
+                crate::events::flatten_value(key.clone(), value, &mut vars);
+            }
+        }
+        vars
+    }
+
+    /// Run all mappings matching the payload.
+    #[cfg(feature = "ssr")]
+    pub async fn run(
+        &self,
+        manager: &AlertManager,
+        payload: &serde_json::Value,
+    ) -> Vec<ActionOutcome> {
+        let vars = self.vars(payload);
+        let actions: Vec<_> = self
+            .mappings
+            .iter()
+            .filter(|m| m.conditions.iter().all(|c| c.matches(&vars)))
+            .map(|m| m.action.clone())
+            .collect();
+        manager.run_actions(&actions, &vars).await
+    }
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Clone)]
+pub struct Webhooks {
+    path: PathBuf,
+    webhooks: Arc<RwLock<BTreeMap<WebhookId, InboundWebhook>>>,
+}
+
+#[cfg(feature = "ssr")]
+impl Webhooks {
+    pub async fn load(db_path: &std::path::Path) -> Result<Self, eyre::Report> {
+        let path = db_path.join("webhooks");
+        tokio::fs::create_dir_all(&path).await?;
+        let mut webhooks = BTreeMap::new();
+        let mut dir = tokio::fs::read_dir(&path).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                let webhook: InboundWebhook =
+                    serde_json::from_slice(&tokio::fs::read(&path).await?)?;
+                webhooks.insert(webhook.webhook_id.clone(), webhook);
+            }
+        }
+        Ok(Self {
+            path,
+            webhooks: Arc::new(RwLock::new(webhooks)),
+        })
+    }
+
+    fn webhook_path(&self, webhook_id: &WebhookIdRef) -> PathBuf {
+        self.path.join(format!("{webhook_id}.json"))
+    }
+
+    pub async fn list(&self) -> Vec<InboundWebhook> {
+        self.webhooks.read().await.values().cloned().collect()
+    }
+
+    pub async fn get(&self, webhook_id: &WebhookIdRef) -> Option<InboundWebhook> {
+        self.webhooks.read().await.get(webhook_id).cloned()
+    }
+
+    pub async fn save(&self, webhook: InboundWebhook) -> Result<(), eyre::Report> {
+        let mut webhooks = self.webhooks.write().await;
+        self.write(&webhook).await?;
+        webhooks.insert(webhook.webhook_id.clone(), webhook);
+        Ok(())
+    }
+
+    /// Edit and save a webhook, returning the new webhook.
+    pub async fn edit(
+        &self,
+        webhook_id: &WebhookIdRef,
+        f: impl FnOnce(&mut InboundWebhook) -> Result<(), eyre::Report>,
+    ) -> Result<InboundWebhook, ServerFnError> {
+        // held until the webhook is written, so concurrent edits don't undo each other
+        let mut webhooks = self.webhooks.write().await;
+        let Some(mut webhook) = webhooks.get(webhook_id).cloned() else {
+            return Err(ServerFnError::ServerError("no such webhook".to_owned()));
+        };
+        f(&mut webhook).map_err(|e| {
+            ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+        })?;
+        self.write(&webhook).await.map_err(|e| {
+            ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+        })?;
+        webhooks.insert(webhook.webhook_id.clone(), webhook.clone());
+        Ok(webhook)
+    }
+
+    async fn write(&self, webhook: &InboundWebhook) -> Result<(), eyre::Report> {
+        tokio::fs::write(
+            self.webhook_path(&webhook.webhook_id),
+            serde_json::to_vec(webhook)?,
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn remove(&self, webhook_id: &WebhookIdRef) -> Result<(), eyre::Report> {
+        if self.webhooks.write().await.remove(webhook_id).is_some() {
+            tokio::fs::remove_file(self.webhook_path(webhook_id)).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "ssr")]
+pub fn router<S: Clone + Send + Sync + 'static>() -> axum::Router<S> {
+    use axum::routing::post;
+
+    axum::Router::new().route("/:id", post(webhook_handler))
+}
+
+#[cfg(feature = "ssr")]
+async fn webhook_handler(
+    extract::Path(webhook_id): extract::Path<WebhookId>,
+    Extension(manager): Extension<AlertManager>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> axum::response::Response {
+    let Some(webhook) = manager.webhooks.get(&webhook_id).await else {
+        return (StatusCode::NOT_FOUND, "no such webhook").into_response();
+    };
+    let payload = match parse_body(&headers, &body) {
+        Ok(payload) => payload,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    if !webhook.verification.verify(&headers, &body, &payload) {
+        tracing::warn!(%webhook_id, "webhook verification failed");
+        return (StatusCode::UNAUTHORIZED, "verification failed").into_response();
+    }
+    let outcomes = webhook.run(&manager, &payload).await;
+    for outcome in &outcomes {
+        if let Some(error) = &outcome.error {
+            tracing::warn!(%webhook_id, action = %outcome.action, %error, "webhook action failed");
+        }
+    }
+    axum::Json(outcomes).into_response()
+}
+
+#[server(ListWebhooks, "/backend")]
+pub async fn list_webhooks() -> Result<Vec<ListedInboundWebhook>, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    Ok(manager
+        .webhooks
+        .list()
+        .await
+        .into_iter()
+        .map(Into::into)
+        .collect())
+}
+
+#[server(CreateWebhook, "/backend")]
+#[tracing::instrument(err)]
+pub async fn create_webhook(name: String) -> Result<ListedInboundWebhook, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    let webhook = InboundWebhook {
+        webhook_id: WebhookId::new_id(),
+        name,
+        verification: Default::default(),
+        extract: vec![],
+        mappings: vec![],
+    };
+    manager.webhooks.save(webhook.clone()).await.map_err(|e| {
+        ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+    })?;
+    Ok(webhook.into())
+}
+
+#[server(DeleteWebhook, "/backend")]
+#[tracing::instrument(err)]
+pub async fn delete_webhook(webhook_id: WebhookId) -> Result<(), ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager
+        .webhooks
+        .remove(&webhook_id)
+        .await
+        .map_err(|e| ServerFnError::ServerError(e.to_string()))
+}
+
+/// Set the verification, `value` is the header or payload path depending on `kind`.
+///
+/// An empty `secret` keeps the secret of the current verification if it's of the same kind.
+#[server(UpdateWebhookVerification, "/backend")]
+#[tracing::instrument(skip(secret), err)]
+pub async fn update_webhook_verification(
+    webhook_id: WebhookId,
+    kind: String,
+    value: String,
+    secret: String,
+    prefix: String,
+) -> Result<ListedInboundWebhook, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    let value = value.trim().to_owned();
+    let verification = match kind.as_str() {
+        "none" => WebhookVerification::None,
+        "header" => WebhookVerification::Header {
+            header: value,
+            secret,
+        },
+        "hmac" => WebhookVerification::Hmac {
+            header: value,
+            secret,
+            prefix,
+        },
+        "payload" => WebhookVerification::Payload {
+            path: value,
+            secret,
+        },
+        _ => {
+            return Err(ServerFnError::ServerError(
+                "invalid verification".to_owned(),
+            ))
+        }
+    };
+    manager
+        .webhooks
+        .edit(&webhook_id, move |webhook| {
+            let mut verification = verification;
+            verification.keep_secret(&webhook.verification)?;
+            webhook.verification = verification;
+            Ok(())
+        })
+        .await
+        .map(Into::into)
+}
+
+/// Add a named value, or change the path of an existing one.
+#[server(SaveWebhookExtract, "/backend")]
+#[tracing::instrument(err)]
+pub async fn save_webhook_extract(
+    webhook_id: WebhookId,
+    name: String,
+    path: String,
+) -> Result<ListedInboundWebhook, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    let extract = WebhookExtract {
+        name: name.trim().trim_start_matches('$').to_owned(),
+        path: path.trim().to_owned(),
+    };
+    if extract.name.is_empty() {
+        return Err(ServerFnError::ServerError("missing name".to_owned()));
+    }
+    manager
+        .webhooks
+        .edit(&webhook_id, move |webhook| {
+            match webhook.extract.iter_mut().find(|e| e.name == extract.name) {
+                Some(existing) => *existing = extract,
+                None => webhook.extract.push(extract),
+            }
+            Ok(())
+        })
+        .await
+        .map(Into::into)
+}
+
+#[server(RemoveWebhookExtract, "/backend")]
+#[tracing::instrument(err)]
+pub async fn remove_webhook_extract(
+    webhook_id: WebhookId,
+    name: String,
+) -> Result<ListedInboundWebhook, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager
+        .webhooks
+        .edit(&webhook_id, move |webhook| {
+            webhook.extract.retain(|e| e.name != name);
+            Ok(())
+        })
+        .await
+        .map(Into::into)
+}
+
+#[server(AddWebhookMapping, "/backend")]
+#[tracing::instrument(err)]
+pub async fn add_webhook_mapping(
+    webhook_id: WebhookId,
+    conditions: String,
+    kind: String,
+    alert_id: AlertId,
+    field: String,
+    value: String,
+) -> Result<ListedInboundWebhook, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager.get_alert(&alert_id).await?;
+    let mapping = WebhookMapping {
+        mapping_id: WebhookMappingId::new_id(),
+        conditions: crate::events::parse_conditions(&conditions).map_err(|e| {
+            ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+        })?,
+        action: AlertAction::from_form(&kind, alert_id, &field, value).map_err(|e| {
+            ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(e.to_string())
+        })?,
+    };
+    manager
+        .webhooks
+        .edit(&webhook_id, move |webhook| {
+            webhook.mappings.push(mapping);
+            Ok(())
+        })
+        .await
+        .map(Into::into)
+}
+
+#[server(RemoveWebhookMapping, "/backend")]
+#[tracing::instrument(err)]
+pub async fn remove_webhook_mapping(
+    webhook_id: WebhookId,
+    mapping_id: WebhookMappingId,
+) -> Result<ListedInboundWebhook, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    manager
+        .webhooks
+        .edit(&webhook_id, move |webhook| {
+            webhook.mappings.retain(|m| m.mapping_id != mapping_id);
+            Ok(())
+        })
+        .await
+        .map(Into::into)
+}
+
+/// Run the mappings for a JSON payload, skipping verification.
+#[server(TestWebhook, "/backend")]
+#[tracing::instrument(err)]
+pub async fn test_webhook(
+    webhook_id: WebhookId,
+    payload: String,
+) -> Result<Vec<ActionOutcome>, ServerFnError> {
+    crate::auth::require_user().await?;
+    let Some(manager): Option<AlertManager> = use_context() else {
+        return Err(ServerFnError::ServerError("Missing manager".to_owned()));
+    };
+    let Some(webhook) = manager.webhooks.get(&webhook_id).await else {
+        return Err(ServerFnError::ServerError("no such webhook".to_owned()));
+    };
+    let payload: serde_json::Value = serde_json::from_str(&payload).map_err(|e| {
+        ServerFnError::<leptos::server_fn::error::NoCustomError>::ServerError(format!(
+            "invalid payload: {e}"
+        ))
+    })?;
+    Ok(webhook.run(&manager, &payload).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_to_pointers() {
+        assert_eq!(to_pointer("/data/amount"), "/data/amount");
+        assert_eq!(to_pointer("data.amount"), "/data/amount");
+        assert_eq!(to_pointer(" $.items[0].name "), "/items/0/name");
+        assert_eq!(to_pointer("matrix[1][2]"), "/matrix/1/2");
+        assert_eq!(to_pointer("a/b.c~d"), "/a~1b/c~0d");
+        assert_eq!(to_pointer(""), "");
+    }
+
+    #[cfg(feature = "ssr")]
+    fn headers(pairs: &[(&str, &str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| {
+                (
+                    header::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                    value.parse().unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "ssr")]
+    #[test]
+    fn verify_hmac() {
+        use hmac::Mac;
+
+        let verification = WebhookVerification::Hmac {
+            header: "X-Hub-Signature-256".to_owned(),
+            secret: "s3cr3t".to_owned(),
+            prefix: "sha256=".to_owned(),
+        };
+        let body = br#"{"action":"starred"}"#;
+        let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(b"s3cr3t").unwrap();
+        mac.update(body);
+        let signature = hex::encode(mac.finalize().into_bytes());
+        let payload = serde_json::from_slice(body).unwrap();
+
+        let good = headers(&[("X-Hub-Signature-256", &format!("sha256={signature}"))]);
+        assert!(verification.verify(&good, body, &payload));
+        assert!(!verification.verify(&good, br#"{"action":"deleted"}"#, &payload));
+        for bad in [
+            signature.clone(),
+            format!("sha256={}", "0".repeat(64)),
+            "sha256=not hex".to_owned(),
+        ] {
+            let bad = headers(&[("X-Hub-Signature-256", &bad)]);
+            assert!(!verification.verify(&bad, body, &payload));
+        }
+        assert!(!verification.verify(&HeaderMap::new(), body, &payload));
+    }
+
+    #[cfg(feature = "ssr")]
+    #[test]
+    fn verify_header_and_payload() {
+        let verification = WebhookVerification::Header {
+            header: "Authorization".to_owned(),
+            secret: "token".to_owned(),
+        };
+        let payload = serde_json::json!({ "data": { "verification_token": "token" } });
+        assert!(verification.verify(&headers(&[("Authorization", "token")]), b"", &payload));
+        assert!(!verification.verify(&headers(&[("Authorization", "tokens")]), b"", &payload));
+        assert!(!verification.verify(&HeaderMap::new(), b"", &payload));
+
+        let verification = WebhookVerification::Payload {
+            path: "data.verification_token".to_owned(),
+            secret: "token".to_owned(),
+        };
+        assert!(verification.verify(&HeaderMap::new(), b"", &payload));
+        let wrong = serde_json::json!({ "data": { "verification_token": "nope" } });
+        assert!(!verification.verify(&HeaderMap::new(), b"", &wrong));
+        assert!(!verification.verify(&HeaderMap::new(), b"", &serde_json::json!({})));
+    }
+
+    #[cfg(feature = "ssr")]
+    #[test]
+    fn empty_secrets_are_kept_or_rejected() {
+        let current = WebhookVerification::Header {
+            header: "Authorization".to_owned(),
+            secret: "token".to_owned(),
+        };
+
+        let mut verification = WebhookVerification::Header {
+            header: "X-Token".to_owned(),
+            secret: String::new(),
+        };
+        verification.keep_secret(&current).unwrap();
+        assert_eq!(
+            verification,
+            WebhookVerification::Header {
+                header: "X-Token".to_owned(),
+                secret: "token".to_owned(),
+            }
+        );
+
+        let mut verification = WebhookVerification::Header {
+            header: "X-Token".to_owned(),
+            secret: "new".to_owned(),
+        };
+        verification.keep_secret(&current).unwrap();
+        assert_eq!(verification.secret_mut().unwrap(), "new");
+
+        let mut verification = WebhookVerification::Payload {
+            path: "/token".to_owned(),
+            secret: String::new(),
+        };
+        assert!(verification.keep_secret(&current).is_err());
+        let mut verification = WebhookVerification::Hmac {
+            header: "X-Hub-Signature-256".to_owned(),
+            secret: String::new(),
+            prefix: String::new(),
+        };
+        assert!(verification
+            .keep_secret(&WebhookVerification::None)
+            .is_err());
+        assert!(WebhookVerification::None.keep_secret(&current).is_ok());
+
+        let mut listed = ListedInboundWebhook::from(InboundWebhook {
+            webhook_id: WebhookId::new_id(),
+            name: "test".to_owned(),
+            verification: current,
+            extract: vec![],
+            mappings: vec![],
+        });
+        assert!(listed.has_secret);
+        assert_eq!(listed.webhook.verification.secret_mut().unwrap(), "");
+    }
+
+    #[cfg(feature = "ssr")]
+    #[test]
+    fn parse_json_and_form_bodies() {
+        let json = parse_body(&HeaderMap::new(), br#"{"amount": 5}"#).unwrap();
+        assert_eq!(json, serde_json::json!({ "amount": 5 }));
+        assert!(parse_body(&HeaderMap::new(), b"amount=5").is_err());
+
+        let form = headers(&[(
+            "Content-Type",
+            "application/x-www-form-urlencoded; charset=utf-8",
+        )]);
+        let body = parse_body(
+            &form,
+            b"data=%7B%22amount%22%3A%223.00%22%7D&note=%5B1%5D&name=a+b",
+        )
+        .unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "data": { "amount": "3.00" },
+                "note": "[1]",
+                "name": "a b",
+            })
+        );
+    }
+
+    #[cfg(feature = "ssr")]
+    #[tokio::test]
+    async fn concurrent_edits_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let webhooks = Webhooks::load(dir.path()).await.unwrap();
+        let webhook_id = WebhookId::new_id();
+        webhooks
+            .save(InboundWebhook {
+                webhook_id: webhook_id.clone(),
+                name: "test".to_owned(),
+                verification: WebhookVerification::None,
+                extract: vec![],
+                mappings: vec![],
+            })
+            .await
+            .unwrap();
+
+        let edits = (0..20).map(|i| {
+            let webhooks = webhooks.clone();
+            let webhook_id = webhook_id.clone();
+            tokio::spawn(async move {
+                webhooks
+                    .edit(&webhook_id, move |webhook| {
+                        webhook.extract.push(WebhookExtract {
+                            name: i.to_string(),
+                            path: String::new(),
+                        });
+                        Ok(())
+                    })
+                    .await
+                    .unwrap();
+            })
+        });
+        for edit in edits.collect::<Vec<_>>() {
+            edit.await.unwrap();
+        }
+
+        let reloaded = Webhooks::load(dir.path()).await.unwrap();
+        assert_eq!(reloaded.get(&webhook_id).await.unwrap().extract.len(), 20);
+    }
+}